@@ -1,7 +1,7 @@
 use std::fmt::Debug;
 use std::path::PathBuf;
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 use clap::Parser;
 use mimalloc::MiMalloc;
 
@@ -11,11 +11,12 @@ use evobench_tools::{
         all_outputs_all_fields_table::AllOutputsAllFieldsTable,
         data::log_data_and_tree::LogDataAndTree,
         options::{
-            CheckedOutputOptions, EvaluationAndOutputOpts, FieldSelectorDimension3Opt,
-            FieldSelectorDimension4Opt, FlameFieldOpt,
+            CheckedOutputOptions, EvaluationAndOutputOpts, EvaluationOpts,
+            FieldSelectorDimension3Opt, FieldSelectorDimension4Opt, FlameFieldOpt, OutputVariants,
+            StatsCacheOpt,
         },
     },
-    stats_tables::stats::StatsField,
+    stats_tables::{stats::StatsField, tables::json_table_view::json_tables_schema},
     utillib::{
         get_terminal_width::get_terminal_width,
         logging::{LogLevelOpts, set_log_level},
@@ -53,11 +54,20 @@ enum Command {
     /// Print version
     Version,
 
+    /// Print the JSON schema of the files written via `--json`
+    JsonSchema,
+
     /// Show statistics for a single benchmarking log file
     Single {
         #[clap(flatten)]
         evaluation_and_output_opts: EvaluationAndOutputOpts,
 
+        /// Use the statistics from (and add them to) the stats cache
+        /// file at this path (see `--stats-cache` of `summary`),
+        /// e.g. when `path` is a temporary copy of the log file
+        #[clap(long)]
+        stats_cache_file: Option<PathBuf>,
+
         /// The path that was provided via the `EVOBENCH_LOG`
         /// environment variable to the evobench-probes library.
         path: PathBuf,
@@ -72,12 +82,63 @@ enum Command {
         field_selector_dimension_3: FieldSelectorDimension3Opt,
         #[clap(flatten)]
         flame_selector: FlameFieldOpt,
+        #[clap(flatten)]
+        stats_cache: StatsCacheOpt,
 
         /// The paths that were provided via the `EVOBENCH_LOG`
         /// environment variable to the evobench-probes library.
         paths: Vec<PathBuf>,
     },
 
+    /// Show the change between two sets of benchmarking log files,
+    /// each set for one software version (e.g. a pull request and its
+    /// merge-base), as the relation of the averages of the summary
+    /// field (to / from).
+    Change {
+        #[clap(flatten)]
+        evaluation_opts: EvaluationOpts,
+        #[clap(flatten)]
+        field_selector_dimension_3: FieldSelectorDimension3Opt,
+        #[clap(flatten)]
+        stats_cache: StatsCacheOpt,
+
+        /// Path to write Excel output to
+        #[clap(short, long)]
+        excel: PathBuf,
+
+        /// The paths of the log files for the baseline version
+        #[clap(long, required = true, num_args = 1..)]
+        from: Vec<PathBuf>,
+
+        /// The paths of the log files for the version to compare to
+        /// the baseline
+        #[clap(long, required = true, num_args = 1..)]
+        to: Vec<PathBuf>,
+    },
+
+    /// Show the averages of the summary field for several sets of
+    /// benchmarking log files side by side, one column per set
+    /// (e.g. one set per value of a swept custom parameter). Each set
+    /// is given as a label followed by the paths enclosed in square
+    /// brackets, e.g.: `sweep --excel out.xlsx -- N=1 [ a.log b.log ]
+    /// N=8 [ c.log ]`.
+    Sweep {
+        #[clap(flatten)]
+        evaluation_opts: EvaluationOpts,
+        #[clap(flatten)]
+        field_selector_dimension_3: FieldSelectorDimension3Opt,
+        #[clap(flatten)]
+        stats_cache: StatsCacheOpt,
+
+        /// Path to write Excel output to
+        #[clap(short, long)]
+        excel: PathBuf,
+
+        /// The labelled groups of paths of log files
+        #[clap(required = true)]
+        labelled_groups: Vec<String>,
+    },
+
     /// Show statistics across multiple sets of benchmarking log
     /// files, each group consisting of files for the same software
     /// version. Each group is enclosed with square brackets, e.g.:
@@ -100,6 +161,75 @@ enum Command {
     },
 }
 
+/// Read the given log files, up to `NUM_FILES_IN_PARALLEL` at once;
+/// via their stats cache files if `stats_cache` is true.
+fn read_files(
+    paths: &[PathBuf],
+    evaluation_opts: &EvaluationOpts,
+    variants: &OutputVariants<PathBuf>,
+    stats_cache: bool,
+) -> Result<Vec<AllOutputsAllFieldsTable<SingleRunStats>>> {
+    let chunk_size = (paths.len() + NUM_FILES_IN_PARALLEL - 1) / NUM_FILES_IN_PARALLEL;
+    let afts: Vec<Vec<AllOutputsAllFieldsTable<SingleRunStats>>> = paths
+        .chunks(chunk_size)
+        .par_bridge()
+        .map(
+            |source_paths| -> Result<Vec<AllOutputsAllFieldsTable<SingleRunStats>>> {
+                let mut afts = Vec::new();
+                for source_path in source_paths {
+                    if stats_cache {
+                        afts.push(AllOutputsAllFieldsTable::from_log_file_cached(
+                            source_path,
+                            evaluation_opts,
+                            variants.clone(),
+                            false,
+                        )?);
+                    } else {
+                        let ldat = LogDataAndTree::read_file(source_path, None)?;
+                        afts.push(AllOutputsAllFieldsTable::from_log_data_tree(
+                            ldat.tree(),
+                            evaluation_opts,
+                            variants.clone(),
+                            false,
+                        )?);
+                    }
+                }
+                Ok(afts)
+            },
+        )
+        .collect::<Result<_>>()?;
+    Ok(afts.into_flattened())
+}
+
+/// Parse `label [ path ... ] label [ path ... ] ...`
+fn parse_labelled_groups(args: &[String]) -> Result<Vec<(String, Vec<PathBuf>)>> {
+    let mut groups = Vec::new();
+    let mut args = args.iter();
+    while let Some(label) = args.next() {
+        if label == "[" || label == "]" {
+            bail!("expecting a label, got {label:?}")
+        }
+        match args.next() {
+            Some(s) if s == "[" => (),
+            other => bail!("expecting `[` after label {label:?}, got {other:?}"),
+        }
+        let mut paths = Vec::new();
+        loop {
+            match args.next() {
+                Some(s) if s == "]" => break,
+                Some(s) if s == "[" => bail!("nested `[` in group for label {label:?}"),
+                Some(s) => paths.push(PathBuf::from(s)),
+                None => bail!("missing `]` for label {label:?}"),
+            }
+        }
+        if paths.is_empty() {
+            bail!("no paths given for label {label:?}")
+        }
+        groups.push((label.clone(), paths));
+    }
+    Ok(groups)
+}
+
 fn main() -> Result<()> {
     let Opts { log_level, command } = Opts::parse();
 
@@ -107,6 +237,7 @@ fn main() -> Result<()> {
 
     match command {
         Command::Version => println!("{PROGRAM_NAME} version {EVOBENCH_VERSION}"),
+        Command::JsonSchema => println!("{}", json_tables_schema()),
 
         Command::Single {
             evaluation_and_output_opts:
@@ -114,16 +245,27 @@ fn main() -> Result<()> {
                     evaluation_opts,
                     output_opts,
                 },
+            stats_cache_file,
             path,
         } => {
             let CheckedOutputOptions { variants } = output_opts.check()?;
-            let ldat = LogDataAndTree::read_file(&path, None)?;
-            let aoaft = AllOutputsAllFieldsTable::from_log_data_tree(
-                ldat.tree(),
-                &evaluation_opts,
-                variants,
-                true,
-            )?;
+            let aoaft = if let Some(stats_cache_file) = stats_cache_file {
+                AllOutputsAllFieldsTable::from_log_file_with_cache_file(
+                    &path,
+                    &stats_cache_file,
+                    &evaluation_opts,
+                    variants,
+                    true,
+                )?
+            } else {
+                let ldat = LogDataAndTree::read_file(&path, None)?;
+                AllOutputsAllFieldsTable::from_log_data_tree(
+                    ldat.tree(),
+                    &evaluation_opts,
+                    variants,
+                    true,
+                )?
+            };
             aoaft.write_to_files(StatsField::Sum)?;
         }
 
@@ -136,30 +278,12 @@ fn main() -> Result<()> {
             paths,
             field_selector_dimension_3: FieldSelectorDimension3Opt { summary_field },
             flame_selector: FlameFieldOpt { flame_field },
+            stats_cache: StatsCacheOpt { stats_cache },
         } => {
             let CheckedOutputOptions { variants } = output_opts.check()?;
-            let chunk_size = (paths.len() + NUM_FILES_IN_PARALLEL - 1) / NUM_FILES_IN_PARALLEL;
-            let afts: Vec<Vec<AllOutputsAllFieldsTable<SingleRunStats>>> = paths
-                .chunks(chunk_size)
-                .par_bridge()
-                .map(
-                    |source_paths| -> Result<Vec<AllOutputsAllFieldsTable<SingleRunStats>>> {
-                        let mut afts = Vec::new();
-                        for source_path in source_paths {
-                            let ldat = LogDataAndTree::read_file(source_path, None)?;
-                            afts.push(AllOutputsAllFieldsTable::from_log_data_tree(
-                                ldat.tree(),
-                                &evaluation_opts,
-                                variants.clone(),
-                                false,
-                            )?);
-                        }
-                        Ok(afts)
-                    },
-                )
-                .collect::<Result<_>>()?;
+            let afts = read_files(&paths, &evaluation_opts, &variants, stats_cache)?;
             let aft = AllOutputsAllFieldsTable::<SummaryStats>::summary_stats(
-                &afts.into_flattened(),
+                &afts,
                 summary_field,
                 &evaluation_opts,
                 variants, // same as passed to from_log_data_tree above
@@ -168,6 +292,69 @@ fn main() -> Result<()> {
             aft.write_to_files(flame_field)?;
         }
 
+        Command::Change {
+            evaluation_opts,
+            field_selector_dimension_3: FieldSelectorDimension3Opt { summary_field },
+            stats_cache: StatsCacheOpt { stats_cache },
+            excel,
+            from,
+            to,
+        } => {
+            let variants = OutputVariants {
+                excel: Some(excel),
+                flame: None,
+                json: None,
+                csv: None,
+            };
+            let summarize = |paths: &[PathBuf]| -> Result<_> {
+                let afts = read_files(paths, &evaluation_opts, &variants, stats_cache)?;
+                Ok(AllOutputsAllFieldsTable::<SummaryStats>::summary_stats(
+                    &afts,
+                    summary_field,
+                    &evaluation_opts,
+                    variants.clone(),
+                    false,
+                ))
+            };
+            let from = summarize(&from)?;
+            let to = summarize(&to)?;
+            to.write_change_to_excel_file(&from)?;
+        }
+
+        Command::Sweep {
+            evaluation_opts,
+            field_selector_dimension_3: FieldSelectorDimension3Opt { summary_field },
+            stats_cache: StatsCacheOpt { stats_cache },
+            excel,
+            labelled_groups,
+        } => {
+            let variants = OutputVariants {
+                excel: Some(excel.clone()),
+                flame: None,
+                json: None,
+                csv: None,
+            };
+            let columns = parse_labelled_groups(&labelled_groups)?
+                .into_iter()
+                .map(|(label, paths)| -> Result<_> {
+                    let afts = read_files(&paths, &evaluation_opts, &variants, stats_cache)?;
+                    let summary = AllOutputsAllFieldsTable::<SummaryStats>::summary_stats(
+                        &afts,
+                        summary_field,
+                        &evaluation_opts,
+                        variants.clone(),
+                        false,
+                    );
+                    Ok((label, summary))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let columns: Vec<(&str, &AllOutputsAllFieldsTable<SummaryStats>)> = columns
+                .iter()
+                .map(|(label, summary)| (label.as_str(), summary))
+                .collect();
+            AllOutputsAllFieldsTable::write_sweep_to_excel_file(&columns, &excel)?;
+        }
+
         #[allow(unused)]
         Command::Trend {
             evaluation_and_output_opts: evaluation_opts,
@@ -64,6 +64,7 @@ fn main() -> Result<()> {
                 config,
                 |msg| bail!("can't load config: {msg}"),
                 GlobalAppStateDir::new()?,
+                None,
             )?;
 
             info!("migrating the queues");
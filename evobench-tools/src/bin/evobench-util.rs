@@ -6,26 +6,40 @@ use cj_path_util::path_util::AppendToPath;
 use clap::Parser;
 
 use evobench_tools::{
+    config_file::backend_from_path,
     ctx,
     git::GitHash,
+    git_ext::MoreGitWorkingDir,
     info,
     run::{
         config::{RunConfig, RunConfigBundle},
         global_app_state_dir::GlobalAppStateDir,
+        insert_jobs::open_already_inserted,
+        key::BenchmarkingJobParameters,
+        migrate::{check_rekey_already_inserted, rekey_already_inserted},
         output_directory::{
+            archive::{ExportFilter, export_runs, import_runs},
+            commit_overview::regenerate_commit_overviews,
             html_files::regenerate_index_files,
-            post_process::compress_file_as,
+            post_process::{compress_file_as, generate_summaries_for_key_dirs},
+            prune::{prune_output_dir, tagged_commits},
+            rekey::{RekeyMapping, check_no_queued_jobs_to_rekey, rekey_output_dir},
+            results_index::ResultsIndex,
             structure::{KeyDir, OutputSubdir, RunDir, SubDirs},
         },
+        run_queues::RunQueues,
+        sub_command::open_polling_pool,
         working_directory_pool::WorkingDirectoryPoolBaseDir,
     },
-    serde_types::proper_dirname::ProperDirname,
+    serde_types::{date_and_time::DateTimeWithOffset, proper_dirname::ProperDirname},
     util::grep_diff::GrepDiffRegion,
     utillib::{
+        arc::CloneArc,
         get_terminal_width::get_terminal_width,
         into_arc_path::IntoArcPath,
         logging::{LogLevelOpts, set_log_level},
     },
+    warn,
 };
 
 #[derive(clap::Parser, Debug)]
@@ -108,7 +122,8 @@ enum SubCommand {
 
     /// Do the same "summary" post-processing on a set of benchmark
     /// results as `evobench daemon` does--useful in case new
-    /// features were added or the configuration was changed.
+    /// features were added or the configuration was changed. Several
+    /// key dirs are processed in parallel.
     PostProcessSummary {
         /// Run `post-process-single` on all sub-directories for the
         /// individual runs for this 'key', too.
@@ -126,10 +141,84 @@ enum SubCommand {
         #[clap(long)]
         no_summary_stats: bool,
 
-        /// The path to a directory for a particular 'key', i.e. a set
+        /// The paths to directories for particular 'keys', i.e. sets
         /// of individual runs: ending in a directory name that is a
         /// commit id
-        key_dir: PathBuf,
+        #[clap(required = true)]
+        key_dirs: Vec<PathBuf>,
+    },
+
+    /// Maintain the SQLite results index in the output directory
+    /// (queried via `evobench query`)
+    Index {
+        #[clap(subcommand)]
+        subcommand: IndexSubCommand,
+    },
+
+    /// Delete the raw log files of old runs and outdated entries in
+    /// the `latest` directories, as configured in
+    /// `output_dir.retention` (the run daemon does this periodically,
+    /// too). Summaries are never deleted.
+    Prune {
+        /// Only show what would be deleted
+        #[clap(long)]
+        dry_run: bool,
+    },
+
+    /// Write the runs of the selected keys (all of them if no filter
+    /// is given) to a self-describing `.tar.zst` archive with a
+    /// manifest holding a config excerpt, host info and the hashes of
+    /// all files. Summaries are not included, `import` regenerates
+    /// them.
+    Export {
+        /// Select keys: `target=NAME`, `param=VAR=VALUE`,
+        /// `commit=HASH` or `commits=RANGE` (a Git revision range
+        /// like `v1.0..main`, resolved via the polling pool). Can be
+        /// given multiple times; filters of the same kind are or'ed,
+        /// those of different kinds and'ed (for `param`, per
+        /// variable).
+        #[clap(long)]
+        filter: Vec<ExportFilter>,
+
+        /// Path of the archive to write
+        archive: PathBuf,
+    },
+
+    /// Merge the runs from an archive written by `export` into the
+    /// output directory: verifies the hashes, skips runs that are
+    /// already present, updates the results index and regenerates the
+    /// summaries of the affected keys and the index files.
+    Import {
+        /// Skip runs that already exist with different contents,
+        /// instead of aborting the import
+        #[clap(long)]
+        skip_conflicts: bool,
+
+        /// Path of the archive to read
+        archive: PathBuf,
+    },
+
+    /// Move the results to new key paths after the key layout
+    /// changed (a target was renamed, or a custom parameter added or
+    /// dropped), so that the history is not split into separate
+    /// trees. Also fixes the `latest` and `latest-redir` entries, the
+    /// job table shown by `evobench list-all` and the results index,
+    /// and regenerates the affected summaries, comparison reports and
+    /// sweep tables (removing those at the old paths). The mapping is
+    /// checked against the config before anything is moved, and
+    /// nothing is moved while jobs that it would rekey are still
+    /// queued. Stop the run daemon first.
+    Rekey {
+        /// Only show what would be moved
+        #[clap(long)]
+        dry_run: bool,
+
+        /// The mapping file (in any of the config file formats), e.g.
+        /// in RON: `RekeyMapping(rules: [RenameTarget(from: "api", to:
+        /// "api-server"), AddParameter(target: None, var: "THREADS",
+        /// value: "1"), DropParameter(target: Some("api-server"), var:
+        /// "SORTED")])`. The rules are applied in order.
+        mapping_file: PathBuf,
     },
 
     /// Development commands; these are meant for app development, not
@@ -140,9 +229,24 @@ enum SubCommand {
     },
 }
 
+#[derive(clap::Subcommand, Debug)]
+enum IndexSubCommand {
+    /// (Re-)index all runs found in the output directory, and remove
+    /// runs from the index that no longer exist. Commit times and
+    /// first parents that are missing are retrieved via the polling
+    /// pool (which fetches from the remote repository). Then
+    /// regenerates the commit overviews (`commits/` in the output
+    /// directory).
+    Rebuild {
+        /// Do not retrieve missing commit times and first parents
+        #[clap(long)]
+        no_commit_times: bool,
+    },
+}
+
 #[derive(clap::Subcommand, Debug)]
 enum DevSubCommand {
-    /// Regenerate index files
+    /// Regenerate index files, including the commit overviews
     RegenerateIndexFiles,
 
     /// List the contents of a folder, structurally
@@ -214,6 +318,8 @@ fn post_process_single(run_dir: &RunDir, run_config: &RunConfig, no_stats: bool)
         &standard_log_path,
         run_config,
         no_stats,
+        // Keep the commit time already in the results index, if any
+        None,
     )?;
     Ok(())
 }
@@ -234,6 +340,7 @@ fn main() -> Result<()> {
                 config,
                 |msg| bail!("can't load config: {msg}"),
                 GlobalAppStateDir::new()?,
+                None,
             )?)
         }
     };
@@ -260,28 +367,222 @@ fn main() -> Result<()> {
         }
         SubCommand::PostProcessSummary {
             single,
-            key_dir,
+            key_dirs,
             no_single_stats,
             no_summary_stats,
         } => {
             let run_config_bundle = get_config()?;
             let conf = &run_config_bundle.shareable.run_config;
 
-            let key_dir: Arc<_> = KeyDir::try_from(key_dir.into_arc_path())?.into();
+            let key_dirs = key_dirs
+                .into_iter()
+                .map(|key_dir| -> Result<Arc<KeyDir>> {
+                    Ok(KeyDir::try_from(key_dir.into_arc_path())?.into())
+                })
+                .collect::<Result<Vec<_>>>()?;
 
             if single {
-                for run_dir in key_dir.sub_dirs()? {
-                    let run_dir = run_dir?;
-                    post_process_single(&run_dir, conf, no_single_stats)?;
+                for key_dir in &key_dirs {
+                    for run_dir in key_dir.sub_dirs()? {
+                        let run_dir = run_dir?;
+                        post_process_single(&run_dir, conf, no_single_stats)?;
+                    }
+                }
+            }
+
+            generate_summaries_for_key_dirs(&key_dirs, no_summary_stats, conf)?;
+        }
+        SubCommand::Index { subcommand } => match subcommand {
+            IndexSubCommand::Rebuild { no_commit_times } => {
+                let run_config_bundle = get_config()?;
+                let shareable = &run_config_bundle.shareable;
+                let conf = &shareable.run_config;
+
+                let mut index = ResultsIndex::open(conf.output_dir.path.clone_arc())?;
+                let num_indexed = index.rebuild()?;
+                info!("indexed {num_indexed} runs");
+
+                if !no_commit_times {
+                    let commits = index.commits_without_time()?;
+                    let commits_without_parent = index.commits_without_first_parent()?;
+                    if !(commits.is_empty() && commits_without_parent.is_empty()) {
+                        let mut polling_pool = open_polling_pool(shareable)?;
+                        let working_directory_id = polling_pool.updated_working_dir()?;
+                        let (commit_times, first_parents) = polling_pool
+                            .process_in_working_directory(
+                                working_directory_id,
+                                &DateTimeWithOffset::now(None),
+                                |mut working_directory| -> Result<_> {
+                                    let working_directory =
+                                        working_directory.get().expect("still there");
+                                    let git_working_dir = &working_directory.git_working_dir;
+                                    Ok((
+                                        git_working_dir.commit_times(&commits)?,
+                                        git_working_dir.first_parents(&commits_without_parent)?,
+                                    ))
+                                },
+                                "getting commit times and parents",
+                            )?;
+                        if commit_times.len() < commits.len() {
+                            warn!(
+                                "could not find {} of the {} commits without commit time",
+                                commits.len() - commit_times.len(),
+                                commits.len()
+                            );
+                        }
+                        index.set_commit_times(&commit_times)?;
+                        index.set_first_parents(&first_parents)?;
+                    }
+                }
+
+                let num_overviews = regenerate_commit_overviews(conf.output_dir.path.clone_arc())?;
+                info!("wrote {num_overviews} commit overviews");
+            }
+        },
+        SubCommand::Prune { dry_run } => {
+            let run_config_bundle = get_config()?;
+            let shareable = &run_config_bundle.shareable;
+            let conf = &shareable.run_config;
+
+            let Some(retention) = &conf.output_dir.retention else {
+                bail!("no `retention` is configured in `output_dir`")
+            };
+            let tagged_commits = if retention.keep_tagged.unwrap_or(true) {
+                let mut polling_pool = open_polling_pool(shareable)?;
+                let working_directory_id = polling_pool.updated_working_dir()?;
+                Some(polling_pool.process_in_working_directory(
+                    working_directory_id,
+                    &DateTimeWithOffset::now(None),
+                    |mut working_directory| {
+                        let working_directory = working_directory.get().expect("still there");
+                        tagged_commits(&working_directory.git_working_dir, &conf.commit_tags_regex)
+                    },
+                    "getting tagged commits",
+                )?)
+            } else {
+                None
+            };
+            let stats = prune_output_dir(
+                &conf.output_dir.path,
+                retention,
+                tagged_commits.as_ref(),
+                None,
+                dry_run,
+            )?;
+            if dry_run {
+                println!("would delete {stats}");
+            } else {
+                println!("deleted {stats}");
+            }
+        }
+        SubCommand::Export { filter, archive } => {
+            let run_config_bundle = get_config()?;
+            let shareable = &run_config_bundle.shareable;
+            let conf = &shareable.run_config;
+
+            let filter_strings = filter.iter().map(|f| f.to_string()).collect();
+            let ranges: Vec<&str> = filter
+                .iter()
+                .filter_map(|f| match f {
+                    ExportFilter::Commits(range) => Some(range.as_str()),
+                    _ => None,
+                })
+                .collect();
+            let mut filters: Vec<ExportFilter> = filter
+                .iter()
+                .filter(|f| !matches!(f, ExportFilter::Commits(_)))
+                .cloned()
+                .collect();
+            if !ranges.is_empty() {
+                let mut polling_pool = open_polling_pool(shareable)?;
+                let working_directory_id = polling_pool.updated_working_dir()?;
+                let commits = polling_pool.process_in_working_directory(
+                    working_directory_id,
+                    &DateTimeWithOffset::now(None),
+                    |mut working_directory| -> Result<Vec<GitHash>> {
+                        let working_directory = working_directory.get().expect("still there");
+                        let mut commits = Vec::new();
+                        for range in &ranges {
+                            commits.extend(working_directory.git_working_dir.rev_list(range)?);
+                        }
+                        Ok(commits)
+                    },
+                    "resolving commit ranges",
+                )?;
+                if commits.is_empty() {
+                    bail!("the commit ranges {ranges:?} are empty")
                 }
+                filters.extend(commits.into_iter().map(ExportFilter::Commit));
             }
 
-            key_dir.generate_summaries_for_key_dir(no_summary_stats)?;
+            let num_runs = export_runs(conf, &filters, filter_strings, &archive)?;
+            info!("exported {num_runs} runs to {archive:?}");
+        }
+        SubCommand::Import {
+            skip_conflicts,
+            archive,
+        } => {
+            let run_config_bundle = get_config()?;
+            let shareable = &run_config_bundle.shareable;
+            let conf = &shareable.run_config;
+
+            let stats = import_runs(conf, &archive, skip_conflicts)?;
+            println!("{stats}");
+            if stats.imported > 0 {
+                regenerate_index_files(shareable, None, None, None)?;
+            }
+        }
+        SubCommand::Rekey {
+            dry_run,
+            mapping_file,
+        } => {
+            let run_config_bundle = get_config()?;
+            let shareable = &run_config_bundle.shareable;
+            let conf = &shareable.run_config;
+
+            let mapping: RekeyMapping =
+                backend_from_path(&mapping_file)?.load_config_file(&mapping_file)?;
+
+            // Check the whole mapping before moving anything
+            let already_inserted = open_already_inserted(&shareable.global_app_state_dir)?;
+            let rekey =
+                |params: &BenchmarkingJobParameters| mapping.apply_to_job_parameters(params, conf);
+            let num_entries = check_rekey_already_inserted(&already_inserted, rekey)?;
+            let queues = RunQueues::open(
+                conf.queues.clone_arc(),
+                true,
+                &shareable.global_app_state_dir,
+                None,
+            )?;
+            check_no_queued_jobs_to_rekey(&queues, &mapping)?;
+
+            let stats = rekey_output_dir(conf, &mapping, dry_run)?;
+            if dry_run {
+                println!(
+                    "would have {stats}, and rekeyed {num_entries} entries in the \
+                     already_inserted table"
+                );
+            } else {
+                println!("{stats}");
+
+                let n = rekey_already_inserted(&already_inserted, rekey)?;
+                info!("rekeyed {n} entries in the already_inserted table");
+
+                regenerate_index_files(shareable, None, None, None)?;
+            }
         }
         SubCommand::Dev { subcommand } => match subcommand {
             DevSubCommand::RegenerateIndexFiles => {
                 let run_config_bundle = get_config()?;
-                regenerate_index_files(&run_config_bundle.shareable, None, None)?;
+                regenerate_index_files(&run_config_bundle.shareable, None, None, None)?;
+                let output_base_dir = run_config_bundle
+                    .shareable
+                    .run_config
+                    .output_dir
+                    .path
+                    .clone_arc();
+                let num_overviews = regenerate_commit_overviews(output_base_dir)?;
+                info!("wrote {num_overviews} commit overviews");
             }
             DevSubCommand::ListOutputDir { dir_path } => {
                 let dir_path = dir_path.into_arc_path();
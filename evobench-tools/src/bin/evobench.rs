@@ -9,7 +9,7 @@ use chj_unix_util::{
         },
     },
     logging::{TimestampMode, TimestampOpts},
-    polling_signals::PollingSignalsSender,
+    polling_signals::{PollingSignals, PollingSignalsSender},
     timestamp_formatter::TimestampFormatter,
 };
 use clap::{CommandFactory, Parser};
@@ -17,47 +17,65 @@ use itertools::Itertools;
 use url::Url;
 
 use std::{
-    io::{StdoutLock, Write, stdout},
+    io::{Write, stdout},
+    net::SocketAddr,
     os::unix::{ffi::OsStrExt, process::CommandExt},
     path::{Path, PathBuf},
     process::{Command, exit},
     str::FromStr,
     sync::{Arc, atomic::Ordering},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use evobench_tools::{
     config_file::{self, ConfigFile, save_config_file},
     ctx, debug,
-    git::GitHash,
+    git::{GitGraph, GitHash},
     info,
     io_utils::{lockable_file::StandaloneExclusiveFileLock, shell::preferred_shell},
     lazyresult,
     run::{
         bench_tmp_dir::bench_tmp_dir,
         benchmarking_job::{BenchmarkingJobOpts, BenchmarkingJobReasonOpt},
+        coalescing::coalesce_pending_jobs,
         config::{RunConfig, RunConfigBundle, RunConfigOpts},
+        distributed::run_leased_job,
         global_app_state_dir::GlobalAppStateDir,
         insert_jobs::{DryRunOpt, ForceOpt, QuietOpt, insert_jobs},
+        metrics::{DEFAULT_METRICS_LISTEN, MetricsServer, gather_metrics, write_metrics_file},
         open_run_queues::open_run_queues,
-        output_directory::structure::{OutputSubdir, SubDirs},
+        output_directory::{
+            prune::{PruneStats, prune_output_dir, tagged_commits},
+            structure::{OutputSubdir, SubDirs},
+        },
+        polled_heads::PolledHeads,
+        polling_pool::PolledRef,
+        ref_pattern::RefKind,
         run_context::RunContext,
         run_job::JobRunner,
         run_queues::RunQueues,
+        serve::{DEFAULT_SERVE_LISTEN, OutputServer, ServeContext},
         sub_command::{
+            config::ConfigSubCommand,
+            coordinator::Coordinator,
             insert::{Insert, InsertBenchmarkingJobOpts},
             list::ListOpts,
             list_all::ListAllOpts,
             open_polling_pool, open_working_directory_pool,
+            query::QueryOpts,
             wd::{
                 Wd, get_run_lock, open_queue_change_signals, open_working_directory_change_signals,
             },
         },
         versioned_dataset_dir::VersionedDatasetDir,
+        webhook::WebhookServer,
         working_directory_pool::{WorkingDirectoryPool, WorkingDirectoryPoolBaseDir},
     },
-    serde_types::date_and_time::{DateTimeWithOffset, LOCAL_TIME},
+    serde_types::{
+        date_and_time::{DateTimeWithOffset, LOCAL_TIME},
+        proper_dirname::ProperDirname,
+    },
     util::clap_styles::clap_styles,
     utillib::{
         arc::CloneArc,
@@ -66,6 +84,7 @@ use evobench_tools::{
         into_arc_path::IntoArcPath,
         logging::{LogLevel, LogLevelOpts, set_log_level},
     },
+    warn,
 };
 
 type CheckExit<'t> =
@@ -105,6 +124,12 @@ struct Opts {
     #[clap(long)]
     config: Option<PathBuf>,
 
+    /// Select one of the projects configured in the `projects` field
+    /// of the config file (default: the project configured at the
+    /// top level of the config file).
+    #[clap(long)]
+    project: Option<ProperDirname>,
+
     /// The subcommand to run. Use `--help` after the sub-command to
     /// get a list of the allowed options there.
     #[clap(subcommand)]
@@ -138,8 +163,11 @@ enum SubCommand {
     },
 
     /// Insert jobs for new commits on branch names configured in the
-    /// config option `remote_branch_names_for_poll`. For one-off
-    /// manual insertion see `insert` instead. .
+    /// config option `remote_branch_names_for_poll`, and on branches
+    /// and tags matched by `ref_patterns_for_poll` (tags only if new
+    /// since the first poll of their pattern). With `--dry-run`, the
+    /// resolved names are shown, too. For one-off manual
+    /// insertion see `insert` instead. .
     Poll {
         // No QuietOpt since that must be the default. Also, another
         // force option since the help text is different here.
@@ -170,10 +198,40 @@ enum SubCommand {
     /// Run the existing jobs; this takes a lock or stops with an
     /// error if the lock is already taken
     Run {
+        /// Instead of running jobs from the local queues, run jobs
+        /// leased from the coordinator configured in the `worker`
+        /// field of the config file, and upload the results to it.
+        #[clap(long)]
+        worker: bool,
+
         #[clap(subcommand)]
         mode: RunMode,
     },
 
+    /// Commands for workers (`run --worker`) to lease jobs from and
+    /// report results to this installation, if configured as
+    /// coordinator (`queues.coordinator`)
+    Coordinator {
+        #[clap(subcommand)]
+        subcommand: Coordinator,
+    },
+
+    /// Receive push and pull request events from GitHub or Gitea
+    /// and insert jobs for them (configured in
+    /// `remote_repository.webhook`), as an alternative to `poll`
+    Webhook {
+        #[clap(subcommand)]
+        subcommand: WebhookSubCommand,
+    },
+
+    /// Export the latest results and the health of the benchmarking
+    /// system as metrics in the OpenMetrics format (for Prometheus /
+    /// Grafana)
+    Metrics {
+        #[clap(subcommand)]
+        subcommand: MetricsSubCommand,
+    },
+
     /// Handle working directories
     Wd {
         /// The subcommand to run. Use `--help` after the sub-command to
@@ -182,6 +240,15 @@ enum SubCommand {
         subcommand: Wd,
     },
 
+    /// Query the results index in the output directory (see
+    /// `evobench-util index`): filter the per-run, per-probe
+    /// statistics with SQL expressions, output as table, CSV or
+    /// JSON
+    Query {
+        #[clap(flatten)]
+        opts: QueryOpts,
+    },
+
     /// Parse URLs to output directories
     Url {
         /// Open a new $SHELL (or bash) in the output
@@ -197,6 +264,18 @@ enum SubCommand {
     /// list`, `list-all`, `run daemon status`, `poll daemon status`)
     Status {},
 
+    /// Serve the output directory via HTTP for local viewing, together
+    /// with the `list`, `wd list` and `status` views (at
+    /// `/_evobench/list`, `/_evobench/wd`, `/_evobench/status`),
+    /// which reload when the queues or working directories change.
+    /// Runs until terminated.
+    Serve {
+        /// The address to listen on; only loopback addresses are
+        /// allowed.
+        #[clap(long, default_value = DEFAULT_SERVE_LISTEN)]
+        listen: SocketAddr,
+    },
+
     /// Generate a shell completions file. (Redirect stdout to a file
     /// that is `source`d from the shell.)
     Completions {
@@ -205,6 +284,14 @@ enum SubCommand {
         shell: clap_complete_command::Shell,
     },
 
+    /// Show information from the configuration file
+    Config {
+        /// The subcommand to run. Use `--help` after the sub-command to
+        /// get a list of the allowed options there.
+        #[clap(subcommand)]
+        subcommand: ConfigSubCommand,
+    },
+
     /// Show the supported config format types.
     ConfigFormats,
 
@@ -214,6 +301,39 @@ enum SubCommand {
     ConfigSave { output_path: PathBuf },
 }
 
+#[derive(Debug, clap::Subcommand)]
+enum WebhookSubCommand {
+    /// Listen for webhook requests (signed with the configured
+    /// secret), and insert jobs for pushes to and pull requests
+    /// from branches configured in `remote_branch_names_for_poll` or
+    /// matched by `ref_patterns_for_poll`. In `one` mode, handles a
+    /// single request then exits.
+    Serve {
+        #[clap(flatten)]
+        dry_run_opt: DryRunOpt,
+
+        #[clap(subcommand)]
+        mode: RunMode,
+    },
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum MetricsSubCommand {
+    /// Serve the metrics via HTTP at `/metrics`, until terminated
+    Serve {
+        /// The address to listen on; only loopback addresses are
+        /// allowed.
+        #[clap(long, default_value = DEFAULT_METRICS_LISTEN)]
+        listen: SocketAddr,
+    },
+
+    /// Write the metrics to a file (atomically, e.g. into the
+    /// directory of the node exporter's textfile collector; the file
+    /// name needs to end in `.prom` for that), or to stdout if no
+    /// path is given
+    Write { path: Option<PathBuf> },
+}
+
 #[derive(Debug, clap::Subcommand)]
 pub enum RunMode {
     /// Carry out a single run
@@ -261,16 +381,24 @@ enum RunResult {
     StopOrRestart,
 }
 
+/// How many seconds a worker waits before asking the coordinator
+/// again after it had no job to lease
+const WORKER_IDLE_POLL_SECONDS: u64 = 15;
+
 /// Run through the queues forever unless `once` is true (in which
 /// case it returns whether a job was run), but pick up config
 /// changes; it also returns in non-once mode if the binary changes
 /// and true was given for `restart_on_upgrades`. Requires holding the
-/// `_run_lock`, the lock for executing "run" actions.
+/// `_run_lock`, the lock for executing "run" actions. If `worker` is
+/// true, runs jobs leased from the configured coordinator instead of
+/// from `queues` (the caller must have checked that `conf.worker` is
+/// given).
 fn run_queues<'ce>(
     run_config_bundle: RunConfigBundle,
     queues: RunQueues,
     working_directory_base_dir: Arc<WorkingDirectoryPoolBaseDir>,
     mut working_directory_pool: WorkingDirectoryPool,
+    worker: bool,
     once: bool,
     daemon_check_exit: Option<CheckExit<'ce>>,
     queue_change_signals: PollingSignalsSender,
@@ -278,9 +406,19 @@ fn run_queues<'ce>(
     _run_lock: StandaloneExclusiveFileLock,
 ) -> Result<RunResult> {
     let conf = &run_config_bundle.shareable.run_config;
+    let worker_opts = if worker {
+        Some(
+            conf.worker
+                .as_ref()
+                .expect("checked by caller as documented"),
+        )
+    } else {
+        None
+    };
 
     let mut run_context = RunContext::default();
-    let versioned_dataset_dir = VersionedDatasetDir::new();
+    let versioned_dataset_dir =
+        VersionedDatasetDir::open(&run_config_bundle.shareable.global_app_state_dir)?;
 
     // Test-run
     if let Some(versioned_dataset_base_dir) = &conf.versioned_datasets_base_dir {
@@ -352,24 +490,59 @@ fn run_queues<'ce>(
 
     let mut working_directory_change_signals = open_working_directory_change_signals(conf)?;
 
+    let global_run_lock_dir = run_config_bundle
+        .shareable
+        .global_app_state_dir
+        .global_run_lock_dir()?;
+
+    let mut last_prune: Option<Instant> = None;
+
     loop {
         // XX handle errors without exiting? Or do that above
 
-        let queues_data = queues.data()?;
+        if let Some(retention) = &conf.output_dir.retention {
+            let interval =
+                Duration::from_secs(u64::from(retention.interval_hours.unwrap_or(24)) * 3600);
+            if last_prune.is_none_or(|t| t.elapsed() >= interval) {
+                last_prune = Some(Instant::now());
+                match prune_with_working_directory_pool(
+                    conf,
+                    &mut working_directory_pool,
+                    file_cleanup_handler,
+                ) {
+                    Ok(stats) => info!("pruned output dir: deleted {stats}"),
+                    Err(e) => warn!("pruning the output dir failed: {e:#}"),
+                }
+            }
+        }
 
-        let ran = queues_data.run_next_job(
-            JobRunner {
+        let queues_data;
+        let (ran, locally_run) = {
+            // `_run_lock` is per project; this lock makes sure only
+            // one job is run on the machine, across all projects
+            // (blocks while another project's job is running). Take
+            // it before reading the queues, since they may have
+            // changed while blocking.
+            let _global_run_lock = StandaloneExclusiveFileLock::lock_path(&global_run_lock_dir)
+                .map_err(ctx!("locking {global_run_lock_dir:?}"))?;
+            queues_data = queues.data()?;
+            let job_runner = JobRunner {
                 working_directory_pool: &mut working_directory_pool,
                 output_base_dir: &conf.output_dir.path,
                 timestamp: DateTimeWithOffset::now(None),
                 shareable_config: &run_config_bundle.shareable,
                 versioned_dataset_dir: &versioned_dataset_dir,
                 file_cleanup_handler: &file_cleanup_handler,
-            },
-            &mut run_context,
-        )?;
+            };
+            if let Some(worker_opts) = worker_opts {
+                (run_leased_job(worker_opts, job_runner, &queues_data)?, None)
+            } else {
+                let locally_run = queues_data.run_next_job(job_runner, &mut run_context)?;
+                (locally_run.is_some(), locally_run)
+            }
+        };
 
-        if let Some((job, job_status)) = ran {
+        if let Some((job, job_status)) = locally_run {
             if !job_status.can_run_again() {
                 let parameters = job.benchmarking_job_parameters();
                 let key_dir = parameters.to_key_dir(conf.output_dir.path.clone_arc());
@@ -390,15 +563,23 @@ fn run_queues<'ce>(
         }
 
         if once {
-            return Ok(RunResult::OnceResult(ran.is_some()));
+            return Ok(RunResult::OnceResult(ran));
         }
 
-        // XX have something better than polling?
-        thread::sleep(Duration::from_secs(1));
+        // Don't bother the coordinator too often
+        let sleep_seconds = if worker && !ran {
+            WORKER_IDLE_POLL_SECONDS
+        } else {
+            1
+        };
+        for _ in 0..sleep_seconds {
+            // XX have something better than polling?
+            thread::sleep(Duration::from_secs(1));
 
-        if let Some(daemon_check_exit) = daemon_check_exit.as_ref() {
-            if daemon_check_exit.want_exit() {
-                return Ok(RunResult::StopOrRestart);
+            if let Some(daemon_check_exit) = daemon_check_exit.as_ref() {
+                if daemon_check_exit.want_exit() {
+                    return Ok(RunResult::StopOrRestart);
+                }
             }
         }
 
@@ -416,6 +597,119 @@ fn run_queues<'ce>(
     }
 }
 
+/// Prune the output directory as per `output_dir.retention`, getting
+/// the tagged commits (if needed) via a working directory from the
+/// pool.
+fn prune_with_working_directory_pool(
+    conf: &RunConfig,
+    working_directory_pool: &mut WorkingDirectoryPool,
+    file_cleanup_handler: &CleanupHandler,
+) -> Result<PruneStats> {
+    let retention = conf
+        .output_dir
+        .retention
+        .as_ref()
+        .ok_or_else(|| anyhow!("no `retention` is configured in `output_dir`"))?;
+    let tagged_commits = if retention.keep_tagged.unwrap_or(true) {
+        let working_directory_id;
+        {
+            let mut pool = working_directory_pool.lock_mut("evobench::prune")?;
+            working_directory_id = pool.get_first()?;
+            pool.clear_current_working_directory()?;
+        }
+        let (tagged_commits, token) = working_directory_pool.process_in_working_directory(
+            working_directory_id,
+            &DateTimeWithOffset::now(None),
+            |working_directory| -> Result<_> {
+                let working_directory = working_directory.into_inner().expect("still there");
+                // (Via the pool's mirror, if it has one)
+                working_directory.fetch(None)?;
+                tagged_commits(&working_directory.git_working_dir, &conf.commit_tags_regex)
+            },
+            None,
+            "getting tagged commits for pruning",
+            None,
+        )?;
+        working_directory_pool.working_directory_cleanup(token)?;
+        Some(tagged_commits)
+    } else {
+        None
+    };
+    prune_output_dir(
+        &conf.output_dir.path,
+        retention,
+        tagged_commits.as_ref(),
+        Some(file_cleanup_handler),
+        false,
+    )
+}
+
+/// The text shown by `evobench status`
+fn write_status(
+    run_config_bundle: &RunConfigBundle,
+    working_directory_base_dir: &WorkingDirectoryPoolBaseDir,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let conf = &run_config_bundle.shareable.run_config;
+    let show_status = |daemon_name: &str, paths: &DaemonPaths, out: &mut dyn Write| -> Result<_> {
+        let daemon = EvobenchDaemon {
+            paths: paths.clone(),
+            opts: DaemonOpts::default(),
+            log_level: LogLevel::Quiet,
+            restart_for_executable_change_opts: RestartForExecutableChangeOpts::default(),
+            restart_for_config_change_opts: RestartForConfigChangeOpts::default(),
+            config_file: run_config_bundle.config_file.clone_arc(),
+            inner_run: |_| Ok(()),
+        }
+        .into_daemon()?;
+        let s = daemon.status_string(true)?;
+        let logs = &paths.log_dir;
+        writeln!(out, "  {daemon_name} daemon: {s}, logs: {logs:?}")?;
+        Ok(())
+    };
+
+    writeln!(
+        out,
+        "Evobench system status and configuration information:\n"
+    )?;
+    if let Some(project) = &conf.project {
+        writeln!(out, "  project: {}\n", project.as_str())?;
+    }
+    show_status(" run", &conf.run_jobs_daemon, out)?;
+    show_status("poll", &conf.polling_daemon, out)?;
+    if conf.remote_repository.webhook.is_some() {
+        show_status("webhook", &conf.webhook_daemon, out)?;
+    }
+
+    // writeln!(out, "\nPaths:")?;
+    writeln!(out, "")?;
+    writeln!(
+        out,
+        "               Queues: {:?}",
+        conf.queues
+            .run_queues_basedir(false, &run_config_bundle.shareable.global_app_state_dir)?
+    )?;
+    writeln!(
+        out,
+        "  Working directories: {:?} -- but modify via `evobench wd` only",
+        working_directory_base_dir.path()
+    )?;
+    writeln!(
+        out,
+        "        Temporary dir: {:?}",
+        bench_tmp_dir()?.as_ref(),
+    )?;
+    writeln!(out, "              Outputs: {:?}", conf.output_dir.path,)?;
+    writeln!(out, "          Outputs URL: {:?}", conf.output_dir.url,)?;
+    writeln!(
+        out,
+        "          Config file: {:?}",
+        run_config_bundle.config_file.path()
+    )?;
+
+    Ok(())
+}
+
 struct EvobenchDaemon<F: FnOnce(CheckExit) -> Result<()>> {
     paths: DaemonPaths,
     opts: DaemonOpts,
@@ -498,6 +792,7 @@ fn run() -> Result<Option<ExecutionResult>> {
         log_level_opts,
         log_level,
         config,
+        project,
         subcommand,
     } = Opts::parse();
 
@@ -531,6 +826,7 @@ fn run() -> Result<Option<ExecutionResult>> {
         config,
         |msg| bail!("need a config file, {msg}"),
         GlobalAppStateDir::new()?,
+        project,
     )?;
 
     let conf = &run_config_bundle.shareable.run_config;
@@ -563,6 +859,11 @@ fn run() -> Result<Option<ExecutionResult>> {
             Ok(None)
         }
 
+        SubCommand::Config { subcommand } => {
+            subcommand.run(conf)?;
+            Ok(None)
+        }
+
         SubCommand::ListAll { opts } => {
             opts.run(&run_config_bundle.shareable)?;
             Ok(None)
@@ -590,35 +891,103 @@ fn run() -> Result<Option<ExecutionResult>> {
             dry_run_opt,
             mode,
         } => {
+            // Kept across polls, thus only the new commits are read
+            // from Git each time
+            let git_graph = GitGraph::open(
+                run_config_bundle
+                    .shareable
+                    .global_app_state_dir
+                    .git_graph_cache_path(),
+            )?;
+
             // Returns whether at least 1 job was inserted
             let try_run_poll = |daemon_check_exit: Option<CheckExit>| -> Result<bool> {
                 loop {
+                    let polled_heads =
+                        PolledHeads::open(&run_config_bundle.shareable.global_app_state_dir)?;
                     let (commits, non_resolving) = {
                         let mut polling_pool = open_polling_pool(&run_config_bundle.shareable)?;
 
                         let working_directory_id = polling_pool.updated_working_dir()?;
-                        polling_pool.resolve_branch_names(
+                        let (polled_refs, non_resolving) = polling_pool
+                            .resolve_branch_names(working_directory_id, &conf.remote_repository)?;
+                        if let Err(e) = polling_pool.update_git_graph(
                             working_directory_id,
-                            &conf.remote_repository.remote_branch_names_for_poll,
-                        )?
+                            &git_graph,
+                            polled_refs.iter().map(|polled_ref| &polled_ref.commit_id),
+                        ) {
+                            warn!("could not update the git graph: {e:#}");
+                        }
+                        let commits = polled_refs
+                            .into_iter()
+                            .map(|polled_ref| -> Result<_> {
+                                let new_commits = polled_heads.new_commits(
+                                    &mut polling_pool,
+                                    working_directory_id,
+                                    &polled_ref.name,
+                                    polled_ref.pattern,
+                                    &polled_ref.commit_id,
+                                    &conf.remote_repository.commit_range,
+                                )?;
+                                Ok((polled_ref, new_commits))
+                            })
+                            .collect::<Result<Vec<_>>>()?;
+                        (commits, non_resolving)
                     };
-                    let num_commits = commits.len();
+                    let num_commits: usize = commits
+                        .iter()
+                        .map(|(_, new_commits)| new_commits.len())
+                        .sum();
+
+                    if dry_run_opt.dry_run {
+                        for (
+                            PolledRef {
+                                name,
+                                pattern,
+                                commit_id,
+                                job_templates: _,
+                            },
+                            new_commits,
+                        ) in &commits
+                        {
+                            let pattern = if let Some(pattern) = pattern {
+                                format!("\tmatched by {pattern}")
+                            } else {
+                                String::new()
+                            };
+                            println!(
+                                "{name}\t{commit_id}\t{} new commit(s){pattern}",
+                                new_commits.len()
+                            );
+                        }
+                    }
 
+                    // Jobs are inserted oldest commit first, which
+                    // keeps the commit order for jobs of equal
+                    // priority
                     let mut benchmarking_jobs = Vec::new();
-                    for (branch_name, commit_id, job_templates) in commits {
-                        let opts = BenchmarkingJobOpts {
-                            insert_benchmarking_job_opts: InsertBenchmarkingJobOpts {
-                                reason: BenchmarkingJobReasonOpt {
-                                    reason: branch_name.as_str().to_owned().into(),
+                    for (polled_ref, new_commits) in &commits {
+                        let PolledRef {
+                            name,
+                            pattern: _,
+                            commit_id: _,
+                            job_templates,
+                        } = polled_ref;
+                        for commit_id in new_commits {
+                            let opts = BenchmarkingJobOpts {
+                                insert_benchmarking_job_opts: InsertBenchmarkingJobOpts {
+                                    reason: BenchmarkingJobReasonOpt {
+                                        reason: name.to_string().into(),
+                                    },
+                                    benchmarking_job_settings: (*conf.benchmarking_job_settings)
+                                        .clone(),
+                                    priority: None,
+                                    initial_boost: None,
                                 },
-                                benchmarking_job_settings: (*conf.benchmarking_job_settings)
-                                    .clone(),
-                                priority: None,
-                                initial_boost: None,
-                            },
-                            commit_id,
-                        };
-                        benchmarking_jobs.append(&mut opts.complete_jobs(&job_templates));
+                                commit_id: commit_id.clone(),
+                            };
+                            benchmarking_jobs.append(&mut opts.complete_jobs(job_templates));
+                        }
                     }
 
                     let n_original = benchmarking_jobs.len();
@@ -634,6 +1003,28 @@ fn run() -> Result<Option<ExecutionResult>> {
                         QuietOpt { quiet: true },
                         &queues,
                     )?;
+
+                    if !dry_run_opt.dry_run {
+                        for (polled_ref, _) in &commits {
+                            polled_heads.set(&polled_ref.name, &polled_ref.commit_id)?;
+                        }
+                        for (matcher, _) in &conf.remote_repository.ref_patterns_for_poll {
+                            if matcher.pattern.kind() == RefKind::Tag {
+                                polled_heads.set_tag_pattern_polled(&matcher.pattern)?;
+                            }
+                        }
+                    }
+
+                    let mut num_superseded = 0;
+                    for (branch_name, policy) in &conf.remote_repository.coalescing {
+                        num_superseded += coalesce_pending_jobs(
+                            &queues,
+                            &run_config_bundle.shareable.global_app_state_dir,
+                            branch_name.as_str(),
+                            policy,
+                            dry_run_opt.dry_run,
+                        )?;
+                    }
                     regenerate_index_files.run_one();
 
                     if non_resolving.is_empty() || !fail {
@@ -643,6 +1034,9 @@ fn run() -> Result<Option<ExecutionResult>> {
                                     "inserted {n}/{n_original} jobs (for {num_commits} commits)"
                                 );
                             }
+                            if num_superseded > 0 {
+                                println!("superseded {num_superseded} pending jobs");
+                            }
                         }
                     } else {
                         bail!(
@@ -708,7 +1102,11 @@ fn run() -> Result<Option<ExecutionResult>> {
             }
         }
 
-        SubCommand::Run { mode } => {
+        SubCommand::Run { worker, mode } => {
+            if worker && conf.worker.is_none() {
+                bail!("`--worker` requires the `worker` field in the config file")
+            }
+
             let open_working_directory_pool = |conf: &RunConfig| -> Result<_> {
                 Ok(open_working_directory_pool(
                     conf,
@@ -733,6 +1131,7 @@ fn run() -> Result<Option<ExecutionResult>> {
                         queues,
                         working_directory_base_dir,
                         working_directory_pool,
+                        worker,
                         true,
                         None,
                         queue_change_signals.force()?.clone(),
@@ -785,6 +1184,7 @@ fn run() -> Result<Option<ExecutionResult>> {
                             queues,
                             working_directory_base_dir.clone(),
                             working_directory_pool,
+                            worker,
                             false,
                             Some(daemon_check_exit.clone()),
                             queue_change_signals.force()?.clone(),
@@ -816,6 +1216,116 @@ fn run() -> Result<Option<ExecutionResult>> {
             }
         }
 
+        SubCommand::Coordinator { subcommand } => {
+            let (queues, regenerate_index_files) = queues.force()?;
+            subcommand.run(conf, queues)?;
+            regenerate_index_files.run_one();
+            Ok(None)
+        }
+
+        SubCommand::Webhook { subcommand } => match subcommand {
+            WebhookSubCommand::Serve { dry_run_opt, mode } => {
+                let Some(webhook_opts) = conf.remote_repository.webhook.clone() else {
+                    bail!(
+                        "`webhook serve` requires the `remote_repository.webhook` field \
+                         in the config file"
+                    )
+                };
+
+                // Returns whether at least 1 job was inserted (for the
+                // last request, if `once` is true)
+                let serve = |daemon_check_exit: Option<CheckExit>, once: bool| -> Result<bool> {
+                    let server = WebhookServer::bind(&webhook_opts)?;
+                    let (queues, regenerate_index_files) = queues.force()?;
+                    loop {
+                        if let Some(n) = server.handle_next_request(
+                            &run_config_bundle.shareable,
+                            &queues,
+                            &dry_run_opt,
+                            Duration::from_secs(1),
+                        )? {
+                            if n > 0 {
+                                regenerate_index_files.run_one();
+                            }
+                            if once {
+                                return Ok(n > 0);
+                            }
+                        }
+                        if let Some(daemon_check_exit) = &daemon_check_exit {
+                            if daemon_check_exit.want_exit() {
+                                return Ok(false);
+                            }
+                        }
+                    }
+                };
+
+                match mode {
+                    RunMode::One { false_if_none } => {
+                        let did_insert = serve(None, true)?;
+                        if false_if_none && !did_insert {
+                            exit(1);
+                        }
+                        Ok(None)
+                    }
+                    RunMode::Daemon {
+                        opts,
+                        restart_for_executable_change_opts,
+                        restart_for_config_change_opts,
+                        log_level_opts,
+                        log_level,
+                        action,
+                    } => {
+                        let paths = conf.webhook_daemon.clone();
+                        let config_file = run_config_bundle.config_file.clone_arc();
+                        let inner_run = |daemon_check_exit: CheckExit| -> Result<()> {
+                            serve(Some(daemon_check_exit), false)?;
+                            Ok(())
+                        };
+
+                        let log_level = log_level_opts
+                            .xor_log_level(log_level)
+                            .map_err(ctx!("parsing `webhook serve daemon` log level options"))?
+                            .or(top_level_log_level)
+                            .unwrap_or(LogLevel::Info);
+
+                        let daemon = EvobenchDaemon {
+                            paths,
+                            opts,
+                            log_level,
+                            restart_for_executable_change_opts,
+                            restart_for_config_change_opts,
+                            config_file,
+                            inner_run,
+                        }
+                        .into_daemon()?;
+                        let r = daemon.execute(action, DEFAULT_IS_HARD)?;
+                        Ok(Some(r))
+                    }
+                }
+            }
+        },
+
+        SubCommand::Metrics { subcommand } => {
+            let (queues, _regenerate_index_files) = queues.force()?;
+            let gather = || gather_metrics(conf, queues, &working_directory_base_dir);
+            match subcommand {
+                MetricsSubCommand::Serve { listen } => {
+                    MetricsServer::bind(listen)?.serve(gather)?;
+                }
+                MetricsSubCommand::Write { path } => {
+                    let metrics = gather()?;
+                    if let Some(path) = path {
+                        write_metrics_file(&metrics, &path)?;
+                    } else {
+                        let mut out = stdout().lock();
+                        out.write_all(metrics.as_bytes())?;
+                        out.flush()?;
+                    }
+                }
+            }
+            Ok(None)
+        }
+
         SubCommand::Wd { subcommand } => {
             subcommand.run(
                 &run_config_bundle.shareable,
@@ -825,6 +1335,11 @@ fn run() -> Result<Option<ExecutionResult>> {
             Ok(None)
         }
 
+        SubCommand::Query { opts } => {
+            opts.run(conf)?;
+            Ok(None)
+        }
+
         SubCommand::Url { cd, url } => {
             // Allow both copy-paste from web browser (at least
             // Firefox encodes '=' in path part via url_encoding), and
@@ -874,67 +1389,39 @@ fn run() -> Result<Option<ExecutionResult>> {
         }
 
         SubCommand::Status {} => {
-            let show_status =
-                |daemon_name: &str, paths: &DaemonPaths, out: &mut StdoutLock| -> Result<_> {
-                    let daemon = EvobenchDaemon {
-                        paths: paths.clone(),
-                        opts: DaemonOpts::default(),
-                        log_level: LogLevel::Quiet,
-                        restart_for_executable_change_opts: RestartForExecutableChangeOpts::default(
-                        ),
-                        restart_for_config_change_opts: RestartForConfigChangeOpts::default(),
-                        config_file: run_config_bundle.config_file.clone_arc(),
-                        inner_run: |_| Ok(()),
-                    }
-                    .into_daemon()?;
-                    let s = daemon.status_string(true)?;
-                    let logs = &paths.log_dir;
-                    writeln!(out, "  {daemon_name} daemon: {s}, logs: {logs:?}")?;
-                    Ok(())
-                };
-
             let mut out = stdout().lock();
-            writeln!(
-                &mut out,
-                "Evobench system status and configuration information:\n"
-            )?;
-            show_status(" run", &conf.run_jobs_daemon, &mut out)?;
-            show_status("poll", &conf.polling_daemon, &mut out)?;
-
-            // writeln!(&mut out, "\nPaths:")?;
-            writeln!(&mut out, "")?;
-            writeln!(
-                &mut out,
-                "               Queues: {:?}",
-                conf.queues
-                    .run_queues_basedir(false, &run_config_bundle.shareable.global_app_state_dir)?
-            )?;
-            writeln!(
-                &mut out,
-                "  Working directories: {:?} -- but modify via `evobench wd` only",
-                working_directory_base_dir.path()
-            )?;
-            writeln!(
-                &mut out,
-                "        Temporary dir: {:?}",
-                bench_tmp_dir()?.as_ref(),
-            )?;
-            writeln!(
-                &mut out,
-                "              Outputs: {:?}",
-                conf.output_dir.path,
-            )?;
-            writeln!(&mut out, "          Outputs URL: {:?}", conf.output_dir.url,)?;
-            writeln!(
-                &mut out,
-                "          Config file: {:?}",
-                run_config_bundle.config_file.path()
-            )?;
-
+            write_status(&run_config_bundle, &working_directory_base_dir, &mut out)?;
             out.flush()?;
             Ok(None)
         }
 
+        SubCommand::Serve { listen } => {
+            let (queues, _regenerate_index_files) = queues.force()?;
+            let change_signals = vec![
+                PollingSignals::open(
+                    &run_config_bundle
+                        .shareable
+                        .global_app_state_dir
+                        .run_queue_signal_change_path(),
+                    0,
+                )?,
+                open_working_directory_change_signals(conf)?,
+            ];
+            let status = || -> Result<String> {
+                let mut out = Vec::new();
+                write_status(&run_config_bundle, &working_directory_base_dir, &mut out)?;
+                Ok(String::from_utf8_lossy(&out).into_owned())
+            };
+            let context = ServeContext {
+                conf,
+                working_directory_base_dir: &working_directory_base_dir,
+                queues,
+                status: &status,
+            };
+            OutputServer::bind(listen, change_signals)?.serve(&context)?;
+            Ok(None)
+        }
+
         SubCommand::Completions { shell } => {
             shell.generate(&mut Opts::command(), &mut std::io::stdout());
             Ok(None)
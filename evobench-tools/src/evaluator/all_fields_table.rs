@@ -5,7 +5,7 @@ use std::{
     path::PathBuf,
 };
 
-use anyhow::Result;
+use anyhow::{Result, anyhow, bail};
 use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
 
 use crate::{
@@ -16,6 +16,7 @@ use crate::{
         },
         index_by_call_path::IndexByCallPath,
         options::TILE_COUNT,
+        stats_cache::{CachedKeyDetails, CachedRow, CachedStatsOrCount, StatsCacheEntry},
     },
     join::{self, KeyVal, keyval_inner_join},
     stats_tables::{
@@ -25,8 +26,11 @@ use crate::{
             weighted::{WEIGHT_ONE, WeightedValue},
         },
         tables::{
+            change::{Change, SmallerIsBetter},
+            columns_table::ColumnsTable,
             table::{Table, TableKind},
             table_field_view::TableFieldView,
+            table_view::TableView,
         },
     },
     times::{MicroTime, NanoTime},
@@ -428,6 +432,106 @@ impl AllFieldsTable<SingleRunStats> {
     }
 }
 
+fn table_to_cached_rows<K: KeyDetails>(
+    table: &Table<'static, K, StatsOrCountOrSubStats<K::ViewType, TILE_COUNT>>,
+) -> Vec<CachedRow> {
+    table
+        .rows
+        .iter()
+        .map(|KeyVal { key, val }| CachedRow {
+            key: key.to_string(),
+            val: match val {
+                StatsOrCountOrSubStats::StatsOrCount(StatsOrCount::Stats(stats)) => {
+                    CachedStatsOrCount::Stats(stats.to_data())
+                }
+                StatsOrCountOrSubStats::StatsOrCount(StatsOrCount::Count(count)) => {
+                    CachedStatsOrCount::Count(*count)
+                }
+                StatsOrCountOrSubStats::SubStats(_sub_stats) => {
+                    unreachable!("SingleRunStats cannot contain SubStats")
+                }
+            },
+        })
+        .collect()
+}
+
+fn table_from_cached_rows<K: KeyDetails>(
+    key_details: &KeyRuntimeDetails,
+    rows: &[CachedRow],
+) -> Result<Table<'static, K, StatsOrCountOrSubStats<K::ViewType, TILE_COUNT>>> {
+    let rows = rows
+        .iter()
+        .map(|CachedRow { key, val }| -> Result<_> {
+            let val = match val {
+                CachedStatsOrCount::Stats(data) => StatsOrCount::Stats(
+                    Stats::from_data(data.clone()).map_err(|e| anyhow!("row {key:?}: {e:#}"))?,
+                ),
+                CachedStatsOrCount::Count(count) => StatsOrCount::Count(*count),
+            };
+            Ok(KeyVal {
+                key: key.clone().into(),
+                val: val.into(),
+            })
+        })
+        .collect::<Result<_>>()?;
+    Ok(Table {
+        kind: K::new(key_details.clone()),
+        rows,
+    })
+}
+
+impl AllFieldsTable<SingleRunStats> {
+    /// Convert to the form stored in the stats cache (see
+    /// `stats_cache.rs`)
+    pub fn to_stats_cache_entry(&self) -> StatsCacheEntry {
+        let Self {
+            kind: SingleRunStats,
+            params,
+            real_time,
+            cpu_time,
+            sys_time,
+            ctx_switches,
+        } = self;
+        StatsCacheEntry {
+            key_details: (&params.key_details).into(),
+            real_time: table_to_cached_rows(real_time),
+            cpu_time: table_to_cached_rows(cpu_time),
+            sys_time: table_to_cached_rows(sys_time),
+            ctx_switches: table_to_cached_rows(ctx_switches),
+        }
+    }
+
+    /// Convert back from the form stored in the stats cache; the key
+    /// details of `entry` must match `params.key_details`.
+    pub fn from_stats_cache_entry(
+        entry: &StatsCacheEntry,
+        params: AllFieldsTableKindParams,
+    ) -> Result<Self> {
+        let StatsCacheEntry {
+            key_details,
+            real_time,
+            cpu_time,
+            sys_time,
+            ctx_switches,
+        } = entry;
+        if *key_details != CachedKeyDetails::from(&params.key_details) {
+            bail!(
+                "key details of cache entry, {key_details:?}, do not match {:?}",
+                params.key_details
+            )
+        }
+        let key_details = &params.key_details;
+        Ok(AllFieldsTable {
+            kind: SingleRunStats,
+            real_time: table_from_cached_rows(key_details, real_time)?,
+            cpu_time: table_from_cached_rows(key_details, cpu_time)?,
+            sys_time: table_from_cached_rows(key_details, sys_time)?,
+            ctx_switches: table_from_cached_rows(key_details, ctx_switches)?,
+            params,
+        })
+    }
+}
+
 /// `K::all_fields_table_extract` extracts the field out of
 /// `AllFieldsTable` (e.g. cpu time, or ctx switches),
 /// `extract_stats_field` the kind of statistical value (e.g. median,
@@ -549,3 +653,77 @@ impl AllFieldsTable<SummaryStats> {
         }
     }
 }
+
+/// The change from one `AllFieldsTable<SummaryStats>` to another, for
+/// each of the 4 fields (for all of which smaller values are better).
+pub struct AllFieldsChangeTable {
+    pub real_time: Table<'static, RealTime, Change<SmallerIsBetter>>,
+    pub cpu_time: Table<'static, CpuTime, Change<SmallerIsBetter>>,
+    pub sys_time: Table<'static, SysTime, Change<SmallerIsBetter>>,
+    pub ctx_switches: Table<'static, CtxSwitches, Change<SmallerIsBetter>>,
+}
+
+impl AllFieldsChangeTable {
+    /// Return a list of tables, one for each field, to e.g. be output
+    /// to excel.
+    pub fn tables(&self) -> Vec<&dyn TableView> {
+        let mut tables: Vec<&dyn TableView> = vec![];
+        let Self {
+            real_time,
+            cpu_time,
+            sys_time,
+            ctx_switches,
+        } = self;
+        tables.push(real_time);
+        tables.push(cpu_time);
+        tables.push(sys_time);
+        tables.push(ctx_switches);
+        tables
+    }
+}
+
+impl AllFieldsTable<SummaryStats> {
+    /// The change from `self` to `to`, comparing the averages of the
+    /// summarized values (i.e. of the field selected via
+    /// `field_selector` when creating the summaries). Rows only
+    /// present on one side are dropped.
+    pub fn change(&self, to: &Self) -> AllFieldsChangeTable {
+        AllFieldsChangeTable {
+            real_time: self
+                .real_time
+                .change(&to.real_time, |stats| stats.get(StatsField::Average)),
+            cpu_time: self
+                .cpu_time
+                .change(&to.cpu_time, |stats| stats.get(StatsField::Average)),
+            sys_time: self
+                .sys_time
+                .change(&to.sys_time, |stats| stats.get(StatsField::Average)),
+            ctx_switches: self
+                .ctx_switches
+                .change(&to.ctx_switches, |stats| stats.get(StatsField::Average)),
+        }
+    }
+
+    /// One table per field, with one column per entry of `columns`
+    /// (labelled with the given string, e.g. the value of a swept
+    /// parameter) showing the averages of the summarized values.
+    pub fn sweep_tables(columns: &[(&str, &Self)]) -> Vec<ColumnsTable> {
+        let column_tables: Vec<(&str, Vec<&dyn TableFieldView<TILE_COUNT>>)> = columns
+            .iter()
+            .map(|(label, aft)| (*label, aft.tables()))
+            .collect();
+        let num_fields = column_tables
+            .first()
+            .map(|(_, tables)| tables.len())
+            .unwrap_or(0);
+        (0..num_fields)
+            .filter_map(|i| {
+                let field_columns: Vec<(&str, &dyn TableFieldView<TILE_COUNT>)> = column_tables
+                    .iter()
+                    .map(|(label, tables)| (*label, tables[i]))
+                    .collect();
+                ColumnsTable::from_table_field_views(&field_columns, StatsField::Average)
+            })
+            .collect()
+    }
+}
@@ -2,7 +2,7 @@ use std::{
     fs::File,
     io::{BufWriter, Write},
     ops::Deref,
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 use anyhow::{Result, anyhow, bail};
@@ -11,14 +11,20 @@ use run_git::path_util::add_extension;
 
 use crate::{
     config_file::ron_to_file_pretty,
+    ctx,
+    evaluator::data::log_data_and_tree::LogDataAndTree,
     evaluator::data::log_data_tree::LogDataTree,
     evaluator::options::TILE_COUNT,
+    evaluator::stats_cache::{StatsCacheFile, stats_cache_path},
     info,
     io_utils::tempfile_utils::TempfileOptions,
     join::KeyVal,
     stats_tables::{
         stats::StatsField,
-        tables::{excel_table_view::excel_file_write, table_view::TableView},
+        tables::{
+            csv_table_view::csv_file_write, excel_table_view::excel_file_write,
+            json_table_view::json_file_write, table_view::TableView,
+        },
     },
     util::tree::Tree,
     warn,
@@ -41,6 +47,12 @@ pub struct AllFieldsTableWithOutputPathOrBase<Kind: AllFieldsTableKind> {
     is_final_file: bool,
 }
 
+impl<Kind: AllFieldsTableKind> AllFieldsTableWithOutputPathOrBase<Kind> {
+    pub fn aft(&self) -> &AllFieldsTable<Kind> {
+        &self.aft
+    }
+}
+
 /// A wrapper holding the table sets for all requested
 /// outputs. (Wrapping since we want to have the same fields and
 /// mapping methods. A type alias would currently lose the trait
@@ -57,7 +69,9 @@ impl<Kind: AllFieldsTableKind> Deref for AllOutputsAllFieldsTable<Kind> {
     }
 }
 
-fn key_details_for(
+/// The key details of the tables for the output `case` (these
+/// determine the rows, and which stats cache entry is used)
+pub fn key_details_for(
     case: CheckedOutputOptionsMapCase,
     evaluation_opts: &EvaluationOpts,
 ) -> KeyRuntimeDetails {
@@ -78,13 +92,19 @@ fn key_details_for(
         prefix,
     );
     match case {
-        CheckedOutputOptionsMapCase::Excel => {
+        CheckedOutputOptionsMapCase::Excel
+        | CheckedOutputOptionsMapCase::Json
+        | CheckedOutputOptionsMapCase::Csv => {
             normal_separator = " > ";
             reverse_separator = " < ";
             show_probe_names = true;
             show_paths_without_thread_number = true;
             show_paths_reversed_too = *show_reversed;
-            key_column_width = Some(*key_width);
+            key_column_width = if case == CheckedOutputOptionsMapCase::Excel {
+                Some(*key_width)
+            } else {
+                None
+            };
             skip_process = false;
             prefix = None;
         }
@@ -137,6 +157,112 @@ impl AllOutputsAllFieldsTable<SingleRunStats> {
     }
 }
 
+impl AllOutputsAllFieldsTable<SingleRunStats> {
+    /// Like `from_log_data_tree` on the log file at `log_path`, but
+    /// only reads that file if the stats cache file next to it (see
+    /// `stats_cache.rs`) is missing, invalid, or lacks an entry for
+    /// one of the requested outputs, in which case the cache file is
+    /// (re)written. Failures writing the cache file are only warned
+    /// about. If the log file has been pruned, the cache file must
+    /// have entries for all requested outputs.
+    pub fn from_log_file_cached(
+        log_path: &Path,
+        evaluation_opts: &EvaluationOpts,
+        output_opts: OutputVariants<PathBuf>,
+        is_final_file: bool,
+    ) -> Result<Self> {
+        let cache_path = stats_cache_path(log_path)?;
+        Self::from_log_file_with_cache_file(
+            log_path,
+            &cache_path,
+            evaluation_opts,
+            output_opts,
+            is_final_file,
+        )
+    }
+
+    /// Like `from_log_file_cached`, but using the cache file at
+    /// `cache_path` instead of the one next to the log file (for
+    /// when `log_path` is a temporary copy of the log file).
+    pub fn from_log_file_with_cache_file(
+        log_path: &Path,
+        cache_path: &Path,
+        evaluation_opts: &EvaluationOpts,
+        output_opts: OutputVariants<PathBuf>,
+        is_final_file: bool,
+    ) -> Result<Self> {
+        let cache = StatsCacheFile::load(cache_path, log_path)?;
+
+        if let Some(cache) = &cache {
+            let OutputVariants {
+                excel,
+                flame,
+                json,
+                csv,
+            } = output_opts.clone().try_map(|case, path| -> Result<_> {
+                let key_details = key_details_for(case, evaluation_opts);
+                let Some(entry) = cache.entry(&key_details) else {
+                    return Ok(None);
+                };
+                Ok(Some(AllFieldsTableWithOutputPathOrBase {
+                    aft: AllFieldsTable::from_stats_cache_entry(
+                        entry,
+                        AllFieldsTableKindParams {
+                            source_path: log_path.into(),
+                            key_details,
+                        },
+                    )?,
+                    output_path_or_base: path,
+                    is_final_file,
+                }))
+            })?;
+            let all_cached = [&excel, &flame, &json, &csv]
+                .into_iter()
+                .all(|variant| !matches!(variant, Some(None)));
+            if all_cached {
+                return Ok(Self(OutputVariants {
+                    excel: excel.flatten(),
+                    flame: flame.flatten(),
+                    json: json.flatten(),
+                    csv: csv.flatten(),
+                }));
+            }
+        }
+
+        if !std::fs::exists(log_path).map_err(ctx!("checking path {log_path:?}"))? {
+            if cache.is_some() {
+                bail!(
+                    "log file {log_path:?} does not exist (pruned?), and its stats cache \
+                     {cache_path:?} lacks some of the requested outputs"
+                )
+            }
+            bail!("log file {log_path:?} does not exist (pruned?), and it has no valid stats cache")
+        }
+
+        let ldat = LogDataAndTree::read_file(log_path, None)?;
+        let aoaft =
+            Self::from_log_data_tree(ldat.tree(), evaluation_opts, output_opts, is_final_file)?;
+
+        let mut cache = cache.unwrap_or_else(StatsCacheFile::empty);
+        {
+            let OutputVariants {
+                excel,
+                flame,
+                json,
+                csv,
+            } = &aoaft.0;
+            for variant in [excel, flame, json, csv].into_iter().flatten() {
+                cache.insert(variant.aft.to_stats_cache_entry());
+            }
+        }
+        if let Err(e) = cache.save(cache_path) {
+            warn!("ignoring error writing stats cache {cache_path:?}: {e:#}");
+        }
+
+        Ok(aoaft)
+    }
+}
+
 impl AllOutputsAllFieldsTable<SummaryStats> {
     pub fn summary_stats(
         aoafts: &[AllOutputsAllFieldsTable<SingleRunStats>],
@@ -165,7 +291,9 @@ impl AllOutputsAllFieldsTable<SummaryStats> {
             aft: AllFieldsTable::summary_stats(
                 afts.as_slice(),
                 match case {
-                    CheckedOutputOptionsMapCase::Excel => field_selector,
+                    CheckedOutputOptionsMapCase::Excel
+                    | CheckedOutputOptionsMapCase::Json
+                    | CheckedOutputOptionsMapCase::Csv => field_selector,
                     // Flame graphs always need the sums, thus ignore
                     // the user option for those
                     CheckedOutputOptionsMapCase::Flame => StatsField::Sum,
@@ -179,6 +307,44 @@ impl AllOutputsAllFieldsTable<SummaryStats> {
     }
 }
 
+impl AllOutputsAllFieldsTable<SummaryStats> {
+    /// Write the change from `from` to `self` to the Excel file path
+    /// given for `self`. Both must have been created with the same
+    /// options; there is no flame graph output for changes, thus
+    /// only Excel output may be requested.
+    pub fn write_change_to_excel_file(&self, from: &Self) -> Result<()> {
+        if self.flame.is_some() || self.json.is_some() || self.csv.is_some() {
+            bail!("only Excel output is supported for changes")
+        }
+        let (Some(from), Some(to)) = (&from.excel, &self.excel) else {
+            bail!("missing Excel output path")
+        };
+        let change = from.aft.change(&to.aft);
+        excel_file_write(change.tables(), &to.output_path_or_base)
+    }
+
+    /// Write tables with one column per entry of `columns` (e.g. the
+    /// values of a swept parameter, see
+    /// `AllFieldsTable::sweep_tables`) to the Excel file at
+    /// `path`. The Excel output paths given for the entries are
+    /// ignored, but Excel output must have been requested for all of
+    /// them.
+    pub fn write_sweep_to_excel_file(columns: &[(&str, &Self)], path: &Path) -> Result<()> {
+        let afts = columns
+            .iter()
+            .map(|(label, aoaft)| -> Result<_> {
+                let excel = aoaft
+                    .excel
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("missing Excel output for column {label:?}"))?;
+                Ok((*label, &excel.aft))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let tables = AllFieldsTable::sweep_tables(&afts);
+        excel_file_write(tables.iter().map(|table| table as &dyn TableView), path)
+    }
+}
+
 /// Get the sum of the children's values, and if those don't have a
 /// value, their children's values recursively. XX Could be a bit
 /// costly if there are many gaps!
@@ -268,6 +434,28 @@ impl<Kind: AllFieldsTableKind> AllOutputsAllFieldsTable<Kind> {
                         &output_path_or_base,
                     )?;
                 }
+                CheckedOutputOptionsMapCase::Json => {
+                    json_file_write(
+                        tables.iter().map(|v| {
+                            let v: &dyn TableView = *v;
+                            v
+                        }),
+                        &output_path_or_base,
+                    )?;
+                }
+                CheckedOutputOptionsMapCase::Csv => {
+                    let curdir = PathBuf::from(".");
+                    let csv_base_dir = output_path_or_base.parent().unwrap_or(&*curdir);
+                    let csv_base_name = output_path_or_base
+                        .file_name()
+                        .ok_or_else(|| anyhow!("--csv option argument is missing a file name"))?
+                        .to_string_lossy();
+                    for table in tables {
+                        let path = csv_base_dir
+                            .append(format!("{csv_base_name}-{}.csv", table.table_name()));
+                        csv_file_write(table, &path)?;
+                    }
+                }
                 CheckedOutputOptionsMapCase::Flame => {
                     let curdir = PathBuf::from(".");
                     let flame_base_dir = output_path_or_base.parent().unwrap_or(&*curdir);
@@ -8,3 +8,4 @@ pub mod all_outputs_all_fields_table;
 pub mod data;
 pub mod index_by_call_path;
 pub mod options;
+pub mod stats_cache;
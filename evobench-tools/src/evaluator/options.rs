@@ -37,6 +37,17 @@ pub struct EvaluationOpts {
     pub show_reversed: bool,
 }
 
+#[derive(clap::Args, Debug)]
+pub struct StatsCacheOpt {
+    /// Keep the statistics calculated from each log file in a cache
+    /// file next to it (the log file name without a `.zstd` suffix,
+    /// plus `.stats-cache.json.zstd`), and use those instead of
+    /// reading the log file again when they are still valid. Cache
+    /// files from other evobench versions are replaced.
+    #[clap(long)]
+    pub stats_cache: bool,
+}
+
 /// Using private fields, to enforce calling .check()!
 #[derive(clap::Args, Debug)]
 pub struct OutputOpts {
@@ -49,6 +60,18 @@ pub struct OutputOpts {
     /// "ctx-switches".
     #[clap(short, long)]
     flame: Option<PathBuf>,
+
+    /// Path to write JSON output to (all tables, with column names,
+    /// units and values; `evobench-eval json-schema` prints the
+    /// schema of the format)
+    #[clap(long)]
+    json: Option<PathBuf>,
+
+    /// Base path to write CSV output to; "-$type.csv" is appended,
+    /// where type is "real", "cpu", "sys" or "ctx-switches". The
+    /// units are given in parentheses in the header row.
+    #[clap(long)]
+    csv: Option<PathBuf>,
 }
 
 /// Do not use for level 0 (i.e. `single` subcommand), there sum must
@@ -72,15 +95,32 @@ pub struct CheckedOutputOptions {
 
 impl OutputOpts {
     pub fn check(self) -> Result<CheckedOutputOptions> {
-        let Self { excel, flame } = self;
-
-        let any_given = [excel.is_some(), flame.is_some()].iter().any(|b| *b);
+        let Self {
+            excel,
+            flame,
+            json,
+            csv,
+        } = self;
+
+        let any_given = [
+            excel.is_some(),
+            flame.is_some(),
+            json.is_some(),
+            csv.is_some(),
+        ]
+        .iter()
+        .any(|b| *b);
         if !any_given {
             bail!("no output files were specified")
         }
 
         Ok(CheckedOutputOptions {
-            variants: OutputVariants { excel, flame },
+            variants: OutputVariants {
+                excel,
+                flame,
+                json,
+                csv,
+            },
         })
     }
 }
@@ -91,12 +131,16 @@ impl OutputOpts {
 pub struct OutputVariants<T> {
     pub excel: Option<T>,
     pub flame: Option<T>,
+    pub json: Option<T>,
+    pub csv: Option<T>,
 }
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum CheckedOutputOptionsMapCase {
     Excel,
     Flame,
+    Json,
+    Csv,
 }
 
 impl<T> OutputVariants<T> {
@@ -105,15 +149,24 @@ impl<T> OutputVariants<T> {
         match case {
             CheckedOutputOptionsMapCase::Excel => &self.excel,
             CheckedOutputOptionsMapCase::Flame => &self.flame,
+            CheckedOutputOptionsMapCase::Json => &self.json,
+            CheckedOutputOptionsMapCase::Csv => &self.csv,
         }
     }
 
     /// `f` is applied to all fields that are `Some`
     pub fn map<U>(self, f: impl Fn(CheckedOutputOptionsMapCase, T) -> U) -> OutputVariants<U> {
-        let Self { excel, flame } = self;
+        let Self {
+            excel,
+            flame,
+            json,
+            csv,
+        } = self;
         OutputVariants {
             excel: excel.map(|v| f(CheckedOutputOptionsMapCase::Excel, v)),
             flame: flame.map(|v| f(CheckedOutputOptionsMapCase::Flame, v)),
+            json: json.map(|v| f(CheckedOutputOptionsMapCase::Json, v)),
+            csv: csv.map(|v| f(CheckedOutputOptionsMapCase::Csv, v)),
         }
     }
 
@@ -122,7 +175,12 @@ impl<T> OutputVariants<T> {
         self,
         f: impl Fn(CheckedOutputOptionsMapCase, T) -> Result<U, E>,
     ) -> Result<OutputVariants<U>, E> {
-        let Self { excel, flame } = self;
+        let Self {
+            excel,
+            flame,
+            json,
+            csv,
+        } = self;
         Ok(OutputVariants {
             excel: excel
                 .map(|v| f(CheckedOutputOptionsMapCase::Excel, v))
@@ -130,6 +188,12 @@ impl<T> OutputVariants<T> {
             flame: flame
                 .map(|v| f(CheckedOutputOptionsMapCase::Flame, v))
                 .transpose()?,
+            json: json
+                .map(|v| f(CheckedOutputOptionsMapCase::Json, v))
+                .transpose()?,
+            csv: csv
+                .map(|v| f(CheckedOutputOptionsMapCase::Csv, v))
+                .transpose()?,
         })
     }
 }
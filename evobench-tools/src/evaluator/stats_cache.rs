@@ -0,0 +1,306 @@
+//! Cache for the statistics calculated from a single log file
+//!
+//! Reading and parsing the `evobench.log` files is the expensive
+//! part of generating summaries, and the summaries for a key are
+//! regenerated from all of its runs each time a run is added. Thus
+//! the `AllFieldsTable<SingleRunStats>` tables calculated from a log
+//! file are stored in a zstd-compressed JSON file next to it, with
+//! one entry per set of key details (the rows differ e.g. between
+//! Excel and flame graph outputs). Cache files written by a different
+//! evobench version, or older than their log file, are ignored (and
+//! replaced). Once the log file has been deleted (see
+//! `RetentionOpts`), the cache file stands in for it, as long as its
+//! format is still understood.
+
+use std::{
+    io::{BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+use cj_path_util::path_util::AppendToPath;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ctx,
+    evaluator::all_fields_table::KeyRuntimeDetails,
+    info,
+    io_utils::{
+        tempfile_utils::{TempfileOptions, tempfile},
+        zstd_file::{compress_file, decompressed_file},
+    },
+    stats_tables::stats::StatsData,
+    warn,
+};
+
+include!("../../include/evobench_version.rs");
+
+/// Increment when changing the types below
+pub const STATS_CACHE_FORMAT_VERSION: u32 = 1;
+
+/// Appended to the log file name (after removing a `.zstd` suffix)
+/// to get the cache file name
+pub const STATS_CACHE_SUFFIX: &str = "stats-cache.json.zstd";
+
+/// The path of the cache file for the log file at `log_path`,
+/// e.g. `evobench.log.stats-cache.json.zstd` for
+/// `evobench.log.zstd`.
+pub fn stats_cache_path(log_path: &Path) -> Result<PathBuf> {
+    let file_name = log_path
+        .file_name()
+        .ok_or_else(|| anyhow!("log path is missing a file name: {log_path:?}"))?
+        .to_str()
+        .ok_or_else(|| anyhow!("log file name is not valid UTF-8: {log_path:?}"))?;
+    let base_name = file_name.strip_suffix(".zstd").unwrap_or(file_name);
+    let cache_file_name = format!("{base_name}.{STATS_CACHE_SUFFIX}");
+    Ok(match log_path.parent() {
+        Some(dir) => dir.append(cache_file_name),
+        None => cache_file_name.into(),
+    })
+}
+
+/// The parts of `KeyRuntimeDetails` that determine the rows of the
+/// tables (`key_column_width` is presentation only)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CachedKeyDetails {
+    pub normal_separator: String,
+    pub reverse_separator: String,
+    pub show_probe_names: bool,
+    pub show_paths_without_thread_number: bool,
+    pub show_paths_with_thread_number: bool,
+    pub show_paths_reversed_too: bool,
+    pub prefix: Option<String>,
+    pub skip_process: bool,
+}
+
+impl From<&KeyRuntimeDetails> for CachedKeyDetails {
+    fn from(key_details: &KeyRuntimeDetails) -> Self {
+        let KeyRuntimeDetails {
+            normal_separator,
+            reverse_separator,
+            show_probe_names,
+            show_paths_without_thread_number,
+            show_paths_with_thread_number,
+            show_paths_reversed_too,
+            key_column_width: _,
+            prefix,
+            skip_process,
+        } = key_details;
+        Self {
+            normal_separator: (*normal_separator).into(),
+            reverse_separator: (*reverse_separator).into(),
+            show_probe_names: *show_probe_names,
+            show_paths_without_thread_number: *show_paths_without_thread_number,
+            show_paths_with_thread_number: *show_paths_with_thread_number,
+            show_paths_reversed_too: *show_paths_reversed_too,
+            prefix: prefix.map(Into::into),
+            skip_process: *skip_process,
+        }
+    }
+}
+
+/// A `StatsOrCount` without the `ViewType`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CachedStatsOrCount {
+    Stats(StatsData),
+    Count(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CachedRow {
+    pub key: String,
+    pub val: CachedStatsOrCount,
+}
+
+/// The 4 tables of an `AllFieldsTable<SingleRunStats>`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StatsCacheEntry {
+    pub key_details: CachedKeyDetails,
+    pub real_time: Vec<CachedRow>,
+    pub cpu_time: Vec<CachedRow>,
+    pub sys_time: Vec<CachedRow>,
+    pub ctx_switches: Vec<CachedRow>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StatsCacheFile {
+    pub format_version: u32,
+    pub evobench_version: String,
+    pub entries: Vec<StatsCacheEntry>,
+}
+
+impl StatsCacheFile {
+    pub fn empty() -> Self {
+        Self {
+            format_version: STATS_CACHE_FORMAT_VERSION,
+            evobench_version: EVOBENCH_VERSION.into(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Returns `None` if there is no file at `path`, or if it is not
+    /// valid for the log file at `log_path` (older than it, from a
+    /// different evobench version, or unreadable). If the log file
+    /// does not exist (anymore), the cache is only required to be of
+    /// the current format.
+    pub fn load(path: &Path, log_path: &Path) -> Result<Option<Self>> {
+        let cache_mtime = match std::fs::metadata(path) {
+            Ok(meta) => meta.modified().map_err(ctx!("getting mtime of {path:?}"))?,
+            Err(e) => match e.kind() {
+                std::io::ErrorKind::NotFound => return Ok(None),
+                _ => Err(e).map_err(ctx!("getting metadata of {path:?}"))?,
+            },
+        };
+        let have_log = match std::fs::metadata(log_path) {
+            Ok(meta) => {
+                let log_mtime = meta
+                    .modified()
+                    .map_err(ctx!("getting mtime of {log_path:?}"))?;
+                if log_mtime > cache_mtime {
+                    info!("stats cache {path:?} is older than {log_path:?}, ignoring it");
+                    return Ok(None);
+                }
+                true
+            }
+            Err(e) => match e.kind() {
+                std::io::ErrorKind::NotFound => false,
+                _ => Err(e).map_err(ctx!("getting metadata of {log_path:?}"))?,
+            },
+        };
+
+        let file: Self = match (|| -> Result<Self> {
+            let input = decompressed_file(path, Some("json"))?;
+            Ok(serde_json::from_reader(BufReader::new(input))?)
+        })() {
+            Ok(file) => file,
+            Err(e) => {
+                warn!("ignoring unreadable stats cache {path:?}: {e:#}");
+                return Ok(None);
+            }
+        };
+        let Self {
+            format_version,
+            evobench_version,
+            entries: _,
+        } = &file;
+        if *format_version != STATS_CACHE_FORMAT_VERSION
+            || (have_log && evobench_version != EVOBENCH_VERSION)
+        {
+            info!(
+                "stats cache {path:?} is from evobench version {evobench_version} \
+                 (format {format_version}), ignoring it"
+            );
+            return Ok(None);
+        }
+        Ok(Some(file))
+    }
+
+    pub fn entry(&self, key_details: &KeyRuntimeDetails) -> Option<&StatsCacheEntry> {
+        let key_details = CachedKeyDetails::from(key_details);
+        self.entries
+            .iter()
+            .find(|entry| entry.key_details == key_details)
+    }
+
+    /// Replaces an existing entry for the same key details
+    pub fn insert(&mut self, entry: StatsCacheEntry) {
+        self.entries
+            .retain(|existing| existing.key_details != entry.key_details);
+        self.entries.push(entry);
+    }
+
+    /// Atomically (re)place the file at `path`
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json_path = path.with_extension("");
+        let (json_tmp_file, mut out) = tempfile(json_path, false)?;
+        serde_json::to_writer(&mut out, self)
+            .map_err(ctx!("writing to {:?}", json_tmp_file.temp_path()))?;
+        out.flush()
+            .map_err(ctx!("writing to {:?}", json_tmp_file.temp_path()))?;
+        drop(out);
+
+        let zstd_tmp_file = TempfileOptions {
+            target_path: path.into(),
+            retain_tempfile: false,
+            migrate_access: false,
+        }
+        .tempfile()?;
+        compress_file(json_tmp_file.temp_path(), zstd_tmp_file.temp_path(), true)?;
+        zstd_tmp_file.finish()?;
+        // `json_tmp_file` is deleted when dropped
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        time::{Duration, SystemTime},
+    };
+
+    use crate::utillib::test_dir::TestDir;
+
+    use super::*;
+
+    fn set_mtime(path: &Path, mtime: SystemTime) {
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+    }
+
+    #[test]
+    fn t_stats_cache_path() -> Result<()> {
+        assert_eq!(
+            stats_cache_path("a/b/evobench.log.zstd".as_ref())?,
+            Path::new("a/b/evobench.log.stats-cache.json.zstd")
+        );
+        assert_eq!(
+            stats_cache_path("evobench.log".as_ref())?,
+            Path::new("evobench.log.stats-cache.json.zstd")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn t_stats_cache_invalidation() -> Result<()> {
+        let test_dir = TestDir::new("stats-cache-invalidation");
+        let log_path = test_dir.path().join("evobench.log.zstd");
+        let path = stats_cache_path(&log_path)?;
+        let now = SystemTime::now();
+
+        std::fs::write(&log_path, "log")?;
+        set_mtime(&log_path, now - Duration::from_secs(10));
+        StatsCacheFile::empty().save(&path)?;
+        assert!(StatsCacheFile::load(&path, &log_path)?.is_some());
+
+        // The log was re-written after the cache
+        set_mtime(&log_path, now + Duration::from_secs(10));
+        assert!(StatsCacheFile::load(&path, &log_path)?.is_none());
+        set_mtime(&log_path, now - Duration::from_secs(10));
+
+        // Written by a different evobench version
+        let mut other_version = StatsCacheFile::empty();
+        other_version.evobench_version = format!("{EVOBENCH_VERSION}-other");
+        other_version.save(&path)?;
+        assert!(StatsCacheFile::load(&path, &log_path)?.is_none());
+
+        // ...but still stands in for the log once that was pruned
+        std::fs::remove_file(&log_path)?;
+        assert!(StatsCacheFile::load(&path, &log_path)?.is_some());
+
+        // Unless the format changed
+        let mut other_format = StatsCacheFile::empty();
+        other_format.format_version = STATS_CACHE_FORMAT_VERSION + 1;
+        other_format.save(&path)?;
+        assert!(StatsCacheFile::load(&path, &log_path)?.is_none());
+        Ok(())
+    }
+}
@@ -2,12 +2,22 @@
 //!
 //! For tracking performance changes across the history. Should
 //! perhaps be moved to the `run-git` crate.
+//!
+//! A `GitGraph` can be opened with a cache file (see
+//! `GitGraph::open`), in which case the graph is loaded from it and
+//! only commits not in the graph yet are retrieved from Git; `save`
+//! writes the graph back if it has grown. The file format is a
+//! header line followed by one record per commit, in the order of
+//! the ids: the 20 hash bytes, author and committer time as u64, the
+//! number of parents as u8 and the parent ids as u32 (all little
+//! endian).
 
 use std::{
-    collections::{BTreeSet, HashMap, HashSet},
+    collections::{BTreeSet, BinaryHeap, HashMap, HashSet},
     fmt::{Debug, Display},
+    io::{BufReader, Read, Write},
     ops::Index,
-    path::Path,
+    path::{Path, PathBuf},
     process::{Command, Stdio},
     sync::{Arc, Mutex},
 };
@@ -17,8 +27,10 @@ use itertools::Itertools;
 use kstring::KString;
 use smallvec::SmallVec;
 
-use crate::date_and_time::unixtime::Unixtime;
 pub use crate::serde_types::git_hash::GitHash;
+use crate::{
+    ctx, date_and_time::unixtime::Unixtime, info, io_utils::tempfile_utils::tempfile, warn,
+};
 
 #[derive(Debug)]
 pub struct GitCommit<RefType> {
@@ -99,6 +111,9 @@ impl Display for EnrichedGitCommit<GitHash> {
     }
 }
 
+/// Ids are assigned in the order in which commits are added to the
+/// graph, which is always parents before their children; thus
+/// sorting by id gives a topological order.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Id<Kind>(u32, Kind);
 
@@ -112,8 +127,78 @@ pub struct ToEnrichedCommit;
 pub struct GitGraphData {
     by_hash: HashMap<GitHash, Id<ToEnrichedCommit>>,
     ecommits: Vec<EnrichedGitCommit<Id<ToEnrichedCommit>>>,
+    /// Where to save the graph, if opened with a cache file
+    cache_path: Option<PathBuf>,
+    /// The number of commits in the cache file
+    saved_len: usize,
+}
+
+/// A set of commits in a `GitGraphData`. Since the ids are dense,
+/// this is a plain bitmap (the set of all ancestors of a commit
+/// takes 1 bit per commit in the graph). Iterates in id order,
+/// i.e. in topological order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommitSet {
+    words: Vec<u64>,
+}
+
+impl CommitSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn index(id: Id<ToEnrichedCommit>) -> (usize, u64) {
+        let i = usize::try_from(id.0).expect("at least 32 bit platform");
+        (i / 64, 1 << (i % 64))
+    }
+
+    /// Returns true if `id` was not in the set yet
+    pub fn insert(&mut self, id: Id<ToEnrichedCommit>) -> bool {
+        let (word, bit) = Self::index(id);
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        let is_new = self.words[word] & bit == 0;
+        self.words[word] |= bit;
+        is_new
+    }
+
+    pub fn contains(&self, id: Id<ToEnrichedCommit>) -> bool {
+        let (word, bit) = Self::index(id);
+        self.words.get(word).map(|w| w & bit != 0).unwrap_or(false)
+    }
+
+    pub fn len(&self) -> usize {
+        self.words
+            .iter()
+            .map(|w| usize::try_from(w.count_ones()).expect("fits"))
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|w| *w == 0)
+    }
+
+    /// In id order, i.e. parents before children
+    pub fn iter(&self) -> impl Iterator<Item = Id<ToEnrichedCommit>> + '_ {
+        self.words.iter().enumerate().flat_map(|(word_i, word)| {
+            (0..64).filter_map(move |bit_i| {
+                if word & (1 << bit_i) != 0 {
+                    let i = word_i * 64 + bit_i;
+                    Some(Id(
+                        u32::try_from(i).expect("only ids from u32 are inserted"),
+                        ToEnrichedCommit,
+                    ))
+                } else {
+                    None
+                }
+            })
+        })
+    }
 }
 
+const CACHE_FILE_HEADER: &[u8] = b"evobench git graph v1\n";
+
 #[derive(thiserror::Error, Debug)]
 #[error("more than u32::max commits")]
 pub struct MoreThanU32Commits;
@@ -129,6 +214,8 @@ impl GitGraphData {
         Self {
             by_hash: HashMap::new(),
             ecommits: Vec::new(),
+            cache_path: None,
+            saved_len: 0,
         }
     }
 
@@ -209,10 +296,8 @@ impl GitGraphData {
         for_id: Id<ToEnrichedCommit>,
         is_match: impl Fn(Id<ToEnrichedCommit>) -> bool,
     ) -> Result<Option<Id<ToEnrichedCommit>>> {
-        // XX Could use roaring bitmaps instead of HashSet.
-
         // To detect fork points that were already visited.
-        let mut seen_commit_ids = HashSet::new();
+        let mut seen_commit_ids = CommitSet::new();
         seen_commit_ids.insert(for_id);
 
         // Can't use Vec since we'd need to splice. Linked list or:
@@ -247,15 +332,129 @@ impl GitGraphData {
             current_commits.remove(&commit_id_to_follow);
             let commit_to_follow = get_id(commit_id_to_follow);
             for commit_id in &commit_to_follow.commit.parents {
-                if !seen_commit_ids.contains(commit_id) {
+                if seen_commit_ids.insert(*commit_id) {
                     current_commits.insert(*commit_id);
-                    seen_commit_ids.insert(*commit_id);
                 }
             }
         }
         Ok(None)
     }
 
+    /// All ancestors of `id`, including `id` itself
+    pub fn ancestors(&self, id: Id<ToEnrichedCommit>) -> CommitSet {
+        let mut ids = CommitSet::new();
+        ids.insert(id);
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            for parent in &self[id].commit.parents {
+                if ids.insert(*parent) {
+                    stack.push(*parent);
+                }
+            }
+        }
+        ids
+    }
+
+    /// Whether `ancestor` is an ancestor of (or the same as)
+    /// `descendant`
+    pub fn is_ancestor(
+        &self,
+        ancestor: Id<ToEnrichedCommit>,
+        descendant: Id<ToEnrichedCommit>,
+    ) -> bool {
+        // Ancestors always have a smaller depth, thus paths through
+        // commits with a depth not larger than `ancestor`'s can be
+        // cut short
+        let ancestor_depth = self[ancestor].depth;
+        let mut seen = CommitSet::new();
+        seen.insert(descendant);
+        let mut stack = vec![descendant];
+        while let Some(id) = stack.pop() {
+            if id == ancestor {
+                return true;
+            }
+            if self[id].depth <= ancestor_depth {
+                continue;
+            }
+            for parent in &self[id].commit.parents {
+                if seen.insert(*parent) {
+                    stack.push(*parent);
+                }
+            }
+        }
+        false
+    }
+
+    /// A best common ancestor of `a` and `b` (one with the largest
+    /// depth, as there can be multiple), or None if they have no
+    /// common history.
+    pub fn merge_base(
+        &self,
+        a: Id<ToEnrichedCommit>,
+        b: Id<ToEnrichedCommit>,
+    ) -> Option<Id<ToEnrichedCommit>> {
+        let ancestors_of_a = self.ancestors(a);
+        // Visit the ancestors of `b` deepest first, the first one
+        // that is also an ancestor of `a` has the largest depth
+        let mut seen = CommitSet::new();
+        seen.insert(b);
+        let mut queue = BinaryHeap::new();
+        queue.push((self[b].depth, b));
+        while let Some((_, id)) = queue.pop() {
+            if ancestors_of_a.contains(id) {
+                return Some(id);
+            }
+            for parent in &self[id].commit.parents {
+                if seen.insert(*parent) {
+                    queue.push((self[*parent].depth, *parent));
+                }
+            }
+        }
+        None
+    }
+
+    /// `id` followed by its first parent, that one's first parent,
+    /// etc., down to the root commit
+    pub fn first_parent_chain(
+        &self,
+        id: Id<ToEnrichedCommit>,
+    ) -> impl Iterator<Item = Id<ToEnrichedCommit>> + '_ {
+        std::iter::successors(Some(id), |id| self[*id].commit.parents.first().copied())
+    }
+
+    /// `ids` sorted so that parents come before their children
+    pub fn topologically_sorted(
+        &self,
+        ids: impl IntoIterator<Item = Id<ToEnrichedCommit>>,
+    ) -> Vec<Id<ToEnrichedCommit>> {
+        let mut ids: Vec<_> = ids.into_iter().collect();
+        ids.sort();
+        ids
+    }
+
+    /// The commits that have no children in the graph (as the graph
+    /// holds the complete history of each commit, none of them is an
+    /// ancestor of another)
+    fn tips(&self) -> Vec<&GitHash> {
+        let mut have_children = CommitSet::new();
+        for ecommit in &self.ecommits {
+            for parent in &ecommit.commit.parents {
+                have_children.insert(*parent);
+            }
+        }
+        self.ecommits
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| {
+                !have_children.contains(Id(
+                    u32::try_from(*i).expect("ids are u32"),
+                    ToEnrichedCommit,
+                ))
+            })
+            .map(|(_, ecommit)| &ecommit.commit.commit_hash)
+            .collect()
+    }
+
     pub fn sorted_by<T: Ord>(
         &self,
         commits: &BTreeSet<Id<ToEnrichedCommit>>,
@@ -279,25 +478,134 @@ impl GitGraphData {
         &self.ecommits
     }
 
+    /// Add the history of `entry_reference` in the Git repository at
+    /// `in_directory`, retrieving only the commits that are not in
+    /// the graph yet.
     pub fn add_history_from_dir_ref(
         &mut self,
         in_directory: impl AsRef<Path>,
         entry_reference: &str,
     ) -> Result<GitEntrypoint> {
         let in_directory = in_directory.as_ref();
-        // XX first check if entry_reference is already indexed? But
-        // need name index, too, then. And should make directory part
-        // of the context, here, really.
-        let commits = git_log_commits(in_directory, entry_reference)?;
+        let name = KString::from_ref(entry_reference);
+        if let Ok(commit_hash) = entry_reference.parse::<GitHash>() {
+            if let Some(commit_id) = self.get_by_hash(&commit_hash) {
+                return Ok(GitEntrypoint { name, commit_id });
+            }
+        }
+        let commit_hash =
+            git_rev_parse_commit(in_directory, entry_reference)?.ok_or_else(|| {
+                anyhow!("invalid Git reference {entry_reference:?} in Git dir {in_directory:?}")
+            })?;
+        if let Some(commit_id) = self.get_by_hash(&commit_hash) {
+            return Ok(GitEntrypoint { name, commit_id });
+        }
+        let commits =
+            git_log_commits_excluding(in_directory, &commit_hash.to_string(), &self.tips())?;
         if commits.is_empty() {
-            bail!("invalid Git reference {entry_reference:?} in Git dir {in_directory:?}")
+            bail!("no history for Git reference {entry_reference:?} in Git dir {in_directory:?}")
         }
         Ok(GitEntrypoint::from_commits(
-            KString::from_ref(entry_reference),
+            name,
             commits.iter().rev(),
             self,
         )?)
     }
+
+    fn write_to(&self, out: &mut impl Write) -> Result<()> {
+        out.write_all(CACHE_FILE_HEADER)?;
+        for ecommit in &self.ecommits {
+            let GitCommit {
+                commit_hash,
+                author_time,
+                committer_time,
+                parents,
+            } = &ecommit.commit;
+            out.write_all(commit_hash.as_bytes())?;
+            out.write_all(&author_time.0.to_le_bytes())?;
+            out.write_all(&committer_time.0.to_le_bytes())?;
+            out.write_all(&[u8::try_from(parents.len())
+                .map_err(|_| anyhow!("commit {commit_hash} has more than 255 parents"))?])?;
+            for parent in parents {
+                out.write_all(&parent.0.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a graph written by `write_to`
+    fn read_from(input: &mut impl Read) -> Result<Self> {
+        let mut header = [0; CACHE_FILE_HEADER.len()];
+        input.read_exact(&mut header)?;
+        if header.as_slice() != CACHE_FILE_HEADER {
+            bail!("not a git graph cache file, or of another version")
+        }
+        let mut data = Self::new();
+        loop {
+            let mut hash = [0; 20];
+            match input.read_exact(&mut hash) {
+                Ok(()) => (),
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(e) => Err(e)?,
+            }
+            let mut u64_bytes = [0; 8];
+            input.read_exact(&mut u64_bytes)?;
+            let author_time = Unixtime(u64::from_le_bytes(u64_bytes));
+            input.read_exact(&mut u64_bytes)?;
+            let committer_time = Unixtime(u64::from_le_bytes(u64_bytes));
+            let mut num_parents = [0; 1];
+            input.read_exact(&mut num_parents)?;
+            let mut parents = SmallVec::new();
+            for _ in 0..num_parents[0] {
+                let mut u32_bytes = [0; 4];
+                input.read_exact(&mut u32_bytes)?;
+                let parent = Id(u32::from_le_bytes(u32_bytes), ToEnrichedCommit);
+                if data.get(parent).is_none() {
+                    bail!("parent id {} is not before its child", parent.0)
+                }
+                parents.push(parent);
+            }
+            data.unchecked_push(GitCommit {
+                commit_hash: GitHash::from(hash),
+                author_time,
+                committer_time,
+                parents,
+            })?;
+        }
+        data.saved_len = data.ecommits.len();
+        Ok(data)
+    }
+
+    /// Write the graph to the cache file, if it was opened with one
+    /// and has grown since loading or the last save. Replaces the
+    /// file atomically; concurrent processes saving the same file
+    /// may lose each other's additions, which are then retrieved
+    /// from Git again.
+    pub fn save(&mut self) -> Result<()> {
+        let Some(path) = &self.cache_path else {
+            return Ok(());
+        };
+        if self.ecommits.len() == self.saved_len {
+            return Ok(());
+        }
+        // (A separate temporary file per process, as both the
+        // polling and the running daemon update the graph)
+        let (tmp_file, mut out) = tempfile(path.clone(), false)?;
+        let tmp_path = tmp_file.temp_path();
+        self.write_to(&mut out)
+            .map_err(ctx!("writing file {tmp_path:?}"))?;
+        out.flush().map_err(ctx!("writing file {tmp_path:?}"))?;
+        drop(out);
+        tmp_file
+            .finish()
+            .map_err(ctx!("renaming temporary file to {path:?}"))?;
+        info!(
+            "saved git graph with {} commits to {path:?}",
+            self.ecommits.len()
+        );
+        self.saved_len = self.ecommits.len();
+        Ok(())
+    }
 }
 
 impl Index<Id<ToEnrichedCommit>> for GitGraphData {
@@ -316,6 +624,28 @@ impl GitGraph {
         Self(Mutex::new(GitGraphData::new())).into()
     }
 
+    /// A graph loaded from the cache file at `cache_path`, if it
+    /// exists, and saved there by `GitGraphData::save`. An unreadable
+    /// cache file is ignored with a warning (and overwritten on the
+    /// next save).
+    pub fn open(cache_path: PathBuf) -> Result<Arc<Self>> {
+        let mut data = match std::fs::File::open(&cache_path) {
+            Ok(file) => match GitGraphData::read_from(&mut BufReader::new(file)) {
+                Ok(data) => data,
+                Err(e) => {
+                    warn!("ignoring git graph cache file {cache_path:?}: {e:#}");
+                    GitGraphData::new()
+                }
+            },
+            Err(e) => match e.kind() {
+                std::io::ErrorKind::NotFound => GitGraphData::new(),
+                _ => Err(e).map_err(ctx!("opening file {cache_path:?}"))?,
+            },
+        };
+        data.cache_path = Some(cache_path);
+        Ok(Self(Mutex::new(data)).into())
+    }
+
     pub fn lock(&self) -> std::sync::MutexGuard<'_, GitGraphData> {
         match self.0.lock() {
             Ok(l) => l,
@@ -398,29 +728,77 @@ pub fn git_log_commits(
     in_directory: impl AsRef<Path>,
     entry_reference: &str,
 ) -> Result<Vec<GitCommit<GitHash>>> {
-    let in_directory = in_directory.as_ref();
-    let mut c = Command::new("git");
+    git_log_commits_with(in_directory.as_ref(), entry_reference, &[], None)
+}
 
-    c.args(&[
-        "log",
-        &{
-            let commit_hash = "%H";
-            let author_time = "%at";
-            let committer_time = "%ct";
-            let parent_hashes = "%P";
-            format!("--pretty={commit_hash},{author_time},{committer_time},{parent_hashes}")
-        },
+/// Like `git_log_commits`, but leaves out the history of the
+/// `exclude` commits (which are ignored if not in the repository).
+/// `entry_reference` should be known to resolve (e.g. via
+/// `git_rev_parse_commit`), as Git might fall back to `HEAD`
+/// otherwise. The exclusions are passed via stdin, as there can be
+/// many of them.
+pub fn git_log_commits_excluding(
+    in_directory: impl AsRef<Path>,
+    entry_reference: &str,
+    exclude: &[&GitHash],
+) -> Result<Vec<GitCommit<GitHash>>> {
+    let extra_args = ["--ignore-missing".to_string(), "--stdin".to_string()];
+    let input: String = exclude
+        .iter()
+        .map(|commit_hash| format!("^{commit_hash}\n"))
+        .collect();
+    git_log_commits_with(
+        in_directory.as_ref(),
         entry_reference,
-    ]);
+        &extra_args,
+        Some(&input),
+    )
+}
+
+fn git_log_commits_with(
+    in_directory: &Path,
+    entry_reference: &str,
+    extra_args: &[String],
+    stdin_input: Option<&str>,
+) -> Result<Vec<GitCommit<GitHash>>> {
+    let mut c = Command::new("git");
+
+    c.args(&["log", &{
+        let commit_hash = "%H";
+        let author_time = "%at";
+        let committer_time = "%ct";
+        let parent_hashes = "%P";
+        format!("--pretty={commit_hash},{author_time},{committer_time},{parent_hashes}")
+    }]);
+    c.args(extra_args);
+    c.arg(entry_reference);
     let str_from_bytes =
         |bs| std::str::from_utf8(bs).expect("git always gives ascii with given arguments");
     c.current_dir(in_directory);
 
     c.stdout(Stdio::piped());
     // stderr, too, but it's the case by default, anyway!
-    let output = c
-        .output()
-        .with_context(|| anyhow!("in directory {in_directory:?}"))?;
+    let output = if let Some(input) = stdin_input {
+        c.stdin(Stdio::piped());
+        let mut child = c
+            .spawn()
+            .with_context(|| anyhow!("in directory {in_directory:?}"))?;
+        // `git log --stdin` reads all of its input before writing
+        // any output, thus can write it all before reading
+        let mut stdin = child.stdin.take().expect("piped");
+        let write_result = stdin.write_all(input.as_bytes());
+        drop(stdin);
+        let output = child
+            .wait_with_output()
+            .with_context(|| anyhow!("in directory {in_directory:?}"))?;
+        if output.status.success() {
+            write_result.map_err(ctx!("writing to git log in directory {in_directory:?}"))?;
+        }
+        output
+    } else {
+        c.output()
+            .with_context(|| anyhow!("in directory {in_directory:?}"))?
+    };
     if !output.status.success() {
         // And I have such code already (in run-git, right?)
         let err = String::from_utf8_lossy(&output.stderr);
@@ -456,10 +834,172 @@ pub fn git_log_commits(
         .collect()
 }
 
+/// The commit that `reference` resolves to, None if it doesn't
+pub fn git_rev_parse_commit(in_directory: &Path, reference: &str) -> Result<Option<GitHash>> {
+    let output = Command::new("git")
+        .args(&[
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("{reference}^{{commit}}"),
+        ])
+        .current_dir(in_directory)
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| anyhow!("in directory {in_directory:?}"))?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let s = std::str::from_utf8(&output.stdout)?;
+    Ok(Some(s.trim().parse()?))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// A graph without Git: commit `i` has hash bytes `[i; 20]` and
+    /// the given parents (by index, which is also the id)
+    fn synthetic_graph(parents: &[&[u32]]) -> GitGraphData {
+        let mut data = GitGraphData::new();
+        for (i, parents) in parents.iter().enumerate() {
+            let i = u8::try_from(i).unwrap();
+            data.unchecked_push(GitCommit {
+                commit_hash: GitHash::from([i; 20]),
+                author_time: Unixtime(u64::from(i)),
+                committer_time: Unixtime(u64::from(i)),
+                parents: parents.iter().map(|p| Id(*p, ToEnrichedCommit)).collect(),
+            })
+            .unwrap();
+        }
+        data
+    }
+
+    fn id(i: u32) -> Id<ToEnrichedCommit> {
+        Id(i, ToEnrichedCommit)
+    }
+
+    #[test]
+    fn t_queries() -> Result<()> {
+        //   0 - 1 - 2 ----- 5 - 6
+        //        \         /
+        //         3 ----- 4
+        let data = synthetic_graph(&[&[], &[0], &[1], &[1], &[3], &[2, 4], &[5]]);
+
+        assert!(data.is_ancestor(id(1), id(6)));
+        assert!(data.is_ancestor(id(4), id(5)));
+        assert!(data.is_ancestor(id(3), id(3)));
+        assert!(!data.is_ancestor(id(2), id(4)));
+        assert!(!data.is_ancestor(id(6), id(0)));
+
+        assert_eq!(data.merge_base(id(2), id(4)), Some(id(1)));
+        assert_eq!(data.merge_base(id(6), id(4)), Some(id(4)));
+        assert_eq!(data.merge_base(id(3), id(3)), Some(id(3)));
+
+        assert_eq!(
+            data.first_parent_chain(id(6)).collect::<Vec<_>>(),
+            [id(6), id(5), id(2), id(1), id(0)]
+        );
+        assert_eq!(
+            data.ancestors(id(4)).iter().collect::<Vec<_>>(),
+            [id(0), id(1), id(3), id(4)]
+        );
+        assert_eq!(
+            data.topologically_sorted([id(5), id(0), id(3)]),
+            [id(0), id(3), id(5)]
+        );
+
+        let mut tips: Vec<_> = data.tips().into_iter().cloned().collect();
+        tips.sort();
+        assert_eq!(tips, [GitHash::from([6; 20])]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn t_cache_file_roundtrip() -> Result<()> {
+        let data = synthetic_graph(&[&[], &[0], &[0], &[1, 2]]);
+        let mut bytes = Vec::new();
+        data.write_to(&mut bytes)?;
+        let data2 = GitGraphData::read_from(&mut bytes.as_slice())?;
+        assert_eq!(data2.saved_len, 4);
+        let as_strings = |data: &GitGraphData| -> Vec<String> {
+            data.commits()
+                .iter()
+                .map(|c| c.with_ids_as_hashes(data).to_string())
+                .collect()
+        };
+        assert_eq!(as_strings(&data2), as_strings(&data));
+        assert_eq!(data2.get_by_hash(&GitHash::from([3; 20])), Some(id(3)));
+        assert_eq!(data2[id(3)].depth, 2);
+
+        assert!(GitGraphData::read_from(&mut &bytes[..bytes.len() - 1]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn t_add_history_incrementally() -> Result<()> {
+        let test_dir = crate::utillib::test_dir::TestDir::new("git-graph-incremental");
+        let dir = test_dir.path();
+        let git = |args: &[&str]| -> String {
+            let output = Command::new("git")
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(dir)
+                .output()
+                .expect("running git");
+            assert!(output.status.success(), "git {args:?}");
+            String::from_utf8(output.stdout).unwrap().trim().into()
+        };
+        let commit = |message: &str| -> GitHash {
+            git(&["commit", "--quiet", "--allow-empty", "-m", message]);
+            git(&["rev-parse", "HEAD"]).parse().unwrap()
+        };
+        git(&["init", "--quiet", "--initial-branch=main"]);
+        let c1 = commit("1");
+        let c2 = commit("2");
+        git(&["checkout", "--quiet", "-b", "b", &c1.to_string()]);
+        let c3 = commit("3");
+
+        let graph = GitGraph::new();
+        let mut data = graph.lock();
+        data.add_history_from_dir_ref(dir, "main")?;
+        assert_eq!(data.commits().len(), 2);
+        data.add_history_from_dir_ref(dir, "b")?;
+        // Only c3 was added
+        assert_eq!(data.commits().len(), 3);
+        assert_eq!(data.tips(), [&c2, &c3]);
+
+        git(&["checkout", "--quiet", "main"]);
+        git(&["merge", "--quiet", "--no-edit", "b"]);
+        let c4: GitHash = git(&["rev-parse", "HEAD"]).parse()?;
+        let entry = data.add_history_from_dir_ref(dir, "main")?;
+        assert_eq!(data.commits().len(), 4);
+        assert_eq!(data[entry.commit_id].commit.commit_hash, c4);
+        assert_eq!(data.tips(), [&c4]);
+        let parents: Vec<GitHash> = data[entry.commit_id]
+            .commit
+            .with_ids_as_hashes(&data)
+            .parents
+            .into_iter()
+            .collect();
+        assert_eq!(parents, [c2, c3]);
+
+        // Many exclusions (unknown ones are ignored)
+        let unknown: Vec<GitHash> = (0..5000u32)
+            .map(|i| {
+                let mut bytes = [0xee; 20];
+                bytes[..4].copy_from_slice(&i.to_le_bytes());
+                GitHash::from(bytes)
+            })
+            .collect();
+        let mut exclude: Vec<&GitHash> = unknown.iter().collect();
+        exclude.push(&c1);
+        let commits = git_log_commits_excluding(dir, "main", &exclude)?;
+        assert_eq!(commits.len(), 3);
+        Ok(())
+    }
+
     #[test]
     fn t_() -> Result<()> {
         let git_in_cwd = AsRef::<Path>::as_ref("../.git");
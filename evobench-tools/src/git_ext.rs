@@ -1,12 +1,18 @@
 //! Extension trait for `GitWorkingDir` from the `run-git` crate
 
-use std::str::FromStr;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    str::FromStr,
+};
 
 use anyhow::{Result, bail};
 use run_git::git::GitWorkingDir;
 
-use crate::serde_types::{
-    git_branch_name::GitBranchName, git_reference::GitReference, git_url::GitUrl,
+use crate::{
+    ctx,
+    date_and_time::unixtime::Unixtime,
+    git::GitHash,
+    serde_types::{git_branch_name::GitBranchName, git_reference::GitReference, git_url::GitUrl},
 };
 
 pub trait MoreGitWorkingDir {
@@ -20,6 +26,9 @@ pub trait MoreGitWorkingDir {
         quiet: bool,
     ) -> Result<()>;
     fn get_current_branch(&self) -> Result<Option<GitBranchName>>;
+    fn commit_times(&self, commits: &[GitHash]) -> Result<BTreeMap<GitHash, Unixtime>>;
+    fn first_parents(&self, commits: &[GitHash]) -> Result<BTreeMap<GitHash, Option<GitHash>>>;
+    fn rev_list(&self, range: &str) -> Result<Vec<GitHash>>;
 }
 
 impl MoreGitWorkingDir for GitWorkingDir {
@@ -74,4 +83,88 @@ impl MoreGitWorkingDir for GitWorkingDir {
             GitBranchName::from_str(&s).expect("git always returns branch names for show-current")
         }))
     }
+
+    /// Get the committer times of those of `commits` that exist in
+    /// the repository (missing ones are not an error, they are just
+    /// not in the result).
+    fn commit_times(&self, commits: &[GitHash]) -> Result<BTreeMap<GitHash, Unixtime>> {
+        if commits.is_empty() {
+            return Ok(BTreeMap::new());
+        }
+        let commit_strs: Vec<String> = commits.iter().map(|c| c.to_string()).collect();
+        let mut args = vec![
+            "log",
+            "--no-walk=unsorted",
+            "--ignore-missing",
+            "--format=%H %ct",
+        ];
+        args.extend(commit_strs.iter().map(|s| s.as_str()));
+        let output = self.git_stdout_string_trimmed(&args)?;
+
+        // If none of the commits exist, git falls back to HEAD, thus
+        // only accept the requested ones.
+        let requested: BTreeSet<&GitHash> = commits.iter().collect();
+        let mut times = BTreeMap::new();
+        for line in output.lines() {
+            let Some((hash, time)) = line.split_once(' ') else {
+                bail!("invalid line from git log: {line:?}")
+            };
+            let hash = GitHash::from_str(hash).map_err(ctx!("parsing output of git log"))?;
+            if requested.contains(&hash) {
+                let time = Unixtime(time.parse().map_err(ctx!("parsing output of git log"))?);
+                times.insert(hash, time);
+            }
+        }
+        Ok(times)
+    }
+
+    /// Get the first parents of those of `commits` that exist in the
+    /// repository (`None` for root commits; missing commits are not
+    /// in the result).
+    fn first_parents(&self, commits: &[GitHash]) -> Result<BTreeMap<GitHash, Option<GitHash>>> {
+        if commits.is_empty() {
+            return Ok(BTreeMap::new());
+        }
+        let commit_strs: Vec<String> = commits.iter().map(|c| c.to_string()).collect();
+        let mut args = vec![
+            "log",
+            "--no-walk=unsorted",
+            "--ignore-missing",
+            "--format=%H %P",
+        ];
+        args.extend(commit_strs.iter().map(|s| s.as_str()));
+        let output = self.git_stdout_string_trimmed(&args)?;
+
+        // As in `commit_times`, only accept the requested commits
+        let requested: BTreeSet<&GitHash> = commits.iter().collect();
+        let mut parents = BTreeMap::new();
+        for line in output.lines() {
+            let mut hashes = line.split(' ').filter(|s| !s.is_empty());
+            let Some(hash) = hashes.next() else {
+                bail!("invalid line from git log: {line:?}")
+            };
+            let hash = GitHash::from_str(hash).map_err(ctx!("parsing output of git log"))?;
+            if requested.contains(&hash) {
+                let first_parent = hashes
+                    .next()
+                    .map(|s| GitHash::from_str(s).map_err(ctx!("parsing output of git log")))
+                    .transpose()?;
+                parents.insert(hash, first_parent);
+            }
+        }
+        Ok(parents)
+    }
+
+    /// The commits in a revision range like `v1.0..main` (as
+    /// understood by `git rev-list`)
+    fn rev_list(&self, range: &str) -> Result<Vec<GitHash>> {
+        if range.starts_with('-') {
+            bail!("invalid revision range {range:?}")
+        }
+        let output = self.git_stdout_string_trimmed(&["rev-list", range, "--"])?;
+        output
+            .lines()
+            .map(|line| GitHash::from_str(line).map_err(ctx!("parsing output of git rev-list")))
+            .collect()
+    }
 }
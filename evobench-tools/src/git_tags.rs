@@ -61,6 +61,12 @@ impl GitTags {
         Ok(GitTags { tags, by_hash })
     }
 
+    /// All tags, in the order in which they appear in `git tag
+    /// --list`.
+    pub fn tags(&self) -> &[GitTag] {
+        &self.tags
+    }
+
     /// Returns the empty set for unknown commit ids. Returns tag
     /// names in the order in which they appear in `git tag --list`.
     pub fn get_by_commit(&self, commit_id: &GitHash) -> impl ExactSizeIterator<Item = &str> {
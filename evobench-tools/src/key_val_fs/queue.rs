@@ -268,6 +268,13 @@ impl<'basedir, V: DeserializeOwned + Serialize> QueueItem<'basedir, V> {
         Ok(())
     }
 
+    /// Whether this item holds its lock (i.e. `no_lock` was false and
+    /// the entry still existed after taking the lock)
+    pub fn is_locked(&self) -> bool {
+        let (_entry, perhaps_lock) = self.borrow_perhaps_lock();
+        matches!(perhaps_lock, PerhapsLock::Lock(_))
+    }
+
     /// Lock this item lazily (when `no_lock` == true given, but now a
     /// lock is needed). If `no_lock` was false, then this gives a
     /// `KeyValError::AlreadyLocked` error rather than dead-locking.
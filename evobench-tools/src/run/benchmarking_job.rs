@@ -288,6 +288,41 @@ impl BenchmarkingJob {
     }
 }
 
+#[cfg(test)]
+impl BenchmarkingJob {
+    /// A job without custom parameters, for tests. `commit_id` must
+    /// be 40 hex digits.
+    pub fn for_tests(target_name: &str, commit_id: &str, reason: Option<&str>) -> Self {
+        use std::collections::BTreeMap;
+
+        use crate::run::config::PreExecLevel2;
+
+        Self {
+            public: BenchmarkingJobPublic {
+                reason: reason.map(String::from),
+                run_parameters: Arc::new(RunParameters {
+                    commit_id: commit_id.parse().expect("valid commit id"),
+                    custom_parameters: Arc::new(CustomParameters::from(BTreeMap::new())),
+                }),
+                command: Arc::new(BenchmarkingCommand {
+                    target_name: target_name.parse().expect("valid target name"),
+                    subdir: ".".into(),
+                    command: "make".into(),
+                    arguments: vec!["bench".into()],
+                    pre_exec_bash_code: PreExecLevel2::new(None),
+                }),
+            },
+            state: BenchmarkingJobState {
+                remaining_count: 1,
+                remaining_error_budget: 1,
+                last_working_directory: None,
+            },
+            priority: Priority::NORMAL,
+            current_boost: Priority::NORMAL,
+        }
+    }
+}
+
 impl BenchmarkingJobOpts {
     /// Make one job per job template, filling the missing values
     /// (currently just `priority` and `initial_boost`) and all other
@@ -317,6 +352,7 @@ impl BenchmarkingJobOpts {
                     initial_boost,
                     command,
                     custom_parameters,
+                    sweep: _,
                 } = job_template;
 
                 BenchmarkingJob {
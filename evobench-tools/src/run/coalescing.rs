@@ -0,0 +1,206 @@
+//! Commit coalescing: when many commits from the same branch are
+//! waiting to be benchmarked, only keep the jobs for the newest commit
+//! (and optionally every nth older one), moving the others to the
+//! `superseded_jobs_queue` (if configured).
+//!
+//! Superseded jobs are also removed from the `already_inserted`
+//! table, so that their commits can later be inserted again
+//! (e.g. for bisection) without `--force`.
+
+use std::{collections::BTreeMap, io::stdout, num::NonZeroUsize};
+
+use anyhow::Result;
+
+use crate::{
+    config_file::ron_to_string_pretty,
+    git::GitHash,
+    info,
+    key_val_fs::{
+        key_val::KeyValError,
+        queue::{QueueGetItemOptions, TimeKey},
+    },
+    run::{insert_jobs::open_already_inserted, key::BenchmarkingJobParametersHash},
+    utillib::logging::{LogLevel, log_level},
+};
+
+use super::{
+    benchmarking_job::BenchmarkingJob, global_app_state_dir::GlobalAppStateDir,
+    run_queues::RunQueues,
+};
+
+/// Per-branch coalescing policy (see `RemoteRepository.coalescing`)
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CoalescingPolicy {
+    /// Coalescing only happens when jobs for more than this many
+    /// commits from the branch are pending (i.e. none of their runs
+    /// has started yet).
+    pub backlog_threshold: usize,
+
+    /// Besides the newest pending commit, also keep every nth older
+    /// one (counting from the newest). If `None`, only the newest
+    /// commit is kept.
+    pub keep_every_nth: Option<NonZeroUsize>,
+}
+
+impl CoalescingPolicy {
+    /// Whether the commit at position `index` in the list of pending
+    /// commits (sorted newest first) keeps its jobs
+    pub fn keeps(&self, index: usize) -> bool {
+        index == 0
+            || self
+                .keep_every_nth
+                .map(|n| index % n.get() == 0)
+                .unwrap_or(false)
+    }
+}
+
+/// A pending job as found in the pipeline: index of the queue in the
+/// pipeline, its key in that queue, and the job.
+struct PendingJob {
+    queue_index: usize,
+    key: TimeKey,
+    job: BenchmarkingJob,
+}
+
+/// Apply `policy` to the pending jobs that were inserted with the
+/// given `reason` (which for `evobench poll` is the branch
+/// name). Jobs that are currently locked (i.e. being run) are left
+/// alone. If `dry_run` is true, only reports what would be done to
+/// stdout. Returns the number of jobs superseded (or which would be
+/// superseded).
+pub fn coalesce_pending_jobs(
+    queues: &RunQueues,
+    global_app_state_dir: &GlobalAppStateDir,
+    reason: &str,
+    policy: &CoalescingPolicy,
+    dry_run: bool,
+) -> Result<usize> {
+    // Commit -> newest insertion time of a job for it, and its jobs
+    let mut pending_by_commit: BTreeMap<GitHash, (TimeKey, Vec<PendingJob>)> = BTreeMap::new();
+    {
+        let queues_data = queues.data()?;
+        for (queue_index, rq) in queues_data.run_queue_with_nexts().enumerate() {
+            if rq.current.run_queue().schedule_condition.is_inactive() {
+                continue;
+            }
+            for (key, job, _priority) in rq.current.entries() {
+                if job.public.reason.as_deref() != Some(reason) {
+                    continue;
+                }
+                if job.state.last_working_directory.is_some() {
+                    // Has been run at least once, finish it
+                    continue;
+                }
+                let (newest, jobs) = pending_by_commit
+                    .entry(job.public.run_parameters.commit_id.clone())
+                    .or_insert_with(|| (key.clone(), Vec::new()));
+                if *key > *newest {
+                    *newest = key.clone();
+                }
+                jobs.push(PendingJob {
+                    queue_index,
+                    key: key.clone(),
+                    job: job.clone(),
+                });
+            }
+        }
+    }
+
+    if pending_by_commit.len() <= policy.backlog_threshold {
+        return Ok(0);
+    }
+
+    // Newest first; insertion order reflects the order in which
+    // `poll` saw the commits.
+    let mut pending: Vec<(TimeKey, GitHash, Vec<PendingJob>)> = pending_by_commit
+        .into_iter()
+        .map(|(commit_id, (newest, jobs))| (newest, commit_id, jobs))
+        .collect();
+    pending.sort_by(|a, b| b.0.cmp(&a.0));
+
+    let verbose = log_level() >= LogLevel::Info;
+    let already_inserted = open_already_inserted(global_app_state_dir)?;
+    let _lock = already_inserted.lock_exclusive()?;
+    let superseded_jobs_queue = queues.superseded_jobs_queue();
+
+    let mut num_superseded = 0;
+    for (index, (_newest, commit_id, jobs)) in pending.into_iter().enumerate() {
+        if policy.keeps(index) {
+            continue;
+        }
+        for PendingJob {
+            queue_index,
+            key,
+            job,
+        } in jobs
+        {
+            if dry_run {
+                use std::io::Write;
+                let mut out = stdout().lock();
+                writeln!(
+                    &mut out,
+                    "would supersede job for commit {commit_id}:\n{}",
+                    ron_to_string_pretty(&job).expect("no err")
+                )?;
+                num_superseded += 1;
+                continue;
+            }
+
+            let queue = &queues.pipeline()[queue_index].queue;
+            let item = match queue.get_item(
+                &key,
+                QueueGetItemOptions {
+                    verbose,
+                    no_lock: false,
+                    error_when_locked: true,
+                    delete_first: false,
+                },
+            ) {
+                Ok(Some(item)) => item,
+                // Disappeared in the mean time
+                Ok(None) => continue,
+                // Being run right now
+                Err(KeyValError::LockTaken { .. }) => continue,
+                Err(e) => Err(e)?,
+            };
+
+            if let Some(superseded_jobs_queue) = superseded_jobs_queue {
+                superseded_jobs_queue.push_front(&job)?;
+            } else {
+                info!(
+                    "job superseded and no configured superseded_jobs_queue: {}",
+                    ron_to_string_pretty(&job).expect("no err")
+                );
+            }
+            item.delete()?;
+            already_inserted.delete(&BenchmarkingJobParametersHash::from(
+                &job.benchmarking_job_parameters(),
+            ))?;
+            num_superseded += 1;
+        }
+    }
+    Ok(num_superseded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_keeps() {
+        let only_newest = CoalescingPolicy {
+            backlog_threshold: 3,
+            keep_every_nth: None,
+        };
+        let kept: Vec<usize> = (0..7).filter(|i| only_newest.keeps(*i)).collect();
+        assert_eq!(kept, [0]);
+
+        let every_third = CoalescingPolicy {
+            backlog_threshold: 3,
+            keep_every_nth: Some(NonZeroUsize::new(3).unwrap()),
+        };
+        let kept: Vec<usize> = (0..8).filter(|i| every_third.keeps(*i)).collect();
+        assert_eq!(kept, [0, 3, 6]);
+    }
+}
@@ -9,7 +9,7 @@ use std::{
 };
 
 use anyhow::Result;
-use chrono::DateTime;
+use chrono::{DateTime, FixedOffset};
 
 use crate::{
     ctx, io_utils::output_capture_log::OutputCaptureLog, io_utils::zstd_file::decompressed_file,
@@ -134,4 +134,18 @@ impl<'l, P: AsRef<Path>> CommandLog<'l, P> {
             (self.borrow_contents(), 1)
         }
     }
+
+    /// The timestamps of the first and the last timestamped line
+    /// after the head, i.e. roughly when the benchmarking command
+    /// started and ended. None if there are no timestamped lines.
+    pub fn time_span(&self) -> Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)> {
+        let (rest, _lineno) = self.log_contents_rest();
+        let mut timestamps = rest.split('\n').filter_map(|line| {
+            let (t, _) = line.split_once('\t')?;
+            DateTime::parse_from_rfc3339(t).ok()
+        });
+        let first = timestamps.next()?;
+        let last = timestamps.last().unwrap_or(first);
+        Some((first, last))
+    }
 }
@@ -2,6 +2,7 @@ use std::{
     borrow::Cow,
     collections::{BTreeMap, BTreeSet},
     fmt::{Debug, Display},
+    num::NonZeroU32,
     path::{Path, PathBuf},
     str::FromStr,
     sync::Arc,
@@ -15,10 +16,14 @@ use kstring::KString;
 
 use crate::{
     config_file::{ConfigFile, DefaultConfigPath, ron_to_string_pretty},
+    ctx,
     date_and_time::time_ranges::{DateTimeRange, LocalNaiveTimeRange},
     info,
     io_utils::{bash::bash_string_from_cmd, div::create_dir_if_not_exists},
-    run::{env_vars::AllowableCustomEnvVar, key::CustomParameters},
+    run::{
+        env_vars::AllowableCustomEnvVar,
+        key::{CustomParameters, custom_parameters_matrix},
+    },
     run_with_pre_exec::{BashSettings, BashSettingsLevel, RunWithPreExec, join_pre_exec_bash_code},
     serde_types::{
         allowed_env_var::AllowedEnvVar,
@@ -37,11 +42,20 @@ use crate::{
 };
 
 use super::{
-    benchmarking_job::BenchmarkingJobSettingsOpts, custom_parameter::AllowedCustomParameter,
-    global_app_state_dir::GlobalAppStateDir, working_directory_pool::WorkingDirectoryPoolOpts,
+    benchmarking_job::BenchmarkingJobSettingsOpts,
+    coalescing::CoalescingPolicy,
+    custom_parameter::{AllowedCustomParameter, AllowedCustomParameterType},
+    distributed::{CoordinatorOpts, WorkerOpts},
+    fair_share::FairShareOpts,
+    global_app_state_dir::GlobalAppStateDir,
+    polled_heads::{PollCommitRange, PollCommitRangeOpts},
+    ref_pattern::{RefKind, RefMatcher, RefPattern},
+    webhook::WebhookOpts,
+    working_directory::CheckoutOpts,
+    working_directory_pool::WorkingDirectoryPoolOpts,
 };
 
-#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 #[serde(deny_unknown_fields)]
 pub enum ScheduleCondition {
     /// Run jobs in this queue once right away
@@ -294,10 +308,30 @@ pub struct QueuesConfig {
     /// silently unless verbose flag is given).
     pub done_jobs_queue: Option<(ProperFilename, ScheduleCondition)>,
 
+    /// The queue where to put jobs that were superseded by jobs for
+    /// newer commits on the same branch via the `coalescing` policy
+    /// in `RemoteRepository` (if `None` is given, the jobs will be
+    /// dropped--silently unless verbose flag is given). Must be of
+    /// scheduling type Inactive.
+    pub superseded_jobs_queue: Option<(ProperFilename, ScheduleCondition)>,
+
     /// How many jobs to show in the extra queues
     /// (`erroneous_jobs_queue` and `done_jobs_queue`) when no `--all`
     /// option is given
     pub view_jobs_max_len: usize,
+
+    /// Optional fair-share scheduling policy: if given, the total
+    /// priority of each job is lowered according to how much run
+    /// time its group (e.g. branch) has received recently. If `None`,
+    /// only the priorities decide.
+    pub fair_share: Option<FairShareOpts>,
+
+    /// If given, the queues can be run by other machines: workers
+    /// lease jobs via `evobench coordinator lease` and upload their
+    /// results via `evobench coordinator complete` (see the `worker`
+    /// setting for the other side). Leased jobs are skipped by the
+    /// local `evobench run`.
+    pub coordinator: Option<CoordinatorOpts>,
 }
 
 impl QueuesConfig {
@@ -328,11 +362,77 @@ pub struct RemoteRepositoryOpts {
     /// The remote branches to track
     pub remote_branch_names_for_poll:
         BTreeMap<GitBranchName, ValOrRef<JobTemplateListsField, Vec<JobTemplateOpts>>>,
+
+    /// Optional patterns over the remote branch and tag names to
+    /// track, e.g. `(BranchGlob("bench/*"), [...])` or
+    /// `(TagRegex("v[0-9]+\\..*"), [...])`. Branches listed in
+    /// `remote_branch_names_for_poll` take precedence, otherwise the
+    /// first matching pattern determines the job templates.
+    pub ref_patterns_for_poll: Option<
+        Vec<(
+            RefPattern,
+            ValOrRef<JobTemplateListsField, Vec<JobTemplateOpts>>,
+        )>,
+    >,
+
+    /// Optional coalescing policies for branches listed in
+    /// `remote_branch_names_for_poll` or matched by a branch pattern
+    /// in `ref_patterns_for_poll`: when jobs for too many commits
+    /// from the branch are waiting, only benchmark the newest (and
+    /// optionally every nth) commit, moving the jobs for the others
+    /// to `queues.superseded_jobs_queue`.
+    pub coalescing: Option<BTreeMap<GitBranchName, CoalescingPolicy>>,
+
+    /// Which commits to insert jobs for when a polled branch has
+    /// moved by more than one commit since the last poll. Default:
+    /// all new commits on the first-parent chain, up to 100.
+    pub commit_range: Option<PollCommitRangeOpts>,
+
+    /// Optional settings for receiving push and pull request events
+    /// from GitHub or Gitea via `evobench webhook serve`, as an
+    /// alternative to polling.
+    pub webhook: Option<Arc<WebhookOpts>>,
+
+    /// Optional settings for projects using Git submodules or Git
+    /// LFS, to complete the working tree after each checkout.
+    pub checkout: Option<CheckoutOpts>,
 }
 
 pub struct RemoteRepository {
     pub url: GitUrl,
     pub remote_branch_names_for_poll: BTreeMap<GitBranchName, Arc<[JobTemplate]>>,
+    pub ref_patterns_for_poll: Vec<(RefMatcher, Arc<[JobTemplate]>)>,
+    pub coalescing: BTreeMap<GitBranchName, CoalescingPolicy>,
+    pub commit_range: PollCommitRange,
+    pub webhook: Option<Arc<WebhookOpts>>,
+    pub checkout: CheckoutOpts,
+}
+
+impl RemoteRepository {
+    /// The job templates for the given branch, either from
+    /// `remote_branch_names_for_poll` or from the first matching
+    /// branch pattern.
+    pub fn job_templates_for_branch(
+        &self,
+        branch_name: &GitBranchName,
+    ) -> Option<&Arc<[JobTemplate]>> {
+        self.remote_branch_names_for_poll
+            .get(branch_name)
+            .or_else(|| self.job_templates_for_pattern(RefKind::Branch, branch_name.as_str()))
+    }
+
+    /// The job templates of the first pattern in
+    /// `ref_patterns_for_poll` matching the given ref name.
+    pub fn job_templates_for_pattern(
+        &self,
+        kind: RefKind,
+        name: &str,
+    ) -> Option<&Arc<[JobTemplate]>> {
+        self.ref_patterns_for_poll
+            .iter()
+            .find(|(matcher, _)| matcher.matches(kind, name))
+            .map(|(_, job_templates)| job_templates)
+    }
 }
 
 impl RemoteRepositoryOpts {
@@ -344,29 +444,82 @@ impl RemoteRepositoryOpts {
         let Self {
             url,
             remote_branch_names_for_poll,
+            ref_patterns_for_poll,
+            coalescing,
+            commit_range,
+            webhook,
+            checkout,
         } = self;
 
+        let check_job_templates = |job_template_optss: &ValOrRef<
+            JobTemplateListsField,
+            Vec<JobTemplateOpts>,
+        >|
+         -> Result<Arc<[JobTemplate]>> {
+            let job_templates: ValOrRef<JobTemplateListsField, Arc<[JobTemplate]>> =
+                job_template_optss.try_map(
+                    |job_template_optss: &Vec<JobTemplateOpts>| -> Result<Arc<[JobTemplate]>> {
+                        let job_templates: Vec<Vec<JobTemplate>> = job_template_optss
+                            .iter()
+                            .map(|job_template_opts| job_template_opts.check(targets))
+                            .collect::<Result<_>>()?;
+                        Ok(job_templates.into_iter().flatten().collect())
+                    },
+                )?;
+            let job_templates = job_templates.value_with_context(job_template_lists)?;
+            Ok(job_templates.clone_arc())
+        };
+
         let remote_branch_names_for_poll = remote_branch_names_for_poll
             .iter()
             .map(|(branch_name, job_template_optss)| -> Result<_> {
-                let job_templates: ValOrRef<JobTemplateListsField, Arc<[JobTemplate]>> =
-                    job_template_optss.try_map(
-                        |job_template_optss: &Vec<JobTemplateOpts>| -> Result<Arc<[JobTemplate]>> {
-                            job_template_optss
-                                .iter()
-                                .map(|job_template_opts| job_template_opts.check(targets))
-                                .collect()
-                        },
-                    )?;
-                let job_templates = job_templates.value_with_context(job_template_lists)?;
-                Ok((branch_name.clone(), job_templates.clone_arc()))
+                Ok((
+                    branch_name.clone(),
+                    check_job_templates(job_template_optss)?,
+                ))
+            })
+            .collect::<Result<_>>()?;
+
+        let ref_patterns_for_poll = ref_patterns_for_poll
+            .iter()
+            .flatten()
+            .map(|(pattern, job_template_optss)| -> Result<_> {
+                Ok((
+                    pattern.to_matcher()?,
+                    check_job_templates(job_template_optss)
+                        .with_context(|| anyhow!("job templates for pattern {pattern}"))?,
+                ))
             })
             .collect::<Result<_>>()?;
 
-        Ok(RemoteRepository {
+        let remote_repository = RemoteRepository {
             url: url.clone(),
             remote_branch_names_for_poll,
-        })
+            ref_patterns_for_poll,
+            coalescing: coalescing.clone().unwrap_or_default(),
+            commit_range: commit_range
+                .as_ref()
+                .map(PollCommitRangeOpts::check)
+                .unwrap_or_default(),
+            webhook: webhook.clone(),
+            checkout: checkout.clone().unwrap_or_default(),
+        };
+
+        for branch_name in remote_repository.coalescing.keys() {
+            if remote_repository
+                .job_templates_for_branch(branch_name)
+                .is_none()
+            {
+                bail!(
+                    "branch name {:?} in `RemoteRepository.coalescing` is neither listed in \
+                     `remote_branch_names_for_poll` nor matched by a branch pattern in \
+                     `ref_patterns_for_poll`",
+                    branch_name.as_str()
+                )
+            }
+        }
+
+        Ok(remote_repository)
     }
 }
 
@@ -459,6 +612,52 @@ pub struct BenchmarkingTarget {
     pub log_extracts: Option<Vec<LogExtract>>,
 }
 
+/// The values of a swept custom parameter (see `JobTemplateOpts.sweep`)
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum SweepValuesOpts {
+    /// The given values
+    Values(Vec<KString>),
+    /// The integers from `start` to `end` (inclusive) in increments
+    /// of `step` (default: 1); only for parameters of type
+    /// `NonZeroU32`, `U32` or `U32Range`.
+    Range {
+        start: u32,
+        end: u32,
+        step: Option<NonZeroU32>,
+    },
+}
+
+impl SweepValuesOpts {
+    /// `r#type` is the type of the parameter, if it is allowed at all
+    /// (if not, the error is reported later)
+    fn expand(&self, r#type: Option<&AllowedCustomParameterType>) -> Result<Vec<KString>> {
+        match self {
+            SweepValuesOpts::Values(values) => {
+                if values.is_empty() {
+                    bail!("empty list of values")
+                }
+                Ok(values.clone())
+            }
+            SweepValuesOpts::Range { start, end, step } => {
+                if let Some(r#type) = r#type {
+                    if !r#type.is_integer() {
+                        bail!("`Range` is only supported for integer parameters, not {type}")
+                    }
+                }
+                if start > end {
+                    bail!("`Range` start {start} is larger than end {end}")
+                }
+                let step = step.map(NonZeroU32::get).unwrap_or(1);
+                Ok((*start..=*end)
+                    .step_by(step as usize)
+                    .map(|i| KString::from_string(i.to_string()))
+                    .collect())
+            }
+        }
+    }
+}
+
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 #[serde(deny_unknown_fields)]
 #[serde(rename = "JobTemplate")]
@@ -471,6 +670,18 @@ pub struct JobTemplateOpts {
     // would be required, and `allowed_custom_parameters` already have
     // the type, no *need* to specify it again, OK?)
     custom_parameters: BTreeMap<AllowedEnvVar<AllowableCustomEnvVar>, KString>,
+
+    /// Parameters to sweep over: the template expands to one job
+    /// template per combination of the values of all swept parameters
+    /// (the cartesian product), each with the `custom_parameters`
+    /// added. A parameter must not appear in both. Also see
+    /// `sweep_exclude`.
+    sweep: Option<BTreeMap<AllowedEnvVar<AllowableCustomEnvVar>, SweepValuesOpts>>,
+
+    /// Combinations of swept parameter values to skip: a combination
+    /// is skipped if it contains all key/value pairs of any of the
+    /// entries (e.g. `[{"CONCURRENCY": "64", "DATASET": "small"}]`).
+    sweep_exclude: Option<Vec<BTreeMap<AllowedEnvVar<AllowableCustomEnvVar>, KString>>>,
 }
 
 pub struct JobTemplate {
@@ -478,37 +689,137 @@ pub struct JobTemplate {
     pub initial_boost: Priority,
     pub command: Arc<BenchmarkingCommand>,
     pub custom_parameters: Arc<CustomParameters>,
+    /// If this template was expanded from a template with a `sweep`,
+    /// the swept parameters with all of their values (shared by all
+    /// templates from the expansion)
+    pub sweep: Option<Arc<BTreeMap<AllowedEnvVar<AllowableCustomEnvVar>, Vec<KString>>>>,
 }
 
 impl JobTemplateOpts {
+    /// A template given on the command line (`evobench insert
+    /// adhoc`), with normal priority and no initial boost (both can
+    /// be overridden via the insert options). Parameters in `sweep`
+    /// are swept over.
+    pub fn adhoc(
+        target_name: ProperDirname,
+        custom_parameters: BTreeMap<AllowedEnvVar<AllowableCustomEnvVar>, KString>,
+        sweep: BTreeMap<AllowedEnvVar<AllowableCustomEnvVar>, Vec<KString>>,
+    ) -> Self {
+        let sweep = if sweep.is_empty() {
+            None
+        } else {
+            Some(
+                sweep
+                    .into_iter()
+                    .map(|(key, values)| (key, SweepValuesOpts::Values(values)))
+                    .collect(),
+            )
+        };
+        Self {
+            priority: Priority::NORMAL,
+            initial_boost: Priority::NORMAL,
+            target_name,
+            custom_parameters,
+            sweep,
+            sweep_exclude: None,
+        }
+    }
+
+    /// Check against the targets and expand sweeps; gives one
+    /// `JobTemplate` without a sweep, or one per (not excluded)
+    /// combination of swept values.
     pub fn check(
         &self,
         targets: &BTreeMap<ProperDirname, Arc<BenchmarkingTarget>>,
-    ) -> Result<JobTemplate> {
+    ) -> Result<Vec<JobTemplate>> {
         let Self {
             priority,
             initial_boost,
             target_name,
             custom_parameters,
+            sweep,
+            sweep_exclude,
         } = self;
 
         let target = targets
             .get(target_name)
             .ok_or_else(|| anyhow!("unknown target name {:?}", target_name.as_str()))?;
 
-        let custom_parameters =
-            CustomParameters::checked_from(custom_parameters, &target.allowed_custom_parameters)
-                .with_context(|| {
-                    let context = ron_to_string_pretty(self).expect("no serialisation errors");
-                    anyhow!("processing {context}")
-                })?;
+        let context = || {
+            let context = ron_to_string_pretty(self).expect("no serialisation errors");
+            anyhow!("processing {context}")
+        };
 
-        Ok(JobTemplate {
-            priority: *priority,
-            initial_boost: *initial_boost,
-            command: target.benchmarking_command.clone_arc(),
-            custom_parameters: custom_parameters.into(),
-        })
+        let sweep: BTreeMap<AllowedEnvVar<AllowableCustomEnvVar>, Vec<KString>> = sweep
+            .iter()
+            .flatten()
+            .map(|(key, values)| -> Result<_> {
+                if custom_parameters.contains_key(key) {
+                    bail!(
+                        "parameter {:?} is given both in `custom_parameters` and `sweep`",
+                        key.as_str()
+                    )
+                }
+                let r#type = target
+                    .allowed_custom_parameters
+                    .get(key)
+                    .map(|allowed| &allowed.r#type);
+                let values = values
+                    .expand(r#type)
+                    .map_err(ctx!("sweep values for parameter {:?}", key.as_str()))?;
+                Ok((key.clone(), values))
+            })
+            .collect::<Result<_>>()
+            .with_context(context)?;
+
+        for exclude in sweep_exclude.iter().flatten() {
+            for key in exclude.keys() {
+                if !sweep.contains_key(key) {
+                    return Err(anyhow!(
+                        "`sweep_exclude` refers to parameter {:?} which is not in `sweep`",
+                        key.as_str()
+                    ))
+                    .with_context(context);
+                }
+            }
+        }
+        let is_excluded = |combination: &BTreeMap<_, KString>| {
+            sweep_exclude.iter().flatten().any(|exclude| {
+                exclude
+                    .iter()
+                    .all(|(key, value)| combination.get(key) == Some(value))
+            })
+        };
+
+        let shared_sweep = if sweep.is_empty() {
+            None
+        } else {
+            Some(Arc::new(sweep.clone()))
+        };
+
+        let mut job_templates = Vec::new();
+        for combination in custom_parameters_matrix(&sweep) {
+            if is_excluded(&combination) {
+                continue;
+            }
+            let mut keyvals = custom_parameters.clone();
+            keyvals.extend(combination);
+            let custom_parameters =
+                CustomParameters::checked_from(&keyvals, &target.allowed_custom_parameters)
+                    .with_context(context)?;
+            job_templates.push(JobTemplate {
+                priority: *priority,
+                initial_boost: *initial_boost,
+                command: target.benchmarking_command.clone_arc(),
+                custom_parameters: custom_parameters.into(),
+                sweep: shared_sweep.clone(),
+            });
+        }
+        if job_templates.is_empty() {
+            return Err(anyhow!("all combinations of swept values are excluded"))
+                .with_context(context);
+        }
+        Ok(job_templates)
     }
 }
 
@@ -525,7 +836,7 @@ pub struct EvalSettings {
     pub show_thread_number: bool,
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
 #[serde(deny_unknown_fields)]
 #[serde(rename = "DaemonPaths")]
 pub struct DaemonPathsOpts {
@@ -536,20 +847,32 @@ pub struct DaemonPathsOpts {
 }
 
 impl DaemonPathsOpts {
+    /// Explicitly configured paths are shared by all projects, thus
+    /// for a project (`project` is `Some`), `projects/$name` is
+    /// appended to them (the defaults are already below the
+    /// project's `global_app_state_dir`).
     fn check(
         &self,
         global_app_state_dir: &GlobalAppStateDir,
         default_state_subdir: &str,
+        project: Option<&ProperDirname>,
     ) -> Result<DaemonPaths> {
         let DaemonPathsOpts { state_dir, log_dir } = self;
 
+        let for_project = |path: PathBuf| -> PathBuf {
+            if let Some(project) = project {
+                path.append("projects").append(project.as_str())
+            } else {
+                path
+            }
+        };
         let state_dir: Arc<Path> = if let Some(path) = state_dir {
-            path.resolve()?.into()
+            for_project(path.resolve()?).into()
         } else {
             global_app_state_dir.subdir(default_state_subdir)?.into()
         };
         let log_dir = if let Some(path) = log_dir {
-            path.resolve()?.into()
+            for_project(path.resolve()?).into()
         } else {
             (&state_dir).append("logs").into()
         };
@@ -569,20 +892,68 @@ pub struct OutputDirOpts {
     /// URL where the same path is served (optional). Used for
     /// `evobench list path url`.
     pub url: Option<Arc<str>>,
+
+    /// If given, the raw log files of old runs are deleted by the run
+    /// daemon periodically (or via `evobench-util prune`).
+    pub retention: Option<RetentionOpts>,
 }
 
 impl OutputDirOpts {
     fn resolve(&self) -> Result<OutputDir> {
-        let Self { path, url } = self;
+        let Self {
+            path,
+            url,
+            retention,
+        } = self;
         let path = path.resolve()?.into();
         let url = url.clone();
-        Ok(OutputDir { path, url })
+        let retention = retention.clone();
+        Ok(OutputDir {
+            path,
+            url,
+            retention,
+        })
     }
 }
 
 pub struct OutputDir {
     pub path: Arc<Path>,
     pub url: Option<Arc<str>>,
+    pub retention: Option<RetentionOpts>,
+}
+
+/// Which raw log files (`evobench.log.zstd`, `standard.log.zstd`,
+/// `bench_output.log.zstd`) of runs in the output directory to keep;
+/// those of runs not matching any of the criteria are deleted. The
+/// summaries (Excel and flame graph files, the results index) are
+/// kept forever, as are the stats cache files, which stand in for the
+/// deleted `evobench.log.zstd` files when summaries are regenerated
+/// (an `evobench.log.zstd` file without a valid stats cache holding
+/// all the entries needed for the summaries is kept).
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(rename = "Retention")]
+pub struct RetentionOpts {
+    /// Keep the raw logs of runs younger than this many days
+    pub raw_logs_days: u16,
+
+    /// Keep the raw logs of the runs for the last this many commits
+    /// per target and custom parameters (ordered by their most recent
+    /// run). Default: 0
+    pub raw_logs_last_commits: Option<usize>,
+
+    /// Keep the raw logs of the runs for commits that have a tag
+    /// matching `commit_tags_regex`. Default: true
+    pub keep_tagged: Option<bool>,
+
+    /// Remove the entries in the `latest` and `latest-redir`
+    /// directories that are older than this many days. Default:
+    /// keep them (`latest` entries pointing to directories that don't
+    /// exist anymore are always removed).
+    pub latest_days: Option<u16>,
+
+    /// How often the run daemon prunes, in hours. Default: 24
+    pub interval_hours: Option<u16>,
 }
 
 /// Direct representation of the evobench config file
@@ -633,13 +1004,19 @@ pub struct RunConfigOpts {
     /// The paths for the `evobench run daemon`. The defaults are
     /// `~/.evobench/run_jobs_daemon` for the `state_dir` and
     /// the `logs` subdir below that for `logs_dir`.  The paths
-    /// support `~/` notation.
+    /// support `~/` notation. For the projects in `projects`, the
+    /// defaults are below `~/.evobench/projects/$name/`, and
+    /// `projects/$name` is appended to explicitly given paths.
     run_jobs_daemon: DaemonPathsOpts,
 
     /// The same as above for the `evobench poll daemon`, just
     /// with the `polling_daemon` subdir as the default.
     polling_daemon: DaemonPathsOpts,
 
+    /// The same as above for the `evobench webhook serve daemon`,
+    /// with the `webhook_daemon` subdir as the default. Optional.
+    webhook_daemon: Option<DaemonPathsOpts>,
+
     /// Optional directory holding directories whose name is taken
     /// from the (optional, depending on the configuration) `DATASET`
     /// custom variable (hacky to mis-use a custom variable for
@@ -657,6 +1034,45 @@ pub struct RunConfigOpts {
     /// passed to the target in the `COMMIT_TAGS` env variable (as
     /// comma-separated strings). By default, all tags are passed.
     pub commit_tags_regex: Option<SerializableRegex>,
+
+    /// Additional named projects, selected via the `--project`
+    /// option of `evobench`. The settings above form the default
+    /// project, which is used when no `--project` option is
+    /// given. State files (including the default queues and working
+    /// directories, and daemon paths) of a project are kept below
+    /// `~/.evobench/projects/$name/`. All projects share one lock for
+    /// running jobs, thus only one job is run at a time on the
+    /// machine.
+    pub projects: Option<BTreeMap<ProperDirname, ProjectOpts>>,
+
+    /// If given, `evobench run --worker` runs jobs leased from the
+    /// coordinator configured here instead of jobs from the local
+    /// queues, and uploads the results to it.
+    pub worker: Option<Arc<WorkerOpts>>,
+}
+
+/// The settings that a project in `RunConfig.projects` has on its
+/// own; all other settings are taken from the top level of the
+/// `RunConfig`. For docs on the fields see there.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(rename = "Project")]
+pub struct ProjectOpts {
+    pub queues: Arc<QueuesConfig>,
+
+    /// If not given, the top-level setting is used, except that
+    /// `base_dir` is always the default (below the project's state
+    /// directory), since the working directories hold clones of the
+    /// project's repository.
+    pub working_directory_pool: Option<Arc<WorkingDirectoryPoolOpts>>,
+
+    pub targets: Vec<Arc<BenchmarkingTarget>>,
+
+    pub job_template_lists: BTreeMap<KString, Vec<JobTemplateOpts>>,
+
+    pub remote_repository: RemoteRepositoryOpts,
+
+    pub output_dir: OutputDirOpts,
 }
 
 #[derive(Debug)]
@@ -675,9 +1091,12 @@ impl DefaultConfigPath for RunConfigOpts {
 
 /// Checked, produced from `RunConfigOpts`, for docs see there.
 pub struct RunConfig {
+    /// The selected project (`None` for the default project)
+    pub project: Option<ProperDirname>,
     pub queues: Arc<QueuesConfig>,
     pub run_jobs_daemon: DaemonPaths,
     pub polling_daemon: DaemonPaths,
+    pub webhook_daemon: DaemonPaths,
     pub working_directory_pool: Arc<WorkingDirectoryPoolOpts>,
     pub target_pre_exec_bash_code: Option<Arc<str>>,
     // targets: BTreeMap<ProperDirname, Arc<BenchmarkingTarget>>,
@@ -689,17 +1108,45 @@ pub struct RunConfig {
     pub versioned_datasets_base_dir: Option<Arc<Path>>,
     pub targets: BTreeMap<ProperDirname, Arc<BenchmarkingTarget>>,
     pub commit_tags_regex: SerializableRegex,
+    pub worker: Option<Arc<WorkerOpts>>,
 }
 
 impl RunConfig {
     pub fn working_directory_change_signals_path(&self) -> PathBuf {
         (&self.run_jobs_daemon.state_dir).append("working_directory_change.signals")
     }
+
+    /// All job templates in the configuration, from
+    /// `job_template_lists` and from the polling settings in
+    /// `remote_repository` (may contain the same template multiple
+    /// times).
+    pub fn all_job_templates(&self) -> impl Iterator<Item = &JobTemplate> {
+        let RemoteRepository {
+            remote_branch_names_for_poll,
+            ref_patterns_for_poll,
+            ..
+        } = &self.remote_repository;
+        self.job_template_lists
+            .values()
+            .chain(remote_branch_names_for_poll.values())
+            .chain(
+                ref_patterns_for_poll
+                    .iter()
+                    .map(|(_, job_templates)| job_templates),
+            )
+            .flat_map(|job_templates| job_templates.iter())
+    }
 }
 
 impl RunConfigOpts {
-    /// Don't take ownership since RunConfigWithReload can't give it
-    pub fn check(&self, global_app_state_dir: &GlobalAppStateDir) -> Result<RunConfig> {
+    /// Don't take ownership since RunConfigWithReload can't give
+    /// it. `global_app_state_dir` must already be the one for
+    /// `project`, if given.
+    pub fn check(
+        &self,
+        global_app_state_dir: &GlobalAppStateDir,
+        project: Option<&ProperDirname>,
+    ) -> Result<RunConfig> {
         let RunConfigOpts {
             queues,
             working_directory_pool,
@@ -712,10 +1159,74 @@ impl RunConfigOpts {
             output_dir,
             run_jobs_daemon,
             polling_daemon,
+            webhook_daemon,
             versioned_datasets_base_dir,
             commit_tags_regex,
+            projects,
+            worker,
         } = self;
 
+        // Replace the project specific settings
+        let working_directory_pool_without_base_dir;
+        let (
+            queues,
+            working_directory_pool,
+            targets,
+            job_template_lists,
+            remote_repository,
+            output_dir,
+        ) = if let Some(project) = project {
+            let ProjectOpts {
+                queues,
+                working_directory_pool: project_working_directory_pool,
+                targets,
+                job_template_lists,
+                remote_repository,
+                output_dir,
+            } = projects
+                .as_ref()
+                .and_then(|projects| projects.get(project))
+                .ok_or_else(|| {
+                    let names: Vec<&str> = projects
+                        .iter()
+                        .flat_map(|projects| projects.keys())
+                        .map(|name| name.as_str())
+                        .collect();
+                    anyhow!(
+                        "project {:?} is not configured in the `projects` field, \
+                         the configured projects are: {names:?}",
+                        project.as_str()
+                    )
+                })?;
+            let working_directory_pool =
+                if let Some(working_directory_pool) = project_working_directory_pool {
+                    working_directory_pool
+                } else {
+                    working_directory_pool_without_base_dir = Arc::new(WorkingDirectoryPoolOpts {
+                        base_dir: None,
+                        ..(**working_directory_pool).clone()
+                    });
+                    &working_directory_pool_without_base_dir
+                };
+            (
+                queues,
+                working_directory_pool,
+                targets,
+                job_template_lists,
+                remote_repository,
+                output_dir,
+            )
+        } else {
+            (
+                queues,
+                working_directory_pool,
+                targets,
+                job_template_lists,
+                remote_repository,
+                output_dir,
+            )
+        };
+
         let targets: BTreeMap<ProperDirname, Arc<BenchmarkingTarget>> = {
             let mut seen = BTreeSet::new();
             targets
@@ -726,6 +1237,15 @@ impl RunConfigOpts {
                         bail!("duplicate `target_name` value {:?}", name.as_str())
                     }
                     seen.insert(name);
+                    for (key, allowed_custom_parameter) in
+                        &benchmarking_target.allowed_custom_parameters
+                    {
+                        allowed_custom_parameter.check().map_err(ctx!(
+                            "custom parameter {:?} of target {:?}",
+                            key.as_str(),
+                            name.as_str()
+                        ))?;
+                    }
                     Ok((name.clone(), benchmarking_target.clone_arc()))
                 })
                 .collect::<Result<_>>()?
@@ -741,7 +1261,10 @@ impl RunConfigOpts {
                             template_list
                                 .iter()
                                 .map(|job_template_opts| job_template_opts.check(&targets))
-                                .collect::<Result<_>>()?,
+                                .collect::<Result<Vec<_>>>()?
+                                .into_iter()
+                                .flatten()
+                                .collect(),
                         ))
                     },
                 )
@@ -750,6 +1273,10 @@ impl RunConfigOpts {
 
         let remote_repository = remote_repository.check(&job_template_lists, &targets)?;
 
+        if let Some(worker) = worker {
+            worker.check()?;
+        }
+
         let commit_tags_regex: SerializableRegex =
             if let Some(commit_tags_regex) = commit_tags_regex {
                 (*commit_tags_regex).clone()
@@ -758,6 +1285,7 @@ impl RunConfigOpts {
             };
 
         Ok(RunConfig {
+            project: project.cloned(),
             queues: queues.clone_arc(),
             working_directory_pool: working_directory_pool.clone_arc(),
             target_pre_exec_bash_code: target_pre_exec_bash_code.clone(),
@@ -773,8 +1301,21 @@ impl RunConfigOpts {
                 .transpose()?
                 .map(Arc::<Path>::from),
             commit_tags_regex,
-            run_jobs_daemon: run_jobs_daemon.check(global_app_state_dir, "run_jobs_daemon")?,
-            polling_daemon: polling_daemon.check(global_app_state_dir, "polling_daemon")?,
+            worker: worker.clone(),
+            run_jobs_daemon: run_jobs_daemon.check(
+                global_app_state_dir,
+                "run_jobs_daemon",
+                project,
+            )?,
+            polling_daemon: polling_daemon.check(
+                global_app_state_dir,
+                "polling_daemon",
+                project,
+            )?,
+            webhook_daemon: webhook_daemon
+                .as_ref()
+                .unwrap_or(&DaemonPathsOpts::default())
+                .check(global_app_state_dir, "webhook_daemon", project)?,
         })
     }
 }
@@ -796,16 +1337,26 @@ pub struct RunConfigBundle {
 }
 
 impl RunConfigBundle {
+    /// `project` selects one of the projects configured in
+    /// `RunConfig.projects`, `None` means the default project.
     pub fn load(
         provided_path: Option<Arc<Path>>,
         or_else: impl FnOnce(&str) -> Result<RunConfigOpts>,
         global_app_state_dir: GlobalAppStateDir,
+        project: Option<ProperDirname>,
     ) -> Result<Self> {
         let config_file = Arc::new(ConfigFile::<RunConfigOpts>::load_config(
             provided_path,
             or_else,
         )?);
-        let run_config = config_file.check(&global_app_state_dir)?.into();
+        let global_app_state_dir = if let Some(project) = &project {
+            global_app_state_dir.for_project(project)?
+        } else {
+            global_app_state_dir
+        };
+        let run_config = config_file
+            .check(&global_app_state_dir, project.as_ref())?
+            .into();
         Ok(Self {
             config_file,
             shareable: ShareableConfig {
@@ -815,3 +1366,246 @@ impl RunConfigBundle {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_sweep_values_expand() -> Result<()> {
+        let strs = |values: Vec<KString>| -> Vec<String> {
+            values.into_iter().map(|s| s.to_string()).collect()
+        };
+        let range = |start, end, step| SweepValuesOpts::Range {
+            start,
+            end,
+            step: NonZeroU32::new(step),
+        };
+        assert_eq!(
+            strs(range(1, 16, 4).expand(Some(&AllowedCustomParameterType::U32))?),
+            ["1", "5", "9", "13"]
+        );
+        assert_eq!(strs(range(3, 5, 0).expand(None)?), ["3", "4", "5"]);
+        assert_eq!(strs(range(7, 7, 1).expand(None)?), ["7"]);
+        assert!(range(8, 7, 1).expand(None).is_err());
+        assert!(
+            range(1, 2, 1)
+                .expand(Some(&AllowedCustomParameterType::String))
+                .is_err()
+        );
+        assert!(SweepValuesOpts::Values(vec![]).expand(None).is_err());
+        Ok(())
+    }
+
+    fn sweep_test_targets() -> BTreeMap<ProperDirname, Arc<BenchmarkingTarget>> {
+        let target_name: ProperDirname = "bench".parse().expect("valid name");
+        let allowed = |r#type| AllowedCustomParameter {
+            required: false,
+            r#type,
+            default: None,
+        };
+        let target = BenchmarkingTarget {
+            benchmarking_command: Arc::new(BenchmarkingCommand {
+                target_name: target_name.clone(),
+                subdir: ".".into(),
+                command: "make".into(),
+                arguments: vec!["bench".into()],
+                pre_exec_bash_code: PreExecLevel2::new(None),
+            }),
+            allowed_custom_parameters: [
+                ("CONCURRENCY", allowed(AllowedCustomParameterType::U32)),
+                (
+                    "DATASET",
+                    allowed(AllowedCustomParameterType::Enum {
+                        values: vec!["small".into(), "large".into()],
+                    }),
+                ),
+                ("MODE", allowed(AllowedCustomParameterType::String)),
+            ]
+            .into_iter()
+            .map(|(key, allowed)| (key.parse().unwrap(), allowed))
+            .collect(),
+            log_extracts: None,
+        };
+        [(target_name, Arc::new(target))].into()
+    }
+
+    fn keyvals(
+        keyvals: &[(&str, &str)],
+    ) -> BTreeMap<AllowedEnvVar<AllowableCustomEnvVar>, KString> {
+        keyvals
+            .iter()
+            .map(|(key, value)| (key.parse().unwrap(), KString::from_ref(value)))
+            .collect()
+    }
+
+    fn sweep_test_template(
+        sweep_exclude: Option<Vec<BTreeMap<AllowedEnvVar<AllowableCustomEnvVar>, KString>>>,
+    ) -> JobTemplateOpts {
+        JobTemplateOpts {
+            priority: Priority::NORMAL,
+            initial_boost: Priority::NORMAL,
+            target_name: "bench".parse().expect("valid name"),
+            custom_parameters: keyvals(&[("MODE", "x")]),
+            sweep: Some(
+                [
+                    (
+                        "CONCURRENCY".parse().unwrap(),
+                        SweepValuesOpts::Range {
+                            start: 1,
+                            end: 4,
+                            step: NonZeroU32::new(2),
+                        },
+                    ),
+                    (
+                        "DATASET".parse().unwrap(),
+                        SweepValuesOpts::Values(vec!["small".into(), "large".into()]),
+                    ),
+                ]
+                .into(),
+            ),
+            sweep_exclude,
+        }
+    }
+
+    fn custom_parameters_strings(job_templates: &[JobTemplate]) -> Vec<String> {
+        job_templates
+            .iter()
+            .map(|job_template| job_template.custom_parameters.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn t_job_template_check_sweep() -> Result<()> {
+        let targets = sweep_test_targets();
+
+        // The cartesian product, the last parameter varying fastest
+        let job_templates = sweep_test_template(None).check(&targets)?;
+        assert_eq!(
+            custom_parameters_strings(&job_templates),
+            [
+                "CONCURRENCY=1,DATASET=small,MODE=x",
+                "CONCURRENCY=1,DATASET=large,MODE=x",
+                "CONCURRENCY=3,DATASET=small,MODE=x",
+                "CONCURRENCY=3,DATASET=large,MODE=x",
+            ]
+        );
+        let sweep = job_templates[0].sweep.as_ref().expect("swept");
+        assert_eq!(
+            **sweep,
+            BTreeMap::from([
+                ("CONCURRENCY".parse()?, vec!["1".into(), "3".into()]),
+                ("DATASET".parse()?, vec!["small".into(), "large".into()]),
+            ])
+        );
+        assert!(
+            job_templates
+                .iter()
+                .all(|job_template| job_template.sweep.as_ref() == Some(sweep))
+        );
+
+        // Without a sweep
+        let mut opts = sweep_test_template(None);
+        opts.sweep = None;
+        let job_templates = opts.check(&targets)?;
+        assert_eq!(custom_parameters_strings(&job_templates), ["MODE=x"]);
+        assert!(job_templates[0].sweep.is_none());
+
+        // Given both in `custom_parameters` and `sweep`
+        let mut opts = sweep_test_template(None);
+        opts.custom_parameters = keyvals(&[("MODE", "x"), ("DATASET", "small")]);
+        assert!(opts.check(&targets).is_err());
+
+        // Swept values are checked against the parameter types
+        let mut opts = sweep_test_template(None);
+        opts.sweep.as_mut().expect("swept").insert(
+            "DATASET".parse()?,
+            SweepValuesOpts::Values(vec!["small".into(), "medium".into()]),
+        );
+        assert!(opts.check(&targets).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn t_job_template_check_sweep_exclude() -> Result<()> {
+        let targets = sweep_test_targets();
+
+        // A combination is skipped if it has all pairs of an entry
+        let job_templates = sweep_test_template(Some(vec![
+            keyvals(&[("CONCURRENCY", "3"), ("DATASET", "large")]),
+            keyvals(&[("DATASET", "small"), ("CONCURRENCY", "1")]),
+        ]))
+        .check(&targets)?;
+        assert_eq!(
+            custom_parameters_strings(&job_templates),
+            [
+                "CONCURRENCY=1,DATASET=large,MODE=x",
+                "CONCURRENCY=3,DATASET=small,MODE=x",
+            ]
+        );
+
+        // Entries with fewer parameters exclude more combinations
+        let job_templates =
+            sweep_test_template(Some(vec![keyvals(&[("DATASET", "small")])])).check(&targets)?;
+        assert_eq!(
+            custom_parameters_strings(&job_templates),
+            [
+                "CONCURRENCY=1,DATASET=large,MODE=x",
+                "CONCURRENCY=3,DATASET=large,MODE=x",
+            ]
+        );
+
+        // Values that are not swept over exclude nothing
+        let job_templates =
+            sweep_test_template(Some(vec![keyvals(&[("CONCURRENCY", "2")])])).check(&targets)?;
+        assert_eq!(job_templates.len(), 4);
+
+        // Parameters that are not swept over
+        assert!(
+            sweep_test_template(Some(vec![keyvals(&[("MODE", "x")])]))
+                .check(&targets)
+                .is_err()
+        );
+
+        // Excluding everything
+        assert!(
+            sweep_test_template(Some(vec![
+                keyvals(&[("DATASET", "small")]),
+                keyvals(&[("DATASET", "large")]),
+            ]))
+            .check(&targets)
+            .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn t_daemon_paths_for_project() -> Result<()> {
+        let test_dir = crate::utillib::test_dir::TestDir::new("daemon-paths");
+        let root_dir = test_dir.path().to_owned();
+        let project: ProperDirname = "foo".parse().expect("valid name");
+        let main_state_dir = GlobalAppStateDir::with_root_dir(root_dir.clone())?;
+        let project_state_dir = main_state_dir.for_project(&project)?;
+
+        // Defaults
+        let paths = DaemonPathsOpts::default().check(&project_state_dir, "d", Some(&project))?;
+        assert_eq!(&*paths.state_dir, root_dir.join("projects/foo/d"));
+        assert_eq!(&*paths.log_dir, root_dir.join("projects/foo/d/logs"));
+
+        // Explicitly configured paths
+        let opts = DaemonPathsOpts {
+            state_dir: Some("/var/evobench/state".parse()?),
+            log_dir: Some("/var/log/evobench".parse()?),
+        };
+        let paths = opts.check(&main_state_dir, "d", None)?;
+        assert_eq!(&*paths.state_dir, Path::new("/var/evobench/state"));
+        assert_eq!(&*paths.log_dir, Path::new("/var/log/evobench"));
+        let paths = opts.check(&project_state_dir, "d", Some(&project))?;
+        assert_eq!(
+            &*paths.state_dir,
+            Path::new("/var/evobench/state/projects/foo")
+        );
+        assert_eq!(&*paths.log_dir, Path::new("/var/log/evobench/projects/foo"));
+        Ok(())
+    }
+}
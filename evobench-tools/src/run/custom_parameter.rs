@@ -1,13 +1,15 @@
-use std::{ffi::OsStr, num::NonZeroU32, str::FromStr};
+use std::{ffi::OsStr, fmt::Display, num::NonZeroU32, str::FromStr};
 
 use anyhow::{Result, anyhow, bail};
+use itertools::Itertools;
 use kstring::KString;
 
 use crate::{
+    ctx,
     run::env_vars::AllowableCustomEnvVar,
     serde_types::{
         allowed_env_var::AllowEnvVar, proper_dirname::ProperDirname,
-        proper_filename::ProperFilename,
+        proper_filename::ProperFilename, regex::SerializableRegex,
     },
 };
 
@@ -26,11 +28,185 @@ pub enum CustomParameterType {
     U32,
 }
 
+/// The type of a custom parameter as declared in the configuration:
+/// either one of the plain value types of `CustomParameterType`, or
+/// a value type with additional constraints on the values.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum AllowedCustomParameterType {
+    String,
+    Filename,
+    Dirname,
+    Bool,
+    NonZeroU32,
+    U32,
+    /// One of the given strings
+    Enum {
+        values: Vec<KString>,
+    },
+    /// An integer from `min` to `max` (inclusive)
+    U32Range {
+        min: u32,
+        max: u32,
+    },
+    /// A string matching the given regular expression (use `^` and
+    /// `$` to require the whole value to match)
+    Regex {
+        regex: SerializableRegex,
+    },
+}
+
+impl AllowedCustomParameterType {
+    /// The type the values are stored with
+    pub fn value_type(&self) -> CustomParameterType {
+        match self {
+            AllowedCustomParameterType::String => CustomParameterType::String,
+            AllowedCustomParameterType::Filename => CustomParameterType::Filename,
+            AllowedCustomParameterType::Dirname => CustomParameterType::Dirname,
+            AllowedCustomParameterType::Bool => CustomParameterType::Bool,
+            AllowedCustomParameterType::NonZeroU32 => CustomParameterType::NonZeroU32,
+            AllowedCustomParameterType::U32 => CustomParameterType::U32,
+            AllowedCustomParameterType::Enum { .. } => CustomParameterType::String,
+            AllowedCustomParameterType::U32Range { .. } => CustomParameterType::U32,
+            AllowedCustomParameterType::Regex { .. } => CustomParameterType::String,
+        }
+    }
+
+    /// Whether the values are integers
+    pub fn is_integer(&self) -> bool {
+        match self.value_type() {
+            CustomParameterType::NonZeroU32 | CustomParameterType::U32 => true,
+            CustomParameterType::String
+            | CustomParameterType::Filename
+            | CustomParameterType::Dirname
+            | CustomParameterType::Bool => false,
+        }
+    }
+
+    /// Check the declaration itself
+    fn check(&self) -> Result<()> {
+        match self {
+            AllowedCustomParameterType::Enum { values } => {
+                if values.is_empty() {
+                    bail!("`Enum` needs at least one value")
+                }
+                for (i, value) in values.iter().enumerate() {
+                    if values[..i].contains(value) {
+                        bail!("duplicate `Enum` value {:?}", value.as_str())
+                    }
+                    CustomParameterValue::checked_from(self.value_type(), value)
+                        .map_err(ctx!("`Enum` value {:?}", value.as_str()))?;
+                }
+            }
+            AllowedCustomParameterType::U32Range { min, max } => {
+                if min > max {
+                    bail!("`U32Range` min {min} is larger than max {max}")
+                }
+            }
+            AllowedCustomParameterType::String
+            | AllowedCustomParameterType::Filename
+            | AllowedCustomParameterType::Dirname
+            | AllowedCustomParameterType::Bool
+            | AllowedCustomParameterType::NonZeroU32
+            | AllowedCustomParameterType::U32
+            | AllowedCustomParameterType::Regex { .. } => (),
+        }
+        Ok(())
+    }
+
+    pub fn checked_value(&self, value: &KString) -> Result<CustomParameterValue> {
+        let checked_value = CustomParameterValue::checked_from(self.value_type(), value)?;
+        match self {
+            AllowedCustomParameterType::Enum { values } => {
+                if !values.contains(value) {
+                    bail!(
+                        "invalid value {:?}, expecting one of: {}",
+                        value.as_str(),
+                        values
+                            .iter()
+                            .map(|v| format!("{:?}", v.as_str()))
+                            .join(", ")
+                    )
+                }
+            }
+            AllowedCustomParameterType::U32Range { min, max } => {
+                let n = u32::from_str(value).expect("checked by value type");
+                if !(*min..=*max).contains(&n) {
+                    bail!("value {n} is outside the allowed range {min}..={max}")
+                }
+            }
+            AllowedCustomParameterType::Regex { regex } => {
+                if !regex.is_match(value) {
+                    bail!(
+                        "value {:?} does not match the regex {regex}",
+                        value.as_str()
+                    )
+                }
+            }
+            AllowedCustomParameterType::String
+            | AllowedCustomParameterType::Filename
+            | AllowedCustomParameterType::Dirname
+            | AllowedCustomParameterType::Bool
+            | AllowedCustomParameterType::NonZeroU32
+            | AllowedCustomParameterType::U32 => (),
+        }
+        Ok(checked_value)
+    }
+}
+
+impl Display for AllowedCustomParameterType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AllowedCustomParameterType::Enum { values } => {
+                write!(
+                    f,
+                    "one of {}",
+                    values
+                        .iter()
+                        .map(|v| format!("{:?}", v.as_str()))
+                        .join(", ")
+                )
+            }
+            AllowedCustomParameterType::U32Range { min, max } => {
+                write!(f, "integer from {min} to {max}")
+            }
+            AllowedCustomParameterType::Regex { regex } => write!(f, "string matching {regex}"),
+            AllowedCustomParameterType::String
+            | AllowedCustomParameterType::Filename
+            | AllowedCustomParameterType::Dirname
+            | AllowedCustomParameterType::Bool
+            | AllowedCustomParameterType::NonZeroU32
+            | AllowedCustomParameterType::U32 => write!(f, "{:?}", self.value_type()),
+        }
+    }
+}
+
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct AllowedCustomParameter {
     pub required: bool,
-    pub r#type: CustomParameterType,
+    pub r#type: AllowedCustomParameterType,
+    /// The value to use when the parameter is not given (thus it is
+    /// never missing, regardless of `required`)
+    pub default: Option<KString>,
+}
+
+impl AllowedCustomParameter {
+    /// Check the declaration, including the default value, if any
+    pub fn check(&self) -> Result<()> {
+        let Self {
+            required: _,
+            r#type,
+            default,
+        } = self;
+        r#type.check()?;
+        if let Some(default) = default {
+            r#type
+                .checked_value(default)
+                .map_err(ctx!("checking `default` value"))?;
+        }
+        Ok(())
+    }
 }
 
 /// A checked value
@@ -104,3 +280,47 @@ impl AsRef<str> for CustomParameterValue {
         self.as_str().as_ref()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_checked_value() -> Result<()> {
+        let check = |r#type: &AllowedCustomParameterType, value: &str| {
+            r#type.checked_value(&KString::from_ref(value)).is_ok()
+        };
+
+        let enum_type = AllowedCustomParameterType::Enum {
+            values: vec!["SC2open".into(), "small".into()],
+        };
+        assert!(check(&enum_type, "SC2open"));
+        assert!(!check(&enum_type, "SC2opne"));
+
+        let range_type = AllowedCustomParameterType::U32Range { min: 1, max: 64 };
+        assert!(check(&range_type, "1"));
+        assert!(check(&range_type, "64"));
+        assert!(!check(&range_type, "0"));
+        assert!(!check(&range_type, "65"));
+        assert!(!check(&range_type, "x"));
+        assert!(
+            AllowedCustomParameterType::U32Range { min: 2, max: 1 }
+                .check()
+                .is_err()
+        );
+
+        let regex_type = AllowedCustomParameterType::Regex {
+            regex: "^q[0-9]+$".parse()?,
+        };
+        assert!(check(&regex_type, "q12"));
+        assert!(!check(&regex_type, "q12a"));
+
+        let allowed = AllowedCustomParameter {
+            required: false,
+            r#type: enum_type,
+            default: Some("large".into()),
+        };
+        assert!(allowed.check().is_err());
+        Ok(())
+    }
+}
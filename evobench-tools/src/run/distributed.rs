@@ -0,0 +1,831 @@
+//! Distributing jobs to several benchmarking machines: the
+//! installation holding the queues (the "coordinator") leases jobs
+//! to "workers", which run them and upload the resulting run
+//! directory back into the coordinator's output tree.
+//!
+//! The protocol is simply invoking `evobench coordinator ...` on the
+//! coordinator via the `coordinator_command` configured on the
+//! worker (typically via `ssh`): `lease` prints the leased job as
+//! RON to stdout, `complete` receives the run directory as a tar
+//! stream on stdin. Leases are kept as a `KeyVal` database in the
+//! queues base directory; leased queue entries are skipped by the
+//! local scheduler, and expired leases (e.g. from a worker that
+//! crashed) make their entry available again.
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::Read,
+    path::Path,
+    process::{Command, Stdio},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Result, anyhow, bail};
+use rand::Rng;
+
+use crate::{
+    config_file::ron_to_string_pretty,
+    ctx, info,
+    io_utils::lockable_file::ExclusiveFileLock,
+    key_val_fs::{
+        as_key::AsKey,
+        key_val::{KeyVal, KeyValConfig, KeyValSync},
+        queue::{QueueGetItemOptions, TimeKey},
+    },
+    serde_types::{date_and_time::DateTimeWithOffset, proper_filename::ProperFilename},
+    utillib::{
+        arc::CloneArc,
+        logging::{LogLevel, log_level},
+    },
+    warn,
+};
+
+use super::{
+    benchmarking_job::BenchmarkingJob,
+    config::{RunConfig, ScheduleCondition},
+    output_directory::structure::{RunDir, ToPath},
+    run_job::{JobRunner, JobRunnerJobData, JobRunnerWithJob},
+    run_queue::JobStatus,
+    run_queues::{RunQueues, RunQueuesData},
+};
+
+/// Name of the subdirectory of the queues base directory holding the
+/// leases (leading dot so that it isn't confused with a queue)
+const LEASES_DIR_NAME: &str = ".leases";
+
+/// The file in a `RunDir` recording the host class of the worker
+/// that produced it (absent for runs done by the coordinator itself)
+pub const HOST_CLASS_FILE_NAME: &str = "host_class.ron";
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(rename = "Coordinator")]
+pub struct CoordinatorOpts {
+    /// The workers that are allowed to lease jobs (by the `name`
+    /// they use in their `Worker` config), with their host
+    /// class. Results are summarized per host class, thus workers
+    /// with identical hardware and setup should share a class, and
+    /// differing ones must not.
+    pub workers: BTreeMap<ProperFilename, ProperFilename>,
+
+    /// After how many hours a lease expires, making the job
+    /// available again (e.g. because the worker has crashed). Must
+    /// be longer than the longest run of a job, or jobs may be run
+    /// twice.
+    pub lease_timeout_hours: f64,
+}
+
+impl CoordinatorOpts {
+    pub fn check(&self) -> Result<()> {
+        let Self {
+            workers,
+            lease_timeout_hours,
+        } = self;
+        if workers.is_empty() {
+            bail!("`Coordinator.workers` must list at least one worker")
+        }
+        if !(*lease_timeout_hours > 0.) {
+            bail!("`Coordinator.lease_timeout_hours` must be > 0, got {lease_timeout_hours}")
+        }
+        Ok(())
+    }
+
+    fn lease_timeout(&self) -> Duration {
+        Duration::from_secs_f64(self.lease_timeout_hours * 3600.)
+    }
+
+    pub fn host_class_of(&self, worker: &ProperFilename) -> Result<&ProperFilename> {
+        self.workers.get(worker).ok_or_else(|| {
+            anyhow!(
+                "worker {:?} is not registered in `Coordinator.workers`",
+                worker.as_str()
+            )
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(rename = "Worker")]
+pub struct WorkerOpts {
+    /// The name under which this machine is registered in the
+    /// `workers` field of the coordinator's `Coordinator` settings.
+    pub name: ProperFilename,
+
+    /// The program and arguments to invoke `evobench` on the
+    /// coordinator, the `coordinator ...` arguments are appended to
+    /// it. E.g. `["ssh", "bench@queuehost", "evobench"]`, or
+    /// `["env", "HOME=/home/me", "evobench"]` to test with workers on
+    /// the same machine (each worker running with its own
+    /// `HOME`). Add `--project` here if the coordinator's queues
+    /// belong to a project.
+    pub coordinator_command: Vec<String>,
+}
+
+impl WorkerOpts {
+    pub fn check(&self) -> Result<()> {
+        if self.coordinator_command.is_empty() {
+            bail!("`Worker.coordinator_command` must at least contain a program name/path")
+        }
+        Ok(())
+    }
+
+    /// A `Command` invoking `evobench coordinator $args` on the
+    /// coordinator
+    fn coordinator_command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(&self.coordinator_command[0]);
+        command
+            .args(&self.coordinator_command[1..])
+            .arg("coordinator")
+            .args(args);
+        command
+    }
+}
+
+/// Identifies a lease (random hex string, hard to guess so that
+/// workers can't complete leases of other workers by accident)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct LeaseId(String);
+
+impl LeaseId {
+    fn new_random() -> Self {
+        let mut rng = rand::thread_rng();
+        let n: u128 = rng.r#gen();
+        Self(format!("{n:032x}"))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for LeaseId {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() == 32 && s.chars().all(|c| c.is_ascii_hexdigit()) {
+            Ok(Self(s.into()))
+        } else {
+            Err("a lease id consists of 32 hexadecimal digits")
+        }
+    }
+}
+
+impl AsKey for LeaseId {
+    fn as_filename_str(&self) -> Cow<'_, str> {
+        (&self.0).into()
+    }
+
+    fn try_from_filename_str(file_name: &str) -> Option<Self> {
+        file_name.parse().ok()
+    }
+}
+
+/// The record of a lease, as kept by the coordinator
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Lease {
+    pub worker: ProperFilename,
+    pub host_class: ProperFilename,
+    /// The queue holding the leased entry
+    pub queue_name: ProperFilename,
+    /// The key of the leased entry in that queue (as file name)
+    pub queue_key: String,
+    /// A copy of the leased job, so that results can be stored even
+    /// if the entry disappeared from the queue in the mean time
+    /// (e.g. moved on by `move_when_time_window_ends`)
+    pub job: BenchmarkingJob,
+    /// The timestamp of the run, i.e. the name of the run directory
+    pub timestamp: DateTimeWithOffset,
+    pub leased_at: SystemTime,
+}
+
+impl Lease {
+    pub fn queue_key(&self) -> Result<TimeKey> {
+        TimeKey::try_from_filename_str(&self.queue_key)
+            .ok_or_else(|| anyhow!("invalid queue key in lease: {:?}", self.queue_key))
+    }
+
+    pub fn is_expired(&self, opts: &CoordinatorOpts, now: SystemTime) -> bool {
+        now.duration_since(self.leased_at).unwrap_or(Duration::ZERO) > opts.lease_timeout()
+    }
+}
+
+/// What a worker receives from `evobench coordinator lease`
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LeaseGrant {
+    pub lease_id: LeaseId,
+    pub job: BenchmarkingJob,
+    /// The schedule condition of the queue the job was leased from,
+    /// stored with the results as for local runs
+    pub schedule_condition: ScheduleCondition,
+    /// The timestamp to use for the run
+    pub timestamp: DateTimeWithOffset,
+}
+
+/// Access to the persistent leases
+#[derive(Debug)]
+pub struct Leases {
+    key_val: KeyVal<LeaseId, Lease>,
+}
+
+impl Leases {
+    pub fn open(run_queues_basedir: &Path, create_dir_if_not_exists: bool) -> Result<Self> {
+        let key_val = KeyVal::open(
+            run_queues_basedir.join(LEASES_DIR_NAME),
+            KeyValConfig {
+                sync: KeyValSync::All,
+                create_dir_if_not_exists,
+            },
+            None,
+        )?;
+        Ok(Self { key_val })
+    }
+
+    /// Taken while granting leases
+    pub fn lock_exclusive(&self) -> Result<ExclusiveFileLock<'_, File>> {
+        Ok(self.key_val.lock_exclusive()?)
+    }
+
+    /// All leases, including expired ones
+    pub fn all(&self) -> Result<Vec<(LeaseId, Lease)>> {
+        let mut leases = Vec::new();
+        for id in self.key_val.keys(false, None)? {
+            let id = id?;
+            // Entries can disappear concurrently; just skip those.
+            if let Some(lease) = self.key_val.get(&id)? {
+                leases.push((id, lease));
+            }
+        }
+        Ok(leases)
+    }
+
+    pub fn get(&self, id: &LeaseId) -> Result<Option<Lease>> {
+        Ok(self.key_val.get(id)?)
+    }
+
+    /// The lease `id`, which must exist and have been granted to
+    /// `worker`
+    pub fn get_held_by(&self, id: &LeaseId, worker: &ProperFilename) -> Result<Lease> {
+        let lease = self.get(id)?.ok_or_else(|| {
+            anyhow!(
+                "unknown lease {} (has it expired and been leased again?)",
+                id.as_str()
+            )
+        })?;
+        if lease.worker != *worker {
+            bail!(
+                "lease {} is held by worker {:?}, not {:?}",
+                id.as_str(),
+                lease.worker.as_str(),
+                worker.as_str()
+            )
+        }
+        Ok(lease)
+    }
+
+    pub fn delete(&self, id: &LeaseId) -> Result<bool> {
+        Ok(self.key_val.delete(id)?)
+    }
+
+    /// The keys of the queue entries covered by unexpired leases, by
+    /// queue name
+    pub fn leased_entries(
+        &self,
+        opts: &CoordinatorOpts,
+        now: SystemTime,
+    ) -> Result<BTreeMap<ProperFilename, BTreeSet<TimeKey>>> {
+        let mut entries: BTreeMap<ProperFilename, BTreeSet<TimeKey>> = BTreeMap::new();
+        for (_id, lease) in self.all()? {
+            if !lease.is_expired(opts, now) {
+                entries
+                    .entry(lease.queue_name.clone())
+                    .or_default()
+                    .insert(lease.queue_key()?);
+            }
+        }
+        Ok(entries)
+    }
+}
+
+fn coordinator_opts_and_leases(queues: &RunQueues) -> Result<(&CoordinatorOpts, &Leases)> {
+    match (&queues.borrow_config().coordinator, queues.leases()) {
+        (Some(opts), Some(leases)) => Ok((opts, leases)),
+        _ => bail!("this installation is not configured as a coordinator (`queues.coordinator`)"),
+    }
+}
+
+/// Lease the most prioritized runnable job to `worker`, if any.
+pub fn lease_job(queues: &RunQueues, worker: &ProperFilename) -> Result<Option<LeaseGrant>> {
+    let (opts, leases) = coordinator_opts_and_leases(queues)?;
+    let host_class = opts.host_class_of(worker)?;
+
+    let _lock = leases.lock_exclusive()?;
+    let now = SystemTime::now();
+    for (id, lease) in leases.all()? {
+        if lease.is_expired(opts, now) {
+            warn!(
+                "lease {} of worker {:?} has expired, making its job available again",
+                id.as_str(),
+                lease.worker.as_str()
+            );
+            leases.delete(&id)?;
+        }
+    }
+
+    // Load after taking the lock, so that the leased entries are
+    // up to date
+    let queues_data = queues.data()?;
+    let Some((run_queue, key, job)) = queues_data.next_job_for_lease(now.into())? else {
+        return Ok(None);
+    };
+
+    let lease_id = LeaseId::new_random();
+    let timestamp = DateTimeWithOffset::now(None);
+    leases.key_val.insert(
+        &lease_id,
+        &Lease {
+            worker: worker.clone(),
+            host_class: host_class.clone(),
+            queue_name: run_queue.file_name.clone(),
+            queue_key: key.as_filename_str().into_owned(),
+            job: job.clone(),
+            timestamp: timestamp.clone(),
+            leased_at: now,
+        },
+        true,
+    )?;
+    info!(
+        "leased entry {key} in queue {:?} to worker {:?} as {}",
+        run_queue.file_name.as_str(),
+        worker.as_str(),
+        lease_id.as_str()
+    );
+
+    Ok(Some(LeaseGrant {
+        lease_id,
+        job: job.clone(),
+        schedule_condition: run_queue.schedule_condition.clone(),
+        timestamp,
+    }))
+}
+
+/// Check a member name as listed by `tar -t`: it must be relative
+/// and must not contain `..` components.
+fn check_tar_member_name(name: &str) -> Result<()> {
+    if name.starts_with('/') {
+        bail!("run dir upload contains an absolute path: {name:?}")
+    }
+    if name.split('/').any(|component| component == "..") {
+        bail!("run dir upload contains a path with `..`: {name:?}")
+    }
+    Ok(())
+}
+
+/// Reject anything below `dir` that is neither a plain file nor a
+/// directory (symlinks in particular).
+fn check_files_and_dirs_only(dir: &Path) -> Result<()> {
+    for entry in std::fs::read_dir(dir).map_err(ctx!("opening dir {dir:?}"))? {
+        let entry = entry.map_err(ctx!("reading dir {dir:?}"))?;
+        let path = entry.path();
+        // (`file_type` does not follow symlinks)
+        let file_type = entry
+            .file_type()
+            .map_err(ctx!("getting type of {path:?}"))?;
+        if file_type.is_dir() {
+            check_files_and_dirs_only(&path)?;
+        } else if !file_type.is_file() {
+            bail!("run dir upload contains {path:?}, which is neither a file nor a directory")
+        }
+    }
+    Ok(())
+}
+
+/// Unpack the tar stream from `input` as the run directory for
+/// `lease`, and record the host class in it. The stream is saved to
+/// a file and its listing checked before unpacking, and the unpacked
+/// files are checked before they are moved into place, since the
+/// worker is not trusted to write outside the run directory.
+fn receive_run_dir(
+    output_base_dir: &Arc<Path>,
+    lease: &Lease,
+    mut input: impl Read,
+) -> Result<RunDir> {
+    let key_dir = lease
+        .job
+        .benchmarking_job_parameters()
+        .to_key_dir(output_base_dir.clone_arc());
+    let run_dir = key_dir.clone_arc().append_subdir(lease.timestamp.clone());
+    let path = run_dir.to_path();
+    if path.exists() {
+        bail!("run directory {path:?} already exists")
+    }
+
+    // Use names that do not parse as run directories, so that
+    // incomplete uploads are never taken as results.
+    let key_dir_path = key_dir.to_path();
+    std::fs::create_dir_all(key_dir_path).map_err(ctx!("create_dir_all {key_dir_path:?}"))?;
+    let tar_path = key_dir_path.join(format!("{}.incoming.tar", lease.timestamp));
+    let tmp_path = key_dir_path.join(format!("{}.incoming", lease.timestamp));
+    if tmp_path.exists() {
+        // Left over from an interrupted upload
+        std::fs::remove_dir_all(&tmp_path).map_err(ctx!("removing {tmp_path:?}"))?;
+    }
+
+    let result = (|| -> Result<()> {
+        {
+            let mut out = File::create(&tar_path).map_err(ctx!("creating {tar_path:?}"))?;
+            std::io::copy(&mut input, &mut out).map_err(ctx!("writing {tar_path:?}"))?;
+        }
+
+        let mut command = Command::new("tar");
+        command.arg("-tf").arg(&tar_path).stderr(Stdio::inherit());
+        let output = command
+            .output()
+            .map_err(ctx!("running command {command:?}"))?;
+        if !output.status.success() {
+            bail!("command {command:?} failed: {}", output.status)
+        }
+        let listing =
+            String::from_utf8(output.stdout).map_err(ctx!("decoding output of {command:?}"))?;
+        for name in listing.lines() {
+            check_tar_member_name(name)?;
+        }
+
+        std::fs::create_dir(&tmp_path).map_err(ctx!("creating dir {tmp_path:?}"))?;
+        let mut command = Command::new("tar");
+        command
+            .arg("--no-same-owner")
+            .arg("-xf")
+            .arg(&tar_path)
+            .arg("-C")
+            .arg(&tmp_path);
+        let status = command
+            .status()
+            .map_err(ctx!("running command {command:?}"))?;
+        if !status.success() {
+            bail!("command {command:?} failed: {status}")
+        }
+        check_files_and_dirs_only(&tmp_path)?;
+
+        std::fs::rename(&tmp_path, path).map_err(ctx!("renaming {tmp_path:?} to {path:?}"))?;
+        Ok(())
+    })();
+    // (Either may not exist, depending on where it failed)
+    let _ = std::fs::remove_file(&tar_path);
+    if result.is_err() {
+        let _ = std::fs::remove_dir_all(&tmp_path);
+    }
+    result?;
+
+    let host_class_path = run_dir.append_str(HOST_CLASS_FILE_NAME)?;
+    std::fs::write(&host_class_path, ron_to_string_pretty(&lease.host_class)?)
+        .map_err(ctx!("saving to {host_class_path:?}"))?;
+
+    Ok(run_dir)
+}
+
+/// Finish the leased job: unless `failed` is true, store the run
+/// directory (read as tar stream from stdin) and update the
+/// summaries, then update the job in the queue as `evobench run`
+/// does. The lease must be held by `worker`. Returns the job status,
+/// or `None` if the job was not in its queue anymore (the results are
+/// still stored in that case).
+pub fn complete_lease(
+    queues: &RunQueues,
+    conf: &RunConfig,
+    worker: &ProperFilename,
+    lease_id: &LeaseId,
+    failed: bool,
+) -> Result<Option<JobStatus>> {
+    let (_opts, leases) = coordinator_opts_and_leases(queues)?;
+    let lease = leases.get_held_by(lease_id, worker)?;
+
+    let store_results = || -> Result<()> {
+        if failed {
+            bail!("the job failed on worker {:?}", lease.worker.as_str())
+        }
+        let run_dir = receive_run_dir(&conf.output_dir.path, &lease, std::io::stdin().lock())?;
+        run_dir.update_results_index(None, None);
+        run_dir.parent().generate_summaries_for_key_dir(
+            // Do not omit generation of evobench.log stats
+            false, conf,
+        )?;
+        Ok(())
+    };
+
+    let key = lease.queue_key()?;
+    let queue_item = if let Some(rqwn) = queues.get_run_queue_with_next_by_name(&lease.queue_name) {
+        rqwn.current
+            .queue
+            .get_item(
+                &key,
+                QueueGetItemOptions {
+                    verbose: log_level() >= LogLevel::Info,
+                    no_lock: true,
+                    error_when_locked: false,
+                    delete_first: false,
+                },
+            )?
+            .map(|item| (rqwn, item))
+    } else {
+        None
+    };
+    let job_status = if let Some((rqwn, item)) = queue_item {
+        Some(rqwn.run_job_with(
+            &item,
+            &lease.job,
+            queues.erroneous_jobs_queue(),
+            queues.done_jobs_queue(),
+            queues.job_counters(),
+            None,
+            |_reason, _schedule_condition| store_results(),
+        )?)
+    } else {
+        warn!(
+            "entry {key} of lease {} is not in queue {:?} anymore",
+            lease_id.as_str(),
+            lease.queue_name.as_str()
+        );
+        if !failed {
+            store_results()?;
+        }
+        None
+    };
+
+    leases.delete(lease_id)?;
+    Ok(job_status)
+}
+
+/// Get a lease from the coordinator, if it has a runnable job
+fn request_lease(worker_opts: &WorkerOpts) -> Result<Option<LeaseGrant>> {
+    let mut command =
+        worker_opts.coordinator_command(&["lease", "--worker", worker_opts.name.as_str()]);
+    command.stderr(Stdio::inherit());
+    let output = command
+        .output()
+        .map_err(ctx!("running command {command:?}"))?;
+    if !output.status.success() {
+        bail!("command {command:?} failed: {}", output.status)
+    }
+    let s = String::from_utf8(output.stdout).map_err(ctx!("decoding output of {command:?}"))?;
+    Ok(ron::from_str(&s).map_err(ctx!("parsing output of {command:?}"))?)
+}
+
+/// Report the outcome of the run for `lease_id` to the coordinator,
+/// uploading `run_dir` if given (i.e. the run succeeded)
+fn report_completion(
+    worker_opts: &WorkerOpts,
+    lease_id: &LeaseId,
+    run_dir: Option<&RunDir>,
+) -> Result<()> {
+    let worker = worker_opts.name.as_str();
+    let mut complete = if run_dir.is_some() {
+        worker_opts.coordinator_command(&["complete", "--worker", worker, lease_id.as_str()])
+    } else {
+        worker_opts.coordinator_command(&[
+            "complete",
+            "--worker",
+            worker,
+            "--failed",
+            lease_id.as_str(),
+        ])
+    };
+
+    let mut tar = None;
+    if let Some(run_dir) = run_dir {
+        let mut command = Command::new("tar");
+        command
+            .arg("-C")
+            .arg(run_dir.to_path().as_os_str())
+            // The uncompressed evobench.log is only a cache
+            .args(["--exclude=*.uncompressed", "-cf", "-", "."])
+            .stdout(Stdio::piped());
+        let mut child = command
+            .spawn()
+            .map_err(ctx!("spawning command {command:?}"))?;
+        complete.stdin(child.stdout.take().expect("configured as piped"));
+        tar = Some((command, child));
+    } else {
+        complete.stdin(Stdio::null());
+    }
+
+    let status = complete
+        .status()
+        .map_err(ctx!("running command {complete:?}"))?;
+    if let Some((command, mut child)) = tar {
+        let tar_status = child.wait()?;
+        if !tar_status.success() {
+            bail!("command {command:?} failed: {tar_status}")
+        }
+    }
+    if !status.success() {
+        bail!("command {complete:?} failed: {status}")
+    }
+    Ok(())
+}
+
+/// Lease a job from the coordinator configured in `worker_opts`, run
+/// it locally via `job_runner` (whose `timestamp` is replaced with the
+/// one from the lease), and report the outcome back. Returns whether
+/// a job was leased. `run_queues_data` are the worker's own (normally
+/// empty) queues, used for working directory allocation.
+pub fn run_leased_job(
+    worker_opts: &WorkerOpts,
+    job_runner: JobRunner,
+    run_queues_data: &RunQueuesData,
+) -> Result<bool> {
+    let Some(LeaseGrant {
+        lease_id,
+        job,
+        schedule_condition,
+        timestamp,
+    }) = request_lease(worker_opts)?
+    else {
+        return Ok(false);
+    };
+    info!(
+        "got lease {} for job {}",
+        lease_id.as_str(),
+        ron_to_string_pretty(&job).expect("no err")
+    );
+
+    let working_directory_id;
+    {
+        let mut lock = job_runner
+            .working_directory_pool
+            .lock_mut("distributed::run_leased_job")?;
+        working_directory_id =
+            lock.get_a_working_directory_for(&job.public.run_parameters, run_queues_data)?;
+
+        lock.clear_current_working_directory()?;
+    }
+
+    let output_base_dir = job_runner.output_base_dir.clone_arc();
+    let result = JobRunnerWithJob {
+        job_runner: JobRunner {
+            timestamp: timestamp.clone(),
+            ..job_runner
+        },
+        job_data: JobRunnerJobData {
+            job: &job,
+            run_queues_data,
+        },
+    }
+    .run_job(
+        working_directory_id,
+        &job.public.reason,
+        &schedule_condition,
+    );
+
+    match result {
+        Ok(()) => {
+            let run_dir = job
+                .benchmarking_job_parameters()
+                .to_key_dir(output_base_dir)
+                .append_subdir(timestamp);
+            report_completion(worker_opts, &lease_id, Some(&run_dir))?;
+
+            // The job may run again, but on any worker, thus don't
+            // keep the cache
+            let uncompressed_path = run_dir.evobench_log_uncompressed_path();
+            match std::fs::remove_file(&uncompressed_path) {
+                Ok(()) => info!("deleted {uncompressed_path:?}"),
+                Err(e) => match e.kind() {
+                    std::io::ErrorKind::NotFound => (),
+                    _ => warn!("ignoring error deleting {uncompressed_path:?}: {e:#}"),
+                },
+            }
+        }
+        Err(e) => {
+            warn!("job for lease {} failed: {e:#}", lease_id.as_str());
+            report_completion(worker_opts, &lease_id, None)?;
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utillib::test_dir::TestDir;
+
+    use super::*;
+
+    fn test_lease() -> Lease {
+        Lease {
+            worker: "w1".parse().expect("valid name"),
+            host_class: "c".parse().expect("valid name"),
+            queue_name: "immediate".parse().expect("valid name"),
+            queue_key: TimeKey::now().as_filename_str().into_owned(),
+            job: BenchmarkingJob::for_tests(
+                "bench",
+                "1111111111111111111111111111111111111111",
+                None,
+            ),
+            timestamp: DateTimeWithOffset::now(None),
+            leased_at: SystemTime::now(),
+        }
+    }
+
+    /// The contents of `dir` as a tar stream
+    fn tar_of(dir: &Path) -> Result<Vec<u8>> {
+        let output = Command::new("tar")
+            .arg("-C")
+            .arg(dir)
+            .args(["-cf", "-", "."])
+            .output()?;
+        assert!(output.status.success());
+        Ok(output.stdout)
+    }
+
+    #[test]
+    fn t_get_held_by() -> Result<()> {
+        let test_dir = TestDir::new("leases-held-by");
+        let leases = Leases::open(test_dir.path(), true)?;
+        let id = LeaseId::new_random();
+        leases.key_val.insert(&id, &test_lease(), false)?;
+        let w1: ProperFilename = "w1".parse().expect("valid name");
+        let w2: ProperFilename = "w2".parse().expect("valid name");
+
+        assert_eq!(leases.get_held_by(&id, &w1)?.worker, w1);
+        let e = leases.get_held_by(&id, &w2).unwrap_err();
+        assert!(format!("{e:#}").contains("is held by worker"), "{e:#}");
+        assert!(leases.get_held_by(&LeaseId::new_random(), &w1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn t_check_tar_member_name() {
+        assert!(check_tar_member_name("./").is_ok());
+        assert!(check_tar_member_name("./evobench.log.zstd").is_ok());
+        assert!(check_tar_member_name("a/b..c").is_ok());
+        assert!(check_tar_member_name("/etc/passwd").is_err());
+        assert!(check_tar_member_name("../x").is_err());
+        assert!(check_tar_member_name("./a/../../x").is_err());
+        assert!(check_tar_member_name("a/..").is_err());
+    }
+
+    #[test]
+    fn t_receive_run_dir() -> Result<()> {
+        let test_dir = TestDir::new("receive-run-dir");
+        let output_base_dir: Arc<Path> = test_dir.path().join("output").into();
+        let upload_dir = test_dir.path().join("upload");
+        std::fs::create_dir_all(upload_dir.join("sub"))?;
+        std::fs::write(upload_dir.join("evobench.log.zstd"), "log")?;
+        std::fs::write(upload_dir.join("sub/file"), "file")?;
+
+        let lease = test_lease();
+        let run_dir = receive_run_dir(&output_base_dir, &lease, &*tar_of(&upload_dir)?)?;
+        let path = run_dir.to_path();
+        assert_eq!(
+            std::fs::read_to_string(path.join("evobench.log.zstd"))?,
+            "log"
+        );
+        assert_eq!(std::fs::read_to_string(path.join("sub/file"))?, "file");
+        assert!(path.join(HOST_CLASS_FILE_NAME).is_file());
+        // Only the run dir is left in the key dir
+        assert_eq!(std::fs::read_dir(run_dir.parent().to_path())?.count(), 1);
+
+        // Uploading again for the same lease fails
+        assert!(receive_run_dir(&output_base_dir, &lease, &*tar_of(&upload_dir)?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn t_receive_run_dir_rejects_symlinks() -> Result<()> {
+        let test_dir = TestDir::new("receive-run-dir-symlink");
+        let output_base_dir: Arc<Path> = test_dir.path().join("output").into();
+        let upload_dir = test_dir.path().join("upload");
+        std::fs::create_dir_all(&upload_dir)?;
+        std::fs::write(upload_dir.join("evobench.log.zstd"), "log")?;
+        std::os::unix::fs::symlink("/etc", upload_dir.join("etc"))?;
+
+        let lease = test_lease();
+        assert!(receive_run_dir(&output_base_dir, &lease, &*tar_of(&upload_dir)?).is_err());
+        let key_dir = lease
+            .job
+            .benchmarking_job_parameters()
+            .to_key_dir(output_base_dir.clone_arc());
+        // Nothing is left behind
+        assert_eq!(std::fs::read_dir(key_dir.to_path())?.count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn t_lease_id() {
+        let id = LeaseId::new_random();
+        assert_eq!(id.as_str().len(), 32);
+        assert_eq!(
+            LeaseId::try_from_filename_str(&id.as_filename_str()),
+            Some(id.clone())
+        );
+        assert_ne!(id, LeaseId::new_random());
+        assert!(LeaseId::from_str("abc").is_err());
+        assert!(LeaseId::from_str("0123456789abcdef0123456789abcdeg").is_err());
+    }
+}
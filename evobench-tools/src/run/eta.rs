@@ -0,0 +1,532 @@
+//! Estimating when queued jobs will be run (for `evobench list`).
+//!
+//! The duration of a job run is estimated from the durations of
+//! previous runs with the same parameters (the same `KeyDir`, or if
+//! there are none, other commits of the same `ParametersDir`): a run
+//! starts at the time given by its `RunDir` name and ends with the
+//! last timestamped line of its `standard.log`. The durations of the
+//! runs are kept by `RunDurationEstimator` for its lifetime (finished
+//! runs don't change), thus keeping an estimator around makes
+//! repeated estimates cheap.
+//!
+//! With those estimates, the scheduler is simulated, with the same
+//! rules as `RunQueuesData::run_next_job` and `RunContext` apply:
+//! time windows, `repeatedly` and `move_when_time_window_ends`. The
+//! simulation does not account for failures, nor for how the
+//! fair-share usage changes as jobs are run, and assumes that a job
+//! that is currently running has just started.
+
+use std::{
+    collections::BTreeMap,
+    ops::Neg,
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+
+use crate::{
+    date_and_time::time_ranges::LocalNaiveTimeRange,
+    info,
+    key_val_fs::queue::TimeKey,
+    serde_types::{priority::Priority, proper_filename::ProperFilename},
+};
+
+use super::{
+    benchmarking_job::BenchmarkingJob,
+    command_log_file::CommandLogFile,
+    config::ScheduleCondition,
+    output_directory::structure::{KeyDir, RunDir, SubDirs, ToPath},
+    run_queues::RunQueuesData,
+};
+
+/// How many of the most recent runs are used for an estimate
+const MAX_SAMPLES: usize = 5;
+
+/// How far into the future the scheduler is simulated
+const SIMULATION_HORIZON_DAYS: i64 = 60;
+
+/// The duration of a finished run, if its `standard.log` is present
+/// and contains timestamped lines.
+pub fn run_dir_duration(run_dir: &RunDir) -> Result<Option<Duration>> {
+    let log_path = run_dir.standard_log_path();
+    if !log_path.exists() {
+        return Ok(None);
+    }
+    let log_file = CommandLogFile::from(&log_path);
+    let command_log = log_file.command_log()?;
+    let (rest, _lineno) = command_log.log_contents_rest();
+    let last_timestamp = rest.lines().rev().find_map(|line| {
+        let (t, _) = line.split_once('\t')?;
+        DateTime::parse_from_rfc3339(t).ok()
+    });
+    Ok(last_timestamp.and_then(|end| (end - run_dir.timestamp().to_datetime()).to_std().ok()))
+}
+
+/// Estimates run durations from the output directory. The estimates
+/// are cached by `KeyDir` and `ParametersDir` path until
+/// `clear_estimates` is called (`simulate` does so), the durations of
+/// the runs they are calculated from are kept.
+pub struct RunDurationEstimator {
+    output_base_dir: Arc<Path>,
+    cache: BTreeMap<Arc<Path>, Option<Duration>>,
+    /// By run dir path; only for runs that have a `standard.log`
+    /// (those are finished)
+    run_dir_durations: BTreeMap<Arc<Path>, Option<Duration>>,
+}
+
+impl RunDurationEstimator {
+    pub fn new(output_base_dir: Arc<Path>) -> Self {
+        Self {
+            output_base_dir,
+            cache: Default::default(),
+            run_dir_durations: Default::default(),
+        }
+    }
+
+    /// Forget the estimates, so that runs added since are taken into
+    /// account
+    pub fn clear_estimates(&mut self) {
+        self.cache.clear();
+    }
+
+    fn run_dir_duration(&mut self, run_dir: &RunDir) -> Option<Duration> {
+        let path = run_dir.to_path();
+        if let Some(duration) = self.run_dir_durations.get(path) {
+            return *duration;
+        }
+        match run_dir_duration(run_dir) {
+            Ok(duration) => {
+                if duration.is_some() || run_dir.standard_log_path().exists() {
+                    self.run_dir_durations.insert(path.clone(), duration);
+                }
+                duration
+            }
+            Err(e) => {
+                info!("ignoring run dir {path:?}: {e:#}");
+                None
+            }
+        }
+    }
+
+    /// The median of the durations of the most recent (up to
+    /// `MAX_SAMPLES`) runs in `run_dirs` that have a duration.
+    fn estimate_from_run_dirs(&mut self, mut run_dirs: Vec<RunDir>) -> Option<Duration> {
+        run_dirs.sort_by_key(|run_dir| run_dir.timestamp().to_systemtime());
+        let mut durations: Vec<Duration> = run_dirs
+            .iter()
+            .rev()
+            .filter_map(|run_dir| self.run_dir_duration(run_dir))
+            .take(MAX_SAMPLES)
+            .collect();
+        durations.sort();
+        durations.get(durations.len() / 2).copied()
+    }
+
+    /// The estimated duration of one run of `job`, `None` if there
+    /// are no usable previous runs with the same parameters.
+    pub fn estimate(&mut self, job: &BenchmarkingJob) -> Result<Option<Duration>> {
+        let key_dir = KeyDir::from_base_target_params(
+            self.output_base_dir.clone(),
+            job.public.command.target_name.clone(),
+            &job.public.run_parameters,
+        );
+        if let Some(estimate) = self.cache.get(key_dir.to_path()) {
+            return Ok(*estimate);
+        }
+        let mut estimate = None;
+        if key_dir.to_path().exists() {
+            estimate = self.estimate_from_run_dirs(key_dir.sub_dirs()?.collect::<Result<_>>()?);
+        }
+        self.cache.insert(key_dir.to_path().clone(), estimate);
+        if estimate.is_some() {
+            return Ok(estimate);
+        }
+
+        // Fall back to the runs for other commits
+        let parameters_dir = key_dir.parent();
+        if let Some(estimate) = self.cache.get(parameters_dir.to_path()) {
+            return Ok(*estimate);
+        }
+        let mut estimate = None;
+        if parameters_dir.to_path().exists() {
+            let mut run_dirs = Vec::new();
+            for key_dir in parameters_dir.sub_dirs()? {
+                let key_dir = Arc::new(key_dir?);
+                for run_dir in key_dir.sub_dirs()? {
+                    run_dirs.push(run_dir?);
+                }
+            }
+            estimate = self.estimate_from_run_dirs(run_dirs);
+        }
+        self.cache
+            .insert(parameters_dir.to_path().clone(), estimate);
+        Ok(estimate)
+    }
+}
+
+/// A job as tracked by the simulation
+struct SimJob<'d> {
+    /// The queue entry that the job is listed as currently, i.e. the
+    /// one the ETA is reported for
+    origin: (ProperFilename, TimeKey),
+    job: &'d BenchmarkingJob,
+    queue_index: usize,
+    /// Position within its queue (the real queues are ordered by
+    /// insertion time)
+    seq: usize,
+    /// Whether the job still has its `current_boost` (it's dropped
+    /// when the job is reinserted after a run)
+    boosted: bool,
+    remaining_count: u8,
+    duration: Duration,
+}
+
+/// The result of simulating the scheduler
+#[derive(Debug, Default)]
+pub struct Etas {
+    /// Estimated completion time of the next run of each queue entry
+    /// (by queue file name and key)
+    pub eta: BTreeMap<(ProperFilename, TimeKey), DateTime<Local>>,
+    /// Sum of the estimated durations of all runs
+    pub total_run_time: Duration,
+    /// When the last simulated run ends
+    pub finished_at: Option<DateTime<Local>>,
+    /// Number of jobs without previous runs to estimate from
+    pub num_jobs_without_history: usize,
+    /// The duration assumed for the runs of those jobs (the median
+    /// over the jobs that have estimates)
+    pub assumed_duration: Option<Duration>,
+    /// Number of jobs in runnable queues that did not get to run
+    /// within the simulation horizon
+    pub num_unscheduled: usize,
+}
+
+impl Etas {
+    /// A one-line summary for showing above the queues
+    pub fn summary(&self) -> String {
+        let Self {
+            eta: _,
+            total_run_time,
+            finished_at,
+            num_jobs_without_history,
+            assumed_duration,
+            num_unscheduled,
+        } = self;
+        let mut s = if let Some(finished_at) = finished_at {
+            format!(
+                "Estimated backlog: {} of run time, done around {}",
+                format_duration(*total_run_time),
+                finished_at.format("%Y-%m-%d %H:%M")
+            )
+        } else if *num_jobs_without_history > 0 && assumed_duration.is_none() {
+            "Estimated backlog: unknown (no previous runs to estimate from)".into()
+        } else {
+            "Estimated backlog: none".into()
+        };
+        if *num_jobs_without_history > 0 {
+            if let Some(assumed_duration) = assumed_duration {
+                s.push_str(&format!(
+                    " ({num_jobs_without_history} jobs without previous runs, \
+                     assumed {} per run)",
+                    format_duration(*assumed_duration)
+                ));
+            }
+        }
+        if *num_unscheduled > 0 {
+            s.push_str(&format!(
+                "; {num_unscheduled} jobs not scheduled within \
+                 {SIMULATION_HORIZON_DAYS} days"
+            ));
+        }
+        s
+    }
+}
+
+/// Short human-readable duration, e.g. "2d 3h", "1h 05m", "42s"
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (days, hours, minutes) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+    if days > 0 {
+        format!("{days}d {hours}h")
+    } else if hours > 0 {
+        format!("{hours}h {minutes:02}m")
+    } else if minutes > 0 {
+        format!("{minutes}m")
+    } else {
+        format!("{secs}s")
+    }
+}
+
+/// Simulate the scheduler on the jobs in `data`, starting at `now`.
+pub fn simulate(
+    data: &RunQueuesData,
+    estimator: &mut RunDurationEstimator,
+    now: SystemTime,
+) -> Result<Etas> {
+    estimator.clear_estimates();
+    let now: DateTime<Local> = now.into();
+    let queues: Vec<_> = data
+        .run_queue_with_nexts()
+        .map(|rq| rq.current.run_queue())
+        .collect();
+
+    let mut etas = Etas::default();
+
+    // Collect the jobs with their estimates
+    let mut jobs_with_estimates = Vec::new();
+    for (queue_index, rq) in data.run_queue_with_nexts().enumerate() {
+        for (key, job, _priority) in rq.current.entries() {
+            let estimate = estimator.estimate(job)?;
+            if estimate.is_none() {
+                etas.num_jobs_without_history += 1;
+            }
+            jobs_with_estimates.push((queue_index, key, job, estimate));
+        }
+    }
+    {
+        let mut known: Vec<Duration> = jobs_with_estimates
+            .iter()
+            .filter_map(|(_, _, _, estimate)| *estimate)
+            .collect();
+        known.sort();
+        etas.assumed_duration = known.get(known.len() / 2).copied();
+    }
+    let Some(assumed_duration) = etas.assumed_duration else {
+        // Nothing to estimate from
+        return Ok(etas);
+    };
+
+    let mut next_seq = 0;
+    let mut jobs: Vec<SimJob> = jobs_with_estimates
+        .into_iter()
+        .map(|(queue_index, key, job, estimate)| {
+            let seq = next_seq;
+            next_seq += 1;
+            SimJob {
+                origin: (queues[queue_index].file_name.clone(), key.clone()),
+                job,
+                queue_index,
+                seq,
+                boosted: true,
+                remaining_count: job.state.remaining_count,
+                duration: estimate.unwrap_or(assumed_duration),
+            }
+        })
+        .collect();
+
+    let scheduling_priority = |sim_job: &SimJob| -> Result<Priority> {
+        let job_priority = if sim_job.boosted {
+            sim_job.job.priority()?
+        } else {
+            sim_job
+                .job
+                .clone_for_queue_reinsertion(sim_job.job.state.clone())
+                .priority()?
+        };
+        let queue_priority = queues[sim_job.queue_index]
+            .schedule_condition
+            .priority()
+            .unwrap_or_default();
+        Ok(data.scheduling_priority(sim_job.job, (job_priority + queue_priority)?))
+    };
+
+    let horizon = now + chrono::Duration::days(SIMULATION_HORIZON_DAYS);
+    // Queue index -> end of the time window, for queues with
+    // `move_when_time_window_ends` that had jobs run from them
+    let mut open_queues: BTreeMap<usize, DateTime<Local>> = BTreeMap::new();
+    let mut t = now;
+    while !jobs.is_empty() && t < horizon {
+        // Close the windows that have ended (`RunContext::close_open_queues`)
+        let closed: Vec<usize> = open_queues
+            .iter()
+            .filter(|(_, end)| t >= **end)
+            .map(|(queue_index, _)| *queue_index)
+            .collect();
+        for queue_index in closed {
+            open_queues.remove(&queue_index);
+            let mut moved: Vec<&mut SimJob> = jobs
+                .iter_mut()
+                .filter(|j| j.queue_index == queue_index)
+                .collect();
+            moved.sort_by_key(|j| j.seq);
+            for j in moved {
+                // Jobs are dropped if there is no next queue
+                j.queue_index += 1;
+                j.seq = next_seq;
+                next_seq += 1;
+            }
+            jobs.retain(|j| j.queue_index < queues.len());
+        }
+
+        // Pick the most prioritized job across the runnable queues
+        // (`RunQueuesData::most_prioritized_job`)
+        let mut best: Option<(Priority, usize, usize)> = None;
+        let mut windows = BTreeMap::new();
+        for (i, j) in jobs.iter().enumerate() {
+            let schedule_condition = queues[j.queue_index].schedule_condition;
+            let Some(dtr) = schedule_condition.is_runnable_at(t) else {
+                continue;
+            };
+            windows.insert(j.queue_index, dtr);
+            let neg_priority = scheduling_priority(j)?.neg();
+            let is_better = match &best {
+                None => true,
+                Some((best_neg_priority, best_i, _)) => {
+                    let b = &jobs[*best_i];
+                    (neg_priority, j.queue_index, j.seq)
+                        < (*best_neg_priority, b.queue_index, b.seq)
+                }
+            };
+            if is_better {
+                best = Some((neg_priority, i, j.queue_index));
+            }
+        }
+
+        if let Some((_, i, queue_index)) = best {
+            let schedule_condition = queues[queue_index].schedule_condition;
+            if let Some(Some(dtr)) = windows.get(&queue_index) {
+                if schedule_condition.move_when_time_window_ends() {
+                    open_queues.insert(queue_index, dtr.to);
+                }
+            }
+            let j = &mut jobs[i];
+            t = t + chrono::Duration::from_std(j.duration)?;
+            etas.total_run_time += j.duration;
+            etas.finished_at = Some(t);
+            etas.eta.entry(j.origin.clone()).or_insert(t);
+
+            // Where the job goes next (`RunQueueWithNext::run_job`)
+            j.remaining_count = j.remaining_count.saturating_sub(1);
+            j.boosted = false;
+            j.seq = next_seq;
+            next_seq += 1;
+            let stays = match schedule_condition {
+                ScheduleCondition::LocalNaiveTimeWindow { repeatedly, .. } => *repeatedly,
+                ScheduleCondition::Immediately { .. } | ScheduleCondition::Inactive => false,
+            };
+            if !stays {
+                j.queue_index += 1;
+            }
+            if j.remaining_count == 0 || j.queue_index >= queues.len() {
+                jobs.swap_remove(i);
+            }
+        } else {
+            // Idle until the next window opens or closes
+            let next_start = jobs
+                .iter()
+                .filter_map(|j| {
+                    let (from, to) = queues[j.queue_index].schedule_condition.time_range()?;
+                    let dtr = LocalNaiveTimeRange { from, to }.after_datetime(&t, true)?;
+                    (dtr.from > t).then_some(dtr.from)
+                })
+                .min();
+            let next_close = open_queues.values().copied().filter(|end| *end > t).min();
+            match next_start.into_iter().chain(next_close).min() {
+                Some(next) => t = next,
+                None => break,
+            }
+        }
+    }
+
+    etas.num_unscheduled = jobs
+        .iter()
+        .filter(|j| {
+            !queues[j.queue_index].schedule_condition.is_inactive()
+                && !etas.eta.contains_key(&j.origin)
+        })
+        .count();
+
+    Ok(etas)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crate::{
+        io_utils::zstd_file::compress_file,
+        run::output_directory::test_runs::{add_test_run, job_key_dir},
+        serde_types::date_and_time::DateTimeWithOffset,
+        utillib::test_dir::TestDir,
+    };
+
+    use super::*;
+
+    /// Create a run dir for `job` starting at `start` with a
+    /// `standard.log` whose last timestamped line is `secs` later
+    fn add_run(output_base_dir: &Arc<Path>, job: &BenchmarkingJob, start: &str, secs: i64) {
+        let timestamp: DateTimeWithOffset = start.parse().unwrap();
+        let end = timestamp.to_datetime() + chrono::Duration::seconds(secs);
+        let run_dir = add_test_run(&job_key_dir(output_base_dir, job), timestamp.clone(), None);
+        let uncompressed_path = run_dir.to_path().join("standard.log");
+        let mut out = std::fs::File::create(&uncompressed_path).unwrap();
+        write!(
+            out,
+            "reason: null\n\n{}\tstarting\n{}\tdone\nnot timestamped\n",
+            timestamp.to_datetime().to_rfc3339(),
+            end.to_rfc3339()
+        )
+        .unwrap();
+        drop(out);
+        compress_file(&uncompressed_path, &run_dir.standard_log_path(), true).unwrap();
+    }
+
+    #[test]
+    fn t_estimate() -> Result<()> {
+        let test_dir = TestDir::new("eta-estimate");
+        let output_base_dir: Arc<Path> = test_dir.path().into();
+        let commit1 = "1111111111111111111111111111111111111111";
+        let commit2 = "2222222222222222222222222222222222222222";
+        let job1 = BenchmarkingJob::for_tests("bench", commit1, None);
+        let job2 = BenchmarkingJob::for_tests("bench", commit2, None);
+        let other_target = BenchmarkingJob::for_tests("other", commit1, None);
+
+        // The median of the most recent `MAX_SAMPLES` runs: the
+        // oldest (1000 s) is not included
+        add_run(&output_base_dir, &job1, "2026-01-01T00:00:00+00:00", 1000);
+        for (i, secs) in [10, 50, 20, 40, 30].into_iter().enumerate() {
+            add_run(
+                &output_base_dir,
+                &job1,
+                &format!("2026-01-0{}T00:00:00+00:00", i + 2),
+                secs,
+            );
+        }
+        let mut estimator = RunDurationEstimator::new(output_base_dir.clone());
+        assert_eq!(estimator.estimate(&job1)?, Some(Duration::from_secs(30)));
+
+        // No runs for this commit: falls back to the runs of the
+        // other commits with the same parameters
+        assert_eq!(estimator.estimate(&job2)?, Some(Duration::from_secs(30)));
+
+        // Nothing with the same parameters
+        assert_eq!(estimator.estimate(&other_target)?, None);
+
+        // Runs added later are only seen after clearing the
+        // estimates
+        for day in 7..=9 {
+            add_run(
+                &output_base_dir,
+                &job1,
+                &format!("2026-01-0{day}T00:00:00+00:00"),
+                100,
+            );
+        }
+        assert_eq!(estimator.estimate(&job1)?, Some(Duration::from_secs(30)));
+        estimator.clear_estimates();
+        assert_eq!(estimator.estimate(&job1)?, Some(Duration::from_secs(100)));
+        Ok(())
+    }
+
+    #[test]
+    fn t_format_duration() {
+        let t = |secs| format_duration(Duration::from_secs(secs));
+        assert_eq!(t(42), "42s");
+        assert_eq!(t(60 * 5 + 3), "5m");
+        assert_eq!(t(3600 + 60 * 5), "1h 05m");
+        assert_eq!(t(86400 * 2 + 3600 * 3 + 59), "2d 3h");
+    }
+}
@@ -0,0 +1,370 @@
+//! Optional fair-share scheduling: lower the priority of jobs whose
+//! group (e.g. the branch the commit was found on) has recently
+//! received a lot of run time, so that a burst of jobs from one
+//! source does not starve the others.
+//!
+//! The usage history is kept as a `KeyVal` database (one entry per
+//! group) in the queues base directory. Usage decays exponentially
+//! with the configured half life.
+
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Result, bail};
+use kstring::KString;
+
+use crate::{
+    key_val_fs::{
+        as_key::AsKey,
+        key_val::{KeyVal, KeyValConfig, KeyValSync},
+    },
+    serde_types::priority::Priority,
+    utillib::crypto_hash::crypto_hash,
+};
+
+use super::benchmarking_job::BenchmarkingJob;
+
+/// Name of the subdirectory of the queues base directory holding the
+/// usage history (leading dot so that it isn't confused with a queue)
+const FAIR_SHARE_USAGE_DIR_NAME: &str = ".fair_share_usage";
+
+/// The group name used for jobs without a `reason`
+const NO_REASON_GROUP: &str = "(none)";
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum FairShareGroupBy {
+    /// Account run time by the `reason` field of jobs; for jobs
+    /// inserted by `evobench poll` that is the branch name. Jobs
+    /// without a reason are grouped together.
+    Reason,
+    /// Account run time by the target name of jobs.
+    TargetName,
+}
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(rename = "FairShare")]
+pub struct FairShareOpts {
+    /// What jobs are grouped by for accounting their run time.
+    pub group_by: FairShareGroupBy,
+
+    /// After how many hours recorded run time counts only half as
+    /// much.
+    pub half_life_hours: f64,
+
+    /// How much priority is subtracted per hour of (decayed) run
+    /// time that the group of a job has received. E.g. with 1.0,
+    /// a group that received 2 hours of run time recently has its
+    /// jobs lowered by 2 priority levels relative to a group that
+    /// received none. Must be > 0.
+    pub weight: f64,
+
+    /// Optional relative shares per group name (default for groups
+    /// not listed: 1.0). A group with share 2.0 can receive twice
+    /// the run time of a group with share 1.0 for the same priority
+    /// penalty. Must be > 0.
+    pub shares: Option<BTreeMap<KString, f64>>,
+}
+
+impl FairShareOpts {
+    pub fn check(&self) -> Result<()> {
+        let Self {
+            group_by: _,
+            half_life_hours,
+            weight,
+            shares,
+        } = self;
+        if !(*half_life_hours > 0.) {
+            bail!("`FairShare.half_life_hours` must be > 0, got {half_life_hours}")
+        }
+        // A zero weight would disable fair sharing, a zero share divide
+        // by zero, and negative values favour the groups with the most
+        // usage
+        if !(weight.is_finite() && *weight > 0.) {
+            bail!("`FairShare.weight` must be a finite number > 0, got {weight}")
+        }
+        if let Some(shares) = shares {
+            for (group, share) in shares {
+                if !(share.is_finite() && *share > 0.) {
+                    bail!(
+                        "`FairShare.shares` must be finite numbers > 0, got {share} for group {:?}",
+                        group.as_str()
+                    )
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The name of the group that `job` is accounted to
+    pub fn group_of<'j>(&self, job: &'j BenchmarkingJob) -> &'j str {
+        match self.group_by {
+            FairShareGroupBy::Reason => job.public.reason.as_deref().unwrap_or(NO_REASON_GROUP),
+            FairShareGroupBy::TargetName => job.public.command.target_name.as_str(),
+        }
+    }
+
+    fn share_of(&self, group: &str) -> f64 {
+        self.shares
+            .as_ref()
+            .and_then(|shares| shares.get(group))
+            .copied()
+            .unwrap_or(1.)
+    }
+
+    /// `usage_seconds` decayed from `since` to `now`
+    fn decayed(&self, usage_seconds: f64, since: SystemTime, now: SystemTime) -> f64 {
+        let elapsed = now
+            .duration_since(since)
+            .unwrap_or(Duration::ZERO)
+            .as_secs_f64();
+        let half_lifes = elapsed / (self.half_life_hours * 3600.);
+        usage_seconds * 0.5_f64.powf(half_lifes)
+    }
+}
+
+/// Key for the usage database: group names can contain any
+/// characters (e.g. '/' in branch names), thus hashed.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FairShareGroupHash(String);
+
+impl FairShareGroupHash {
+    fn from_group(group: &str) -> Self {
+        Self(crypto_hash(&group))
+    }
+}
+
+impl AsKey for FairShareGroupHash {
+    fn as_filename_str(&self) -> Cow<'_, str> {
+        (&self.0).into()
+    }
+
+    fn try_from_filename_str(file_name: &str) -> Option<Self> {
+        Some(Self(file_name.into()))
+    }
+}
+
+/// The usage record for one group
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FairShareUsageRecord {
+    /// The unhashed group name, for inspection and for listing
+    pub group: String,
+    /// Run time in seconds, decayed up to `last_update`
+    pub usage_seconds: f64,
+    pub last_update: SystemTime,
+}
+
+/// Access to the persistent usage history
+#[derive(Debug)]
+pub struct FairShareUsage {
+    key_val: KeyVal<FairShareGroupHash, FairShareUsageRecord>,
+}
+
+impl FairShareUsage {
+    /// Returns `None` if `create_dir_if_not_exists` is false and no
+    /// usage was recorded yet (the directory doesn't exist).
+    pub fn open(run_queues_basedir: &Path, create_dir_if_not_exists: bool) -> Result<Option<Self>> {
+        let dir = run_queues_basedir.join(FAIR_SHARE_USAGE_DIR_NAME);
+        if !create_dir_if_not_exists && !dir.exists() {
+            return Ok(None);
+        }
+        let key_val = KeyVal::open(
+            dir,
+            KeyValConfig {
+                sync: KeyValSync::All,
+                create_dir_if_not_exists,
+            },
+            None,
+        )?;
+        Ok(Some(Self { key_val }))
+    }
+
+    /// Load all usage records, decayed to `now`
+    pub fn snapshot(&self, opts: &FairShareOpts, now: SystemTime) -> Result<FairShareSnapshot> {
+        let mut usage_seconds_by_group = BTreeMap::new();
+        for key in self.key_val.keys(false, None)? {
+            let key = key?;
+            // Entries can disappear concurrently; just skip those.
+            if let Some(record) = self.key_val.get(&key)? {
+                let FairShareUsageRecord {
+                    group,
+                    usage_seconds,
+                    last_update,
+                } = record;
+                usage_seconds_by_group.insert(group, opts.decayed(usage_seconds, last_update, now));
+            }
+        }
+        Ok(FairShareSnapshot {
+            usage_seconds_by_group,
+        })
+    }
+
+    /// Add `run_time` to the usage of the group of `job`
+    pub fn record(
+        &self,
+        opts: &FairShareOpts,
+        job: &BenchmarkingJob,
+        run_time: Duration,
+    ) -> Result<()> {
+        let group = opts.group_of(job);
+        let key = FairShareGroupHash::from_group(group);
+        let now = SystemTime::now();
+
+        let _lock = self.key_val.lock_exclusive()?;
+        let previous_usage_seconds = if let Some(record) = self.key_val.get(&key)? {
+            opts.decayed(record.usage_seconds, record.last_update, now)
+        } else {
+            0.
+        };
+        self.key_val.insert(
+            &key,
+            &FairShareUsageRecord {
+                group: group.into(),
+                usage_seconds: previous_usage_seconds + run_time.as_secs_f64(),
+                last_update: now,
+            },
+            false,
+        )?;
+        Ok(())
+    }
+}
+
+/// The usage of all groups at a particular time
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FairShareSnapshot {
+    usage_seconds_by_group: BTreeMap<String, f64>,
+}
+
+impl FairShareSnapshot {
+    pub fn usage_seconds(&self, group: &str) -> f64 {
+        self.usage_seconds_by_group
+            .get(group)
+            .copied()
+            .unwrap_or(0.)
+    }
+
+    /// `priority` (the total priority of `job` including the queue
+    /// priority) lowered according to the usage of the job's group.
+    pub fn adjusted_priority(
+        &self,
+        opts: &FairShareOpts,
+        job: &BenchmarkingJob,
+        priority: Priority,
+    ) -> Priority {
+        let group = opts.group_of(job);
+        let usage_hours = self.usage_seconds(group) / 3600.;
+        let penalty = opts.weight * usage_hours / opts.share_of(group);
+        // `weight` and shares are checked to be finite and > 0, and
+        // usage is never NaN, thus this doesn't fail; keep the
+        // original priority if it does anyway.
+        priority.sub(penalty).unwrap_or(priority)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utillib::test_dir::TestDir;
+
+    const COMMIT: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    fn opts(shares: Option<&[(&str, f64)]>) -> FairShareOpts {
+        FairShareOpts {
+            group_by: FairShareGroupBy::Reason,
+            half_life_hours: 1.,
+            weight: 1.,
+            shares: shares.map(|shares| {
+                shares
+                    .iter()
+                    .map(|(group, share)| (KString::from_ref(group), *share))
+                    .collect()
+            }),
+        }
+    }
+
+    #[test]
+    fn t_check() {
+        assert!(opts(None).check().is_ok());
+        assert!(opts(Some(&[("big", 2.)])).check().is_ok());
+        for weight in [0., -1., f64::NAN, f64::INFINITY] {
+            let opts = FairShareOpts {
+                weight,
+                ..opts(None)
+            };
+            assert!(opts.check().is_err(), "weight {weight}");
+        }
+        for share in [0., -1., f64::NAN, f64::INFINITY] {
+            assert!(
+                opts(Some(&[("big", share)])).check().is_err(),
+                "share {share}"
+            );
+        }
+    }
+
+    #[test]
+    fn t_decayed() {
+        let opts = opts(None);
+        let t0 = SystemTime::UNIX_EPOCH;
+        assert_eq!(opts.decayed(100., t0, t0), 100.);
+        assert_eq!(opts.decayed(100., t0, t0 + Duration::from_secs(3600)), 50.);
+        assert_eq!(opts.decayed(100., t0, t0 + Duration::from_secs(7200)), 25.);
+        // Clock going backwards does not increase usage
+        assert_eq!(opts.decayed(100., t0 + Duration::from_secs(10), t0), 100.);
+    }
+
+    #[test]
+    fn t_adjusted_priority_ordering() {
+        let opts = opts(Some(&[("big", 2.)]));
+        let snapshot = FairShareSnapshot {
+            usage_seconds_by_group: [
+                ("busy".to_string(), 2. * 3600.),
+                ("big".to_string(), 2. * 3600.),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let busy = BenchmarkingJob::for_tests("api", COMMIT, Some("busy"));
+        let big = BenchmarkingJob::for_tests("api", COMMIT, Some("big"));
+        let idle = BenchmarkingJob::for_tests("api", COMMIT, Some("idle"));
+        let none = BenchmarkingJob::for_tests("api", COMMIT, None);
+
+        let p = |job| snapshot.adjusted_priority(&opts, job, Priority::NORMAL);
+        assert_eq!(p(&busy), Priority::new(-2.).unwrap());
+        // Twice the share, half the penalty
+        assert_eq!(p(&big), Priority::new(-1.).unwrap());
+        assert_eq!(p(&idle), Priority::NORMAL);
+        assert_eq!(p(&none), Priority::NORMAL);
+        assert!(p(&idle) > p(&big));
+        assert!(p(&big) > p(&busy));
+    }
+
+    #[test]
+    fn t_usage_recording() -> Result<()> {
+        let dir = TestDir::new("fair-share");
+        assert!(FairShareUsage::open(dir.path(), false)?.is_none());
+        let usage = FairShareUsage::open(dir.path(), true)?.expect("created");
+        let opts = opts(None);
+
+        let a = BenchmarkingJob::for_tests("api", COMMIT, Some("a"));
+        let b = BenchmarkingJob::for_tests("api", COMMIT, Some("feature/b"));
+        usage.record(&opts, &a, Duration::from_secs(600))?;
+        usage.record(&opts, &a, Duration::from_secs(600))?;
+        usage.record(&opts, &b, Duration::from_secs(60))?;
+
+        let snapshot = usage.snapshot(&opts, SystemTime::now())?;
+        // Allow for the decay during the test run
+        assert!((snapshot.usage_seconds("a") - 1200.).abs() < 1.);
+        assert!((snapshot.usage_seconds("feature/b") - 60.).abs() < 1.);
+        assert_eq!(snapshot.usage_seconds("c"), 0.);
+
+        let later = SystemTime::now() + Duration::from_secs(3600);
+        let snapshot = usage.snapshot(&opts, later)?;
+        assert!((snapshot.usage_seconds("a") - 600.).abs() < 1.);
+        Ok(())
+    }
+}
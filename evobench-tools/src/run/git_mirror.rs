@@ -0,0 +1,546 @@
+//! A bare mirror of the remote repository, holding the Git objects
+//! shared by the working directories of a pool (see
+//! `WorkingDirectoryPoolOpts.shared_objects`).
+//!
+//! The working directories are clones of the mirror made with
+//! `--reference` to it, i.e. they use the mirror's object store via
+//! `.git/objects/info/alternates`, and fetch from the mirror after it
+//! has been updated from the remote repository; only the mirror
+//! fetches from the network. They thus remain
+//! normal (non-bare, non-worktree) repositories as far as the rest
+//! of the code is concerned.
+//!
+//! If submodules are enabled (`RemoteRepositoryOpts.checkout`), the
+//! mirror also holds mirrors of the submodules, under `modules/`.
+
+use std::{
+    path::{Component, Path},
+    sync::Arc,
+};
+
+use anyhow::{Result, anyhow, bail};
+use cj_path_util::path_util::AppendToPath;
+use run_git::git::GitWorkingDir;
+
+use crate::{
+    ctx, git::GitHash, git_ext::MoreGitWorkingDir, info, run::working_directory::REMOTE_NAME,
+    serde_types::git_url::GitUrl, utillib::arc::CloneArc,
+};
+
+/// The name of the mirror in the pool's base directory (contains a
+/// dot so that it is not taken as a working directory)
+pub const MIRROR_DIR_NAME: &str = "mirror.git";
+
+/// Commits fetched by hash that are not reachable from the branches
+/// or tags are kept under this prefix in the mirror (and fetched into
+/// the working directories from there)
+pub const KEPT_COMMITS_REF_PREFIX: &str = "refs/evobench/commits/";
+
+/// Clone `url` as a bare mirror to `base_dir/dir_file_name`. Clones
+/// into a temporary name first, so that an interrupted clone is not
+/// taken as a mirror.
+fn clone_mirror(base_dir: &Path, dir_file_name: &str, url: &str) -> Result<()> {
+    let base_git = GitWorkingDir {
+        working_dir_path: Arc::<Path>::from(base_dir.to_path_buf()),
+    };
+    let path = base_dir.append(dir_file_name);
+    let tmp_file_name = format!("{dir_file_name}.tmp");
+    let tmp_path = base_dir.append(&tmp_file_name);
+    if std::fs::exists(&tmp_path).map_err(ctx!("checking path {tmp_path:?}"))? {
+        std::fs::remove_dir_all(&tmp_path).map_err(ctx!("deleting dir {tmp_path:?}"))?;
+    }
+    if !base_git.git(&["clone", "--mirror", url, tmp_file_name.as_str()], false)? {
+        bail!("git clone --mirror {url} into {tmp_path:?} failed")
+    }
+    let tmp_git = GitWorkingDir {
+        working_dir_path: tmp_path.clone().into(),
+    };
+    // The working directories depend on the objects in the mirror,
+    // thus never drop unreachable ones (e.g. after force pushes)
+    // while they may still be in use
+    for (key, value) in [
+        ("gc.pruneExpire", "never"),
+        ("gc.reflogExpireUnreachable", "never"),
+    ] {
+        if !tmp_git.git(&["config", key, value], false)? {
+            bail!("setting git config {key} in {tmp_path:?} failed")
+        }
+    }
+    std::fs::rename(&tmp_path, &path).map_err(ctx!("renaming {tmp_path:?} to {path:?}"))?;
+    Ok(())
+}
+
+/// Submodule names are taken from `.gitmodules` of the checked-out
+/// commit, and used as paths below the mirror, thus must be relative
+/// and must not leave the `modules` dir.
+fn check_submodule_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        bail!("empty submodule name")
+    }
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(_) => (),
+            Component::Prefix(_) | Component::RootDir => {
+                bail!("submodule name {name:?} is an absolute path")
+            }
+            Component::CurDir | Component::ParentDir => {
+                bail!("submodule name {name:?} contains a {component:?} component")
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Run `git $args` in `git_working_dir`, failing on non-zero exit
+/// status
+fn run_git(git_working_dir: &GitWorkingDir, args: &[&str]) -> Result<()> {
+    if !git_working_dir.git(args, false)? {
+        bail!(
+            "git {args:?} in {:?} failed",
+            git_working_dir.working_dir_path_ref()
+        )
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct GitMirror {
+    pool_base_dir: Arc<Path>,
+    path: Arc<Path>,
+    url: GitUrl,
+}
+
+impl GitMirror {
+    /// Does not access the file system; the mirror is created when
+    /// first needed.
+    pub fn new(pool_base_dir: Arc<Path>, url: GitUrl) -> Self {
+        Self {
+            path: pool_base_dir.append(MIRROR_DIR_NAME).into(),
+            pool_base_dir,
+            url,
+        }
+    }
+
+    pub fn path(&self) -> &Arc<Path> {
+        &self.path
+    }
+
+    pub fn path_str(&self) -> Result<&str> {
+        self.path
+            .to_str()
+            .ok_or_else(|| anyhow!("mirror path is not valid UTF-8: {:?}", self.path))
+    }
+
+    fn git_working_dir(&self) -> GitWorkingDir {
+        GitWorkingDir {
+            working_dir_path: self.path.clone_arc(),
+        }
+    }
+
+    /// Clone the mirror if it doesn't exist yet, or update its
+    /// origin url if it has changed.
+    fn ensure_exists(&self) -> Result<()> {
+        let path = &self.path;
+        if std::fs::exists(path).map_err(ctx!("checking path {path:?}"))? {
+            let git_working_dir = self.git_working_dir();
+            if git_working_dir.get_url(REMOTE_NAME)? != self.url.as_str() {
+                git_working_dir.set_url(REMOTE_NAME, &self.url)?;
+            }
+            return Ok(());
+        }
+
+        clone_mirror(&self.pool_base_dir, MIRROR_DIR_NAME, self.url.as_str())?;
+        info!("created mirror {path:?} of {}", self.url);
+        Ok(())
+    }
+
+    /// Create the mirror if needed, then fetch all references from
+    /// the remote repository, and `commit_id` if given. If
+    /// `commit_id` is not reachable from a branch or tag, it is kept
+    /// under `KEPT_COMMITS_REF_PREFIX`.
+    pub fn fetch(&self, commit_id: Option<&GitHash>) -> Result<()> {
+        self.ensure_exists()?;
+        let git_working_dir = self.git_working_dir();
+        if !git_working_dir.git(&["fetch", REMOTE_NAME, "--tags"], true)? {
+            bail!("git fetch in mirror {:?} failed", self.path)
+        }
+        if let Some(commit_id) = commit_id {
+            let commit_str = commit_id.to_string();
+            if !git_working_dir.contains_reference(&commit_str)? {
+                git_working_dir.fetch_references(
+                    REMOTE_NAME,
+                    false,
+                    [commit_id.to_reference()],
+                    true,
+                )?;
+            }
+            let containing_ref = git_working_dir.git_stdout_string_trimmed(&[
+                "for-each-ref",
+                "--count=1",
+                "--contains",
+                &commit_str,
+                "refs/heads",
+                "refs/tags",
+            ])?;
+            if containing_ref.is_empty() {
+                let ref_name = format!("{KEPT_COMMITS_REF_PREFIX}{commit_str}");
+                if !git_working_dir.git(&["update-ref", &ref_name, &commit_str], false)? {
+                    bail!("git update-ref {ref_name} in mirror {:?} failed", self.path)
+                }
+            }
+        }
+        info!("fetched into mirror {:?}", self.path);
+        Ok(())
+    }
+
+    /// Fetch the branches (as remote-tracking branches of
+    /// `REMOTE_NAME`), tags and kept commits from the mirror into the
+    /// working directory at `git_working_dir`, after updating the
+    /// mirror (`commit_id` is passed to `fetch`). The objects
+    /// themselves are not copied if the working directory was cloned
+    /// with `--reference` to the mirror.
+    pub fn fetch_into(
+        &self,
+        git_working_dir: &GitWorkingDir,
+        commit_id: Option<&GitHash>,
+    ) -> Result<()> {
+        self.fetch(commit_id)?;
+        let heads_refspec = format!("+refs/heads/*:refs/remotes/{REMOTE_NAME}/*");
+        let kept_refspec = format!("+{KEPT_COMMITS_REF_PREFIX}*:{KEPT_COMMITS_REF_PREFIX}*");
+        let args = [
+            "fetch",
+            self.path_str()?,
+            "--tags",
+            heads_refspec.as_str(),
+            kept_refspec.as_str(),
+        ];
+        if !git_working_dir.git(&args, true)? {
+            bail!(
+                "git {args:?} in {:?} failed",
+                git_working_dir.working_dir_path_ref()
+            )
+        }
+        Ok(())
+    }
+
+    /// Clone the mirror (which is updated first) into
+    /// `base_dir/dir_file_name`, using its objects via `--reference`,
+    /// then set the origin url of the clone to the remote repository
+    /// url, as for a normal clone.
+    pub fn clone_with_reference(
+        &self,
+        base_dir: &Path,
+        dir_file_name: &str,
+        quiet: bool,
+    ) -> Result<GitWorkingDir> {
+        self.fetch(None)?;
+        let base_git = GitWorkingDir {
+            working_dir_path: Arc::<Path>::from(base_dir.to_path_buf()),
+        };
+        let mirror_path = self.path_str()?;
+        if !base_git.git(
+            &[
+                "clone",
+                "--reference",
+                mirror_path,
+                mirror_path,
+                dir_file_name,
+            ],
+            quiet,
+        )? {
+            bail!("git clone --reference {mirror_path:?} into {base_dir:?}/{dir_file_name} failed")
+        }
+        let git_working_dir = GitWorkingDir {
+            working_dir_path: base_dir.append(dir_file_name).into(),
+        };
+        git_working_dir.set_url(REMOTE_NAME, &self.url)?;
+        Ok(git_working_dir)
+    }
+
+    /// Create or update a mirror of the submodule `name` with the
+    /// given url, at `modules/$name` inside the mirror, which is
+    /// where `git submodule update` looks for a reference repository
+    /// when run with `submodule.alternateLocation=superproject` in a
+    /// working directory cloned with `--reference` to the mirror.
+    pub fn fetch_submodule(&self, name: &str, url: &str) -> Result<()> {
+        check_submodule_name(name)?;
+        self.ensure_exists()?;
+        let modules_dir = self.path.append("modules");
+        let path = modules_dir.append(name);
+        if std::fs::exists(&path).map_err(ctx!("checking path {path:?}"))? {
+            let git_working_dir = GitWorkingDir {
+                working_dir_path: path.clone().into(),
+            };
+            if git_working_dir.get_url(REMOTE_NAME)? != url {
+                if !git_working_dir.git(&["remote", "set-url", REMOTE_NAME, url], false)? {
+                    bail!("setting url {url:?} for submodule mirror {path:?} failed")
+                }
+            }
+            if !git_working_dir.git(&["fetch", REMOTE_NAME, "--tags"], true)? {
+                bail!("git fetch in submodule mirror {path:?} failed")
+            }
+        } else {
+            // `name` may contain slashes
+            let (parent, file_name) = match name.rsplit_once('/') {
+                Some((dir, file_name)) => (modules_dir.append(dir), file_name),
+                None => (modules_dir, name),
+            };
+            std::fs::create_dir_all(&parent).map_err(ctx!("creating dir {parent:?}"))?;
+            clone_mirror(&parent, file_name, url)?;
+            info!("created mirror {path:?} of submodule {name:?} from {url}");
+        }
+        Ok(())
+    }
+
+    /// Update the mirrors of the submodules registered in the
+    /// working directory at `git_working_dir` (cloned with
+    /// `--reference` to this mirror, and with `git submodule init`
+    /// already run), then recursively initialize and update its
+    /// submodules using them as reference repositories, discarding
+    /// local changes in them.
+    pub fn update_submodules_of(&self, git_working_dir: &GitWorkingDir) -> Result<()> {
+        let names = git_working_dir.git_stdout_string_trimmed(&[
+            "config",
+            "--file",
+            ".gitmodules",
+            "--name-only",
+            "--get-regexp",
+            r"^submodule\..*\.path$",
+        ])?;
+        for name in names.lines() {
+            let Some(name) = name
+                .strip_prefix("submodule.")
+                .and_then(|s| s.strip_suffix(".path"))
+            else {
+                continue;
+            };
+            let url = git_working_dir
+                .git_stdout_string_trimmed(&["config", &format!("submodule.{name}.url")])?;
+            self.fetch_submodule(name, &url)?;
+        }
+        run_git(
+            git_working_dir,
+            &[
+                "-c",
+                "submodule.alternateLocation=superproject",
+                "-c",
+                "submodule.alternateErrorStrategy=info",
+                "submodule",
+                "update",
+                "--init",
+                "--recursive",
+                "--force",
+            ],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use crate::utillib::test_dir::TestDir;
+
+    use super::*;
+
+    /// Run git in `dir`, returning its trimmed stdout
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(["-c", "protocol.file.allow=always"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .expect("running git");
+        assert!(
+            output.status.success(),
+            "git {args:?} in {dir:?}: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap().trim().into()
+    }
+
+    /// A repository with one commit on `main`, allowing to fetch
+    /// unreachable commits by hash (as e.g. GitHub does)
+    fn init_repository(path: &Path) {
+        std::fs::create_dir_all(path).unwrap();
+        git(path, &["init", "--quiet", "--initial-branch=main"]);
+        git(path, &["config", "uploadpack.allowAnySHA1InWant", "true"]);
+        git(
+            path,
+            &["commit", "--quiet", "--allow-empty", "-m", "initial"],
+        );
+    }
+
+    fn git_working_dir(path: &Path) -> GitWorkingDir {
+        GitWorkingDir {
+            working_dir_path: path.to_path_buf().into(),
+        }
+    }
+
+    #[test]
+    fn t_fetch_into_unreachable_commit() -> Result<()> {
+        let test_dir = TestDir::new("git-mirror-fetch-into");
+        let remote_path = test_dir.path().append("remote");
+        init_repository(&remote_path);
+
+        // A commit that is not on any branch (e.g. of a force-pushed
+        // or deleted branch)
+        git(&remote_path, &["checkout", "--quiet", "-b", "tmp"]);
+        git(
+            &remote_path,
+            &["commit", "--quiet", "--allow-empty", "-m", "gone"],
+        );
+        let commit_str = git(&remote_path, &["rev-parse", "HEAD"]);
+        git(&remote_path, &["checkout", "--quiet", "main"]);
+        git(&remote_path, &["branch", "--quiet", "-D", "tmp"]);
+        let commit_id: GitHash = commit_str.parse()?;
+
+        let pool_base_dir: Arc<Path> = test_dir.path().append("pool").into();
+        std::fs::create_dir_all(&pool_base_dir)?;
+        let mirror = GitMirror::new(
+            pool_base_dir.clone_arc(),
+            remote_path.to_str().unwrap().parse()?,
+        );
+
+        // A working directory that was not cloned with `--reference`
+        // to the mirror (e.g. from before the mirror was configured)
+        let working_dir_path = pool_base_dir.append("0");
+        git(
+            &pool_base_dir,
+            &["clone", "--quiet", remote_path.to_str().unwrap(), "0"],
+        );
+        let working_dir = git_working_dir(&working_dir_path);
+        assert!(!working_dir.contains_reference(&commit_str)?);
+
+        mirror.fetch_into(&working_dir, Some(&commit_id))?;
+        assert!(working_dir.contains_reference(&commit_str)?);
+        assert_eq!(
+            git(
+                &working_dir_path,
+                &[
+                    "rev-parse",
+                    &format!("{KEPT_COMMITS_REF_PREFIX}{commit_str}")
+                ]
+            ),
+            commit_str
+        );
+
+        // Commits on branches are not kept separately
+        let main_str = git(&remote_path, &["rev-parse", "main"]);
+        mirror.fetch_into(&working_dir, Some(&main_str.parse()?))?;
+        let kept = git(
+            mirror.path(),
+            &[
+                "for-each-ref",
+                "--format=%(objectname)",
+                KEPT_COMMITS_REF_PREFIX,
+            ],
+        );
+        assert_eq!(kept, commit_str);
+        Ok(())
+    }
+
+    /// Submodules with local urls are only cloned if the file
+    /// transport is allowed explicitly, which can't be done via the
+    /// config of the superproject, thus set it in the environment of
+    /// the test process (only relaxes this restriction for the git
+    /// processes of other tests, too).
+    fn allow_file_protocol_for_submodules() {
+        static ONCE: std::sync::Once = std::sync::Once::new();
+        ONCE.call_once(|| {
+            // Safety: the variables are only read by the git child
+            // processes, via the environment copied on spawning
+            unsafe {
+                std::env::set_var("GIT_CONFIG_COUNT", "1");
+                std::env::set_var("GIT_CONFIG_KEY_0", "protocol.file.allow");
+                std::env::set_var("GIT_CONFIG_VALUE_0", "always");
+            }
+        });
+    }
+
+    #[test]
+    fn t_check_submodule_name() {
+        for name in ["sub", "libs/sub", "libs/sub.git", "a..b"] {
+            assert!(check_submodule_name(name).is_ok(), "{name:?}");
+        }
+        for name in ["", "/sub", "..", "../sub", "libs/../../sub", "libs/./sub"] {
+            assert!(check_submodule_name(name).is_err(), "{name:?}");
+        }
+    }
+
+    #[test]
+    fn t_update_submodules_of() -> Result<()> {
+        allow_file_protocol_for_submodules();
+        let test_dir = TestDir::new("git-mirror-submodules");
+        let sub_path = test_dir.path().append("sub");
+        init_repository(&sub_path);
+        std::fs::write(sub_path.append("file"), "1")?;
+        git(&sub_path, &["add", "file"]);
+        git(&sub_path, &["commit", "--quiet", "-m", "1"]);
+
+        let super_path = test_dir.path().append("super");
+        init_repository(&super_path);
+        let sub_url = sub_path.to_str().unwrap();
+        git(
+            &super_path,
+            &["submodule", "--quiet", "add", sub_url, "libs/sub"],
+        );
+        git(&super_path, &["commit", "--quiet", "-m", "add submodule"]);
+
+        let pool_base_dir: Arc<Path> = test_dir.path().append("pool").into();
+        std::fs::create_dir_all(&pool_base_dir)?;
+        let mirror = GitMirror::new(
+            pool_base_dir.clone_arc(),
+            super_path.to_str().unwrap().parse()?,
+        );
+        let working_dir = mirror.clone_with_reference(&pool_base_dir, "0", true)?;
+        let working_dir_path = working_dir.working_dir_path_ref().to_path_buf();
+        git(&working_dir_path, &["submodule", "--quiet", "init"]);
+
+        mirror.update_submodules_of(&working_dir)?;
+        assert_eq!(
+            std::fs::read_to_string(working_dir_path.append("libs/sub/file"))?,
+            "1"
+        );
+        let module_mirror_path = mirror.path().append("modules/libs/sub");
+        assert!(module_mirror_path.is_dir());
+        // The submodule uses the objects of the submodule's mirror
+        let alternates = std::fs::read_to_string(
+            working_dir_path.append(".git/modules/libs/sub/objects/info/alternates"),
+        )?;
+        assert!(
+            alternates.contains("modules/libs/sub"),
+            "alternates: {alternates:?}"
+        );
+
+        // A new submodule commit: the submodule mirror is updated
+        std::fs::write(sub_path.append("file"), "2")?;
+        git(&sub_path, &["commit", "--quiet", "-am", "2"]);
+        let sub_commit = git(&sub_path, &["rev-parse", "HEAD"]);
+        git(
+            &super_path.append("libs/sub"),
+            &["pull", "--quiet", "origin", "main"],
+        );
+        git(
+            &super_path,
+            &["commit", "--quiet", "-am", "update submodule"],
+        );
+        mirror.fetch_into(&working_dir, None)?;
+        git(
+            &working_dir_path,
+            &["reset", "--quiet", "--hard", &format!("{REMOTE_NAME}/main")],
+        );
+        mirror.update_submodules_of(&working_dir)?;
+        assert_eq!(
+            std::fs::read_to_string(working_dir_path.append("libs/sub/file"))?,
+            "2"
+        );
+        assert_eq!(git(&module_mirror_path, &["rev-parse", "main"]), sub_commit);
+
+        // Names leaving the modules dir are rejected
+        assert!(mirror.fetch_submodule("../escaped", sub_url).is_err());
+        assert!(!pool_base_dir.append("escaped").exists());
+        Ok(())
+    }
+}
@@ -3,7 +3,7 @@ use std::{fs::create_dir_all, path::PathBuf};
 use anyhow::Result;
 use cj_path_util::path_util::AppendToPath;
 
-use crate::{ctx, utillib::home::home_dir};
+use crate::{ctx, serde_types::proper_dirname::ProperDirname, utillib::home::home_dir};
 
 /// Relative path to directory from $HOME in which to keep state files
 /// for the application.
@@ -12,8 +12,11 @@ const GLOBAL_APP_STATE_DIR_NAME: &str = ".evobench";
 /// Representation of a directory below $HOME in which to keep state
 /// for the installation. The full folder structure of that folder
 /// should be represented via this type. Method calls to particular
-/// subfolders create subfolder(s) as necessary.
+/// subfolders create subfolder(s) as necessary. For projects
+/// configured in `RunConfig.projects`, `base_dir` is the project's
+/// subfolder, `root_dir` still the main folder.
 pub struct GlobalAppStateDir {
+    root_dir: PathBuf,
     base_dir: PathBuf,
 }
 
@@ -22,9 +25,35 @@ impl GlobalAppStateDir {
     /// necessary.
     pub fn new() -> Result<Self, anyhow::Error> {
         let home = home_dir()?;
-        let base_dir = home.append(GLOBAL_APP_STATE_DIR_NAME);
+        Self::with_root_dir(home.append(GLOBAL_APP_STATE_DIR_NAME))
+    }
+
+    /// Use `root_dir` as the main folder, created if necessary.
+    pub fn with_root_dir(root_dir: PathBuf) -> Result<Self> {
+        create_dir_all(&root_dir).map_err(ctx!("creating dir {root_dir:?}"))?;
+        Ok(Self {
+            base_dir: root_dir.clone(),
+            root_dir,
+        })
+    }
+
+    /// The state dir for the given project (`projects/$name` below
+    /// the main folder), created if necessary.
+    pub fn for_project(&self, project: &ProperDirname) -> Result<Self> {
+        let base_dir = (&self.root_dir).append("projects").append(project.as_str());
         create_dir_all(&base_dir).map_err(ctx!("creating dir {base_dir:?}"))?;
-        Ok(Self { base_dir })
+        Ok(Self {
+            root_dir: self.root_dir.clone(),
+            base_dir,
+        })
+    }
+
+    /// A directory that is locked while running a job, shared by all
+    /// projects so that only one job is ever run at the same time.
+    pub fn global_run_lock_dir(&self) -> Result<PathBuf> {
+        let dir = (&self.root_dir).append("global_run_lock");
+        create_dir_all(&dir).map_err(ctx!("creating dir {dir:?}"))?;
+        Ok(dir)
     }
 
     pub fn subdir(&self, dir_name: &str) -> Result<PathBuf> {
@@ -65,4 +94,61 @@ impl GlobalAppStateDir {
     pub fn already_inserted_base(&self) -> Result<PathBuf> {
         self.subdir("already_inserted")
     }
+
+    /// The cache file for the commit graph of the target project
+    /// (see `GitGraph::open`)
+    pub fn git_graph_cache_path(&self) -> PathBuf {
+        self.base_dir.join("git_graph.cache")
+    }
+
+    /// A KeyVal database of (branch name hash -> last seen commit),
+    /// for `evobench poll`.
+    pub fn polled_heads_base(&self) -> Result<PathBuf> {
+        self.subdir("polled_heads")
+    }
+
+    /// A KeyVal database of (tag pattern hash -> time of first poll),
+    /// for `evobench poll`.
+    pub fn polled_tag_patterns_base(&self) -> Result<PathBuf> {
+        self.subdir("polled_tag_patterns")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utillib::test_dir::TestDir;
+
+    use super::*;
+
+    #[test]
+    fn t_for_project() -> Result<()> {
+        let test_dir = TestDir::new("global-app-state-dir");
+        let root_dir = test_dir.path().to_owned();
+        let project = |name: &str| -> ProperDirname { name.parse().expect("valid name") };
+        let main = GlobalAppStateDir::with_root_dir(root_dir.clone())?;
+        let foo = main.for_project(&project("foo"))?;
+        let bar = main.for_project(&project("bar"))?;
+
+        assert_eq!(main.run_queues_basedir()?, root_dir.join("queues"));
+        assert_eq!(
+            foo.run_queues_basedir()?,
+            root_dir.join("projects/foo/queues")
+        );
+        assert_eq!(
+            bar.working_directory_pool_base()?,
+            root_dir.join("projects/bar/working_directory_pool")
+        );
+        assert!(
+            root_dir
+                .join("projects/bar/working_directory_pool")
+                .is_dir()
+        );
+
+        // The run lock is shared
+        let lock_dir = root_dir.join("global_run_lock");
+        assert_eq!(main.global_run_lock_dir()?, lock_dir);
+        assert_eq!(foo.global_run_lock_dir()?, lock_dir);
+        assert_eq!(bar.global_run_lock_dir()?, lock_dir);
+        Ok(())
+    }
 }
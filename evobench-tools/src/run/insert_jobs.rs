@@ -59,7 +59,7 @@ pub struct DryRunOpt {
     /// Show the details of the jobs that would be inserted
     /// instead of inserting them.
     #[clap(long)]
-    dry_run: bool,
+    pub dry_run: bool,
 }
 
 /// Unless `dry_run` is true (in which a report is printed to stdout),
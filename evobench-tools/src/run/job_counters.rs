@@ -0,0 +1,114 @@
+//! Counters of the job runs carried out from the queues (including
+//! the runs carried out on workers), for the `_total` counters
+//! exported by `metrics.rs`.
+//!
+//! The counters are kept as a `KeyVal` database (one entry per
+//! counter) in the queues base directory. They only ever increase.
+
+use std::{borrow::Cow, path::Path};
+
+use anyhow::Result;
+
+use crate::key_val_fs::{
+    as_key::AsKey,
+    key_val::{KeyVal, KeyValConfig, KeyValSync},
+};
+
+/// Name of the subdirectory of the queues base directory holding the
+/// counters (leading dot so that it isn't confused with a queue)
+const JOB_COUNTERS_DIR_NAME: &str = ".job_counters";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum JobCounter {
+    /// Runs of jobs, successful or not
+    Runs,
+    /// Runs that failed
+    Failures,
+}
+
+impl AsKey for JobCounter {
+    fn as_filename_str(&self) -> Cow<'_, str> {
+        match self {
+            JobCounter::Runs => "runs",
+            JobCounter::Failures => "failures",
+        }
+        .into()
+    }
+
+    fn try_from_filename_str(file_name: &str) -> Option<Self> {
+        match file_name {
+            "runs" => Some(JobCounter::Runs),
+            "failures" => Some(JobCounter::Failures),
+            _ => None,
+        }
+    }
+}
+
+/// Access to the persistent counters
+#[derive(Debug)]
+pub struct JobCounters {
+    key_val: KeyVal<JobCounter, u64>,
+}
+
+impl JobCounters {
+    /// Returns `None` if `create_dir_if_not_exists` is false and no
+    /// run was recorded yet (the directory doesn't exist).
+    pub fn open(run_queues_basedir: &Path, create_dir_if_not_exists: bool) -> Result<Option<Self>> {
+        let dir = run_queues_basedir.join(JOB_COUNTERS_DIR_NAME);
+        if !create_dir_if_not_exists && !dir.exists() {
+            return Ok(None);
+        }
+        let key_val = KeyVal::open(
+            dir,
+            KeyValConfig {
+                sync: KeyValSync::All,
+                create_dir_if_not_exists,
+            },
+            None,
+        )?;
+        Ok(Some(Self { key_val }))
+    }
+
+    pub fn get(&self, counter: JobCounter) -> Result<u64> {
+        Ok(self.key_val.get(&counter)?.unwrap_or(0))
+    }
+
+    /// Count a run, and a failure unless `succeeded`
+    pub fn record_run(&self, succeeded: bool) -> Result<()> {
+        let _lock = self.key_val.lock_exclusive()?;
+        let increment = |counter: JobCounter| -> Result<()> {
+            let n = self.get(counter)?;
+            self.key_val.insert(&counter, &(n + 1), false)?;
+            Ok(())
+        };
+        increment(JobCounter::Runs)?;
+        if !succeeded {
+            increment(JobCounter::Failures)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utillib::test_dir::TestDir;
+
+    use super::*;
+
+    #[test]
+    fn t_job_counters() -> Result<()> {
+        let test_dir = TestDir::new("job-counters");
+        assert!(JobCounters::open(test_dir.path(), false)?.is_none());
+
+        let counters = JobCounters::open(test_dir.path(), true)?.expect("created");
+        assert_eq!(counters.get(JobCounter::Runs)?, 0);
+        counters.record_run(true)?;
+        counters.record_run(false)?;
+        counters.record_run(true)?;
+
+        let counters = JobCounters::open(test_dir.path(), false)?.expect("exists");
+        assert_eq!(counters.get(JobCounter::Runs)?, 3);
+        assert_eq!(counters.get(JobCounter::Failures)?, 1);
+        Ok(())
+    }
+}
@@ -189,9 +189,10 @@ impl CustomParameters {
             }
             let allowable_key: AllowedEnvVar<AllowableCustomEnvVar> = AllowedEnvVar::from_str(key)?;
             if let Some(allowed_custom_parameter) = custom_parameters_required.get(&allowable_key) {
-                let val =
-                    CustomParameterValue::checked_from(allowed_custom_parameter.r#type, value)
-                        .map_err(ctx!("for variable {:?}", key.as_str()))?;
+                let val = allowed_custom_parameter
+                    .r#type
+                    .checked_value(value)
+                    .map_err(ctx!("for variable {:?}", key.as_str()))?;
 
                 res.insert(key.clone(), val);
             } else {
@@ -206,8 +207,14 @@ impl CustomParameters {
             }
         }
         for (key, allowed_custom_parameter) in custom_parameters_required.iter() {
-            if allowed_custom_parameter.required {
-                if !res.contains_key(key) {
+            if !res.contains_key(key) {
+                if let Some(default) = &allowed_custom_parameter.default {
+                    let val = allowed_custom_parameter
+                        .r#type
+                        .checked_value(default)
+                        .map_err(ctx!("default value for variable {:?}", key.as_str()))?;
+                    res.insert(key.clone(), val);
+                } else if allowed_custom_parameter.required {
                     bail!("missing custom parameter with name {:?}", key.as_str())
                 }
             }
@@ -224,6 +231,29 @@ impl CustomParameters {
     }
 }
 
+/// Expand per-parameter value lists to their cartesian product: one
+/// map per combination of values, the last key varying fastest.
+/// Gives a single empty map for an empty `matrix`, and no maps if any
+/// of the value lists is empty.
+pub fn custom_parameters_matrix(
+    matrix: &BTreeMap<AllowedEnvVar<AllowableCustomEnvVar>, Vec<KString>>,
+) -> Vec<BTreeMap<AllowedEnvVar<AllowableCustomEnvVar>, KString>> {
+    let mut combinations = vec![BTreeMap::new()];
+    for (key, values) in matrix {
+        combinations = combinations
+            .into_iter()
+            .flat_map(|combination| {
+                values.iter().map(move |value| {
+                    let mut combination = combination.clone();
+                    combination.insert(key.clone(), value.clone());
+                    combination
+                })
+            })
+            .collect();
+    }
+    combinations
+}
+
 fn display_for_btreemap<S: AsRef<str>>(
     slf: &BTreeMap<AllowedEnvVar<AllowableCustomEnvVar>, S>,
     f: &mut std::fmt::Formatter<'_>,
@@ -327,3 +357,42 @@ pub struct Key {
     /// evobench-probes library after executing a run.
     pub late_context: LateContext,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_custom_parameters_matrix() -> Result<()> {
+        let var = |s: &str| AllowedEnvVar::<AllowableCustomEnvVar>::from_str(s);
+        let vals =
+            |vs: &[&str]| -> Vec<KString> { vs.iter().map(|v| KString::from_ref(*v)).collect() };
+        let show = |combinations: Vec<BTreeMap<_, _>>| -> Vec<String> {
+            combinations
+                .into_iter()
+                .map(|m| UncheckedCustomParameters(m).to_string())
+                .collect()
+        };
+
+        assert_eq!(show(custom_parameters_matrix(&BTreeMap::new())), [""]);
+
+        let mut matrix = BTreeMap::new();
+        matrix.insert(var("CONCURRENCY")?, vals(&["1", "8"]));
+        matrix.insert(var("DATASET")?, vals(&["a", "b", "c"]));
+        assert_eq!(
+            show(custom_parameters_matrix(&matrix)),
+            [
+                "CONCURRENCY=1,DATASET=a",
+                "CONCURRENCY=1,DATASET=b",
+                "CONCURRENCY=1,DATASET=c",
+                "CONCURRENCY=8,DATASET=a",
+                "CONCURRENCY=8,DATASET=b",
+                "CONCURRENCY=8,DATASET=c",
+            ]
+        );
+
+        matrix.insert(var("EMPTY")?, vals(&[]));
+        assert!(custom_parameters_matrix(&matrix).is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,488 @@
+//! Metrics about the benchmark results and the health of the
+//! benchmarking system in the OpenMetrics text format, for scraping by
+//! Prometheus (`evobench metrics serve`) or for the textfile collector
+//! of the node exporter (`evobench metrics write`).
+//!
+//! Exported are the medians of all probes from the most recent run
+//! of every target / custom parameters / situation / host class
+//! combination (from the results index, see
+//! `output_directory::results_index`), the number of jobs in every
+//! queue (which includes the done and erroneous jobs queues, if
+//! configured), the total number of job runs and of failed ones (see
+//! `job_counters.rs`), the number of working directories per status,
+//! and the number of indexed runs and the time of the last one. Only
+//! local files are read; the HTTP server only listens on loopback
+//! addresses.
+
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fmt::{Display, Write as _},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Result, anyhow, bail};
+use cj_path_util::unix::polyfill::add_extension;
+
+use crate::{
+    ctx, info,
+    io_utils::div::xrename,
+    run::{
+        config::RunConfig,
+        job_counters::JobCounter,
+        output_directory::results_index::{LatestMedian, ResultsIndex},
+        run_queue::RunQueue,
+        run_queues::RunQueues,
+        sub_command::open_working_directory_pool,
+        webhook::{read_request, write_response_with_content_type},
+        working_directory::Status,
+        working_directory_pool::WorkingDirectoryPoolBaseDir,
+    },
+    utillib::arc::CloneArc,
+    warn,
+};
+
+/// The default address for `evobench metrics serve`
+pub const DEFAULT_METRICS_LISTEN: &str = "127.0.0.1:9465";
+
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Timeout for reading a request or writing the response
+const IO_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Escape a label value as required by the text format
+fn escape_label_value(s: &str) -> Cow<'_, str> {
+    if s.contains(['\\', '"', '\n']) {
+        let mut out = String::with_capacity(s.len() + 2);
+        for c in s.chars() {
+            match c {
+                '\\' => out.push_str("\\\\"),
+                '"' => out.push_str("\\\""),
+                '\n' => out.push_str("\\n"),
+                _ => out.push(c),
+            }
+        }
+        out.into()
+    } else {
+        s.into()
+    }
+}
+
+/// Accumulates the text exposition. All samples of a metric family
+/// must be written right after its `family` call.
+struct MetricsWriter {
+    out: String,
+}
+
+impl MetricsWriter {
+    fn new() -> Self {
+        Self { out: String::new() }
+    }
+
+    fn family(&mut self, name: &str, help: &str) {
+        self.family_of_type(name, "gauge", help);
+    }
+
+    /// The samples of a counter family are named `{name}_total`
+    fn counter_family(&mut self, name: &str, help: &str) {
+        self.family_of_type(name, "counter", help);
+    }
+
+    fn family_of_type(&mut self, name: &str, metric_type: &str, help: &str) {
+        let out = &mut self.out;
+        writeln!(out, "# TYPE {name} {metric_type}").expect("no error writing to String");
+        writeln!(out, "# HELP {name} {help}").expect("no error writing to String");
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        let out = &mut self.out;
+        out.push_str(name);
+        if !labels.is_empty() {
+            out.push('{');
+            for (i, (label, label_value)) in labels.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write!(out, "{label}=\"{}\"", escape_label_value(label_value))
+                    .expect("no error writing to String");
+            }
+            out.push('}');
+        }
+        writeln!(out, " {value}").expect("no error writing to String");
+    }
+
+    fn finish(mut self) -> String {
+        self.out.push_str("# EOF\n");
+        self.out
+    }
+}
+
+fn write_result_metrics(
+    w: &mut MetricsWriter,
+    latest_medians: &[LatestMedian],
+    num_runs_and_last_run_time: (u64, Option<i64>),
+    now: SystemTime,
+) {
+    let situation_str = |m: &LatestMedian| -> String { m.situation.clone().unwrap_or_default() };
+    // Empty for runs on the local machine
+    let host_class_str = |m: &LatestMedian| -> String { m.host_class.clone().unwrap_or_default() };
+
+    w.family(
+        "evobench_probe_median",
+        "Median of the probe in the most recent run, in the given unit",
+    );
+    for m in latest_medians {
+        let situation = situation_str(m);
+        let host_class = host_class_str(m);
+        w.sample(
+            "evobench_probe_median",
+            &[
+                ("target", m.target.as_str()),
+                ("custom_parameters", m.custom_parameters.as_str()),
+                ("situation", situation.as_str()),
+                ("host_class", host_class.as_str()),
+                ("field", m.field.as_str()),
+                ("probe", m.probe.as_str()),
+                ("unit", m.unit.as_str()),
+            ],
+            m.median,
+        );
+    }
+
+    w.family(
+        "evobench_latest_run_timestamp_seconds",
+        "Unix time of the most recent run, with its commit",
+    );
+    let mut latest_runs: BTreeMap<(&str, &str, String, String, &str), i64> = BTreeMap::new();
+    for m in latest_medians {
+        latest_runs.insert(
+            (
+                m.target.as_str(),
+                m.custom_parameters.as_str(),
+                situation_str(m),
+                host_class_str(m),
+                m.commit_id.as_str(),
+            ),
+            m.run_time,
+        );
+    }
+    for ((target, custom_parameters, situation, host_class, commit_id), run_time) in &latest_runs {
+        w.sample(
+            "evobench_latest_run_timestamp_seconds",
+            &[
+                ("target", *target),
+                ("custom_parameters", *custom_parameters),
+                ("situation", situation.as_str()),
+                ("host_class", host_class.as_str()),
+                ("commit", *commit_id),
+            ],
+            run_time,
+        );
+    }
+
+    let (num_runs, last_run_time) = num_runs_and_last_run_time;
+    w.family(
+        "evobench_indexed_runs",
+        "Number of runs in the results index",
+    );
+    w.sample("evobench_indexed_runs", &[], num_runs);
+    if let Some(last_run_time) = last_run_time {
+        w.family(
+            "evobench_last_run_timestamp_seconds",
+            "Unix time of the last successful run",
+        );
+        w.sample("evobench_last_run_timestamp_seconds", &[], last_run_time);
+        let now_secs = now
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        w.family(
+            "evobench_seconds_since_last_run",
+            "Seconds since the last successful run",
+        );
+        w.sample(
+            "evobench_seconds_since_last_run",
+            &[],
+            (now_secs - last_run_time).max(0),
+        );
+    }
+}
+
+fn write_queue_metrics(w: &mut MetricsWriter, queues: &RunQueues) -> Result<()> {
+    w.family(
+        "evobench_queue_jobs",
+        "Number of jobs in the queue; kind is pipeline, done, erroneous or superseded",
+    );
+    let mut sample = |kind: &str, run_queue: &RunQueue| -> Result<()> {
+        let n = run_queue.queue.sorted_keys(false, None, false)?.len();
+        w.sample(
+            "evobench_queue_jobs",
+            &[("queue", run_queue.file_name.as_str()), ("kind", kind)],
+            n,
+        );
+        Ok(())
+    };
+    for run_queue in queues.pipeline() {
+        sample("pipeline", run_queue)?;
+    }
+    if let Some(run_queue) = queues.done_jobs_queue() {
+        sample("done", run_queue)?;
+    }
+    if let Some(run_queue) = queues.erroneous_jobs_queue() {
+        sample("erroneous", run_queue)?;
+    }
+    if let Some(run_queue) = queues.superseded_jobs_queue() {
+        sample("superseded", run_queue)?;
+    }
+    Ok(())
+}
+
+fn write_job_counter_metrics(w: &mut MetricsWriter, runs: u64, failures: u64) {
+    w.counter_family(
+        "evobench_jobs_run",
+        "Number of job runs (including failed ones) since the queues were created",
+    );
+    w.sample("evobench_jobs_run_total", &[], runs);
+    w.counter_family(
+        "evobench_jobs_failed",
+        "Number of failed job runs since the queues were created",
+    );
+    w.sample("evobench_jobs_failed_total", &[], failures);
+}
+
+fn write_working_directory_metrics(
+    w: &mut MetricsWriter,
+    conf: &RunConfig,
+    working_directory_base_dir: &Arc<WorkingDirectoryPoolBaseDir>,
+) -> Result<()> {
+    let working_directory_pool =
+        open_working_directory_pool(conf, working_directory_base_dir.clone_arc(), true, None)?
+            .into_inner();
+    let mut counts: BTreeMap<&str, usize> = Status::ALL
+        .iter()
+        .map(|status| (status.as_str(), 0))
+        .collect();
+    for (_, wd) in working_directory_pool.all_entries() {
+        *counts
+            .entry(wd.working_directory_status.status.as_str())
+            .or_default() += 1;
+    }
+    w.family(
+        "evobench_working_directories",
+        "Number of working directories with the given status",
+    );
+    for (status, n) in counts {
+        w.sample("evobench_working_directories", &[("status", status)], n);
+    }
+    w.family(
+        "evobench_working_directories_capacity",
+        "Configured maximum number of working directories",
+    );
+    w.sample(
+        "evobench_working_directories_capacity",
+        &[],
+        working_directory_pool.capacity(),
+    );
+    Ok(())
+}
+
+/// Gather all metrics, in the OpenMetrics text format. If the results
+/// index does not exist or can't be read, the result metrics are left
+/// out (with a warning).
+pub fn gather_metrics(
+    conf: &RunConfig,
+    queues: &RunQueues,
+    working_directory_base_dir: &Arc<WorkingDirectoryPoolBaseDir>,
+) -> Result<String> {
+    let mut w = MetricsWriter::new();
+
+    let results = ResultsIndex::open_read_only(conf.output_dir.path.clone_arc()).and_then(
+        |index| -> Result<_> { Ok((index.latest_medians()?, index.num_runs_and_last_run_time()?)) },
+    );
+    match results {
+        Ok((latest_medians, num_runs_and_last_run_time)) => write_result_metrics(
+            &mut w,
+            &latest_medians,
+            num_runs_and_last_run_time,
+            SystemTime::now(),
+        ),
+        Err(e) => warn!("leaving out the result metrics: {e:#}"),
+    }
+
+    write_queue_metrics(&mut w, queues)?;
+    let (runs, failures) = if let Some(job_counters) = queues.job_counters() {
+        (
+            job_counters.get(JobCounter::Runs)?,
+            job_counters.get(JobCounter::Failures)?,
+        )
+    } else {
+        (0, 0)
+    };
+    write_job_counter_metrics(&mut w, runs, failures);
+    write_working_directory_metrics(&mut w, conf, working_directory_base_dir)?;
+
+    Ok(w.finish())
+}
+
+/// Write `metrics` to `path` atomically, as the textfile collector
+/// requires
+pub fn write_metrics_file(metrics: &str, path: &Path) -> Result<()> {
+    let tmp_path =
+        add_extension(path, "tmp").ok_or_else(|| anyhow!("path misses a filename: {path:?}"))?;
+    std::fs::write(&tmp_path, metrics).map_err(ctx!("writing file {tmp_path:?}"))?;
+    xrename(&tmp_path, path)
+}
+
+/// A minimal HTTP server answering `GET /metrics`, one request per
+/// connection
+pub struct MetricsServer {
+    listener: TcpListener,
+}
+
+impl MetricsServer {
+    /// Only loopback addresses are accepted for `listen`
+    pub fn bind(listen: SocketAddr) -> Result<Self> {
+        if !listen.ip().is_loopback() {
+            bail!(
+                "refusing to listen on {listen}: the metrics server only listens \
+                 on loopback addresses (use a reverse proxy for remote access)"
+            )
+        }
+        let listener = TcpListener::bind(listen).map_err(ctx!("listening on {listen}"))?;
+        info!("metrics server listening on {listen}");
+        Ok(Self { listener })
+    }
+
+    /// Handle requests forever, calling `gather` for every scrape.
+    /// Errors while handling a request are reported to the client
+    /// (and logged), not returned.
+    pub fn serve(&self, gather: impl Fn() -> Result<String>) -> Result<()> {
+        for stream in self.listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("accepting metrics connection: {e}");
+                    continue;
+                }
+            };
+            if let Err(e) = self.handle_request(&mut stream, &gather) {
+                warn!("handling metrics request: {e:#}");
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_request(
+        &self,
+        stream: &mut TcpStream,
+        gather: &impl Fn() -> Result<String>,
+    ) -> Result<()> {
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        let text = "text/plain; charset=utf-8";
+        let request = match read_request(stream) {
+            Ok(request) => request,
+            Err(e) => {
+                return write_response_with_content_type(
+                    stream,
+                    400,
+                    text,
+                    format!("{e:#}\n").as_bytes(),
+                );
+            }
+        };
+        if request.method != "GET" {
+            return write_response_with_content_type(
+                stream,
+                405,
+                text,
+                format!("method {} not allowed\n", request.method).as_bytes(),
+            );
+        }
+        let path = request.path.split('?').next().unwrap_or("");
+        if !(path == "/metrics" || path == "/") {
+            return write_response_with_content_type(stream, 404, text, b"not found\n");
+        }
+        match gather() {
+            Ok(metrics) => {
+                write_response_with_content_type(stream, 200, CONTENT_TYPE, metrics.as_bytes())
+            }
+            Err(e) => {
+                warn!("gathering metrics: {e:#}");
+                write_response_with_content_type(stream, 500, text, format!("{e:#}\n").as_bytes())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_escape_label_value() {
+        assert_eq!(escape_label_value("main > foo"), "main > foo");
+        assert_eq!(escape_label_value("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+
+    #[test]
+    fn t_result_metrics() {
+        let m = LatestMedian {
+            target: "api".into(),
+            custom_parameters: "A=1".into(),
+            situation: None,
+            host_class: Some("w".into()),
+            commit_id: "c1".into(),
+            run_time: 1000,
+            field: "real".into(),
+            probe: "main".into(),
+            unit: "ns".into(),
+            median: 42,
+        };
+        let mut w = MetricsWriter::new();
+        write_result_metrics(
+            &mut w,
+            &[m],
+            (3, Some(1000)),
+            UNIX_EPOCH + Duration::from_secs(1060),
+        );
+        assert_eq!(
+            w.finish(),
+            "# TYPE evobench_probe_median gauge\n\
+             # HELP evobench_probe_median Median of the probe in the most recent run, in the given unit\n\
+             evobench_probe_median{target=\"api\",custom_parameters=\"A=1\",situation=\"\",host_class=\"w\",field=\"real\",probe=\"main\",unit=\"ns\"} 42\n\
+             # TYPE evobench_latest_run_timestamp_seconds gauge\n\
+             # HELP evobench_latest_run_timestamp_seconds Unix time of the most recent run, with its commit\n\
+             evobench_latest_run_timestamp_seconds{target=\"api\",custom_parameters=\"A=1\",situation=\"\",host_class=\"w\",commit=\"c1\"} 1000\n\
+             # TYPE evobench_indexed_runs gauge\n\
+             # HELP evobench_indexed_runs Number of runs in the results index\n\
+             evobench_indexed_runs 3\n\
+             # TYPE evobench_last_run_timestamp_seconds gauge\n\
+             # HELP evobench_last_run_timestamp_seconds Unix time of the last successful run\n\
+             evobench_last_run_timestamp_seconds 1000\n\
+             # TYPE evobench_seconds_since_last_run gauge\n\
+             # HELP evobench_seconds_since_last_run Seconds since the last successful run\n\
+             evobench_seconds_since_last_run 60\n\
+             # EOF\n"
+        );
+    }
+
+    #[test]
+    fn t_job_counter_metrics() {
+        let mut w = MetricsWriter::new();
+        write_job_counter_metrics(&mut w, 7, 2);
+        assert_eq!(
+            w.finish(),
+            "# TYPE evobench_jobs_run counter\n\
+             # HELP evobench_jobs_run Number of job runs (including failed ones) since the queues were created\n\
+             evobench_jobs_run_total 7\n\
+             # TYPE evobench_jobs_failed counter\n\
+             # HELP evobench_jobs_failed Number of failed job runs since the queues were created\n\
+             evobench_jobs_failed_total 2\n\
+             # EOF\n"
+        );
+    }
+}
@@ -14,7 +14,7 @@ use anyhow::{Result, anyhow, bail};
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
 
 use crate::{
-    ctx,
+    ctx, info,
     key_val_fs::{
         as_key::AsKey,
         key_val::{KeyVal, KeyValError},
@@ -38,12 +38,15 @@ trait FromStrMigrating: Sized + DeserializeOwned + Serialize {
     fn from_str_migrating(s: &str) -> Result<(Self, bool)>;
 }
 
-/// Returns how many items were migrated. `handle_conflict` receives
-/// two values resulting from migration or pre-existing entry in the
-/// table, that both yield the same key; its return value is stored
-/// for the key. It can return an error to stop migration.
+/// Returns how many items were migrated. `transform` is applied to
+/// each (migrated) value and returns whether it changed the
+/// value. `handle_conflict` receives two values resulting from
+/// migration or pre-existing entry in the table, that both yield the
+/// same key; its return value is stored for the key. It can return
+/// an error to stop migration.
 fn migrate_key_val<K: AsKey + Debug + Clone + PartialEq + Ord, T: FromStrMigrating>(
     table: &KeyVal<K, T>,
+    transform: impl Fn(T) -> Result<(T, bool)>,
     gen_key: impl Fn(&K, &T) -> K,
     handle_conflict: impl Fn(&K, T, T) -> Result<T>,
 ) -> Result<usize> {
@@ -71,6 +74,8 @@ fn migrate_key_val<K: AsKey + Debug + Clone + PartialEq + Ord, T: FromStrMigrati
             let path = entry.target_path();
             let s = std::fs::read_to_string(path).map_err(ctx!("reading file {path:?}"))?;
             let (value, needs_saving) = T::from_str_migrating(&s)?;
+            let (value, transformed) = transform(value)?;
+            let needs_saving = needs_saving || transformed;
             let new_key = gen_key(&old_key, &value);
             let key_changed = new_key != old_key;
             if needs_saving || key_changed {
@@ -139,6 +144,7 @@ fn migrate_key_val<K: AsKey + Debug + Clone + PartialEq + Ord, T: FromStrMigrati
 pub fn migrate_queue(run_queue: &RunQueue) -> Result<usize> {
     migrate_key_val(
         run_queue.key_val(),
+        |v| Ok((v, false)),
         // The key (a `TimeKey`) remains the same
         |k, _v| k.clone(),
         // Conflicts can't happen since we never change the key
@@ -153,6 +159,7 @@ pub fn migrate_already_inserted(
 ) -> Result<usize> {
     migrate_key_val(
         table,
+        |v| Ok((v, false)),
         // Recalculate the key from the `BenchmarkingJobParameters`
         |_k, v| v.0.slow_hash(),
         |key, (params1, times1), (_params2, times2)| {
@@ -165,6 +172,66 @@ pub fn migrate_already_inserted(
     )
 }
 
+/// Rewrite the parameters of the entries in the already_inserted
+/// table (the table shown by `evobench list-all`) after the key
+/// layout changed (see `evobench-util rekey`). `rekey` returns
+/// `None` for parameters that stay the same. Returns how many items
+/// were changed.
+pub fn rekey_already_inserted(
+    table: &KeyVal<BenchmarkingJobParametersHash, (BenchmarkingJobParameters, Vec<SystemTime>)>,
+    rekey: impl Fn(&BenchmarkingJobParameters) -> Result<Option<BenchmarkingJobParameters>>,
+) -> Result<usize> {
+    migrate_key_val(
+        table,
+        |(params, times)| match rekey(&params)? {
+            Some(params) => Ok(((params, times), true)),
+            None => Ok(((params, times), false)),
+        },
+        |_k, v| v.0.slow_hash(),
+        |key, (params1, mut times1), (_params2, times2)| {
+            info!("after rekeying, two buckets for key {key:?} exist; merging them");
+            times1.extend(times2);
+            times1.sort();
+            Ok((params1, times1))
+        },
+    )
+}
+
+/// Check that `rekey` succeeds for all entries of the
+/// already_inserted table, without changing anything; to be called
+/// before moving the output directories, since
+/// `rekey_already_inserted` can only be run afterwards. Returns how
+/// many items would be changed.
+pub fn check_rekey_already_inserted(
+    table: &KeyVal<BenchmarkingJobParametersHash, (BenchmarkingJobParameters, Vec<SystemTime>)>,
+    rekey: impl Fn(&BenchmarkingJobParameters) -> Result<Option<BenchmarkingJobParameters>>,
+) -> Result<usize> {
+    let mut num_changed = 0;
+    let mut errors = Vec::new();
+    for key in table.keys(false, None)? {
+        let key = key?;
+        if let Some(entry) = table.entry_opt(&key)? {
+            let path = entry.target_path();
+            let s = std::fs::read_to_string(path).map_err(ctx!("reading file {path:?}"))?;
+            let ((params, _times), _) =
+                <(BenchmarkingJobParameters, Vec<SystemTime>)>::from_str_migrating(&s)?;
+            match rekey(&params) {
+                Ok(Some(_)) => num_changed += 1,
+                Ok(None) => (),
+                Err(e) => errors.push(format!(
+                    "target {:?}, commit {}: {e:#}",
+                    params.command.target_name.as_str(),
+                    params.run_parameters.commit_id
+                )),
+            }
+        }
+    }
+    if !errors.is_empty() {
+        bail!("can't rekey some entries of the already_inserted table: {errors:?}")
+    }
+    Ok(num_changed)
+}
+
 #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 #[serde(deny_unknown_fields)]
 #[serde(rename = "BenchmarkingCommand")]
@@ -6,24 +6,35 @@
 
 pub mod bench_tmp_dir;
 pub mod benchmarking_job;
+pub mod coalescing;
 pub mod command_log_file;
 pub mod config;
 pub mod custom_parameter;
 pub mod dataset_dir_env_var;
+pub mod distributed;
 pub mod env_vars;
+pub mod eta;
+pub mod fair_share;
+pub mod git_mirror;
 pub mod global_app_state_dir;
 pub mod insert_jobs;
+pub mod job_counters;
 pub mod key;
+pub mod metrics;
 pub mod migrate;
 pub mod open_run_queues;
 pub mod output_directory;
+pub mod polled_heads;
 pub mod polling_pool;
+pub mod ref_pattern;
 pub mod run_context;
 pub mod run_job;
 pub mod run_queue;
 pub mod run_queues;
+pub mod serve;
 pub mod stop_start_status;
 pub mod sub_command;
 pub mod versioned_dataset_dir;
+pub mod webhook;
 pub mod working_directory;
 pub mod working_directory_pool;
@@ -2,6 +2,7 @@
 //! other than evobench.rs, too, thus this module.
 
 use std::{
+    sync::Mutex,
     thread::{self, JoinHandle},
     time::Duration,
 };
@@ -12,8 +13,9 @@ use chj_unix_util::polling_signals::SharedPollingSignals;
 use crate::{
     clone,
     run::{
-        config::ShareableConfig, output_directory::html_files::regenerate_index_files,
-        run_queues::RunQueues, sub_command::wd::open_queue_change_signals,
+        config::ShareableConfig, eta::RunDurationEstimator,
+        output_directory::html_files::regenerate_index_files, run_queues::RunQueues,
+        sub_command::wd::open_queue_change_signals,
     },
     utillib::arc::CloneArc,
     warn,
@@ -23,12 +25,21 @@ use crate::{
 pub struct RegenerateIndexFiles {
     signal_change: SharedPollingSignals,
     shareable_config: ShareableConfig,
+    /// Kept across regenerations, so that the durations of previous
+    /// runs are only read once for the ETAs
+    estimator: Mutex<RunDurationEstimator>,
 }
 
 impl RegenerateIndexFiles {
     pub fn run_one(&self) {
         if let Some(signal) = self.signal_change.get_latest_signal() {
-            if let Err(e) = regenerate_index_files(&self.shareable_config, None, None) {
+            let mut estimator = self
+                .estimator
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            if let Err(e) =
+                regenerate_index_files(&self.shareable_config, None, None, Some(&mut estimator))
+            {
                 signal.ignore();
                 // XX backoff
                 warn!("error: regenerate_index_files: {e:#}");
@@ -60,6 +71,9 @@ pub fn open_run_queues(
     let signal_change = open_queue_change_signals(&shareable_config.global_app_state_dir)?;
     let signal_change_sender = signal_change.sender();
     clone!(shareable_config);
+    let estimator = Mutex::new(RunDurationEstimator::new(
+        shareable_config.run_config.output_dir.path.clone_arc(),
+    ));
     Ok((
         RunQueues::open(
             shareable_config.run_config.queues.clone_arc(),
@@ -70,6 +84,7 @@ pub fn open_run_queues(
         RegenerateIndexFiles {
             signal_change,
             shareable_config,
+            estimator,
         },
     ))
 }
@@ -0,0 +1,627 @@
+//! Exporting the results of selected runs as a self-describing
+//! `.tar.zst` archive, and importing such archives into another
+//! output directory.
+//!
+//! The archive contains the files of the selected `RunDir`s (at their
+//! path relative to the output directory) plus a manifest
+//! (`EXPORT_MANIFEST_FILE_NAME`) listing the run dirs and the
+//! SHA-256 hash of every file. Summaries and the run report pages
+//! (which link to the neighbouring runs, thus change when runs are
+//! added) are not exported, they are regenerated on import.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+    sync::Arc,
+};
+
+use anyhow::{Result, anyhow, bail};
+use base64::Engine;
+use cj_path_util::unix::polyfill::add_extension;
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+
+use crate::{
+    config_file::ron_to_string_pretty,
+    ctx,
+    git::GitHash,
+    info,
+    run::{
+        config::{BenchmarkingTarget, RunConfig},
+        output_directory::{
+            post_process::generate_summaries_for_key_dirs,
+            run_report::RUN_REPORT_FILE_NAME,
+            structure::{KeyDir, ReplaceBasePath, RunDir, SubDirs, ToPath, find_key_dirs},
+        },
+    },
+    serde_types::{date_and_time::DateTimeWithOffset, proper_dirname::ProperDirname},
+    utillib::{arc::CloneArc, into_arc_path::IntoArcPath},
+};
+
+pub const EXPORT_MANIFEST_FILE_NAME: &str = "evobench-export-manifest.ron";
+
+/// Increased when the archive layout or manifest change incompatibly
+const EXPORT_FORMAT_VERSION: u32 = 1;
+
+/// Selects the key dirs to export. Filters of the same kind are
+/// or'ed, filters of different kinds and'ed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportFilter {
+    /// `target=NAME`
+    Target(ProperDirname),
+    /// `param=VAR=VALUE`, the custom parameter `VAR` has value `VALUE`
+    Param(String, String),
+    /// `commit=HASH`
+    Commit(GitHash),
+    /// `commits=RANGE`, a revision range like `v1.0..main`; needs to
+    /// be resolved to `Commit` filters via Git
+    Commits(String),
+}
+
+impl FromStr for ExportFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, value) = s.split_once('=').ok_or_else(|| {
+            anyhow!("expecting `target=`, `param=`, `commit=` or `commits=`, got {s:?}")
+        })?;
+        match kind {
+            "target" => Ok(Self::Target(
+                ProperDirname::from_str(value)
+                    .map_err(|msg| anyhow!("invalid target name {value:?}: {msg}"))?,
+            )),
+            "param" => {
+                let (var, val) = value
+                    .split_once('=')
+                    .ok_or_else(|| anyhow!("expecting `param=VAR=VALUE`, got {s:?}"))?;
+                Ok(Self::Param(var.into(), val.into()))
+            }
+            "commit" => Ok(Self::Commit(
+                GitHash::from_str(value).map_err(ctx!("parsing commit id {value:?}"))?,
+            )),
+            "commits" => Ok(Self::Commits(value.into())),
+            _ => bail!("unknown filter kind {kind:?} in {s:?}"),
+        }
+    }
+}
+
+impl Display for ExportFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Target(target) => write!(f, "target={}", target.as_str()),
+            Self::Param(var, val) => write!(f, "param={var}={val}"),
+            Self::Commit(commit) => write!(f, "commit={commit}"),
+            Self::Commits(range) => write!(f, "commits={range}"),
+        }
+    }
+}
+
+impl ExportFilter {
+    /// `Commits` filters are ignored; resolve them first.
+    fn matches_all(filters: &[ExportFilter], key_dir: &KeyDir) -> bool {
+        let parameters_dir = key_dir.parent();
+        let custom_parameters = parameters_dir.custom_parameters().key_val_strs();
+
+        let mut targets = filters
+            .iter()
+            .filter_map(|f| match f {
+                Self::Target(t) => Some(t),
+                _ => None,
+            })
+            .peekable();
+        if targets.peek().is_some() && !targets.any(|t| t == parameters_dir.target_name()) {
+            return false;
+        }
+
+        let mut commits = filters
+            .iter()
+            .filter_map(|f| match f {
+                Self::Commit(c) => Some(c),
+                _ => None,
+            })
+            .peekable();
+        if commits.peek().is_some() && !commits.any(|c| c == key_dir.commit_id()) {
+            return false;
+        }
+
+        // Group the params by variable: or'ed per variable, and'ed
+        // across variables
+        let mut params: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for f in filters {
+            if let Self::Param(var, val) = f {
+                params.entry(var.as_str()).or_default().push(val.as_str());
+            }
+        }
+        params.iter().all(|(var, vals)| {
+            custom_parameters
+                .iter()
+                .any(|(k, v)| k == var && vals.contains(v))
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExportHost {
+    pub hostname: String,
+    pub os: String,
+    pub release: String,
+}
+
+impl ExportHost {
+    fn current() -> Result<Self> {
+        let uname = nix::sys::utsname::uname().map_err(ctx!("uname"))?;
+        Ok(Self {
+            hostname: uname.nodename().to_string_lossy().into_owned(),
+            os: uname.sysname().to_string_lossy().into_owned(),
+            release: uname.release().to_string_lossy().into_owned(),
+        })
+    }
+}
+
+/// Stored in the archive as `EXPORT_MANIFEST_FILE_NAME`
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(rename = "ExportManifest")]
+pub struct ExportManifest {
+    pub format_version: u32,
+    pub created: DateTimeWithOffset,
+    pub evobench_version: String,
+    /// The host the export was made on (the host class of each run
+    /// is stored in its run dir if it was run by a worker)
+    pub host: ExportHost,
+    /// The filters given to `evobench-util export`
+    pub filters: Vec<String>,
+    /// Config excerpt: the repository the commits are from
+    pub remote_repository_url: String,
+    /// Config excerpt: the definitions of the exported targets
+    pub targets: BTreeMap<ProperDirname, Arc<BenchmarkingTarget>>,
+    /// The exported run dirs, relative to the output directory
+    pub run_dirs: Vec<PathBuf>,
+    /// Path (relative to the output directory) to the base64-encoded
+    /// SHA-256 hash of the file contents
+    pub files: BTreeMap<PathBuf, String>,
+}
+
+fn file_hash(path: &Path) -> Result<String> {
+    let mut file = File::open(path).map_err(ctx!("opening {path:?}"))?;
+    let mut hasher = sha2::Sha256::new();
+    std::io::copy(&mut file, &mut hasher).map_err(ctx!("reading {path:?}"))?;
+    Ok(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(hasher.finalize()))
+}
+
+/// The files in `run_dir` that belong to the run, with their hashes,
+/// keyed by path relative to `base`. The uncompressed evobench.log
+/// and the report page (re-written when the next run for the same
+/// key is added) are left out, as is the stats cache (only a cache)
+/// unless the evobench.log has been pruned, in which case the stats
+/// cache holds the run's results.
+fn run_dir_files(run_dir: &RunDir, base: &Path) -> Result<BTreeMap<PathBuf, String>> {
+    let dir = run_dir.to_path();
+    let uncompressed_path = run_dir.evobench_log_uncompressed_path();
+    let evobench_log_path = run_dir.evobench_log_path();
+    let have_log =
+        std::fs::exists(&evobench_log_path).map_err(ctx!("checking path {evobench_log_path:?}"))?;
+    let stats_cache_path = run_dir.evobench_stats_cache_path();
+    let report_path = dir.join(RUN_REPORT_FILE_NAME);
+    let mut files = BTreeMap::new();
+    for entry in std::fs::read_dir(dir).map_err(ctx!("opening dir {dir:?}"))? {
+        let entry = entry.map_err(ctx!("reading dir {dir:?}"))?;
+        let path = entry.path();
+        if !entry.file_type()?.is_file() {
+            bail!("run dir {dir:?} contains a non-file entry: {path:?}")
+        }
+        if path == uncompressed_path
+            || (have_log && path == stats_cache_path)
+            || path == report_path
+        {
+            continue;
+        }
+        let relative = path
+            .strip_prefix(base)
+            .map_err(ctx!("path {path:?} is not below {base:?}"))?
+            .to_owned();
+        files.insert(relative, file_hash(&path)?);
+    }
+    Ok(files)
+}
+
+fn run_tar(mut command: Command) -> Result<()> {
+    let status = command
+        .status()
+        .map_err(ctx!("running command {command:?}"))?;
+    if !status.success() {
+        bail!("command {command:?} failed: {status}")
+    }
+    Ok(())
+}
+
+/// Write the runs of the key dirs in `conf.output_dir` matching all
+/// of `filters` to `archive_path`. `ExportFilter::Commits` entries
+/// must already have been resolved to `ExportFilter::Commit` entries;
+/// `filter_strings` are only recorded in the manifest. Returns the
+/// number of exported run dirs.
+pub fn export_runs(
+    conf: &RunConfig,
+    filters: &[ExportFilter],
+    filter_strings: Vec<String>,
+    archive_path: &Path,
+) -> Result<usize> {
+    let output_base_dir = &conf.output_dir.path;
+
+    let mut run_dirs = Vec::new();
+    let mut files = BTreeMap::new();
+    let mut targets = BTreeMap::new();
+    for key_dir in find_key_dirs(output_base_dir)? {
+        if !ExportFilter::matches_all(filters, &key_dir) {
+            continue;
+        }
+        let target_name = key_dir.parent().target_name();
+        if let Some(target) = conf.targets.get(target_name) {
+            targets.insert(target_name.clone(), target.clone_arc());
+        }
+        for run_dir in key_dir.sub_dirs()? {
+            let run_dir = run_dir?;
+            files.extend(run_dir_files(&run_dir, output_base_dir)?);
+            run_dirs.push(
+                run_dir
+                    .to_path()
+                    .strip_prefix(output_base_dir)
+                    .expect("found below output_base_dir")
+                    .to_owned(),
+            );
+        }
+    }
+    if run_dirs.is_empty() {
+        bail!("no runs match the given filters")
+    }
+    let num_run_dirs = run_dirs.len();
+
+    let manifest = ExportManifest {
+        format_version: EXPORT_FORMAT_VERSION,
+        created: DateTimeWithOffset::now(None),
+        evobench_version: env!("CARGO_PKG_VERSION").into(),
+        host: ExportHost::current()?,
+        filters: filter_strings,
+        remote_repository_url: conf.remote_repository.url.to_string(),
+        targets,
+        run_dirs,
+        files,
+    };
+    write_archive(output_base_dir, &manifest, archive_path)?;
+    Ok(num_run_dirs)
+}
+
+/// Write `manifest` and the files listed in it (relative to
+/// `output_base_dir`) to `archive_path`
+fn write_archive(
+    output_base_dir: &Path,
+    manifest: &ExportManifest,
+    archive_path: &Path,
+) -> Result<()> {
+    let staging_dir = add_extension(archive_path, "staging")
+        .ok_or_else(|| anyhow!("archive path {archive_path:?} is missing a file name"))?;
+    std::fs::create_dir(&staging_dir).map_err(ctx!("creating dir {staging_dir:?}"))?;
+    let result = (|| -> Result<()> {
+        let manifest_path = staging_dir.join(EXPORT_MANIFEST_FILE_NAME);
+        std::fs::write(&manifest_path, ron_to_string_pretty(&manifest)?)
+            .map_err(ctx!("writing {manifest_path:?}"))?;
+        let file_list_path = staging_dir.join("files");
+        {
+            let mut out =
+                File::create(&file_list_path).map_err(ctx!("creating {file_list_path:?}"))?;
+            for path in manifest.files.keys() {
+                let path_str = path
+                    .to_str()
+                    .ok_or_else(|| anyhow!("path can't be decoded as string: {path:?}"))?;
+                writeln!(out, "{path_str}").map_err(ctx!("writing {file_list_path:?}"))?;
+            }
+        }
+
+        let tmp_archive_path = add_extension(archive_path, "tmp").expect("checked above");
+        let mut command = Command::new("tar");
+        command
+            .arg("--zstd")
+            .arg("-cf")
+            .arg(&tmp_archive_path)
+            .arg("-C")
+            .arg(&staging_dir)
+            .arg(EXPORT_MANIFEST_FILE_NAME)
+            .arg("-C")
+            .arg(output_base_dir)
+            .args(["--no-recursion", "--verbatim-files-from", "-T"])
+            .arg(&file_list_path);
+        run_tar(command)?;
+        std::fs::rename(&tmp_archive_path, archive_path)
+            .map_err(ctx!("renaming {tmp_archive_path:?} to {archive_path:?}"))?;
+        Ok(())
+    })();
+    std::fs::remove_dir_all(&staging_dir).map_err(ctx!("removing {staging_dir:?}"))?;
+    result
+}
+
+#[derive(Debug, Default)]
+pub struct ImportStats {
+    pub imported: usize,
+    pub already_present: usize,
+    pub conflicts_skipped: usize,
+}
+
+impl Display for ImportStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            imported,
+            already_present,
+            conflicts_skipped,
+        } = self;
+        write!(
+            f,
+            "imported {imported} runs, {already_present} already present, \
+             {conflicts_skipped} conflicting runs skipped"
+        )
+    }
+}
+
+/// Check the unpacked archive in `staging_dir` against its manifest,
+/// returns the manifest.
+fn verify_unpacked(staging_dir: &Path) -> Result<ExportManifest> {
+    let manifest_path = staging_dir.join(EXPORT_MANIFEST_FILE_NAME);
+    let s = std::fs::read_to_string(&manifest_path).map_err(ctx!(
+        "reading {manifest_path:?} (is this an evobench export?)"
+    ))?;
+    let manifest: ExportManifest = ron::from_str(&s).map_err(ctx!("parsing {manifest_path:?}"))?;
+    if manifest.format_version != EXPORT_FORMAT_VERSION {
+        bail!(
+            "unsupported export format version {} (expecting {EXPORT_FORMAT_VERSION})",
+            manifest.format_version
+        )
+    }
+
+    let run_dirs: BTreeSet<&Path> = manifest.run_dirs.iter().map(|p| p.as_path()).collect();
+    for (path, hash) in &manifest.files {
+        if !path.parent().is_some_and(|dir| run_dirs.contains(dir)) {
+            bail!("file {path:?} in manifest is not in any of the listed run dirs")
+        }
+        let unpacked_path = staging_dir.join(path);
+        let actual_hash = file_hash(&unpacked_path)?;
+        if actual_hash != *hash {
+            bail!("hash mismatch for {path:?}: archive is corrupted")
+        }
+    }
+
+    // Reject anything not listed in the manifest
+    fn check_listed(
+        dir: &Path,
+        staging_dir: &Path,
+        files: &BTreeMap<PathBuf, String>,
+    ) -> Result<()> {
+        for entry in std::fs::read_dir(dir).map_err(ctx!("opening dir {dir:?}"))? {
+            let entry = entry.map_err(ctx!("reading dir {dir:?}"))?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                check_listed(&path, staging_dir, files)?;
+            } else {
+                let relative = path.strip_prefix(staging_dir).expect("below staging_dir");
+                if !(file_type.is_file()
+                    && (files.contains_key(relative)
+                        || relative == Path::new(EXPORT_MANIFEST_FILE_NAME)))
+                {
+                    bail!("archive contains entry not listed in the manifest: {relative:?}")
+                }
+            }
+        }
+        Ok(())
+    }
+    check_listed(staging_dir, staging_dir, &manifest.files)?;
+
+    Ok(manifest)
+}
+
+/// Merge the runs from the archive at `archive_path` into
+/// `conf.output_dir`. Runs that already exist with identical files
+/// are skipped; runs that exist with different files are conflicts,
+/// which abort the import before anything is changed unless
+/// `skip_conflicts` is true. The imported runs are added to the
+/// results index, their report pages and the summaries of the
+/// affected key dirs are regenerated (the top-level index files are
+/// not).
+pub fn import_runs(
+    conf: &RunConfig,
+    archive_path: &Path,
+    skip_conflicts: bool,
+) -> Result<ImportStats> {
+    let (stats, imported) =
+        move_runs_into_place(&conf.output_dir.path, archive_path, skip_conflicts)?;
+
+    let mut affected_key_dirs: BTreeMap<PathBuf, Arc<KeyDir>> = BTreeMap::new();
+    for run_dir in &imported {
+        run_dir.update_results_index(None, None);
+        run_dir.update_report_html(conf);
+        let key_dir = run_dir.parent();
+        affected_key_dirs.insert(key_dir.to_path().to_path_buf(), key_dir.clone_arc());
+    }
+    let affected_key_dirs: Vec<Arc<KeyDir>> = affected_key_dirs.into_values().collect();
+    generate_summaries_for_key_dirs(&affected_key_dirs, false, conf)?;
+    Ok(stats)
+}
+
+/// The part of `import_runs` that unpacks the archive and moves the
+/// runs into `output_base_dir`; returns the moved runs, too.
+fn move_runs_into_place(
+    output_base_dir: &Arc<Path>,
+    archive_path: &Path,
+    skip_conflicts: bool,
+) -> Result<(ImportStats, Vec<RunDir>)> {
+    // Unpack inside the output dir, so that the run dirs can be
+    // renamed into place; `find_key_dirs` ignores dot dirs.
+    let staging_dir = output_base_dir.join(format!(".import-{}", DateTimeWithOffset::now(None)));
+    std::fs::create_dir(&staging_dir).map_err(ctx!("creating dir {staging_dir:?}"))?;
+    let result = (|| -> Result<(ImportStats, Vec<RunDir>)> {
+        let mut command = Command::new("tar");
+        command
+            .arg("--zstd")
+            .arg("-xf")
+            .arg(archive_path)
+            .arg("-C")
+            .arg(&staging_dir)
+            .arg("--no-same-owner");
+        run_tar(command)?;
+
+        let manifest = verify_unpacked(&staging_dir)?;
+        let staging_base = staging_dir.as_path().into_arc_path();
+
+        let mut stats = ImportStats::default();
+        let mut to_move = Vec::new();
+        let mut conflicts = Vec::new();
+        for relative in &manifest.run_dirs {
+            let staged = RunDir::try_from(staging_dir.join(relative).into_arc_path())
+                .map_err(ctx!("run dir {relative:?} in archive"))?;
+            if staged.parent().parent().base_path() != &staging_base {
+                bail!("run dir {relative:?} in archive has an unexpected path structure")
+            }
+            let target = staged.replace_base_path(output_base_dir.clone_arc());
+            if target.to_path().exists() {
+                let existing = run_dir_files(&target, output_base_dir)?;
+                let staged_files = run_dir_files(&staged, &staging_dir)?;
+                if existing == staged_files {
+                    stats.already_present += 1;
+                } else {
+                    conflicts.push(relative);
+                }
+            } else {
+                to_move.push((staged, target));
+            }
+        }
+        if !conflicts.is_empty() {
+            if skip_conflicts {
+                for relative in &conflicts {
+                    info!("skipping conflicting run dir {relative:?}");
+                }
+                stats.conflicts_skipped = conflicts.len();
+            } else {
+                bail!(
+                    "these runs already exist with different contents (nothing was imported): \
+                     {conflicts:?}"
+                )
+            }
+        }
+
+        let mut imported = Vec::new();
+        for (staged, target) in to_move {
+            let key_dir_path = target.parent().to_path();
+            std::fs::create_dir_all(key_dir_path)
+                .map_err(ctx!("create_dir_all {key_dir_path:?}"))?;
+            let (from, to) = (staged.to_path(), target.to_path());
+            // (Run dirs without files are not in the archive)
+            std::fs::create_dir_all(from).map_err(ctx!("create_dir_all {from:?}"))?;
+            std::fs::rename(from, to).map_err(ctx!("renaming {from:?} to {to:?}"))?;
+            info!("imported {to:?}");
+            stats.imported += 1;
+            imported.push(target);
+        }
+        Ok((stats, imported))
+    })();
+    std::fs::remove_dir_all(&staging_dir).map_err(ctx!("removing {staging_dir:?}"))?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        run::output_directory::test_runs::{add_test_run, test_key_dir, write_test_stats_cache},
+        utillib::test_dir::TestDir,
+    };
+
+    use super::*;
+
+    #[test]
+    fn t_export_filter() {
+        assert_eq!(
+            ExportFilter::from_str("param=DATASET=SC2open").unwrap(),
+            ExportFilter::Param("DATASET".into(), "SC2open".into())
+        );
+        assert_eq!(
+            ExportFilter::from_str("commits=v1.0..main").unwrap(),
+            ExportFilter::Commits("v1.0..main".into())
+        );
+        let target = ExportFilter::from_str("target=api").unwrap();
+        assert_eq!(target.to_string(), "target=api");
+        assert!(ExportFilter::from_str("param=DATASET").is_err());
+        assert!(ExportFilter::from_str("commit=xyz").is_err());
+        assert!(ExportFilter::from_str("api").is_err());
+    }
+
+    #[test]
+    fn t_run_dir_files_ignores_report() -> Result<()> {
+        let test_dir = TestDir::new("archive-run-dir-files");
+        let output_base_dir = test_dir.path();
+        let key_dir = test_key_dir(output_base_dir, "bench", &"1".repeat(40));
+        let run_dir = add_test_run(&key_dir, DateTimeWithOffset::now(Some(false)), Some("log"));
+        let dir = run_dir.to_path();
+        std::fs::write(dir.join(RUN_REPORT_FILE_NAME), "report")?;
+
+        let files = run_dir_files(&run_dir, output_base_dir)?;
+        assert_eq!(files.len(), 1);
+        assert!(files.contains_key(run_dir.evobench_log_path().strip_prefix(output_base_dir)?));
+
+        // Re-writing the report (when the next run is added) doesn't
+        // make the run conflict with an earlier export of it
+        std::fs::write(dir.join(RUN_REPORT_FILE_NAME), "report, next run")?;
+        assert_eq!(run_dir_files(&run_dir, output_base_dir)?, files);
+        Ok(())
+    }
+
+    #[test]
+    fn t_export_import_pruned_run() -> Result<()> {
+        let test_dir = TestDir::new("archive-pruned-run");
+        let source_dir = test_dir.path().join("source");
+        let key_dir = test_key_dir(&source_dir, "bench", &"1".repeat(40));
+        // Only the stats cache is left of the evobench.log
+        let run_dir = add_test_run(&key_dir, DateTimeWithOffset::now(Some(false)), None);
+        write_test_stats_cache(&run_dir);
+        let stats_cache_path = run_dir.evobench_stats_cache_path();
+
+        let files = run_dir_files(&run_dir, &source_dir)?;
+        assert_eq!(
+            files.keys().collect::<Vec<_>>(),
+            [stats_cache_path.strip_prefix(&source_dir)?]
+        );
+        let manifest = ExportManifest {
+            format_version: EXPORT_FORMAT_VERSION,
+            created: DateTimeWithOffset::now(None),
+            evobench_version: env!("CARGO_PKG_VERSION").into(),
+            host: ExportHost::current()?,
+            filters: Vec::new(),
+            remote_repository_url: "https://example.com/repo.git".into(),
+            targets: BTreeMap::new(),
+            run_dirs: vec![run_dir.to_path().strip_prefix(&source_dir)?.to_owned()],
+            files,
+        };
+        let archive_path = test_dir.path().join("export.tar.zst");
+        write_archive(&source_dir, &manifest, &archive_path)?;
+
+        let target_dir = test_dir.path().join("target").into_arc_path();
+        std::fs::create_dir(&target_dir)?;
+        let (stats, imported) = move_runs_into_place(&target_dir, &archive_path, false)?;
+        assert_eq!(stats.imported, 1);
+        let [imported] = imported.as_slice() else {
+            panic!("expecting one run, got {imported:?}")
+        };
+        assert!(imported.has_evobench_results()?);
+        assert_eq!(
+            std::fs::read(imported.evobench_stats_cache_path())?,
+            std::fs::read(&stats_cache_path)?
+        );
+
+        let (stats, imported) = move_runs_into_place(&target_dir, &archive_path, false)?;
+        assert_eq!(stats.already_present, 1);
+        assert!(imported.is_empty());
+        Ok(())
+    }
+}
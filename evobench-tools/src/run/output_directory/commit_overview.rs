@@ -0,0 +1,470 @@
+//! Commit-centric overview of the results, since the output directory
+//! is organised by target, then custom parameters, then commit.
+//!
+//! For each commit with results, `commits/$commit/` in the output
+//! directory holds `overview.json` and `index.html`, listing every
+//! `KeyDir` for that commit (across all targets and custom
+//! parameters, separated by host class) with the average of the
+//! per-run medians of the probes, and the relative change to the
+//! results for the same key and host class of the first parent
+//! commit.
+//!
+//! The data comes from the results index (see `results_index.rs`),
+//! the first parents are recorded there when running a job, or via
+//! `evobench-util index rebuild`.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use ahtml::{HtmlAllocator, att};
+use anyhow::Result;
+use cj_path_util::path_util::AppendToPath;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ctx,
+    git::GitHash,
+    io_utils::tempfile_utils::tempfile,
+    output_table::{OutputTable, OutputTableTitle, html::HtmlTable},
+    run::output_directory::{
+        html_files::LinkCellValue,
+        results_index::{CommitKey, KeyProbeMedian, ResultsIndex},
+        structure::KeyDir,
+    },
+    utillib::arc::CloneArc,
+    warn,
+};
+
+pub const COMMITS_DIR_NAME: &str = "commits";
+const OVERVIEW_JSON_FILE_NAME: &str = "overview.json";
+const OVERVIEW_HTML_FILE_NAME: &str = "index.html";
+
+/// The field table from which the probe medians are taken
+const OVERVIEW_FIELD: &str = "real time";
+
+/// Field names in `overview.json` are stable (new fields may be
+/// added, existing ones are not renamed or removed without
+/// increasing this version).
+pub const COMMIT_OVERVIEW_FORMAT_VERSION: u32 = 1;
+
+/// The top level of `overview.json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommitOverview {
+    /// `COMMIT_OVERVIEW_FORMAT_VERSION`
+    pub format_version: u32,
+    pub commit_id: GitHash,
+    /// Unix time, if known
+    pub commit_time: Option<i64>,
+    /// If known and the commit has one
+    pub first_parent: Option<GitHash>,
+    /// The field table the medians are from, e.g. "real time"
+    pub field: String,
+    pub keys: Vec<CommitOverviewKey>,
+}
+
+/// A `KeyDir` with results for the commit, for one host class
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommitOverviewKey {
+    /// The path of the key dir relative to the output directory
+    pub key_dir: String,
+    pub target: String,
+    pub custom_parameters: String,
+    /// `null` for runs done locally
+    pub host_class: Option<String>,
+    pub num_runs: i64,
+    /// The number of runs for the same key and host class for the
+    /// first parent
+    pub parent_num_runs: Option<i64>,
+    pub probes: Vec<CommitOverviewProbe>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommitOverviewProbe {
+    pub probe: String,
+    pub unit: String,
+    /// The average of the per-run medians
+    pub median: f64,
+    /// The same for the first parent, if it has results for the key
+    pub parent_median: Option<f64>,
+    /// `(median - parent_median) / parent_median`, e.g. 0.05 if 5%
+    /// slower than the first parent
+    pub change: Option<f64>,
+}
+
+fn relative_change(value: f64, parent_value: f64) -> Option<f64> {
+    if parent_value == 0. {
+        None
+    } else {
+        Some((value - parent_value) / parent_value)
+    }
+}
+
+fn same_key(key: &CommitKey, median: &KeyProbeMedian) -> bool {
+    key.target == median.target
+        && key.custom_parameters == median.custom_parameters
+        && key.host_class == median.host_class
+}
+
+/// Join `keys` and `medians` for a commit with those of its first
+/// parent
+fn overview_keys(
+    keys: Vec<CommitKey>,
+    medians: &[KeyProbeMedian],
+    parent_keys: &[CommitKey],
+    parent_medians: &[KeyProbeMedian],
+) -> Vec<CommitOverviewKey> {
+    keys.into_iter()
+        .map(|key| {
+            let parent_key = parent_keys.iter().find(|parent_key| {
+                parent_key.target == key.target
+                    && parent_key.custom_parameters == key.custom_parameters
+                    && parent_key.host_class == key.host_class
+            });
+            let probes = medians
+                .iter()
+                .filter(|median| same_key(&key, median))
+                .map(|median| {
+                    let parent_median = parent_medians
+                        .iter()
+                        .find(|parent_median| {
+                            same_key(&key, parent_median)
+                                && parent_median.probe == median.probe
+                                && parent_median.unit == median.unit
+                        })
+                        .map(|parent_median| parent_median.median);
+                    CommitOverviewProbe {
+                        probe: median.probe.clone(),
+                        unit: median.unit.clone(),
+                        median: median.median,
+                        parent_median,
+                        change: parent_median.and_then(|parent_median| {
+                            relative_change(median.median, parent_median)
+                        }),
+                    }
+                })
+                .collect();
+            let CommitKey {
+                key_dir,
+                target,
+                custom_parameters,
+                host_class,
+                num_runs,
+            } = key;
+            CommitOverviewKey {
+                key_dir,
+                target,
+                custom_parameters,
+                host_class,
+                num_runs,
+                parent_num_runs: parent_key.map(|parent_key| parent_key.num_runs),
+                probes,
+            }
+        })
+        .collect()
+}
+
+fn text_cell(s: impl Into<String>) -> LinkCellValue {
+    LinkCellValue {
+        s: s.into(),
+        url: None,
+    }
+}
+
+impl CommitOverview {
+    pub fn from_results_index(index: &ResultsIndex, commit_id: &GitHash) -> Result<Self> {
+        let first_parent = index.first_parent(commit_id)?;
+        let (parent_keys, parent_medians) = if let Some(first_parent) = &first_parent {
+            (
+                index.commit_keys(first_parent)?,
+                index.commit_probe_medians(first_parent, OVERVIEW_FIELD)?,
+            )
+        } else {
+            (Vec::new(), Vec::new())
+        };
+        let keys = overview_keys(
+            index.commit_keys(commit_id)?,
+            &index.commit_probe_medians(commit_id, OVERVIEW_FIELD)?,
+            &parent_keys,
+            &parent_medians,
+        );
+        Ok(Self {
+            format_version: COMMIT_OVERVIEW_FORMAT_VERSION,
+            commit_id: commit_id.clone(),
+            commit_time: index.commit_time(commit_id)?,
+            first_parent,
+            field: OVERVIEW_FIELD.into(),
+            keys,
+        })
+    }
+
+    pub fn dir_path(output_base_dir: &Path, commit_id: &GitHash) -> PathBuf {
+        output_base_dir
+            .append(COMMITS_DIR_NAME)
+            .append(commit_id.to_string())
+    }
+
+    fn write_json(&self, dir: &Path) -> Result<()> {
+        let path = dir.append(OVERVIEW_JSON_FILE_NAME);
+        let (tmp_file, mut out) = tempfile(path.clone(), false)?;
+        serde_json::to_writer_pretty(&mut out, self).map_err(ctx!("writing to {path:?}"))?;
+        writeln!(out).map_err(ctx!("writing to {path:?}"))?;
+        out.flush().map_err(ctx!("writing to {path:?}"))?;
+        drop(out);
+        tmp_file.finish()?;
+        Ok(())
+    }
+
+    fn write_html(&self, dir: &Path, output_base_dir: &Path) -> Result<()> {
+        let Self {
+            format_version: _,
+            commit_id,
+            commit_time: _,
+            first_parent,
+            field,
+            keys,
+        } = self;
+
+        let html = HtmlAllocator::new(1000000, Arc::new("commit overview"));
+
+        let mut header = html.new_vec();
+        header.push(html.text(&format!("Commit {commit_id}, first parent: "))?)?;
+        match first_parent {
+            Some(first_parent) => {
+                let text = html.text(&first_parent.to_string())?;
+                if Self::dir_path(output_base_dir, first_parent).exists() {
+                    header.push(html.a(
+                        [att(
+                            "href",
+                            format!("../{first_parent}/{OVERVIEW_HTML_FILE_NAME}"),
+                        )],
+                        [text],
+                    )?)?;
+                } else {
+                    header.push(text)?;
+                }
+            }
+            None => header.push(html.text("(unknown)")?)?,
+        }
+        header.push(html.text(&format!(
+            "; medians from the {field:?} tables; machine-readable: "
+        ))?)?;
+        header.push(html.a(
+            [att("href", OVERVIEW_JSON_FILE_NAME)],
+            [html.text(OVERVIEW_JSON_FILE_NAME)?],
+        )?)?;
+
+        let column_titles = [
+            "target",
+            "custom parameters",
+            "host class",
+            "runs",
+            "parent runs",
+            "probe",
+            "median",
+            "parent median",
+            "change",
+            "unit",
+        ];
+        let mut table = HtmlTable::new(column_titles.len(), &html);
+        let titles: Vec<_> = column_titles
+            .iter()
+            .map(|title| OutputTableTitle {
+                text: (*title).into(),
+                span: 1,
+                anchor_name: None,
+            })
+            .collect();
+        table.write_title_row(&titles, None)?;
+
+        for key in keys {
+            let CommitOverviewKey {
+                key_dir,
+                target,
+                custom_parameters,
+                host_class,
+                num_runs,
+                parent_num_runs,
+                probes,
+            } = key;
+            let key_cells = [
+                LinkCellValue {
+                    s: target.clone(),
+                    url: Some(format!("../../{key_dir}/")),
+                },
+                text_cell(custom_parameters.as_str()),
+                text_cell(host_class.as_deref().unwrap_or("")),
+                text_cell(num_runs.to_string()),
+                text_cell(parent_num_runs.map(|n| n.to_string()).unwrap_or_default()),
+            ];
+            if probes.is_empty() {
+                let mut row: Vec<LinkCellValue> = key_cells.into();
+                row.extend((0..5).map(|_| text_cell("")));
+                table.write_data_row(&row, None)?;
+                continue;
+            }
+            // Show the key cells once only, with the first probe
+            let mut key_cells = Some(key_cells);
+            for probe in probes {
+                let CommitOverviewProbe {
+                    probe,
+                    unit,
+                    median,
+                    parent_median,
+                    change,
+                } = probe;
+                let mut row: Vec<LinkCellValue> = match key_cells.take() {
+                    Some(key_cells) => key_cells.into(),
+                    None => (0..5).map(|_| text_cell("")).collect(),
+                };
+                row.extend([
+                    text_cell(probe.as_str()),
+                    text_cell(format!("{median:.0}")),
+                    text_cell(parent_median.map(|v| format!("{v:.0}")).unwrap_or_default()),
+                    text_cell(
+                        change
+                            .map(|v| format!("{:+.1}%", v * 100.))
+                            .unwrap_or_default(),
+                    ),
+                    text_cell(unit.as_str()),
+                ]);
+                table.write_data_row(&row, None)?;
+            }
+        }
+
+        let doc = html.html(
+            [],
+            [
+                html.head([], [html.meta([att("charset", "utf-8")], [])?])?,
+                html.body([], [html.p([], header)?, html.table([], table.finish()?)?])?,
+            ],
+        )?;
+
+        let path = dir.append(OVERVIEW_HTML_FILE_NAME);
+        let (tmp_file, mut out) = tempfile(path.clone(), false)?;
+        out.write_all(html.to_html_string(doc, true).as_bytes())
+            .map_err(ctx!("writing to {path:?}"))?;
+        out.flush().map_err(ctx!("writing to {path:?}"))?;
+        drop(out);
+        tmp_file.finish()?;
+        Ok(())
+    }
+
+    /// Write the files to `commits/$commit/` in `output_base_dir`
+    pub fn write(&self, output_base_dir: &Path) -> Result<()> {
+        let dir = Self::dir_path(output_base_dir, &self.commit_id);
+        std::fs::create_dir_all(&dir).map_err(ctx!("creating dir {dir:?}"))?;
+        self.write_json(&dir)?;
+        self.write_html(&dir, output_base_dir)
+    }
+}
+
+/// Write the overview for `commit_id` (or remove it if the commit
+/// has no results any longer), as well as those of the commits that
+/// have it as their first parent, since their changes are relative
+/// to it.
+pub fn update_commit_overview(output_base_dir: Arc<Path>, commit_id: &GitHash) -> Result<()> {
+    let index = ResultsIndex::open_read_only(output_base_dir.clone_arc())?;
+    let overview = CommitOverview::from_results_index(&index, commit_id)?;
+    if overview.keys.is_empty() {
+        let dir = CommitOverview::dir_path(&output_base_dir, commit_id);
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).map_err(ctx!("deleting dir {dir:?}"))?;
+        }
+    } else {
+        overview.write(&output_base_dir)?;
+    }
+    for child in index.children_with_runs(commit_id)? {
+        CommitOverview::from_results_index(&index, &child)?.write(&output_base_dir)?;
+    }
+    Ok(())
+}
+
+/// Write the overviews for all commits with results, and remove those
+/// for commits that have none any longer. Returns the number of
+/// overviews written.
+pub fn regenerate_commit_overviews(output_base_dir: Arc<Path>) -> Result<usize> {
+    let index = ResultsIndex::open_read_only(output_base_dir.clone_arc())?;
+    let commits = index.commits_with_runs()?;
+    for commit_id in &commits {
+        CommitOverview::from_results_index(&index, commit_id)?.write(&output_base_dir)?;
+    }
+
+    let commits_dir = output_base_dir.append(COMMITS_DIR_NAME);
+    if commits_dir.exists() {
+        for entry in std::fs::read_dir(&commits_dir).map_err(ctx!("opening dir {commits_dir:?}"))? {
+            let entry = entry.map_err(ctx!("reading dir {commits_dir:?}"))?;
+            let Some(commit_id) = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse::<GitHash>().ok())
+            else {
+                continue;
+            };
+            if commits.binary_search(&commit_id).is_err() {
+                let dir = entry.path();
+                std::fs::remove_dir_all(&dir).map_err(ctx!("deleting dir {dir:?}"))?;
+            }
+        }
+    }
+    Ok(commits.len())
+}
+
+impl KeyDir {
+    /// Update the commit overviews affected by the results in this
+    /// key dir (see `update_commit_overview`). Failures are only
+    /// reported as warnings.
+    pub fn update_commit_overview(&self) {
+        let output_base_dir = self.parent().base_path().clone_arc();
+        if let Err(e) = update_commit_overview(output_base_dir, self.commit_id()) {
+            warn!(
+                "ignoring error updating the commit overview for {}: {e:#}",
+                self.commit_id()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(custom_parameters: &str, num_runs: i64) -> CommitKey {
+        CommitKey {
+            key_dir: format!("api/{custom_parameters}/c"),
+            target: "api".into(),
+            custom_parameters: custom_parameters.into(),
+            host_class: None,
+            num_runs,
+        }
+    }
+
+    fn median(custom_parameters: &str, probe: &str, median: f64) -> KeyProbeMedian {
+        KeyProbeMedian {
+            target: "api".into(),
+            custom_parameters: custom_parameters.into(),
+            host_class: None,
+            probe: probe.into(),
+            unit: "ns".into(),
+            median,
+        }
+    }
+
+    #[test]
+    fn t_overview_keys() {
+        let keys = overview_keys(
+            vec![key("A=1", 3), key("A=2", 1)],
+            &[median("A=1", "main", 110.), median("A=2", "main", 50.)],
+            &[key("A=1", 2)],
+            &[median("A=1", "main", 100.)],
+        );
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].parent_num_runs, Some(2));
+        assert_eq!(keys[0].probes[0].parent_median, Some(100.));
+        assert!((keys[0].probes[0].change.unwrap() - 0.1).abs() < 1e-9);
+        assert_eq!(keys[1].parent_num_runs, None);
+        assert_eq!(keys[1].probes[0].change, None);
+    }
+}
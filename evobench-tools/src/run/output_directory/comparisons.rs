@@ -0,0 +1,438 @@
+//! Comparisons between the results for two commits, e.g. a pull
+//! request head and its merge-base (see `evobench insert pr`).
+//!
+//! Each comparison is a directory `comparisons/$from..$to/` in the
+//! output directory, holding a `comparison.ron` file describing it,
+//! and, for each set of job parameters for which both commits have
+//! results, change reports at the same relative path as the
+//! `ParametersDir`, i.e.
+//! `comparisons/$from..$to/$target_name/$custom_parameters/avg-change.xlsx`
+//! (with `-host-$host_class` before the extension for results from
+//! workers, as for the summaries).
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Result;
+use cj_path_util::path_util::AppendToPath;
+
+use crate::{
+    config_file::ron_to_file_pretty,
+    ctx,
+    git::GitHash,
+    info,
+    run::output_directory::{
+        post_process::evobench_eval,
+        structure::{KeyDir, ParametersDir, ReplaceBasePath, RunDir, SubDirs, ToPath},
+    },
+    serde_types::proper_filename::ProperFilename,
+    utillib::arc::CloneArc,
+};
+
+pub const COMPARISONS_DIR_NAME: &str = "comparisons";
+const COMPARISON_FILE_NAME: &str = "comparison.ron";
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Comparison {
+    /// The job reason shared by the jobs for both commits
+    pub reason: String,
+    /// The baseline, e.g. the merge-base
+    pub from_commit: GitHash,
+    /// The commit compared to the baseline, e.g. the pull request head
+    pub to_commit: GitHash,
+}
+
+fn change_report_file_name(host_class: Option<&ProperFilename>) -> String {
+    let mut file_name = String::from("avg-change");
+    if let Some(host_class) = host_class {
+        file_name = format!("{file_name}-host-{}", host_class.as_str());
+    }
+    file_name.push_str(".xlsx");
+    file_name
+}
+
+impl Comparison {
+    pub fn dir_name(&self) -> String {
+        format!("{}..{}", self.from_commit, self.to_commit)
+    }
+
+    pub fn dir_path(&self, output_base_dir: &Path) -> PathBuf {
+        output_base_dir
+            .append(COMPARISONS_DIR_NAME)
+            .append(self.dir_name())
+    }
+
+    /// Record the comparison in the output directory (overwriting a
+    /// previous record for the same pair of commits)
+    pub fn save(&self, output_base_dir: &Path) -> Result<()> {
+        let dir = self.dir_path(output_base_dir);
+        std::fs::create_dir_all(&dir).map_err(ctx!("creating dir {dir:?}"))?;
+        ron_to_file_pretty(self, dir.append(COMPARISON_FILE_NAME), false, None)
+    }
+
+    /// All recorded comparisons, in no particular order
+    pub fn load_all(output_base_dir: &Path) -> Result<Vec<Self>> {
+        let dir = output_base_dir.append(COMPARISONS_DIR_NAME);
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => match e.kind() {
+                std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+                _ => Err(e).map_err(ctx!("opening dir {dir:?}"))?,
+            },
+        };
+        let mut comparisons = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(ctx!("reading dir {dir:?}"))?;
+            let path = entry.path().append(COMPARISON_FILE_NAME);
+            match std::fs::read_to_string(&path) {
+                Ok(s) => {
+                    comparisons.push(ron::from_str(&s).map_err(ctx!("reading file {path:?}"))?)
+                }
+                Err(e) => match e.kind() {
+                    std::io::ErrorKind::NotFound | std::io::ErrorKind::NotADirectory => (),
+                    _ => Err(e).map_err(ctx!("reading file {path:?}"))?,
+                },
+            }
+        }
+        Ok(comparisons)
+    }
+
+    /// The dir holding the change reports for the given parameters
+    pub fn reports_dir(&self, output_base_dir: &Path, parameters_dir: &ParametersDir) -> PathBuf {
+        parameters_dir
+            .replace_base_path(self.dir_path(output_base_dir).into())
+            .to_path()
+            .to_path_buf()
+    }
+
+    /// The paths of the change reports generated so far, relative to
+    /// the comparison's dir, sorted.
+    pub fn change_reports(&self, output_base_dir: &Path) -> Result<Vec<PathBuf>> {
+        fn walk(dir: &Path, relative: &Path, out: &mut Vec<PathBuf>) -> Result<()> {
+            for entry in std::fs::read_dir(dir).map_err(ctx!("opening dir {dir:?}"))? {
+                let entry = entry.map_err(ctx!("reading dir {dir:?}"))?;
+                let file_name = entry.file_name();
+                if entry.file_type()?.is_dir() {
+                    walk(&entry.path(), &relative.append(&file_name), out)?;
+                } else if let Some(s) = file_name.to_str() {
+                    if s.starts_with("avg-change") && s.ends_with(".xlsx") {
+                        out.push(relative.append(s));
+                    }
+                }
+            }
+            Ok(())
+        }
+        let mut reports = Vec::new();
+        walk(&self.dir_path(output_base_dir), Path::new(""), &mut reports)?;
+        reports.sort();
+        Ok(reports)
+    }
+}
+
+/// A change report to generate from the logs of the runs of both
+/// commits
+#[derive(Debug, Clone, PartialEq)]
+struct ChangeReportJob {
+    report_path: PathBuf,
+    from_log_paths: Vec<PathBuf>,
+    to_log_paths: Vec<PathBuf>,
+}
+
+impl ChangeReportJob {
+    fn evobench_eval_args(&self) -> Vec<OsString> {
+        let Self {
+            report_path,
+            from_log_paths,
+            to_log_paths,
+        } = self;
+        let mut args: Vec<OsString> = vec![
+            "change".into(),
+            "--stats-cache".into(),
+            "--summary-field".into(),
+            "avg".into(),
+            "--excel".into(),
+            report_path.into(),
+            "--from".into(),
+        ];
+        args.extend(from_log_paths.iter().map(OsString::from));
+        args.push("--to".into());
+        args.extend(to_log_paths.iter().map(OsString::from));
+        args
+    }
+}
+
+/// The evobench.log paths of those of `run_dirs` that have results
+/// (see `RunDir::has_evobench_results`)
+pub(super) fn evobench_log_paths(run_dirs: &[RunDir]) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for run_dir in run_dirs {
+        if run_dir.has_evobench_results()? {
+            paths.push(run_dir.evobench_log_path());
+        }
+    }
+    Ok(paths)
+}
+
+impl KeyDir {
+    /// The sibling key dir for `commit_id` (which may not exist)
+    fn for_commit(&self, commit_id: &GitHash) -> Result<Arc<KeyDir>> {
+        Ok(self
+            .parent()
+            .clone_arc()
+            .append_subdir_str(&commit_id.to_string())?
+            .into())
+    }
+
+    /// The change reports to (re-)generate for all recorded
+    /// comparisons involving the commit of this key dir, for which
+    /// the other commit has results with the same parameters (and
+    /// host class), too.
+    fn change_report_jobs(self: &Arc<Self>) -> Result<Vec<ChangeReportJob>> {
+        let output_base_dir = self.parent().base_path();
+        let mut jobs = Vec::new();
+        for comparison in Comparison::load_all(output_base_dir)? {
+            if ![&comparison.from_commit, &comparison.to_commit].contains(&self.commit_id()) {
+                continue;
+            }
+            let from_key_dir = self.for_commit(&comparison.from_commit)?;
+            let to_key_dir = self.for_commit(&comparison.to_commit)?;
+            if !(from_key_dir.to_path().exists() && to_key_dir.to_path().exists()) {
+                continue;
+            }
+            let from_run_dirs = from_key_dir.run_dirs_by_host_class()?;
+            let to_run_dirs = to_key_dir.run_dirs_by_host_class()?;
+            for (host_class, to_run_dirs) in &to_run_dirs {
+                let Some(from_run_dirs) = from_run_dirs.get(host_class) else {
+                    continue;
+                };
+                let from_log_paths = evobench_log_paths(from_run_dirs)?;
+                let to_log_paths = evobench_log_paths(to_run_dirs)?;
+                if from_log_paths.is_empty() || to_log_paths.is_empty() {
+                    continue;
+                }
+                let report_path = comparison
+                    .reports_dir(output_base_dir, self.parent())
+                    .append(change_report_file_name(host_class.as_ref()));
+                jobs.push(ChangeReportJob {
+                    report_path,
+                    from_log_paths,
+                    to_log_paths,
+                });
+            }
+        }
+        Ok(jobs)
+    }
+
+    /// (Re-)generate the change reports of all recorded comparisons
+    /// involving the commit of this key dir, for which the other
+    /// commit has results with the same parameters (and host class),
+    /// too.
+    pub fn update_comparisons_for_key_dir(self: &Arc<Self>) -> Result<()> {
+        for job in self.change_report_jobs()? {
+            let report_path = &job.report_path;
+            if let Some(reports_dir) = report_path.parent() {
+                std::fs::create_dir_all(reports_dir)
+                    .map_err(ctx!("creating dir {reports_dir:?}"))?;
+            }
+            info!("generating change report {report_path:?}");
+            evobench_eval(&job.evobench_eval_args())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        run::{
+            distributed::HOST_CLASS_FILE_NAME,
+            output_directory::test_runs::{add_test_run, test_key_dir},
+        },
+        serde_types::date_and_time::DateTimeWithOffset,
+        utillib::test_dir::TestDir,
+    };
+
+    use super::*;
+
+    const FROM: &str = "1111111111111111111111111111111111111111";
+    const TO: &str = "2222222222222222222222222222222222222222";
+
+    fn key_dir(output_base_dir: &Arc<Path>, commit_id: &str) -> Arc<KeyDir> {
+        test_key_dir(output_base_dir, "bench", commit_id)
+    }
+
+    /// Create a run dir, with an (empty) evobench.log if `with_log`,
+    /// and return the path of the latter
+    fn add_run(
+        key_dir: &Arc<KeyDir>,
+        timestamp: &str,
+        host_class: Option<&str>,
+        with_log: bool,
+    ) -> PathBuf {
+        let timestamp: DateTimeWithOffset = timestamp.parse().unwrap();
+        let run_dir = add_test_run(key_dir, timestamp, with_log.then_some(""));
+        if let Some(host_class) = host_class {
+            std::fs::write(
+                run_dir.append_str(HOST_CLASS_FILE_NAME).unwrap(),
+                format!("{host_class:?}"),
+            )
+            .unwrap();
+        }
+        run_dir.evobench_log_path()
+    }
+
+    #[test]
+    fn t_save_and_load_all() -> Result<()> {
+        let test_dir = TestDir::new("comparisons-load-all");
+        let output_base_dir = test_dir.path();
+        let none: [Comparison; 0] = [];
+        assert_eq!(Comparison::load_all(output_base_dir)?, none);
+
+        let comparison = Comparison {
+            reason: "PR feature vs main".into(),
+            from_commit: FROM.parse().unwrap(),
+            to_commit: TO.parse().unwrap(),
+        };
+        assert_eq!(comparison.dir_name(), format!("{FROM}..{TO}"));
+        comparison.save(output_base_dir)?;
+        // Saving again replaces the record
+        comparison.save(output_base_dir)?;
+        assert_eq!(Comparison::load_all(output_base_dir)?, [comparison.clone()]);
+        assert!(
+            comparison
+                .dir_path(output_base_dir)
+                .starts_with(output_base_dir.append(COMPARISONS_DIR_NAME))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn t_change_report_file_name() {
+        assert_eq!(change_report_file_name(None), "avg-change.xlsx");
+        let host_class: ProperFilename = "fast".parse().expect("valid name");
+        assert_eq!(
+            change_report_file_name(Some(&host_class)),
+            "avg-change-host-fast.xlsx"
+        );
+    }
+
+    #[test]
+    fn t_change_report_jobs() -> Result<()> {
+        let test_dir = TestDir::new("comparisons-change-report-jobs");
+        let output_base_dir: Arc<Path> = test_dir.path().into();
+        let comparison = Comparison {
+            reason: "PR feature vs main".into(),
+            from_commit: FROM.parse().unwrap(),
+            to_commit: TO.parse().unwrap(),
+        };
+        comparison.save(&output_base_dir)?;
+
+        let from_key_dir = key_dir(&output_base_dir, FROM);
+        let to_key_dir = key_dir(&output_base_dir, TO);
+        let other_key_dir = key_dir(&output_base_dir, "3333333333333333333333333333333333333333");
+
+        // No results for the head yet
+        let from_local = add_run(&from_key_dir, "2026-01-01T00:00:00+00:00", None, true);
+        let from_worker = add_run(&from_key_dir, "2026-01-01T01:00:00+00:00", Some("w"), true);
+        let none: [ChangeReportJob; 0] = [];
+        assert_eq!(from_key_dir.change_report_jobs()?, none);
+
+        // Paired by host class; runs without a log are left out, and
+        // results from a host class the baseline does not have are
+        // not compared
+        let to_local = add_run(&to_key_dir, "2026-01-02T00:00:00+00:00", None, true);
+        add_run(&to_key_dir, "2026-01-02T01:00:00+00:00", None, false);
+        add_run(
+            &to_key_dir,
+            "2026-01-02T02:00:00+00:00",
+            Some("other"),
+            true,
+        );
+        let reports_dir = comparison.reports_dir(&output_base_dir, from_key_dir.parent());
+        assert!(reports_dir.starts_with(comparison.dir_path(&output_base_dir).append("bench")));
+        let local_job = ChangeReportJob {
+            report_path: reports_dir.append("avg-change.xlsx"),
+            from_log_paths: vec![from_local.clone()],
+            to_log_paths: vec![to_local.clone()],
+        };
+        assert_eq!(from_key_dir.change_report_jobs()?, [local_job.clone()]);
+        assert_eq!(to_key_dir.change_report_jobs()?, [local_job.clone()]);
+
+        let to_worker = add_run(&to_key_dir, "2026-01-02T03:00:00+00:00", Some("w"), true);
+        let worker_job = ChangeReportJob {
+            report_path: reports_dir.append("avg-change-host-w.xlsx"),
+            from_log_paths: vec![from_worker],
+            to_log_paths: vec![to_worker],
+        };
+        assert_eq!(
+            to_key_dir.change_report_jobs()?,
+            [local_job.clone(), worker_job]
+        );
+
+        // Commits that are not part of the comparison
+        add_run(&other_key_dir, "2026-01-03T00:00:00+00:00", None, true);
+        assert_eq!(other_key_dir.change_report_jobs()?, none);
+
+        let args = local_job.evobench_eval_args();
+        let expected: Vec<OsString> = [
+            "change",
+            "--stats-cache",
+            "--summary-field",
+            "avg",
+            "--excel",
+        ]
+        .into_iter()
+        .map(OsString::from)
+        .chain([
+            local_job.report_path.clone().into(),
+            "--from".into(),
+            from_local.into(),
+            "--to".into(),
+            to_local.into(),
+        ])
+        .collect();
+        assert_eq!(args, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn t_change_reports() -> Result<()> {
+        let test_dir = TestDir::new("comparisons-change-reports");
+        let output_base_dir = test_dir.path();
+        let comparison = Comparison {
+            reason: "PR feature vs main".into(),
+            from_commit: FROM.parse().unwrap(),
+            to_commit: TO.parse().unwrap(),
+        };
+        comparison.save(output_base_dir)?;
+        assert_eq!(
+            comparison.change_reports(output_base_dir)?,
+            [] as [PathBuf; 0]
+        );
+
+        let dir = comparison.dir_path(output_base_dir);
+        std::fs::create_dir_all(dir.append("b/x"))?;
+        std::fs::create_dir_all(dir.append("a"))?;
+        for path in [
+            "b/x/avg-change.xlsx",
+            "b/x/avg-change-host-w.xlsx",
+            "a/avg-change.xlsx",
+            "a/notes.txt",
+        ] {
+            std::fs::write(dir.append(path), "")?;
+        }
+        assert_eq!(
+            comparison.change_reports(output_base_dir)?,
+            [
+                PathBuf::from("a/avg-change.xlsx"),
+                "b/x/avg-change-host-w.xlsx".into(),
+                "b/x/avg-change.xlsx".into(),
+            ]
+        );
+        Ok(())
+    }
+}
@@ -18,7 +18,11 @@ use crate::{
     output_table::{CellValue, OutputTable, OutputTableTitle, html::HtmlTable},
     run::{
         config::{RunConfig, ShareableConfig},
-        output_directory::structure::{ParametersDir, ToPath},
+        eta::RunDurationEstimator,
+        output_directory::{
+            comparisons::{COMPARISONS_DIR_NAME, Comparison},
+            structure::{ParametersDir, ToPath},
+        },
         run_queues::RunQueues,
         sub_command::list::{OutputTableOpts, ParameterView},
         working_directory_pool::WorkingDirectoryPoolBaseDir,
@@ -89,6 +93,7 @@ pub fn print_list(
     output_table_opts: &OutputTableOpts,
     html: Option<&HtmlAllocator>,
     link_skipped: Option<&str>,
+    estimator: Option<&mut RunDurationEstimator>,
     out: impl Write,
 ) -> Result<()> {
     let tmp;
@@ -98,18 +103,14 @@ pub fn print_list(
         tmp = HtmlAllocator::new(1000000, Arc::new("list"));
         &tmp
     };
-    let num_columns = output_table_opts
-        .parameter_view
-        .unwrap_or_default()
-        .titles()
-        .len();
-    let table = HtmlTable::new(num_columns, &html);
+    let table = HtmlTable::new(output_table_opts.num_columns(), &html);
     let body = output_table_opts.output_to_table(
         table,
         conf,
         link_skipped,
         working_directory_base_dir,
         queues,
+        estimator,
     )?;
     print_html_document(body.as_slice(), html, out)
 }
@@ -225,13 +226,76 @@ impl<'url> CellValue<'url> for &KString {
     }
 }
 
+/// A cell with an optional link relative to the output directory
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct LinkCellValue {
+    pub(crate) s: String,
+    pub(crate) url: Option<String>,
+}
+
+impl AsRef<str> for LinkCellValue {
+    fn as_ref(&self) -> &str {
+        &self.s
+    }
+}
+
+impl<'url> CellValue<'url> for LinkCellValue {
+    fn perhaps_url(&self) -> Option<Cow<'static, str>> {
+        self.url.clone().map(Cow::Owned)
+    }
+    fn perhaps_anchor_name(&self) -> Option<&KString> {
+        None
+    }
+}
+
+/// The recorded comparisons (see `comparisons.rs`) with links to
+/// their change reports
+fn comparisons_index(conf: &RunConfig) -> Result<BTreeMap<LinkCellValue, BTreeSet<LinkCellValue>>> {
+    let output_base_dir = &conf.output_dir.path;
+    let mut index = BTreeMap::new();
+    for comparison in Comparison::load_all(output_base_dir)? {
+        let dir_url = format!("{COMPARISONS_DIR_NAME}/{}", comparison.dir_name());
+        let reports: BTreeSet<LinkCellValue> = comparison
+            .change_reports(output_base_dir)?
+            .into_iter()
+            .map(|path| {
+                let path = path.to_string_lossy();
+                LinkCellValue {
+                    url: Some(format!("{dir_url}/{path}")),
+                    s: path.into(),
+                }
+            })
+            .collect();
+        let reports = if reports.is_empty() {
+            [LinkCellValue {
+                s: "(waiting for results)".into(),
+                url: None,
+            }]
+            .into()
+        } else {
+            reports
+        };
+        index.insert(
+            LinkCellValue {
+                s: format!("{} ({})", comparison.reason, comparison.dir_name()),
+                url: Some(dir_url),
+            },
+            reports,
+        );
+    }
+    Ok(index)
+}
+
 /// Does not take a lock: just regenerates the file (via
 /// tempfile-rename) with external values at least from now. For
-/// savings, pass the optional values if you can.
+/// savings, pass the optional values if you can; in particular, pass
+/// an `estimator` kept across calls when regenerating repeatedly, so
+/// that the logs of previous runs are only read once for the ETAs.
 pub fn regenerate_index_files(
     shareable_config: &ShareableConfig,
     working_directory_base_dir: Option<&Arc<WorkingDirectoryPoolBaseDir>>,
     queues: Option<&RunQueues>,
+    estimator: Option<&mut RunDurationEstimator>,
 ) -> Result<()> {
     let conf = &shareable_config.run_config;
 
@@ -266,16 +330,26 @@ pub fn regenerate_index_files(
         &tmp2
     };
 
+    let mut tmp3;
+    let estimator = if let Some(estimator) = estimator {
+        estimator
+    } else {
+        tmp3 = RunDurationEstimator::new(conf.output_dir.path.clone_arc());
+        &mut tmp3
+    };
+
     // / setup
 
     let mut html = HtmlAllocator::new(1000000, Arc::new("regenerate_index_files"));
 
-    let write_jobs_list =
+    let mut write_jobs_list =
         |html: &HtmlAllocator, file_name: &str, all: bool, link: Option<&str>| -> Result<()> {
             let output_table_opts = OutputTableOpts {
                 verbose: false,
                 all,
                 n: None,
+                no_eta: false,
+                output_url: None,
                 parameter_view: Some(ParameterView::Separated),
             };
 
@@ -288,6 +362,7 @@ pub fn regenerate_index_files(
                 &output_table_opts,
                 Some(html),
                 link,
+                Some(&mut *estimator),
                 out,
             )?;
 
@@ -305,6 +380,16 @@ pub fn regenerate_index_files(
     write_jobs_list(&html, "list-unlimited.html", true, None)?;
     html.clear();
 
+    // comparisons (links are relative, thus no need for base_url)
+    write_2_column_table_file(
+        "comparisons.html",
+        &["Comparison", "Change reports"],
+        &comparisons_index(conf)?,
+        conf,
+        &html,
+    )?;
+    html.clear();
+
     // parameter lists
     if let Some(base_url) = &conf.output_dir.url {
         let paths_with_names = {
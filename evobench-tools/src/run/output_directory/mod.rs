@@ -1,3 +1,13 @@
+pub mod archive;
+pub mod commit_overview;
+pub mod comparisons;
 pub mod html_files;
 pub mod post_process;
+pub mod prune;
+pub mod rekey;
+pub mod results_index;
+pub mod run_report;
 pub mod structure;
+pub mod sweeps;
+#[cfg(test)]
+pub mod test_runs;
@@ -2,7 +2,7 @@
 //! of benchmark runs belonging to the same 'key'.
 
 use std::{
-    collections::{HashMap, hash_map::Entry},
+    collections::{BTreeMap, HashMap, hash_map::Entry},
     ffi::OsString,
     path::{Path, PathBuf},
     process::Command,
@@ -11,17 +11,27 @@ use std::{
 
 use anyhow::{Result, bail};
 use cj_path_util::{path_util::AppendToPath, unix::polyfill::add_extension};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
 use crate::{
-    ctx, info,
+    ctx,
+    date_and_time::unixtime::Unixtime,
+    evaluator::{
+        all_fields_table::KeyRuntimeDetails,
+        all_outputs_all_fields_table::key_details_for,
+        options::{CheckedOutputOptionsMapCase, EvaluationOpts},
+    },
+    info,
     io_utils::zstd_file::compress_file,
     run::{
         command_log_file::CommandLogFile,
         config::{RunConfig, ScheduleCondition},
+        distributed::HOST_CLASS_FILE_NAME,
         output_directory::structure::{KeyDir, RunDir, SubDirs, ToPath},
     },
     serde_types::{proper_dirname::ProperDirname, proper_filename::ProperFilename},
     utillib::logging::{LogLevel, log_level},
+    warn,
 };
 
 /// `target_path` must include the `.zstd` extension. XX why does this
@@ -72,6 +82,10 @@ fn generate_summary(
     let mut args: Vec<OsString> = Vec::new();
     args.push("summary".into());
 
+    // Only the first summary for new runs reads their log files
+    // (see `evaluator/stats_cache.rs`)
+    args.push("--stats-cache".into());
+
     args.push("--summary-field".into()); // XXX *is* right one right? OPEN
     args.push(selector.into());
 
@@ -79,11 +93,13 @@ fn generate_summary(
     args.push(key_dir.append(file_base_name).into());
 
     for job_output_dir in job_output_dirs {
-        let evobench_log = job_output_dir.to_path().append("evobench.log.zstd");
-        if std::fs::exists(&evobench_log).map_err(ctx!("checking path {evobench_log:?}"))? {
-            args.push(evobench_log.into());
+        if job_output_dir.has_evobench_results()? {
+            args.push(job_output_dir.evobench_log_path().into());
         } else {
-            info!("missing file {evobench_log:?}, empty dir?");
+            info!(
+                "missing evobench.log and stats cache in {:?}, empty dir?",
+                job_output_dir.to_path()
+            );
         }
     }
 
@@ -92,6 +108,26 @@ fn generate_summary(
     Ok(())
 }
 
+/// The `EvaluationOpts` that `generate_summary` ends up with (the
+/// defaults of `evobench-eval`, as it passes none)
+pub const SUMMARY_EVALUATION_OPTS: EvaluationOpts = EvaluationOpts {
+    key_width: 100.,
+    show_thread_number: false,
+    show_reversed: false,
+};
+
+/// The key details of the stats cache entries that the summaries
+/// (Excel and flame graph files, see `SUMMARIES`) and the results
+/// index need; with those, the stats cache of a run can stand in for
+/// its evobench.log file
+pub fn summary_key_details() -> [KeyRuntimeDetails; 2] {
+    [
+        CheckedOutputOptionsMapCase::Excel,
+        CheckedOutputOptionsMapCase::Flame,
+    ]
+    .map(|case| key_details_for(case, &SUMMARY_EVALUATION_OPTS))
+}
+
 const SUMMARIES: &[(&str, &str, &str)] = &[
     ("sum", "--flame", ""),
     ("avg", "--excel", ".xlsx"),
@@ -99,8 +135,11 @@ const SUMMARIES: &[(&str, &str, &str)] = &[
 ];
 
 /// Situation `None` means across all outputs; otherwise "night" etc.
+/// `host_class` is the host class of the workers that produced the
+/// outputs (see `distributed.rs`), `None` for runs done locally.
 pub fn generate_all_summaries_for_situation(
     situation: Option<&ProperFilename>,
+    host_class: Option<&ProperFilename>,
     key_dir: &Path,
     job_output_dirs: &[RunDir],
 ) -> Result<()> {
@@ -109,6 +148,9 @@ pub fn generate_all_summaries_for_situation(
         if let Some(situation) = situation {
             basename = format!("{basename}-{}", situation.as_str());
         }
+        if let Some(host_class) = host_class {
+            basename = format!("{basename}-host-{}", host_class.as_str());
+        }
         basename.push_str(suffix);
         generate_summary(key_dir, job_output_dirs, selector, target, &basename)?;
     }
@@ -116,6 +158,34 @@ pub fn generate_all_summaries_for_situation(
 }
 
 impl RunDir {
+    /// Whether the run has an evobench.log file, or a stats cache
+    /// file standing in for it after it was pruned (in either case,
+    /// `evobench_log_path` is to be passed to `evobench-eval` with
+    /// `--stats-cache`).
+    pub fn has_evobench_results(&self) -> Result<bool> {
+        for path in [self.evobench_log_path(), self.evobench_stats_cache_path()] {
+            if std::fs::exists(&path).map_err(ctx!("checking path {path:?}"))? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// The host class of the worker that produced this run, `None`
+    /// for runs done locally
+    pub fn host_class(&self) -> Result<Option<ProperFilename>> {
+        let path = self.append_str(HOST_CLASS_FILE_NAME)?;
+        match std::fs::read_to_string(&path) {
+            Ok(s) => Ok(Some(
+                ron::from_str(&s).map_err(ctx!("reading file {path:?}"))?,
+            )),
+            Err(e) => match e.kind() {
+                std::io::ErrorKind::NotFound => Ok(None),
+                _ => Err(e).map_err(ctx!("reading file {path:?}"))?,
+            },
+        }
+    }
+
     /// Produce the "single" extract files, as well as other
     /// configured derivatives. After the standard "single" extracts
     /// succeeded, `evaluating_benchmark_file_succeeded` is run; it
@@ -125,7 +195,10 @@ impl RunDir {
     /// `evobench_log_path` is None, then the standard location is
     /// used.  If `no_summary_stats` is true, skips Excel and
     /// flamegraph generation for the evobench.log data (other
-    /// post-processing is still done, i.e. configured extractions)
+    /// post-processing is still done, i.e. configured extractions).
+    /// The run is also added to the results index, with
+    /// `commit_time` if given, and its `index.html` page is written
+    /// (see `run_report.rs`).
     pub fn post_process_single(
         &self,
         evobench_log_path: Option<&Path>,
@@ -134,6 +207,7 @@ impl RunDir {
         standard_log_path: &Path,
         run_config: &RunConfig,
         no_stats: bool,
+        commit_time: Option<Unixtime>,
     ) -> Result<()> {
         info!("evaluating benchmark file");
 
@@ -150,8 +224,19 @@ impl RunDir {
             // `evaluating_benchmark_file_succeeded`, as a way to ensure
             // that no invalid files end up in the results pool!
 
+            // Both `single` runs (and the results index) share the
+            // stats cache in the run dir, which is also filled for the
+            // summaries that way (`evobench_log_path` may be outside
+            // of the run dir, thus the cache file is given
+            // explicitly)
+            let stats_cache_file = self.evobench_stats_cache_path();
             {
-                let mut args = vec!["single".into(), evobench_log_path.into()];
+                let mut args = vec![
+                    "single".into(),
+                    evobench_log_path.into(),
+                    "--stats-cache-file".into(),
+                    stats_cache_file.clone().into(),
+                ];
                 if run_config.eval_settings.show_thread_number {
                     args.push("--show-thread-number".into());
                 }
@@ -163,16 +248,21 @@ impl RunDir {
 
             // It's a bit inefficient to read the $EVOBENCH_LOG twice, but
             // currently can't change the options (--show-thread-number)
-            // without a separate run. (Will be low cost once caching is
-            // done.)
+            // without a separate run. The results index then gets the
+            // stats from the cache, unless --show-thread-number was
+            // given above.
             evobench_eval(&vec![
                 "single".into(),
                 evobench_log_path.into(),
+                "--stats-cache-file".into(),
+                stats_cache_file.into(),
                 "--flame".into(),
                 self.append_str("single")?.into(),
             ])?;
         }
 
+        self.update_results_index(Some(evobench_log_path), commit_time);
+
         evaluating_benchmark_file_succeeded()?;
         // The above may have unlinked evobench_log_path, thus prevent further use:
         #[allow(unused)]
@@ -201,26 +291,60 @@ impl RunDir {
             );
         }
 
+        self.update_report_html(run_config);
+
         Ok(())
     }
 }
 
 impl KeyDir {
+    /// The run dirs in this key dir, grouped by the host class of the
+    /// worker that produced them (`None` for local runs)
+    pub fn run_dirs_by_host_class(
+        self: &Arc<Self>,
+    ) -> Result<BTreeMap<Option<ProperFilename>, Vec<RunDir>>> {
+        let mut run_dirs_by_host_class: BTreeMap<Option<ProperFilename>, Vec<RunDir>> =
+            BTreeMap::new();
+        for run_dir in self.sub_dirs()? {
+            let run_dir = run_dir?;
+            run_dirs_by_host_class
+                .entry(run_dir.host_class()?)
+                .or_default()
+                .push(run_dir);
+        }
+        Ok(run_dirs_by_host_class)
+    }
+
     /// If `no_summary_stats` is true, skips Excel and flamegraph
     /// generation for the evobench.log data (which currently is all
     /// that this method is doing, but in the future it might do stats
-    /// of other data)
-    pub fn generate_summaries_for_key_dir(self: &Arc<Self>, no_summary_stats: bool) -> Result<()> {
+    /// of other data). `run_config` is used to find the sweeps the
+    /// results are part of. Also updates the commit overview (see
+    /// `commit_overview.rs`).
+    pub fn generate_summaries_for_key_dir(
+        self: &Arc<Self>,
+        no_summary_stats: bool,
+        run_config: &RunConfig,
+    ) -> Result<()> {
+        generate_summaries_for_key_dirs(std::slice::from_ref(self), no_summary_stats, run_config)
+    }
+
+    /// The part of `generate_summaries_for_key_dir` that only writes
+    /// files in this key dir. The summaries across all situations
+    /// are generated first (in parallel for the host classes), as
+    /// they populate the stats caches of new runs; then the per
+    /// situation summaries are generated in parallel.
+    fn generate_summary_files(self: &Arc<Self>, no_summary_stats: bool) -> Result<()> {
         let key_dir = self.to_path();
         info!("(re-)evaluating the summary files across all results in key dir {key_dir:?}");
 
-        let run_dirs = self.sub_dirs()?.collect::<Result<Vec<_>>>()?;
-
-        if !no_summary_stats {
-            generate_all_summaries_for_situation(None, key_dir, &run_dirs)?;
-        }
+        // Results from different host classes are not comparable,
+        // thus summarize them separately
+        let run_dirs_by_host_class = self.run_dirs_by_host_class()?;
 
-        {
+        let mut situation_summaries: Vec<(ProperFilename, Option<ProperFilename>, Vec<RunDir>)> =
+            Vec::new();
+        for (host_class, run_dirs) in &run_dirs_by_host_class {
             let mut job_output_dirs_by_situation: HashMap<ProperFilename, Vec<RunDir>> =
                 HashMap::new();
             for run_dir in run_dirs {
@@ -233,10 +357,10 @@ impl KeyDir {
                             // XX it's just too long, proper abstraction pls?
                             match job_output_dirs_by_situation.entry(situation.clone()) {
                                 Entry::Occupied(mut occupied_entry) => {
-                                    occupied_entry.get_mut().push(run_dir);
+                                    occupied_entry.get_mut().push(run_dir.clone());
                                 }
                                 Entry::Vacant(vacant_entry) => {
-                                    vacant_entry.insert(vec![run_dir]);
+                                    vacant_entry.insert(vec![run_dir.clone()]);
                                 }
                             }
                         }
@@ -247,17 +371,80 @@ impl KeyDir {
                     },
                 }
             }
+            situation_summaries.extend(
+                job_output_dirs_by_situation
+                    .into_iter()
+                    .map(|(situation, run_dirs)| (situation, host_class.clone(), run_dirs)),
+            );
+        }
 
-            for (situation, job_output_dirs) in job_output_dirs_by_situation.iter() {
-                if !no_summary_stats {
+        if !no_summary_stats {
+            run_dirs_by_host_class
+                .par_iter()
+                .map(|(host_class, run_dirs)| {
+                    generate_all_summaries_for_situation(
+                        None,
+                        host_class.as_ref(),
+                        key_dir,
+                        run_dirs,
+                    )
+                })
+                .collect::<Result<()>>()?;
+            situation_summaries
+                .par_iter()
+                .map(|(situation, host_class, run_dirs)| {
                     generate_all_summaries_for_situation(
                         Some(situation),
-                        &key_dir,
-                        job_output_dirs.as_slice(),
-                    )?;
-                }
-            }
+                        host_class.as_ref(),
+                        key_dir,
+                        run_dirs,
+                    )
+                })
+                .collect::<Result<()>>()?;
         }
         Ok(())
     }
+
+    /// The part of `generate_summaries_for_key_dir` that writes files
+    /// shared with other key dirs (comparisons, sweep tables, commit
+    /// overviews); errors are only warned about
+    fn update_outputs_across_key_dirs(
+        self: &Arc<Self>,
+        no_summary_stats: bool,
+        run_config: &RunConfig,
+    ) {
+        let key_dir = self.to_path();
+        if !no_summary_stats {
+            // Failing comparisons or sweep tables should not affect
+            // the job
+            if let Err(e) = self.update_comparisons_for_key_dir() {
+                warn!("ignoring error updating the comparisons for key dir {key_dir:?}: {e:#}");
+            }
+            if let Err(e) = self.update_sweeps_for_key_dir(run_config) {
+                warn!("ignoring error updating the sweep tables for key dir {key_dir:?}: {e:#}");
+            }
+        }
+        self.update_commit_overview();
+    }
+}
+
+/// Does the same as `generate_summaries_for_key_dir` for all of
+/// `key_dirs` (the former is implemented via this function). The
+/// summary files in the key dirs are generated in parallel, the files
+/// shared across key dirs (comparisons, sweep tables, commit
+/// overviews) are updated sequentially afterwards, to avoid
+/// concurrent writes to the same files.
+pub fn generate_summaries_for_key_dirs(
+    key_dirs: &[Arc<KeyDir>],
+    no_summary_stats: bool,
+    run_config: &RunConfig,
+) -> Result<()> {
+    key_dirs
+        .par_iter()
+        .map(|key_dir| key_dir.generate_summary_files(no_summary_stats))
+        .collect::<Result<()>>()?;
+    for key_dir in key_dirs {
+        key_dir.update_outputs_across_key_dirs(no_summary_stats, run_config);
+    }
+    Ok(())
 }
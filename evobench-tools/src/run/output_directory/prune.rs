@@ -0,0 +1,445 @@
+//! Deleting the raw log files of old runs according to the configured
+//! `RetentionOpts`, and cleaning up the `latest` directories.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Result, anyhow};
+use regex::Regex;
+use run_git::git::GitWorkingDir;
+
+use crate::{
+    ctx,
+    evaluator::stats_cache::StatsCacheFile,
+    git::GitHash,
+    git_tags::GitTags,
+    info,
+    run::{
+        config::RetentionOpts,
+        output_directory::{
+            post_process::summary_key_details,
+            structure::{
+                KeyDir, LATEST_DIR_NAME, LATEST_REDIR_DIR_NAME, RunDir, SubDirs, ToPath,
+                find_key_dirs,
+            },
+        },
+    },
+    serde_types::date_and_time::DateTimeWithOffset,
+    utillib::cleanup_daemon::{CleanupHandler, Deletion},
+};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// The commits having a tag matching `commit_tags_regex`, for
+/// `RetentionOpts::keep_tagged`
+pub fn tagged_commits(
+    git_working_dir: &GitWorkingDir,
+    commit_tags_regex: &Regex,
+) -> Result<BTreeSet<GitHash>> {
+    let git_tags = GitTags::from_dir(git_working_dir)?;
+    Ok(git_tags
+        .tags()
+        .iter()
+        .filter(|tag| commit_tags_regex.is_match(&tag.name))
+        .map(|tag| (*tag.commit).clone())
+        .collect())
+}
+
+#[derive(Debug, Default)]
+pub struct PruneStats {
+    pub run_dirs_pruned: usize,
+    pub files_deleted: usize,
+    pub bytes_deleted: u64,
+    pub latest_entries_removed: usize,
+}
+
+impl Display for PruneStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            run_dirs_pruned,
+            files_deleted,
+            bytes_deleted,
+            latest_entries_removed,
+        } = self;
+        write!(
+            f,
+            "{files_deleted} raw log files ({:.1} MB) in {run_dirs_pruned} run dirs, \
+             {latest_entries_removed} `latest` entries",
+            *bytes_deleted as f64 / 1e6
+        )
+    }
+}
+
+/// Carries out deletions, or only reports them if `dry_run` is true
+struct Pruner<'t> {
+    dry_run: bool,
+    cleanup_handler: Option<&'t CleanupHandler>,
+}
+
+impl<'t> Pruner<'t> {
+    /// Returns whether the path existed
+    fn delete(&self, deletion: Deletion) -> Result<bool> {
+        if self.dry_run {
+            println!("would delete {:?}", deletion.path());
+            return Ok(true);
+        }
+        let did = if let Some(cleanup_handler) = self.cleanup_handler {
+            cleanup_handler.delete_now(deletion)?
+        } else {
+            deletion.delete_now()?
+        };
+        if let Some(did) = &did {
+            info!("{did}");
+        }
+        Ok(did.is_some())
+    }
+}
+
+fn age_days(timestamp: &DateTimeWithOffset, now: SystemTime) -> u64 {
+    now.duration_since(timestamp.to_systemtime())
+        .unwrap_or(Duration::ZERO)
+        .as_secs()
+        / SECONDS_PER_DAY
+}
+
+/// Whether the stats cache of `run_dir` is valid and has all the
+/// entries needed to stand in for its evobench.log file (see
+/// `summary_key_details`)
+fn has_complete_stats_cache(run_dir: &RunDir) -> Result<bool> {
+    let Some(cache) = StatsCacheFile::load(
+        &run_dir.evobench_stats_cache_path(),
+        &run_dir.evobench_log_path(),
+    )?
+    else {
+        return Ok(false);
+    };
+    Ok(summary_key_details()
+        .iter()
+        .all(|key_details| cache.entry(key_details).is_some()))
+}
+
+/// The raw log files in `run_dir` that exist, with their sizes. The
+/// stats cache file is kept, it stands in for the evobench.log file
+/// when regenerating summaries and the results index (see
+/// `evaluator/stats_cache.rs`), thus the latter is only included if
+/// its stats cache is complete.
+fn raw_log_files(run_dir: &RunDir) -> Result<Vec<(PathBuf, u64)>> {
+    let mut paths = vec![
+        run_dir.evobench_log_uncompressed_path(),
+        run_dir.standard_log_path(),
+        run_dir.bench_output_log_path(),
+    ];
+    let evobench_log_path = run_dir.evobench_log_path();
+    if std::fs::exists(&evobench_log_path).map_err(ctx!("checking path {evobench_log_path:?}"))? {
+        if has_complete_stats_cache(run_dir)? {
+            paths.push(evobench_log_path);
+        } else {
+            info!("keeping {evobench_log_path:?} as it has no valid and complete stats cache");
+        }
+    }
+
+    let mut files = Vec::new();
+    for path in paths {
+        match std::fs::symlink_metadata(&path) {
+            Ok(metadata) => files.push((path, metadata.len())),
+            Err(e) => match e.kind() {
+                std::io::ErrorKind::NotFound => (),
+                _ => Err(e).map_err(ctx!("getting metadata for {path:?}"))?,
+            },
+        }
+    }
+    Ok(files)
+}
+
+/// Delete the raw logs of the runs in `output_base_dir` that are not
+/// to be kept as per `retention`, as well as outdated entries in the
+/// `latest` directories. `tagged_commits` must be given if
+/// `retention.keep_tagged` is not false. If `cleanup_handler` is
+/// given, the deletions are done through it. If `dry_run` is true,
+/// only prints what would be deleted.
+pub fn prune_output_dir(
+    output_base_dir: &Path,
+    retention: &RetentionOpts,
+    tagged_commits: Option<&BTreeSet<GitHash>>,
+    cleanup_handler: Option<&CleanupHandler>,
+    dry_run: bool,
+) -> Result<PruneStats> {
+    let RetentionOpts {
+        raw_logs_days,
+        raw_logs_last_commits,
+        keep_tagged,
+        latest_days,
+        interval_hours: _,
+    } = retention;
+    let raw_logs_days = u64::from(*raw_logs_days);
+    let raw_logs_last_commits = raw_logs_last_commits.unwrap_or(0);
+    let tagged_commits = if keep_tagged.unwrap_or(true) {
+        Some(tagged_commits.ok_or_else(|| {
+            anyhow!("`keep_tagged` is enabled but the tagged commits were not given")
+        })?)
+    } else {
+        None
+    };
+
+    let pruner = Pruner {
+        dry_run,
+        cleanup_handler,
+    };
+    let now = SystemTime::now();
+    let mut stats = PruneStats::default();
+
+    // Group the key dirs with their runs by parameters dir
+    let mut by_parameters: BTreeMap<Arc<Path>, Vec<(Arc<KeyDir>, Vec<RunDir>)>> = BTreeMap::new();
+    for key_dir in find_key_dirs(output_base_dir)? {
+        let run_dirs: Vec<RunDir> = key_dir.sub_dirs()?.collect::<Result<_>>()?;
+        by_parameters
+            .entry(key_dir.parent().to_path().clone())
+            .or_default()
+            .push((key_dir, run_dirs));
+    }
+
+    for (_, mut key_dirs) in by_parameters {
+        // Most recently run commits first
+        key_dirs.sort_by(|(_, a), (_, b)| {
+            let latest =
+                |run_dirs: &[RunDir]| run_dirs.iter().map(|r| r.timestamp()).max().cloned();
+            latest(b).cmp(&latest(a))
+        });
+
+        for (key_dir, run_dirs) in key_dirs.iter().skip(raw_logs_last_commits) {
+            if let Some(tagged_commits) = tagged_commits {
+                if tagged_commits.contains(key_dir.commit_id()) {
+                    continue;
+                }
+            }
+            for run_dir in run_dirs {
+                if age_days(run_dir.timestamp(), now) < raw_logs_days {
+                    continue;
+                }
+                let files = raw_log_files(run_dir)?;
+                if files.is_empty() {
+                    continue;
+                }
+                for (path, size) in files {
+                    if pruner.delete(Deletion::file(path)?)? {
+                        stats.files_deleted += 1;
+                        stats.bytes_deleted += size;
+                    }
+                }
+                stats.run_dirs_pruned += 1;
+            }
+        }
+    }
+
+    // `latest/` holds symlinks to key dirs, `latest-redir/` holds
+    // dirs with a redirect file; both are named by the timestamp of
+    // the run.
+    for (dir_name, is_symlink_dir) in [(LATEST_DIR_NAME, true), (LATEST_REDIR_DIR_NAME, false)] {
+        let dir = output_base_dir.join(dir_name);
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => match e.kind() {
+                std::io::ErrorKind::NotFound => continue,
+                _ => Err(e).map_err(ctx!("opening dir {dir:?}"))?,
+            },
+        };
+        for entry in entries {
+            let entry = entry.map_err(ctx!("reading dir {dir:?}"))?;
+            let path = entry.path();
+            let is_dangling = is_symlink_dir
+                && entry.file_type()?.is_symlink()
+                && !std::fs::exists(&path).map_err(ctx!("checking path {path:?}"))?;
+            let is_outdated = match (latest_days, entry.file_name().to_str()) {
+                (Some(latest_days), Some(name)) => match DateTimeWithOffset::from_str(name) {
+                    Ok(timestamp) => age_days(&timestamp, now) >= u64::from(*latest_days),
+                    Err(_) => false,
+                },
+                _ => false,
+            };
+            if is_dangling || is_outdated {
+                let deletion = if is_symlink_dir {
+                    Deletion::file(path)?
+                } else {
+                    Deletion::dir(path)?
+                };
+                if pruner.delete(deletion)? {
+                    stats.latest_entries_removed += 1;
+                }
+            }
+        }
+    }
+
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        run::output_directory::test_runs::{add_test_run, test_key_dir, write_test_stats_cache},
+        utillib::test_dir::TestDir,
+    };
+
+    use super::*;
+
+    const OLD: &str = "2020-01-01T00:00:00+00:00";
+
+    fn commit(n: u8) -> String {
+        format!("{n}").repeat(40)
+    }
+
+    fn key_dir(output_base_dir: &Path, commit_id: &str) -> Arc<KeyDir> {
+        test_key_dir(output_base_dir, "bench", commit_id)
+    }
+
+    /// Create a run dir with (dummy) raw log files, and a complete
+    /// stats cache if `with_cache`
+    fn add_run(key_dir: &Arc<KeyDir>, timestamp: DateTimeWithOffset, with_cache: bool) -> RunDir {
+        let run_dir = add_test_run(key_dir, timestamp, Some("log"));
+        std::fs::write(run_dir.standard_log_path(), "log").unwrap();
+        if with_cache {
+            write_test_stats_cache(&run_dir);
+        }
+        run_dir
+    }
+
+    fn old(day: u32) -> DateTimeWithOffset {
+        format!("2020-01-{day:02}T00:00:00+00:00").parse().unwrap()
+    }
+
+    fn recent() -> DateTimeWithOffset {
+        DateTimeWithOffset::now(Some(false))
+    }
+
+    /// (evobench.log, standard.log, stats cache) exist
+    fn files(run_dir: &RunDir) -> (bool, bool, bool) {
+        (
+            run_dir.evobench_log_path().exists(),
+            run_dir.standard_log_path().exists(),
+            run_dir.evobench_stats_cache_path().exists(),
+        )
+    }
+
+    fn retention(raw_logs_last_commits: usize, keep_tagged: bool) -> RetentionOpts {
+        RetentionOpts {
+            raw_logs_days: 30,
+            raw_logs_last_commits: Some(raw_logs_last_commits),
+            keep_tagged: Some(keep_tagged),
+            latest_days: None,
+            interval_hours: None,
+        }
+    }
+
+    #[test]
+    fn t_prune_keep_last_and_tagged() -> Result<()> {
+        let test_dir = TestDir::new("prune-keep");
+        let base = test_dir.path();
+        let last = add_run(&key_dir(base, &commit(1)), old(4), true);
+        let pruned = add_run(&key_dir(base, &commit(2)), old(3), true);
+        let uncached = add_run(&key_dir(base, &commit(3)), old(2), false);
+        let tagged = add_run(&key_dir(base, &commit(4)), old(1), true);
+        // Lacking the entries needed for the summaries
+        let incomplete = add_run(&key_dir(base, &commit(5)), old(2), false);
+        StatsCacheFile::empty().save(&incomplete.evobench_stats_cache_path())?;
+        let tagged_commits = BTreeSet::from([commit(4).parse()?]);
+
+        let stats = prune_output_dir(base, &retention(1, true), None, None, false);
+        assert!(stats.is_err(), "tagged commits are required");
+
+        let stats = prune_output_dir(base, &retention(1, true), Some(&tagged_commits), None, true)?;
+        assert_eq!(stats.files_deleted, 4);
+        assert_eq!(files(&pruned), (true, true, true), "dry run");
+
+        let stats = prune_output_dir(
+            base,
+            &retention(1, true),
+            Some(&tagged_commits),
+            None,
+            false,
+        )?;
+        assert_eq!(stats.run_dirs_pruned, 3);
+        assert_eq!(stats.files_deleted, 4);
+        assert_eq!(files(&last), (true, true, true));
+        assert_eq!(files(&pruned), (false, false, true));
+        assert_eq!(files(&uncached), (true, false, false));
+        assert_eq!(files(&incomplete), (true, false, true));
+        assert_eq!(files(&tagged), (true, true, true));
+
+        let stats = prune_output_dir(
+            base,
+            &retention(1, false),
+            Some(&tagged_commits),
+            None,
+            false,
+        )?;
+        assert_eq!(stats.files_deleted, 2);
+        assert_eq!(files(&last), (true, true, true));
+        assert_eq!(files(&tagged), (false, false, true));
+        Ok(())
+    }
+
+    #[test]
+    fn t_prune_age() -> Result<()> {
+        let test_dir = TestDir::new("prune-age");
+        let base = test_dir.path();
+        let key_dir = key_dir(base, &commit(1));
+        let old_run = add_run(&key_dir, old(1), true);
+        let recent_run = add_run(&key_dir, recent(), true);
+
+        let stats = prune_output_dir(base, &retention(0, false), None, None, false)?;
+        assert_eq!(stats.run_dirs_pruned, 1);
+        assert_eq!(files(&old_run), (false, false, true));
+        assert_eq!(files(&recent_run), (true, true, true));
+        Ok(())
+    }
+
+    #[test]
+    fn t_prune_latest() -> Result<()> {
+        let test_dir = TestDir::new("prune-latest");
+        let base = test_dir.path();
+        let key_dir = key_dir(base, &commit(1));
+        std::fs::create_dir_all(key_dir.to_path())?;
+        let latest = base.join(LATEST_DIR_NAME);
+        let latest_redir = base.join(LATEST_REDIR_DIR_NAME);
+        std::fs::create_dir_all(&latest)?;
+        std::fs::create_dir_all(&latest_redir)?;
+
+        let now = recent();
+        let recent_link = latest.join(now.as_str());
+        let old_link = latest.join(OLD);
+        let dangling_link = latest.join(old(2).as_str());
+        std::os::unix::fs::symlink(key_dir.to_path(), &recent_link)?;
+        std::os::unix::fs::symlink(key_dir.to_path(), &old_link)?;
+        std::os::unix::fs::symlink(base.join("gone"), &dangling_link)?;
+        let recent_redir = latest_redir.join(now.as_str());
+        let old_redir = latest_redir.join(OLD);
+        let other_redir = latest_redir.join("other");
+        for dir in [&recent_redir, &old_redir, &other_redir] {
+            std::fs::create_dir_all(dir)?;
+        }
+        let exists = |path: &Path| path.symlink_metadata().is_ok();
+
+        // Without `latest_days`, only the dangling link is removed
+        let mut retention = retention(0, false);
+        let stats = prune_output_dir(base, &retention, None, None, false)?;
+        assert_eq!(stats.latest_entries_removed, 1);
+        assert!(!exists(&dangling_link));
+        assert!(exists(&old_link));
+        assert!(exists(&old_redir));
+
+        retention.latest_days = Some(7);
+        let stats = prune_output_dir(base, &retention, None, None, false)?;
+        assert_eq!(stats.latest_entries_removed, 2);
+        assert!(!exists(&old_link));
+        assert!(!exists(&old_redir));
+        assert!(exists(&recent_link));
+        assert!(exists(&recent_redir));
+        assert!(exists(&other_redir));
+        Ok(())
+    }
+}
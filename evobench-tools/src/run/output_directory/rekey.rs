@@ -0,0 +1,732 @@
+//! Moving results to new `KeyDir` paths after the key layout changed
+//! (a target was renamed, a custom parameter added or dropped), so
+//! that the history of a key is not split into separate trees.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    os::unix::fs::symlink,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{Result, anyhow, bail};
+use cj_path_util::path_util::AppendToPath;
+use kstring::KString;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ctx,
+    html_files::write_redirect_html_file,
+    info,
+    run::{
+        config::{BenchmarkingCommand, BenchmarkingTarget, RunConfig},
+        custom_parameter::CustomParameterValue,
+        env_vars::AllowableCustomEnvVar,
+        key::{
+            BenchmarkingJobParameters, CustomParameters, ExtendPath, RunParameters,
+            UncheckedCustomParameters,
+        },
+        output_directory::{
+            comparisons::Comparison,
+            post_process::generate_summaries_for_key_dirs,
+            results_index::ResultsIndex,
+            structure::{
+                KeyDir, LATEST_DIR_NAME, LATEST_REDIR_DIR_NAME, RunDir, SubDirs, ToPath,
+                find_key_dirs,
+            },
+            sweeps::SWEEPS_DIR_NAME,
+        },
+        run_queues::RunQueues,
+    },
+    serde_types::{allowed_env_var::AllowedEnvVar, proper_dirname::ProperDirname},
+    utillib::{arc::CloneArc, into_arc_path::IntoArcPath},
+    warn,
+};
+
+type KeyVals = BTreeMap<AllowedEnvVar<AllowableCustomEnvVar>, KString>;
+
+/// A change of the key layout
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum RekeyRule {
+    /// Rename the target `from` to `to`
+    RenameTarget {
+        from: ProperDirname,
+        to: ProperDirname,
+    },
+    /// Add the custom parameter `var` with `value` where it is
+    /// missing (for the given target only, if given)
+    AddParameter {
+        target: Option<ProperDirname>,
+        var: AllowedEnvVar<AllowableCustomEnvVar>,
+        value: KString,
+    },
+    /// Remove the custom parameter `var` (for the given target only,
+    /// if given)
+    DropParameter {
+        target: Option<ProperDirname>,
+        var: AllowedEnvVar<AllowableCustomEnvVar>,
+    },
+}
+
+/// The contents of the mapping file for `evobench-util rekey`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(rename = "RekeyMapping")]
+pub struct RekeyMapping {
+    /// Applied in order; `target` fields refer to the target name as
+    /// resulting from the preceding rules
+    pub rules: Vec<RekeyRule>,
+}
+
+impl RekeyMapping {
+    /// The target name and custom parameters after applying all rules
+    pub fn apply(
+        &self,
+        target_name: &ProperDirname,
+        keyvals: &KeyVals,
+    ) -> (ProperDirname, KeyVals) {
+        let mut target_name = target_name.clone();
+        let mut keyvals = keyvals.clone();
+        let applies_to = |target: &Option<ProperDirname>, target_name: &ProperDirname| {
+            target.as_ref().is_none_or(|target| target == target_name)
+        };
+        for rule in &self.rules {
+            match rule {
+                RekeyRule::RenameTarget { from, to } => {
+                    if &target_name == from {
+                        target_name = to.clone();
+                    }
+                }
+                RekeyRule::AddParameter { target, var, value } => {
+                    if applies_to(target, &target_name) {
+                        keyvals.entry(var.clone()).or_insert_with(|| value.clone());
+                    }
+                }
+                RekeyRule::DropParameter { target, var } => {
+                    if applies_to(target, &target_name) {
+                        keyvals.remove(var);
+                    }
+                }
+            }
+        }
+        (target_name, keyvals)
+    }
+
+    /// Whether the key of a job with `params` is changed by the rules
+    pub fn changes(&self, params: &BenchmarkingJobParameters) -> bool {
+        let BenchmarkingJobParameters {
+            run_parameters,
+            command,
+        } = params;
+        let keyvals = run_parameters.custom_parameters.keyvals();
+        let (target_name, new_keyvals) = self.apply(&command.target_name, &keyvals);
+        target_name != command.target_name || new_keyvals != keyvals
+    }
+
+    /// For `rekey_already_inserted`: the rekeyed job parameters, or
+    /// `None` if they stay the same. The values of added parameters
+    /// are checked against the target definition in `conf`.
+    pub fn apply_to_job_parameters(
+        &self,
+        params: &BenchmarkingJobParameters,
+        conf: &RunConfig,
+    ) -> Result<Option<BenchmarkingJobParameters>> {
+        let BenchmarkingJobParameters {
+            run_parameters,
+            command,
+        } = params;
+        let RunParameters {
+            commit_id,
+            custom_parameters,
+        } = &**run_parameters;
+        if !self.changes(params) {
+            return Ok(None);
+        }
+        let (target_name, keyvals) = self.apply(&command.target_name, &custom_parameters.keyvals());
+
+        let mut values = BTreeMap::new();
+        for (var, value) in keyvals {
+            let old_value = custom_parameters
+                .btree_map()
+                .get(&var)
+                .filter(|old| old.as_str() == value.as_str());
+            let checked = if let Some(old_value) = old_value {
+                old_value.clone()
+            } else {
+                checked_parameter_value(&conf.targets, &target_name, &var, &value)?
+            };
+            values.insert(var, checked);
+        }
+
+        let BenchmarkingCommand {
+            target_name: _,
+            subdir,
+            command,
+            arguments,
+            pre_exec_bash_code,
+        } = &**command;
+        Ok(Some(BenchmarkingJobParameters {
+            run_parameters: Arc::new(RunParameters {
+                commit_id: commit_id.clone(),
+                custom_parameters: Arc::new(CustomParameters::from(values)),
+            }),
+            command: Arc::new(BenchmarkingCommand {
+                target_name,
+                subdir: subdir.clone(),
+                command: command.clone(),
+                arguments: arguments.clone(),
+                pre_exec_bash_code: pre_exec_bash_code.clone(),
+            }),
+        }))
+    }
+}
+
+/// Check the value of a parameter added (or changed) by a mapping
+/// against the definition of the target (as named after the mapping)
+fn checked_parameter_value(
+    targets: &BTreeMap<ProperDirname, Arc<BenchmarkingTarget>>,
+    target_name: &ProperDirname,
+    var: &AllowedEnvVar<AllowableCustomEnvVar>,
+    value: &KString,
+) -> Result<CustomParameterValue> {
+    let target = targets.get(target_name).ok_or_else(|| {
+        anyhow!(
+            "target {:?} is not defined in the config, can't check the \
+             value for the added parameter {:?}",
+            target_name.as_str(),
+            var.as_str()
+        )
+    })?;
+    let allowed = target.allowed_custom_parameters.get(var).ok_or_else(|| {
+        anyhow!(
+            "custom parameter {:?} is not allowed for target {:?}",
+            var.as_str(),
+            target_name.as_str()
+        )
+    })?;
+    allowed
+        .r#type
+        .checked_value(value)
+        .map_err(ctx!("for variable {:?}", var.as_str()))
+}
+
+#[derive(Debug, Default)]
+pub struct RekeyStats {
+    pub key_dirs_moved: usize,
+    pub run_dirs_moved: usize,
+    pub latest_entries_fixed: usize,
+}
+
+impl Display for RekeyStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self {
+            key_dirs_moved,
+            run_dirs_moved,
+            latest_entries_fixed,
+        } = self;
+        write!(
+            f,
+            "moved {key_dirs_moved} key dirs with {run_dirs_moved} runs, \
+             fixed {latest_entries_fixed} `latest` entries"
+        )
+    }
+}
+
+/// Fail if jobs in the pipeline queues of `queues` (i.e. not run yet)
+/// would be rekeyed by `mapping`: their results would end up at the
+/// old paths, or they would fail once the target is renamed in the
+/// configuration. Leaves it to the user to wait for them to run, or
+/// to remove them.
+pub fn check_no_queued_jobs_to_rekey(queues: &RunQueues, mapping: &RekeyMapping) -> Result<()> {
+    let mut jobs = Vec::new();
+    for run_queue in queues.pipeline() {
+        for job in run_queue.data()?.jobs() {
+            let params = job.benchmarking_job_parameters();
+            if mapping.changes(&params) {
+                jobs.push(format!(
+                    "queue {:?}: target {:?}, commit {}",
+                    run_queue.file_name.as_str(),
+                    params.command.target_name.as_str(),
+                    params.run_parameters.commit_id
+                ));
+            }
+        }
+    }
+    if !jobs.is_empty() {
+        bail!(
+            "jobs that the mapping would rekey are still queued, wait until they have run \
+             or remove them (nothing was moved): {jobs:?}"
+        )
+    }
+    Ok(())
+}
+
+/// Remove `dir` and its parents up to (excluding) `base` as long as
+/// they are empty
+fn remove_empty_dirs(mut dir: &Path, base: &Path) {
+    while dir != base && dir.starts_with(base) {
+        if std::fs::remove_dir(dir).is_err() {
+            // Not empty (or vanished)
+            break;
+        }
+        let Some(parent) = dir.parent() else { break };
+        dir = parent;
+    }
+}
+
+/// Remove the files (not subdirs) in `dir`, if it exists
+fn remove_files_in(dir: &Path) -> Result<()> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => match e.kind() {
+            std::io::ErrorKind::NotFound => return Ok(()),
+            _ => Err(e).map_err(ctx!("opening dir {dir:?}"))?,
+        },
+    };
+    for entry in entries {
+        let entry = entry.map_err(ctx!("reading dir {dir:?}"))?;
+        if !entry.file_type()?.is_dir() {
+            let path = entry.path();
+            std::fs::remove_file(&path).map_err(ctx!("removing {path:?}"))?;
+        }
+    }
+    Ok(())
+}
+
+/// The custom parameters of `key_dir` as given by its path
+fn key_dir_keyvals(key_dir: &KeyDir) -> Result<KeyVals> {
+    key_dir
+        .parent()
+        .custom_parameters()
+        .key_val_strs()
+        .into_iter()
+        .map(|(k, v)| -> Result<_> { Ok((k.parse()?, KString::from_ref(v))) })
+        .collect()
+}
+
+/// The dirs outside `key_dir` holding outputs generated from its
+/// results, at paths derived from its key: the change reports of the
+/// `comparisons` involving its commit, and the sweep tables for each
+/// of its parameters (see `sweeps.rs`). (The commit overviews are
+/// keyed by commit only.)
+fn outputs_across_key_dirs(key_dir: &KeyDir, comparisons: &[Comparison]) -> Result<Vec<PathBuf>> {
+    let parameters_dir = key_dir.parent();
+    let output_base_dir = parameters_dir.base_path();
+    let commit_id = key_dir.commit_id();
+    let mut dirs = Vec::new();
+    for comparison in comparisons {
+        if &comparison.from_commit == commit_id || &comparison.to_commit == commit_id {
+            dirs.push(comparison.reports_dir(output_base_dir, parameters_dir));
+        }
+    }
+    let keyvals = key_dir_keyvals(key_dir)?;
+    for swept_key in keyvals.keys() {
+        let mut others = keyvals.clone();
+        others.remove(swept_key);
+        dirs.push(
+            UncheckedCustomParameters::from(others)
+                .extend_path(
+                    output_base_dir
+                        .append(SWEEPS_DIR_NAME)
+                        .append(parameters_dir.target_name().as_str()),
+                )
+                .append(swept_key.as_str())
+                .append(commit_id.to_string()),
+        );
+    }
+    Ok(dirs)
+}
+
+/// Replace the symlink at `path` with one pointing to `target`,
+/// atomically
+fn replace_symlink(path: &Path, target: &Path) -> Result<()> {
+    let tmp_path = path.with_extension("rekey-tmp");
+    symlink(target, &tmp_path).map_err(ctx!("creating symlink at {tmp_path:?}"))?;
+    std::fs::rename(&tmp_path, path).map_err(ctx!("renaming {tmp_path:?} to {path:?}"))
+}
+
+/// Move the key dirs in `conf.output_dir` to the paths resulting from
+/// `mapping` (see `move_key_dirs`), then update the results index and
+/// regenerate the summaries of the resulting key dirs, including the
+/// comparisons, sweep tables and commit overviews their results are
+/// part of (the top-level index files are not regenerated). If
+/// `dry_run` is true, only prints what would be moved.
+pub fn rekey_output_dir(
+    conf: &RunConfig,
+    mapping: &RekeyMapping,
+    dry_run: bool,
+) -> Result<RekeyStats> {
+    let output_base_dir = &conf.output_dir.path;
+    let (stats, key_dirs) = move_key_dirs(
+        output_base_dir,
+        conf.output_dir.url.as_deref(),
+        &conf.targets,
+        mapping,
+        dry_run,
+    )?;
+    if dry_run {
+        return Ok(stats);
+    }
+
+    ResultsIndex::open(output_base_dir.clone_arc())?.rebuild()?;
+    generate_summaries_for_key_dirs(&key_dirs, false, conf)?;
+
+    Ok(stats)
+}
+
+/// Move the key dirs in `output_base_dir` to the paths resulting from
+/// `mapping`, returning the key dirs that received runs. Key dirs
+/// that end up at the same path are merged (their run dirs are moved
+/// individually). Parameters added by `mapping` are checked against
+/// `targets`; failing checks and conflicting run dirs abort before
+/// anything is changed. Fixes the `latest` and `latest-redir`
+/// entries (the latter only if `output_dir_url` is given), and
+/// removes the comparison reports and sweep tables generated at the
+/// old paths. If `dry_run` is true, only prints what would be moved.
+fn move_key_dirs(
+    output_base_dir: &Arc<Path>,
+    output_dir_url: Option<&str>,
+    targets: &BTreeMap<ProperDirname, Arc<BenchmarkingTarget>>,
+    mapping: &RekeyMapping,
+    dry_run: bool,
+) -> Result<(RekeyStats, Vec<Arc<KeyDir>>)> {
+    // New key dir path -> the key dirs to move there
+    let mut moves: BTreeMap<PathBuf, Vec<Arc<KeyDir>>> = BTreeMap::new();
+    let mut invalid = Vec::new();
+    for key_dir in find_key_dirs(output_base_dir)? {
+        let old_keyvals = key_dir_keyvals(&key_dir)?;
+        let (target_name, keyvals) = mapping.apply(key_dir.parent().target_name(), &old_keyvals);
+        let added = keyvals
+            .iter()
+            .filter(|(var, value)| old_keyvals.get(*var) != Some(*value));
+        for (var, value) in added {
+            if let Err(e) = checked_parameter_value(targets, &target_name, var, value) {
+                invalid.push(format!("{:?}: {e:#}", key_dir.to_path()));
+            }
+        }
+        let new_path = UncheckedCustomParameters::from(keyvals)
+            .extend_path(output_base_dir.append(target_name.as_str()))
+            .append(key_dir.commit_id().to_string());
+        if new_path.as_path() != &**key_dir.to_path() {
+            moves.entry(new_path).or_default().push(key_dir);
+        }
+    }
+    if !invalid.is_empty() {
+        bail!("the mapping is not valid for some key dirs (nothing was moved): {invalid:?}")
+    }
+
+    // Check for conflicts before changing anything
+    let mut run_dirs_by_target: BTreeMap<&PathBuf, Vec<RunDir>> = BTreeMap::new();
+    let mut conflicts = Vec::new();
+    for (new_path, key_dirs) in &moves {
+        let mut timestamps = BTreeMap::new();
+        if new_path.exists() {
+            let existing: Arc<KeyDir> =
+                KeyDir::try_from(new_path.as_path().into_arc_path())?.into();
+            for run_dir in existing.sub_dirs()? {
+                let run_dir = run_dir?;
+                timestamps.insert(run_dir.timestamp().clone(), run_dir.to_path().clone_arc());
+            }
+        }
+        let mut run_dirs = Vec::new();
+        for key_dir in key_dirs {
+            for run_dir in key_dir.sub_dirs()? {
+                let run_dir = run_dir?;
+                if let Some(other) =
+                    timestamps.insert(run_dir.timestamp().clone(), run_dir.to_path().clone_arc())
+                {
+                    conflicts.push(format!("{:?} vs. {other:?}", run_dir.to_path()));
+                }
+                run_dirs.push(run_dir);
+            }
+        }
+        run_dirs_by_target.insert(new_path, run_dirs);
+    }
+    if !conflicts.is_empty() {
+        bail!(
+            "runs with the same timestamp would end up in the same key dir \
+             (nothing was moved): {conflicts:?}"
+        )
+    }
+
+    let comparisons = Comparison::load_all(output_base_dir)?;
+    let mut stats = RekeyStats::default();
+    // Old key dir path -> new one, and run timestamp -> new run dir
+    // path, both relative to `output_base_dir`
+    let mut moved_key_dirs: BTreeMap<PathBuf, PathBuf> = BTreeMap::new();
+    let mut moved_run_dirs: BTreeMap<String, PathBuf> = BTreeMap::new();
+    let relative = |path: &Path| -> PathBuf {
+        path.strip_prefix(output_base_dir)
+            .expect("all paths are below output_base_dir")
+            .to_owned()
+    };
+    for (new_path, key_dirs) in &moves {
+        for key_dir in key_dirs {
+            let old_path = key_dir.to_path();
+            for dir in outputs_across_key_dirs(key_dir, &comparisons)? {
+                if dry_run {
+                    if dir.exists() {
+                        println!("would remove the outputs in {dir:?}");
+                    }
+                } else {
+                    remove_files_in(&dir)?;
+                    remove_empty_dirs(&dir, output_base_dir);
+                }
+            }
+            if dry_run {
+                println!("would move {old_path:?} to {new_path:?}");
+            }
+            moved_key_dirs.insert(relative(old_path), relative(new_path));
+        }
+        for run_dir in &run_dirs_by_target[new_path] {
+            moved_run_dirs.insert(
+                run_dir.timestamp().to_string(),
+                relative(new_path).join(run_dir.timestamp().as_str()),
+            );
+        }
+        stats.key_dirs_moved += key_dirs.len();
+        stats.run_dirs_moved += run_dirs_by_target[new_path].len();
+        if dry_run {
+            continue;
+        }
+
+        if let [key_dir] = &key_dirs[..] {
+            if !new_path.exists() {
+                // Move the whole key dir, atomically
+                let old_path = key_dir.to_path();
+                let parent = new_path.parent().expect("has commit id segment");
+                std::fs::create_dir_all(parent).map_err(ctx!("create_dir_all {parent:?}"))?;
+                std::fs::rename(old_path, new_path)
+                    .map_err(ctx!("renaming {old_path:?} to {new_path:?}"))?;
+                info!("moved {old_path:?} to {new_path:?}");
+                remove_empty_dirs(old_path.parent().expect("has parent"), output_base_dir);
+                continue;
+            }
+        }
+        // Merge: move the run dirs individually (each atomically),
+        // then delete the old summaries
+        std::fs::create_dir_all(new_path).map_err(ctx!("create_dir_all {new_path:?}"))?;
+        for run_dir in &run_dirs_by_target[new_path] {
+            let from = run_dir.to_path();
+            let to = new_path.join(run_dir.timestamp().as_str());
+            std::fs::rename(from, &to).map_err(ctx!("renaming {from:?} to {to:?}"))?;
+            info!("moved {from:?} to {to:?}");
+        }
+        for key_dir in key_dirs {
+            let old_path = key_dir.to_path();
+            remove_files_in(old_path)?;
+            if let Err(e) = std::fs::remove_dir(old_path) {
+                warn!("could not remove old key dir {old_path:?}: {e:#}");
+            } else {
+                remove_empty_dirs(old_path.parent().expect("has parent"), output_base_dir);
+            }
+        }
+    }
+    if dry_run {
+        return Ok((stats, Vec::new()));
+    }
+
+    // `latest/` entries are symlinks to `../<key dir>`
+    let latest_dir = output_base_dir.join(LATEST_DIR_NAME);
+    if latest_dir.exists() {
+        for entry in std::fs::read_dir(&latest_dir).map_err(ctx!("opening dir {latest_dir:?}"))? {
+            let entry = entry.map_err(ctx!("reading dir {latest_dir:?}"))?;
+            if !entry.file_type()?.is_symlink() {
+                continue;
+            }
+            let path = entry.path();
+            let target = std::fs::read_link(&path).map_err(ctx!("reading symlink {path:?}"))?;
+            let Ok(target_relative) = target.strip_prefix("..") else {
+                continue;
+            };
+            if let Some(new_relative) = moved_key_dirs.get(target_relative) {
+                replace_symlink(&path, &Path::new("..").join(new_relative))?;
+                stats.latest_entries_fixed += 1;
+            }
+        }
+    }
+
+    // `latest-redir/` entries are dirs named by run timestamp with a
+    // redirect to the run dir URL
+    let latest_redir_dir = output_base_dir.join(LATEST_REDIR_DIR_NAME);
+    if let Some(output_dir_url) = output_dir_url {
+        if latest_redir_dir.exists() {
+            for entry in std::fs::read_dir(&latest_redir_dir)
+                .map_err(ctx!("opening dir {latest_redir_dir:?}"))?
+            {
+                let entry = entry.map_err(ctx!("reading dir {latest_redir_dir:?}"))?;
+                let Some(name) = entry.file_name().to_str().map(String::from) else {
+                    continue;
+                };
+                if let Some(new_relative) = moved_run_dirs.get(&name) {
+                    let url = Path::new(output_dir_url).join(new_relative);
+                    write_redirect_html_file(
+                        &entry.path().append("index.html"),
+                        &url.to_string_lossy(),
+                    )?;
+                    stats.latest_entries_fixed += 1;
+                }
+            }
+        }
+    }
+
+    let key_dirs = moves
+        .keys()
+        .map(|new_path| -> Result<Arc<KeyDir>> {
+            Ok(KeyDir::try_from(new_path.as_path().into_arc_path())?.into())
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok((stats, key_dirs))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        run::{benchmarking_job::BenchmarkingJob, output_directory::test_runs::add_test_run},
+        utillib::test_dir::TestDir,
+    };
+
+    use super::*;
+
+    const COMMIT: &str = "1111111111111111111111111111111111111111";
+    const PARENT: &str = "2222222222222222222222222222222222222222";
+    const TIMESTAMP: &str = "2026-01-01T00:00:00+00:00";
+
+    /// Create a run dir with an evobench.log below `key_dir`
+    /// (relative to `base`), return the log's path
+    fn add_run(base: &Path, key_dir: &str) -> PathBuf {
+        let key_dir: Arc<KeyDir> = KeyDir::try_from(base.join(key_dir).into_arc_path())
+            .unwrap()
+            .into();
+        add_test_run(&key_dir, TIMESTAMP.parse().unwrap(), Some("log")).evobench_log_path()
+    }
+
+    #[test]
+    fn t_move_key_dirs_invalid_mapping() -> Result<()> {
+        let test_dir = TestDir::new("rekey-invalid");
+        let base: Arc<Path> = test_dir.path().into();
+        let log_path = add_run(&base, &format!("bench/THREADS=4/{COMMIT}"));
+        // The new target is not in the (empty) config, thus the added
+        // parameter can't be checked
+        let mapping: RekeyMapping = ron::from_str(
+            r#"RekeyMapping(rules: [
+                RenameTarget(from: "bench", to: "api"),
+                AddParameter(target: None, var: "MODE", value: "x"),
+            ])"#,
+        )?;
+        let e = move_key_dirs(&base, None, &BTreeMap::new(), &mapping, false).unwrap_err();
+        assert!(format!("{e:#}").contains("nothing was moved"), "{e:#}");
+        assert!(log_path.exists());
+        assert!(!base.join("api").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn t_move_key_dirs() -> Result<()> {
+        let test_dir = TestDir::new("rekey-move");
+        let base: Arc<Path> = test_dir.path().into();
+        let old_key_dir = format!("bench/THREADS=4/{COMMIT}");
+        add_run(&base, &old_key_dir);
+        let latest_dir = base.join(LATEST_DIR_NAME);
+        std::fs::create_dir_all(&latest_dir)?;
+        let latest_link = latest_dir.join(TIMESTAMP);
+        symlink(Path::new("..").join(&old_key_dir), &latest_link)?;
+
+        // Outputs generated at paths derived from the old key
+        let comparison = Comparison {
+            reason: "PR".into(),
+            from_commit: PARENT.parse()?,
+            to_commit: COMMIT.parse()?,
+        };
+        comparison.save(&base)?;
+        let key_dir: Arc<KeyDir> =
+            KeyDir::try_from(base.join(&old_key_dir).into_arc_path())?.into();
+        let reports_dir = comparison.reports_dir(&base, key_dir.parent());
+        std::fs::create_dir_all(&reports_dir)?;
+        let report_path = reports_dir.join("avg-change.xlsx");
+        std::fs::write(&report_path, "")?;
+        let sweep_dir = base.join(format!("{SWEEPS_DIR_NAME}/bench/THREADS/{COMMIT}"));
+        std::fs::create_dir_all(&sweep_dir)?;
+        std::fs::write(sweep_dir.join("avg-sweep.xlsx"), "")?;
+
+        let mapping: RekeyMapping =
+            ron::from_str(r#"RekeyMapping(rules: [DropParameter(target: None, var: "THREADS")])"#)?;
+        let (stats, key_dirs) = move_key_dirs(&base, None, &BTreeMap::new(), &mapping, false)?;
+        assert_eq!(stats.key_dirs_moved, 1);
+        assert_eq!(stats.run_dirs_moved, 1);
+        assert_eq!(stats.latest_entries_fixed, 1);
+        let new_key_dir = base.join(format!("bench/{COMMIT}"));
+        assert_eq!(key_dirs.len(), 1);
+        assert_eq!(&**key_dirs[0].to_path(), new_key_dir.as_path());
+        assert!(
+            new_key_dir
+                .join(TIMESTAMP)
+                .join("evobench.log.zstd")
+                .exists()
+        );
+        assert!(!base.join("bench/THREADS=4").exists());
+        assert_eq!(
+            std::fs::read_link(&latest_link)?,
+            Path::new("..").join(format!("bench/{COMMIT}"))
+        );
+        assert!(!report_path.exists());
+        assert_eq!(Comparison::load_all(&base)?, [comparison]);
+        assert!(!base.join(SWEEPS_DIR_NAME).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn t_rekey_mapping_changes() {
+        let mapping: RekeyMapping = ron::from_str(
+            r#"RekeyMapping(rules: [
+                RenameTarget(from: "api", to: "api-server"),
+                DropParameter(target: None, var: "SORTED"),
+            ])"#,
+        )
+        .unwrap();
+        let params = |target_name| {
+            BenchmarkingJob::for_tests(target_name, COMMIT, None).benchmarking_job_parameters()
+        };
+        assert!(mapping.changes(&params("api")));
+        assert!(!mapping.changes(&params("api-server")));
+        // Dropping a parameter that is not there changes nothing
+        assert!(!mapping.changes(&params("other")));
+    }
+
+    #[test]
+    fn t_rekey_mapping_apply() {
+        let mapping: RekeyMapping = ron::from_str(
+            r#"RekeyMapping(rules: [
+                RenameTarget(from: "api", to: "api-server"),
+                AddParameter(target: Some("api-server"), var: "THREADS", value: "1"),
+                DropParameter(target: None, var: "SORTED"),
+            ])"#,
+        )
+        .unwrap();
+        let target = |s: &str| -> ProperDirname { s.parse().unwrap() };
+        let keyvals = |kvs: &[(&str, &str)]| -> KeyVals {
+            kvs.iter()
+                .map(|(k, v)| (k.parse().unwrap(), KString::from_ref(v)))
+                .collect()
+        };
+
+        assert_eq!(
+            mapping.apply(
+                &target("api"),
+                &keyvals(&[("SORTED", "0"), ("THREADS", "4")])
+            ),
+            (target("api-server"), keyvals(&[("THREADS", "4")]))
+        );
+        assert_eq!(
+            mapping.apply(&target("api"), &keyvals(&[("SORTED", "1")])),
+            (target("api-server"), keyvals(&[("THREADS", "1")]))
+        );
+        assert_eq!(
+            mapping.apply(&target("other"), &keyvals(&[("REPEAT", "2")])),
+            (target("other"), keyvals(&[("REPEAT", "2")]))
+        );
+    }
+}
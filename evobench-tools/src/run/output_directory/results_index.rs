@@ -0,0 +1,1066 @@
+//! An SQLite database indexing the results in the output directory,
+//! so that they can be queried without walking the directory tree
+//! and re-parsing the evobench.log files.
+//!
+//! The database lives at the top of the output directory (see
+//! `RESULTS_INDEX_FILE_NAME`). It is only an index: it can always be
+//! recreated from the directory tree (`evobench-util index
+//! rebuild`). Commit times are the exception, they are not stored in
+//! the tree; they are added when indexing a new run or via the
+//! polling pool when rebuilding.
+//!
+//! Tables:
+//!
+//! - `runs`: one row per `RunDir`, keyed by its path relative to the
+//!   output directory (`run_dir`)
+//! - `run_parameters`: the custom parameters of the runs, one row per
+//!   parameter
+//! - `probe_stats`: per run, the summary statistics for every probe
+//!   (or probe path) and field (real, cpu, sys times, ctx switches),
+//!   in the resolution units given in the `unit` column
+//! - `commits`: the committer time of the commits (unix time)
+//! - `commit_parents`: the first parent of the commits (NULL for
+//!   root commits), used for the deltas in the commit overviews (see
+//!   `commit_overview.rs`)
+//!
+//! and the view `results` joining them, which is what `evobench
+//! query` queries by default.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use anyhow::{Result, anyhow, bail};
+use cj_path_util::path_util::AppendToPath;
+use rusqlite::{Connection, OpenFlags, OptionalExtension, params, types::Value};
+
+use crate::{
+    ctx,
+    date_and_time::unixtime::Unixtime,
+    evaluator::{all_outputs_all_fields_table::AllOutputsAllFieldsTable, options::OutputVariants},
+    git::GitHash,
+    info,
+    join::KeyVal,
+    run::{
+        config::ScheduleCondition,
+        output_directory::{
+            post_process::SUMMARY_EVALUATION_OPTS,
+            structure::{KeyDir, RunDir, SubDirs, ToPath, find_key_dirs},
+        },
+    },
+    stats_tables::{stats::StatsField, tables::table_view::TableView},
+    utillib::arc::CloneArc,
+    warn,
+};
+
+pub const RESULTS_INDEX_FILE_NAME: &str = "results_index.sqlite";
+
+/// Bump when changing `SCHEMA` incompatibly; older databases are
+/// then dropped and need `evobench-util index rebuild`.
+const SCHEMA_VERSION: i64 = 2;
+
+/// Versions from which `SCHEMA` upgrades without dropping anything
+/// (it only adds tables).
+const COMPATIBLE_SCHEMA_VERSIONS: &[i64] = &[1];
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    run_dir TEXT PRIMARY KEY NOT NULL,
+    target TEXT NOT NULL,
+    commit_id TEXT NOT NULL,
+    custom_parameters TEXT NOT NULL,
+    timestamp TEXT NOT NULL,
+    run_time INTEGER NOT NULL,
+    situation TEXT,
+    host_class TEXT,
+    reason TEXT
+);
+CREATE INDEX IF NOT EXISTS runs_commit_id ON runs (commit_id);
+
+CREATE TABLE IF NOT EXISTS run_parameters (
+    run_dir TEXT NOT NULL REFERENCES runs (run_dir) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (run_dir, name)
+);
+
+CREATE TABLE IF NOT EXISTS probe_stats (
+    run_dir TEXT NOT NULL REFERENCES runs (run_dir) ON DELETE CASCADE,
+    field TEXT NOT NULL,
+    probe TEXT NOT NULL,
+    unit TEXT NOT NULL,
+    n INTEGER,
+    sum INTEGER,
+    average INTEGER,
+    median INTEGER,
+    sd INTEGER,
+    PRIMARY KEY (run_dir, field, probe)
+);
+
+CREATE TABLE IF NOT EXISTS commits (
+    commit_id TEXT PRIMARY KEY NOT NULL,
+    commit_time INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS commit_parents (
+    commit_id TEXT PRIMARY KEY NOT NULL,
+    first_parent TEXT
+);
+CREATE INDEX IF NOT EXISTS commit_parents_first_parent ON commit_parents (first_parent);
+
+CREATE VIEW IF NOT EXISTS results AS
+SELECT
+    r.target,
+    r.commit_id,
+    c.commit_time,
+    datetime(c.commit_time, 'unixepoch') AS commit_date,
+    r.custom_parameters,
+    r.timestamp,
+    r.run_time,
+    r.situation,
+    r.host_class,
+    s.field,
+    s.probe,
+    s.unit,
+    s.n,
+    s.sum,
+    s.average,
+    s.median,
+    s.sd,
+    r.run_dir
+FROM probe_stats s
+JOIN runs r USING (run_dir)
+LEFT JOIN commits c USING (commit_id);
+";
+
+/// The stats for one probe in one field table of a run
+#[derive(Debug, Default)]
+struct ProbeStats {
+    n: Option<u64>,
+    sum: Option<u64>,
+    average: Option<u64>,
+    median: Option<u64>,
+    sd: Option<u64>,
+}
+
+/// field -> (unit, probe -> stats)
+type RunStats = BTreeMap<String, (String, BTreeMap<String, ProbeStats>)>;
+
+/// Calculate the stats for all probes in the evobench.log file at
+/// `path` (using the same keys as in the Excel summary files), via
+/// the stats cache file at `cache_path` (see
+/// `evaluator/stats_cache.rs`), which stands in for the log file
+/// once that has been pruned.
+fn run_stats(path: &Path, cache_path: &Path) -> Result<RunStats> {
+    let aoaft = AllOutputsAllFieldsTable::from_log_file_with_cache_file(
+        path,
+        cache_path,
+        &SUMMARY_EVALUATION_OPTS,
+        OutputVariants {
+            // (Not written)
+            excel: Some(PathBuf::new()),
+            flame: None,
+            json: None,
+            csv: None,
+        },
+        false,
+    )?;
+    let aft = aoaft.excel.as_ref().expect("requested above").aft();
+
+    let mut stats = RunStats::new();
+    for table in aft.tables() {
+        let mut probes: BTreeMap<String, ProbeStats> = BTreeMap::new();
+        for stats_field in [
+            StatsField::N,
+            StatsField::Sum,
+            StatsField::Average,
+            StatsField::Median,
+            StatsField::SD,
+        ] {
+            for KeyVal { key, val } in table.table_key_vals(stats_field) {
+                let probe = probes.entry(key.to_owned()).or_default();
+                let slot = match stats_field {
+                    StatsField::N => &mut probe.n,
+                    StatsField::Sum => &mut probe.sum,
+                    StatsField::Average => &mut probe.average,
+                    StatsField::Median => &mut probe.median,
+                    StatsField::SD => &mut probe.sd,
+                    StatsField::Tile(_) => unreachable!("not in the list above"),
+                };
+                *slot = Some(val);
+            }
+        }
+        stats.insert(
+            table.table_name().into_owned(),
+            (table.resolution_unit(), probes),
+        );
+    }
+    Ok(stats)
+}
+
+fn u64_to_sql(v: Option<u64>) -> Result<Option<i64>> {
+    v.map(|v| i64::try_from(v).map_err(ctx!("value {v} too large for the database")))
+        .transpose()
+}
+
+/// Read an optional `.ron` file in `run_dir`
+fn read_ron_file<T: serde::de::DeserializeOwned>(
+    run_dir: &RunDir,
+    name: &str,
+) -> Result<Option<T>> {
+    let path = run_dir.append_str(name)?;
+    match std::fs::read_to_string(&path) {
+        Ok(s) => Ok(Some(
+            ron::from_str(&s).map_err(ctx!("reading file {path:?}"))?,
+        )),
+        Err(e) => match e.kind() {
+            std::io::ErrorKind::NotFound => Ok(None),
+            _ => Err(e).map_err(ctx!("reading file {path:?}"))?,
+        },
+    }
+}
+
+/// The result of `ResultsIndex::query`
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+/// The median of a probe (in a field table) from the most recent run
+/// for a combination of target, custom parameters, situation and host
+/// class, as returned by `ResultsIndex::latest_medians`
+#[derive(Debug, PartialEq)]
+pub struct LatestMedian {
+    pub target: String,
+    pub custom_parameters: String,
+    pub situation: Option<String>,
+    /// `None` for runs on the local machine
+    pub host_class: Option<String>,
+    pub commit_id: String,
+    /// Unix time of the run
+    pub run_time: i64,
+    pub field: String,
+    pub probe: String,
+    pub unit: String,
+    pub median: i64,
+}
+
+/// The stats of a probe in a field table of a single run, as
+/// returned by `ResultsIndex::run_probe_stats`
+#[derive(Debug, PartialEq)]
+pub struct RunProbeStats {
+    pub probe: String,
+    pub unit: String,
+    pub n: Option<i64>,
+    pub sum: Option<i64>,
+    pub average: Option<i64>,
+    pub median: Option<i64>,
+    pub sd: Option<i64>,
+}
+
+/// A key dir with runs for a commit, as returned by
+/// `ResultsIndex::commit_keys`
+#[derive(Debug, PartialEq)]
+pub struct CommitKey {
+    /// The path of the key dir relative to the output directory
+    pub key_dir: String,
+    pub target: String,
+    pub custom_parameters: String,
+    pub host_class: Option<String>,
+    pub num_runs: i64,
+}
+
+/// As returned by `ResultsIndex::commit_probe_medians`
+#[derive(Debug, PartialEq)]
+pub struct KeyProbeMedian {
+    pub target: String,
+    pub custom_parameters: String,
+    pub host_class: Option<String>,
+    pub probe: String,
+    pub unit: String,
+    pub median: f64,
+}
+
+pub struct ResultsIndex {
+    output_base_dir: Arc<Path>,
+    connection: Connection,
+}
+
+impl ResultsIndex {
+    pub fn path(output_base_dir: &Path) -> PathBuf {
+        output_base_dir.append(RESULTS_INDEX_FILE_NAME)
+    }
+
+    /// Open the index for `output_base_dir`, creating it if
+    /// necessary.
+    pub fn open(output_base_dir: Arc<Path>) -> Result<Self> {
+        let path = Self::path(&output_base_dir);
+        let connection = Connection::open(&path).map_err(ctx!("opening database {path:?}"))?;
+        // Runs may be indexed while a rebuild is in progress
+        connection.busy_timeout(Duration::from_secs(60))?;
+        connection.pragma_update(None, "foreign_keys", true)?;
+
+        let version: i64 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version != SCHEMA_VERSION {
+            if version != 0 && !COMPATIBLE_SCHEMA_VERSIONS.contains(&version) {
+                warn!(
+                    "results index {path:?} has schema version {version}, \
+                     expected {SCHEMA_VERSION}, dropping it; run \
+                     `evobench-util index rebuild` to repopulate it"
+                );
+                connection.execute_batch(
+                    "DROP VIEW IF EXISTS results;
+                     DROP TABLE IF EXISTS probe_stats;
+                     DROP TABLE IF EXISTS run_parameters;
+                     DROP TABLE IF EXISTS runs;
+                     DROP TABLE IF EXISTS commits;
+                     DROP TABLE IF EXISTS commit_parents;",
+                )?;
+            }
+            connection.execute_batch(SCHEMA)?;
+            connection.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        }
+
+        Ok(Self {
+            output_base_dir,
+            connection,
+        })
+    }
+
+    /// Open an existing index for querying only
+    pub fn open_read_only(output_base_dir: Arc<Path>) -> Result<Self> {
+        let path = Self::path(&output_base_dir);
+        if !path.exists() {
+            bail!(
+                "results index {path:?} does not exist yet, run \
+                 `evobench-util index rebuild` to create it"
+            )
+        }
+        let connection = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(ctx!("opening database {path:?}"))?;
+        connection.busy_timeout(Duration::from_secs(60))?;
+        Ok(Self {
+            output_base_dir,
+            connection,
+        })
+    }
+
+    /// The path of `run_dir` relative to the output directory, as
+    /// used for the `run_dir` column
+    fn relative_run_dir(&self, run_dir: &RunDir) -> Result<String> {
+        let path = run_dir.to_path();
+        let relative = path.strip_prefix(&self.output_base_dir).map_err(|_| {
+            anyhow!(
+                "run dir {path:?} is not below the output directory {:?}",
+                self.output_base_dir
+            )
+        })?;
+        relative
+            .to_str()
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| anyhow!("run dir path is not valid UTF-8: {relative:?}"))
+    }
+
+    /// (Re-)index the run at `run_dir`, reading the evobench.log file
+    /// from `evobench_log_path` (default: the standard location in
+    /// `run_dir`), or rather the stats cache file in `run_dir` if it
+    /// has the needed entry. `commit_time` is stored if given, an
+    /// existing time is kept otherwise.
+    pub fn insert_run(
+        &mut self,
+        run_dir: &RunDir,
+        evobench_log_path: Option<&Path>,
+        commit_time: Option<Unixtime>,
+    ) -> Result<()> {
+        let relative_run_dir = self.relative_run_dir(run_dir)?;
+        let key_dir = run_dir.parent();
+        let parameters_dir = key_dir.parent();
+
+        let stats = {
+            let default_path_;
+            let path = if let Some(path) = evobench_log_path {
+                path
+            } else {
+                default_path_ = run_dir.evobench_log_path();
+                &default_path_
+            };
+            run_stats(path, &run_dir.evobench_stats_cache_path())?
+        };
+        let schedule_condition: Option<ScheduleCondition> =
+            read_ron_file(run_dir, "schedule_condition.ron")?;
+        let situation = schedule_condition
+            .as_ref()
+            .and_then(|c| c.situation())
+            .map(|s| s.as_str().to_owned());
+        let reason: Option<Option<String>> = read_ron_file(run_dir, "reason.ron")?;
+        let host_class = run_dir.host_class()?.map(|h| h.as_str().to_owned());
+        let run_time = run_dir
+            .timestamp()
+            .to_systemtime()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+
+        let tx = self.connection.transaction()?;
+        tx.execute("DELETE FROM runs WHERE run_dir = ?1", [&relative_run_dir])?;
+        tx.execute(
+            "INSERT INTO runs (run_dir, target, commit_id, custom_parameters, \
+             timestamp, run_time, situation, host_class, reason) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                relative_run_dir,
+                parameters_dir.target_name().as_str(),
+                key_dir.commit_id().to_string(),
+                parameters_dir.custom_parameters().to_string(),
+                run_dir.timestamp().as_str(),
+                run_time,
+                situation,
+                host_class,
+                reason.flatten(),
+            ],
+        )?;
+        {
+            let mut stmt = tx
+                .prepare("INSERT INTO run_parameters (run_dir, name, value) VALUES (?1, ?2, ?3)")?;
+            for (name, value) in parameters_dir.custom_parameters().key_val_strs() {
+                stmt.execute(params![relative_run_dir, name, value])?;
+            }
+        }
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO probe_stats (run_dir, field, probe, unit, n, sum, average, median, sd) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )?;
+            for (field, (unit, probes)) in &stats {
+                for (probe, probe_stats) in probes {
+                    let ProbeStats {
+                        n,
+                        sum,
+                        average,
+                        median,
+                        sd,
+                    } = probe_stats;
+                    stmt.execute(params![
+                        relative_run_dir,
+                        field,
+                        probe,
+                        unit,
+                        u64_to_sql(*n)?,
+                        u64_to_sql(*sum)?,
+                        u64_to_sql(*average)?,
+                        u64_to_sql(*median)?,
+                        u64_to_sql(*sd)?,
+                    ])?;
+                }
+            }
+        }
+        if let Some(commit_time) = commit_time {
+            set_commit_time(&tx, key_dir.commit_id(), commit_time)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The commits of indexed runs for which no commit time is known
+    pub fn commits_without_time(&self) -> Result<Vec<GitHash>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT DISTINCT commit_id FROM runs \
+             WHERE commit_id NOT IN (SELECT commit_id FROM commits) \
+             ORDER BY commit_id",
+        )?;
+        let mut rows = stmt.query([])?;
+        let mut commits = Vec::new();
+        while let Some(row) = rows.next()? {
+            let s: String = row.get(0)?;
+            commits.push(s.parse().map_err(ctx!("commit id in results index"))?);
+        }
+        Ok(commits)
+    }
+
+    pub fn set_commit_times(&mut self, commit_times: &BTreeMap<GitHash, Unixtime>) -> Result<()> {
+        let tx = self.connection.transaction()?;
+        for (commit_id, commit_time) in commit_times {
+            set_commit_time(&tx, commit_id, *commit_time)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The commits of indexed runs for which the first parent is not
+    /// known
+    pub fn commits_without_first_parent(&self) -> Result<Vec<GitHash>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT DISTINCT commit_id FROM runs \
+             WHERE commit_id NOT IN (SELECT commit_id FROM commit_parents) \
+             ORDER BY commit_id",
+        )?;
+        let mut rows = stmt.query([])?;
+        let mut commits = Vec::new();
+        while let Some(row) = rows.next()? {
+            let s: String = row.get(0)?;
+            commits.push(s.parse().map_err(ctx!("commit id in results index"))?);
+        }
+        Ok(commits)
+    }
+
+    /// Record the first parents of commits (`None` for root commits)
+    pub fn set_first_parents(
+        &mut self,
+        first_parents: &BTreeMap<GitHash, Option<GitHash>>,
+    ) -> Result<()> {
+        let tx = self.connection.transaction()?;
+        for (commit_id, first_parent) in first_parents {
+            tx.execute(
+                "INSERT OR REPLACE INTO commit_parents (commit_id, first_parent) VALUES (?1, ?2)",
+                params![
+                    commit_id.to_string(),
+                    first_parent.as_ref().map(|c| c.to_string())
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The first parent of `commit_id`, if known and it has one
+    pub fn first_parent(&self, commit_id: &GitHash) -> Result<Option<GitHash>> {
+        let first_parent: Option<String> = self
+            .connection
+            .query_row(
+                "SELECT first_parent FROM commit_parents WHERE commit_id = ?1",
+                [commit_id.to_string()],
+                |row| row.get(0),
+            )
+            .optional()?
+            .flatten();
+        first_parent
+            .map(|s| s.parse().map_err(ctx!("commit id in results index")))
+            .transpose()
+    }
+
+    /// The commits with indexed runs whose first parent is
+    /// `commit_id`
+    pub fn children_with_runs(&self, commit_id: &GitHash) -> Result<Vec<GitHash>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT commit_id FROM commit_parents \
+             WHERE first_parent = ?1 \
+             AND commit_id IN (SELECT commit_id FROM runs) \
+             ORDER BY commit_id",
+        )?;
+        let mut rows = stmt.query([commit_id.to_string()])?;
+        let mut commits = Vec::new();
+        while let Some(row) = rows.next()? {
+            let s: String = row.get(0)?;
+            commits.push(s.parse().map_err(ctx!("commit id in results index"))?);
+        }
+        Ok(commits)
+    }
+
+    /// All commits with indexed runs
+    pub fn commits_with_runs(&self) -> Result<Vec<GitHash>> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT DISTINCT commit_id FROM runs ORDER BY commit_id")?;
+        let mut rows = stmt.query([])?;
+        let mut commits = Vec::new();
+        while let Some(row) = rows.next()? {
+            let s: String = row.get(0)?;
+            commits.push(s.parse().map_err(ctx!("commit id in results index"))?);
+        }
+        Ok(commits)
+    }
+
+    /// The committer time of `commit_id`, if known
+    pub fn commit_time(&self, commit_id: &GitHash) -> Result<Option<i64>> {
+        Ok(self
+            .connection
+            .query_row(
+                "SELECT commit_time FROM commits WHERE commit_id = ?1",
+                [commit_id.to_string()],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// The key dirs with runs for `commit_id`, separated by host
+    /// class
+    pub fn commit_keys(&self, commit_id: &GitHash) -> Result<Vec<CommitKey>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT target, custom_parameters, host_class, COUNT(*), MIN(run_dir) \
+             FROM runs WHERE commit_id = ?1 \
+             GROUP BY target, custom_parameters, IFNULL(host_class, '') \
+             ORDER BY target, custom_parameters, host_class",
+        )?;
+        let rows = stmt.query_map([commit_id.to_string()], |row| {
+            let run_dir: String = row.get(4)?;
+            Ok(CommitKey {
+                key_dir: match run_dir.rsplit_once('/') {
+                    Some((key_dir, _timestamp)) => key_dir.into(),
+                    None => run_dir,
+                },
+                target: row.get(0)?,
+                custom_parameters: row.get(1)?,
+                host_class: row.get(2)?,
+                num_runs: row.get(3)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// The average across the runs for `commit_id` of the median of
+    /// the probes (probe names only, not probe paths) in the `field`
+    /// table, per key as in `commit_keys`
+    pub fn commit_probe_medians(
+        &self,
+        commit_id: &GitHash,
+        field: &str,
+    ) -> Result<Vec<KeyProbeMedian>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT r.target, r.custom_parameters, r.host_class, s.probe, s.unit, AVG(s.median) \
+             FROM runs r JOIN probe_stats s USING (run_dir) \
+             WHERE r.commit_id = ?1 AND s.field = ?2 AND s.median IS NOT NULL \
+             AND instr(s.probe, ' > ') = 0 AND instr(s.probe, ' < ') = 0 \
+             GROUP BY r.target, r.custom_parameters, IFNULL(r.host_class, ''), s.probe, s.unit \
+             ORDER BY r.target, r.custom_parameters, r.host_class, s.probe",
+        )?;
+        let rows = stmt.query_map(params![commit_id.to_string(), field], |row| {
+            Ok(KeyProbeMedian {
+                target: row.get(0)?,
+                custom_parameters: row.get(1)?,
+                host_class: row.get(2)?,
+                probe: row.get(3)?,
+                unit: row.get(4)?,
+                median: row.get(5)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Remove the runs whose directory is not in `run_dirs` (as
+    /// returned by `relative_run_dir`); returns how many were
+    /// removed.
+    fn remove_runs_except(&mut self, run_dirs: &[String]) -> Result<usize> {
+        let tx = self.connection.transaction()?;
+        let existing: Vec<String> = {
+            let mut stmt = tx.prepare("SELECT run_dir FROM runs")?;
+            stmt.query_map([], |row| row.get(0))?
+                .collect::<Result<_, _>>()?
+        };
+        let mut removed = 0;
+        for run_dir in existing {
+            if run_dirs.binary_search(&run_dir).is_err() {
+                tx.execute("DELETE FROM runs WHERE run_dir = ?1", [&run_dir])?;
+                removed += 1;
+            }
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    /// Index all runs found in the output directory, and remove the
+    /// entries for runs that no longer exist. Errors for individual
+    /// runs are reported as warnings. Returns the number of runs
+    /// indexed.
+    pub fn rebuild(&mut self) -> Result<usize> {
+        let key_dirs = find_key_dirs(&self.output_base_dir)?;
+
+        let mut num_indexed = 0;
+        let mut seen = Vec::new();
+        for key_dir in key_dirs {
+            for run_dir in key_dir.sub_dirs()? {
+                let run_dir = run_dir?;
+                seen.push(self.relative_run_dir(&run_dir)?);
+                let path = run_dir.to_path();
+                if !run_dir.has_evobench_results()? {
+                    info!("no evobench.log or stats cache in {path:?}, empty dir?");
+                    continue;
+                }
+                info!("indexing {path:?}");
+                match self.insert_run(&run_dir, None, None) {
+                    Ok(()) => num_indexed += 1,
+                    Err(e) => warn!("could not index run dir {path:?}: {e:#}"),
+                }
+            }
+        }
+        seen.sort();
+        let removed = self.remove_runs_except(&seen)?;
+        if removed > 0 {
+            info!("removed {removed} runs that no longer exist from the index");
+        }
+        Ok(num_indexed)
+    }
+
+    /// Run the SQL statement `sql` (the connection should have been
+    /// opened with `open_read_only` if `sql` comes from the user).
+    pub fn query(&self, sql: &str) -> Result<QueryResult> {
+        let mut stmt = self
+            .connection
+            .prepare(sql)
+            .map_err(ctx!("preparing query {sql:?}"))?;
+        let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+        let num_columns = columns.len();
+        let mut rows = stmt.query([])?;
+        let mut result_rows = Vec::new();
+        while let Some(row) = rows.next()? {
+            let values = (0..num_columns)
+                .map(|i| row.get::<_, Value>(i))
+                .collect::<Result<_, _>>()?;
+            result_rows.push(values);
+        }
+        Ok(QueryResult {
+            columns,
+            rows: result_rows,
+        })
+    }
+
+    /// The number of indexed runs and the unix time of the most
+    /// recent one
+    pub fn num_runs_and_last_run_time(&self) -> Result<(u64, Option<i64>)> {
+        let (num_runs, last_run_time): (i64, Option<i64>) =
+            self.connection
+                .query_row("SELECT COUNT(*), MAX(run_time) FROM runs", [], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })?;
+        Ok((num_runs as u64, last_run_time))
+    }
+
+    /// The stats of the probes in the `field` table (e.g. "real") of
+    /// the run at `run_dir`, the `limit` ones with the largest sum
+    /// first
+    pub fn run_probe_stats(
+        &self,
+        run_dir: &RunDir,
+        field: &str,
+        limit: usize,
+    ) -> Result<Vec<RunProbeStats>> {
+        let relative_run_dir = self.relative_run_dir(run_dir)?;
+        let mut stmt = self.connection.prepare(
+            "SELECT probe, unit, n, sum, average, median, sd FROM probe_stats \
+             WHERE run_dir = ?1 AND field = ?2 \
+             ORDER BY sum DESC, probe \
+             LIMIT ?3",
+        )?;
+        let rows = stmt.query_map(params![relative_run_dir, field, limit as i64], |row| {
+            Ok(RunProbeStats {
+                probe: row.get(0)?,
+                unit: row.get(1)?,
+                n: row.get(2)?,
+                sum: row.get(3)?,
+                average: row.get(4)?,
+                median: row.get(5)?,
+                sd: row.get(6)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// The medians of all probes from the most recent run of every
+    /// combination of target, custom parameters and situation
+    pub fn latest_medians(&self) -> Result<Vec<LatestMedian>> {
+        // SQLite takes the bare `run_dir` column from the row with
+        // the maximum `run_time`
+        let mut stmt = self.connection.prepare(
+            "WITH latest AS ( \
+                 SELECT run_dir, MAX(run_time) FROM runs \
+                 GROUP BY target, custom_parameters, IFNULL(situation, ''), \
+                     IFNULL(host_class, '') \
+             ) \
+             SELECT r.target, r.custom_parameters, r.situation, r.host_class, r.commit_id, \
+                 r.run_time, s.field, s.probe, s.unit, s.median \
+             FROM latest \
+             JOIN runs r USING (run_dir) \
+             JOIN probe_stats s USING (run_dir) \
+             WHERE s.median IS NOT NULL \
+             ORDER BY r.target, r.custom_parameters, r.situation, r.host_class, s.field, \
+                 s.probe",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(LatestMedian {
+                target: row.get(0)?,
+                custom_parameters: row.get(1)?,
+                situation: row.get(2)?,
+                host_class: row.get(3)?,
+                commit_id: row.get(4)?,
+                run_time: row.get(5)?,
+                field: row.get(6)?,
+                probe: row.get(7)?,
+                unit: row.get(8)?,
+                median: row.get(9)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+}
+
+impl RunDir {
+    /// Add this run to the results index in its output directory
+    /// (see `ResultsIndex::insert_run`). Failures are only reported
+    /// as warnings, since the index can be rebuilt.
+    pub fn update_results_index(
+        &self,
+        evobench_log_path: Option<&Path>,
+        commit_time: Option<Unixtime>,
+    ) {
+        let output_base_dir = self.parent().parent().base_path().clone_arc();
+        if let Err(e) = ResultsIndex::open(output_base_dir)
+            .and_then(|mut index| index.insert_run(self, evobench_log_path, commit_time))
+        {
+            warn!(
+                "ignoring error adding run dir {:?} to the results index: {e:#}",
+                self.to_path()
+            );
+        }
+    }
+}
+
+impl KeyDir {
+    /// Record the first parent of the commit of this key dir in the
+    /// results index in its output directory. Failures are only
+    /// reported as warnings, since `evobench-util index rebuild` can
+    /// retrieve it again.
+    pub fn update_first_parent_in_results_index(&self, first_parent: Option<GitHash>) {
+        let output_base_dir = self.parent().base_path().clone_arc();
+        let first_parents = [(self.commit_id().clone(), first_parent)].into();
+        if let Err(e) = ResultsIndex::open(output_base_dir)
+            .and_then(|mut index| index.set_first_parents(&first_parents))
+        {
+            warn!(
+                "ignoring error adding the first parent of commit {} to the results index: {e:#}",
+                self.commit_id()
+            );
+        }
+    }
+}
+
+fn set_commit_time(connection: &Connection, commit_id: &GitHash, time: Unixtime) -> Result<()> {
+    connection.execute(
+        "INSERT OR REPLACE INTO commits (commit_id, commit_time) VALUES (?1, ?2)",
+        params![commit_id.to_string(), time.0 as i64],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        evaluator::{
+            all_outputs_all_fields_table::key_details_for,
+            options::CheckedOutputOptionsMapCase,
+            stats_cache::{StatsCacheEntry, StatsCacheFile},
+        },
+        run::output_directory::test_runs::{add_test_run, test_key_dir},
+        utillib::{into_arc_path::IntoArcPath, test_dir::TestDir},
+    };
+
+    #[test]
+    fn t_schema_and_query() -> Result<()> {
+        let connection = Connection::open_in_memory()?;
+        connection.execute_batch(SCHEMA)?;
+        connection.execute(
+            "INSERT INTO runs (run_dir, target, commit_id, custom_parameters, timestamp, run_time) \
+             VALUES ('api/A=1/c/t', 'api', 'c', 'A=1', 't', 10)",
+            [],
+        )?;
+        connection.execute(
+            "INSERT INTO probe_stats (run_dir, field, probe, unit, n, sum, average, median, sd) \
+             VALUES ('api/A=1/c/t', 'real time', 'main', 'ns', 2, 10, 5, 5, 1)",
+            [],
+        )?;
+        connection.execute(
+            "INSERT INTO commits (commit_id, commit_time) VALUES ('c', 86400)",
+            [],
+        )?;
+        let index = ResultsIndex {
+            output_base_dir: Path::new("/nonexistent").into(),
+            connection,
+        };
+        let result = index.query("SELECT target, commit_date, probe, average FROM results")?;
+        assert_eq!(
+            result.columns,
+            ["target", "commit_date", "probe", "average"]
+        );
+        assert_eq!(
+            result.rows,
+            [[
+                Value::Text("api".into()),
+                Value::Text("1970-01-02 00:00:00".into()),
+                Value::Text("main".into()),
+                Value::Integer(5)
+            ]]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn t_latest_medians() -> Result<()> {
+        let connection = Connection::open_in_memory()?;
+        connection.execute_batch(SCHEMA)?;
+        for (run_dir, commit_id, run_time, host_class, median) in [
+            ("api/A=1/c1/t1", "c1", 10, None, 5),
+            ("api/A=1/c2/t2", "c2", 20, None, 7),
+            // The latest run, but on a worker
+            ("api/A=1/c2/t4", "c2", 30, Some("w"), 3),
+            ("api/A=2/c1/t3", "c1", 15, None, 9),
+        ] {
+            connection.execute(
+                "INSERT INTO runs (run_dir, target, commit_id, custom_parameters, timestamp, \
+                     run_time, host_class) \
+                 VALUES (?1, 'api', ?2, substr(?1, 5, 3), 't', ?3, ?4)",
+                params![run_dir, commit_id, run_time, host_class],
+            )?;
+            connection.execute(
+                "INSERT INTO probe_stats (run_dir, field, probe, unit, median) \
+                 VALUES (?1, 'real', 'main', 'ns', ?2)",
+                params![run_dir, median],
+            )?;
+        }
+        let index = ResultsIndex {
+            output_base_dir: Path::new("/nonexistent").into(),
+            connection,
+        };
+        assert_eq!(index.num_runs_and_last_run_time()?, (4, Some(30)));
+        let medians = index.latest_medians()?;
+        assert_eq!(
+            medians
+                .iter()
+                .map(|m| (
+                    m.custom_parameters.as_str(),
+                    m.host_class.as_deref(),
+                    m.commit_id.as_str(),
+                    m.median
+                ))
+                .collect::<Vec<_>>(),
+            [
+                ("A=1", None, "c2", 7),
+                ("A=1", Some("w"), "c2", 3),
+                ("A=2", None, "c1", 9)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn t_run_probe_stats() -> Result<()> {
+        let connection = Connection::open_in_memory()?;
+        connection.execute_batch(SCHEMA)?;
+        let run_dir_str = "api/A=1/09193b52688a964956b3fae0f52eeae471adc027/\
+                           2026-02-02T11:26:48.563793486+00:00";
+        for (field, probe, sum) in [
+            ("real time", "a", 10),
+            ("real time", "b", 30),
+            ("real time", "c", 20),
+            ("cpu time", "d", 50),
+        ] {
+            connection.execute(
+                "INSERT INTO probe_stats (run_dir, field, probe, unit, sum) \
+                 VALUES (?1, ?2, ?3, 'ns', ?4)",
+                params![run_dir_str, field, probe, sum],
+            )?;
+        }
+        let index = ResultsIndex {
+            output_base_dir: Path::new("/out").into(),
+            connection,
+        };
+        let run_dir = RunDir::try_from(Path::new("/out").append(run_dir_str).into_arc_path())?;
+        let stats = index.run_probe_stats(&run_dir, "real time", 2)?;
+        assert_eq!(
+            stats
+                .iter()
+                .map(|s| (s.probe.as_str(), s.sum))
+                .collect::<Vec<_>>(),
+            [("b", Some(30)), ("c", Some(20))]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn t_commit_keys_and_medians() -> Result<()> {
+        let connection = Connection::open_in_memory()?;
+        connection.execute_batch(SCHEMA)?;
+        let c1 = "09193b52688a964956b3fae0f52eeae471adc027";
+        let c2 = "5a3b7e0e4fa8c4f2d0d3c1d7b2e7ab1b0f1e2d3c";
+        for (run_dir, commit_id, probe, median) in [
+            (format!("api/A=1/{c1}/t1"), c1, "main", 10),
+            (format!("api/A=1/{c1}/t2"), c1, "main", 20),
+            (format!("api/A=1/{c1}/t2"), c1, "main > sub", 5),
+            (format!("api/A=1/{c2}/t3"), c2, "main", 30),
+        ] {
+            connection.execute(
+                "INSERT OR IGNORE INTO runs (run_dir, target, commit_id, custom_parameters, \
+                 timestamp, run_time) VALUES (?1, 'api', ?2, 'A=1', 't', 0)",
+                params![run_dir, commit_id],
+            )?;
+            connection.execute(
+                "INSERT INTO probe_stats (run_dir, field, probe, unit, median) \
+                 VALUES (?1, 'real time', ?2, 'ns', ?3)",
+                params![run_dir, probe, median],
+            )?;
+        }
+        let mut index = ResultsIndex {
+            output_base_dir: Path::new("/nonexistent").into(),
+            connection,
+        };
+        let c1: GitHash = c1.parse()?;
+        let c2: GitHash = c2.parse()?;
+        index.set_first_parents(&[(c2.clone(), Some(c1.clone())), (c1.clone(), None)].into())?;
+        assert_eq!(index.first_parent(&c2)?, Some(c1.clone()));
+        assert_eq!(index.first_parent(&c1)?, None);
+        assert_eq!(index.children_with_runs(&c1)?, [c2.clone()]);
+        assert!(index.commits_without_first_parent()?.is_empty());
+
+        assert_eq!(
+            index.commit_keys(&c1)?,
+            [CommitKey {
+                key_dir: format!("api/A=1/{c1}"),
+                target: "api".into(),
+                custom_parameters: "A=1".into(),
+                host_class: None,
+                num_runs: 2,
+            }]
+        );
+        let medians = index.commit_probe_medians(&c1, "real time")?;
+        assert_eq!(
+            medians
+                .iter()
+                .map(|m| (m.probe.as_str(), m.median))
+                .collect::<Vec<_>>(),
+            [("main", 15.)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn t_rebuild_pruned_run() -> Result<()> {
+        let test_dir = TestDir::new("results-index-pruned");
+        let base = test_dir.path();
+        let key_dir = test_key_dir(base, "api", &"1".repeat(40));
+        // Only the stats cache is left of the evobench.log
+        let run_dir = add_test_run(&key_dir, "2020-01-01T00:00:00+00:00".parse()?, None);
+        let mut cache = StatsCacheFile::empty();
+        cache.insert(StatsCacheEntry {
+            key_details: (&key_details_for(
+                CheckedOutputOptionsMapCase::Excel,
+                &SUMMARY_EVALUATION_OPTS,
+            ))
+                .into(),
+            real_time: Vec::new(),
+            cpu_time: Vec::new(),
+            sys_time: Vec::new(),
+            ctx_switches: Vec::new(),
+        });
+        cache.save(&run_dir.evobench_stats_cache_path())?;
+
+        let mut index = ResultsIndex::open(base.into())?;
+        assert_eq!(index.rebuild()?, 1);
+        assert_eq!(index.num_runs_and_last_run_time()?.0, 1);
+
+        // Without the needed entry, the run can't be indexed
+        StatsCacheFile::empty().save(&run_dir.evobench_stats_cache_path())?;
+        assert!(index.insert_run(&run_dir, None, None).is_err());
+        Ok(())
+    }
+}
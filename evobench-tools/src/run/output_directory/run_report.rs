@@ -0,0 +1,387 @@
+//! The `index.html` page in each `RunDir`, showing the parameters and
+//! results of the run and linking to its files, the neighbouring runs
+//! for the same key, and the summaries in the key dir.
+
+use std::{fs::read_dir, io::Write, path::Path, sync::Arc};
+
+use ahtml::{HtmlAllocator, Node, att};
+use anyhow::Result;
+
+use crate::{
+    ctx,
+    io_utils::tempfile_utils::tempfile,
+    output_table::{OutputTable, OutputTableTitle, html::HtmlTable},
+    run::{
+        command_log_file::CommandLogFile,
+        config::{RunConfig, ScheduleCondition},
+        output_directory::{
+            html_files::LinkCellValue,
+            results_index::{ResultsIndex, RunProbeStats},
+            structure::{RunDir, SubDirs, ToPath},
+        },
+    },
+    utillib::arc::CloneArc,
+    warn,
+};
+
+pub const RUN_REPORT_FILE_NAME: &str = "index.html";
+
+/// The field table from which the top probes are shown
+const TOP_PROBES_FIELD: &str = "real time";
+
+/// How many probes are shown
+const TOP_PROBES_N: usize = 20;
+
+/// The names of the regular files in `dir` for which `filter`
+/// returns true, sorted
+fn file_names(dir: &Path, filter: impl Fn(&str) -> bool) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in read_dir(dir).map_err(ctx!("opening dir {dir:?}"))? {
+        let entry = entry.map_err(ctx!("reading dir {dir:?}"))?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        if let Some(name) = entry.file_name().to_str() {
+            if filter(name) {
+                names.push(name.to_owned());
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+fn text_cell(s: impl Into<String>) -> LinkCellValue {
+    LinkCellValue {
+        s: s.into(),
+        url: None,
+    }
+}
+
+fn link_cell(s: impl Into<String>, url: impl Into<String>) -> LinkCellValue {
+    LinkCellValue {
+        s: s.into(),
+        url: Some(url.into()),
+    }
+}
+
+/// Read an optional file in `run_dir`
+fn read_optional_file(run_dir: &RunDir, name: &str) -> Result<Option<String>> {
+    let path = run_dir.append_str(name)?;
+    match std::fs::read_to_string(&path) {
+        Ok(s) => Ok(Some(s)),
+        Err(e) => match e.kind() {
+            std::io::ErrorKind::NotFound => Ok(None),
+            _ => Err(e).map_err(ctx!("reading file {path:?}"))?,
+        },
+    }
+}
+
+/// A table with a title row spanning all columns, followed by a row
+/// with `column_titles` if given
+fn titled_table<'a>(
+    html: &'a HtmlAllocator,
+    title: &str,
+    num_columns: usize,
+    column_titles: Option<&[&str]>,
+) -> Result<HtmlTable<'a>> {
+    let mut table = HtmlTable::new(num_columns, html);
+    table.write_title_row(
+        &[OutputTableTitle {
+            text: title.into(),
+            span: num_columns,
+            anchor_name: None,
+        }],
+        None,
+    )?;
+    if let Some(column_titles) = column_titles {
+        let titles: Vec<_> = column_titles
+            .iter()
+            .map(|title| OutputTableTitle {
+                text: (*title).into(),
+                span: 1,
+                anchor_name: None,
+            })
+            .collect();
+        table.write_title_row(&titles, None)?;
+    }
+    Ok(table)
+}
+
+fn table_section(html: &HtmlAllocator, table: HtmlTable) -> Result<Node> {
+    Ok(html.div([], html.table([], table.finish()?)?)?)
+}
+
+impl RunDir {
+    /// The other runs in the same key dir, sorted by time, before and
+    /// after this one
+    fn neighbour_runs(&self) -> Result<(Option<RunDir>, Option<RunDir>)> {
+        let mut run_dirs = self.parent().sub_dirs()?.collect::<Result<Vec<RunDir>>>()?;
+        run_dirs.sort_by_key(|run_dir| run_dir.timestamp().to_systemtime());
+        let own_time = self.timestamp().to_systemtime();
+        let previous = run_dirs
+            .iter()
+            .filter(|run_dir| run_dir.timestamp().to_systemtime() < own_time)
+            .last()
+            .cloned();
+        let next = run_dirs
+            .iter()
+            .find(|run_dir| run_dir.timestamp().to_systemtime() > own_time)
+            .cloned();
+        Ok((previous, next))
+    }
+
+    fn report_parameters_table<'a>(&self, html: &'a HtmlAllocator) -> Result<HtmlTable<'a>> {
+        let key_dir = self.parent();
+        let parameters_dir = key_dir.parent();
+        let mut table = titled_table(html, "Run", 2, None)?;
+        let mut row = |key: &str, val: LinkCellValue| -> Result<()> {
+            table.write_data_row(&[text_cell(key), val], None)
+        };
+
+        row("target", text_cell(parameters_dir.target_name().as_str()))?;
+        row(
+            "custom parameters",
+            text_cell(parameters_dir.custom_parameters().to_string()),
+        )?;
+        row("commit", text_cell(key_dir.commit_id().to_string()))?;
+        row("timestamp", text_cell(self.timestamp().as_str()))?;
+        row(
+            "host class",
+            text_cell(match self.host_class()? {
+                Some(host_class) => host_class.as_str().to_owned(),
+                None => "(local)".into(),
+            }),
+        )?;
+
+        if let Some(s) = read_optional_file(self, "reason.ron")? {
+            let path = self.append_str("reason.ron")?;
+            let reason: Option<String> =
+                ron::from_str(&s).map_err(ctx!("reading file {path:?}"))?;
+            row("reason", text_cell(reason.unwrap_or_default()))?;
+        }
+        if let Some(s) = read_optional_file(self, "schedule_condition.ron")? {
+            let path = self.append_str("schedule_condition.ron")?;
+            let schedule_condition: ScheduleCondition =
+                ron::from_str(&s).map_err(ctx!("reading file {path:?}"))?;
+            if let Some(situation) = schedule_condition.situation() {
+                row("situation", text_cell(situation.as_str()))?;
+            }
+            row(
+                "schedule condition",
+                text_cell(ron::to_string(&schedule_condition)?),
+            )?;
+        }
+
+        // Runs only get a run dir if the benchmarking command
+        // succeeded
+        row("exit status", text_cell("0 (success)"))?;
+
+        let standard_log_path = self.standard_log_path();
+        let duration = if standard_log_path.exists() {
+            let command_log_file = CommandLogFile::from(&standard_log_path);
+            let command_log = command_log_file.command_log()?;
+            match command_log.time_span() {
+                Some((start, end)) => {
+                    let duration = end.signed_duration_since(start);
+                    format!(
+                        "{:.3} s (from {start} to {end})",
+                        duration.num_milliseconds() as f64 / 1000.
+                    )
+                }
+                None => "(no timestamped output)".into(),
+            }
+        } else {
+            "(no standard.log)".into()
+        };
+        row("duration", text_cell(duration))?;
+
+        Ok(table)
+    }
+
+    fn report_log_extracts_table<'a>(
+        &self,
+        html: &'a HtmlAllocator,
+        run_config: &RunConfig,
+    ) -> Result<Option<HtmlTable<'a>>> {
+        let target_name = self.parent().parent().target_name();
+        let Some(log_extracts) = run_config
+            .targets
+            .get(target_name)
+            .and_then(|target| target.log_extracts.as_ref())
+        else {
+            return Ok(None);
+        };
+        if log_extracts.is_empty() {
+            return Ok(None);
+        }
+        let mut table = titled_table(html, "Log extracts", 2, Some(&["name", "seconds"]))?;
+        for log_extract in log_extracts {
+            let name = log_extract.filename.as_str();
+            let val = match read_optional_file(self, name)? {
+                Some(s) => link_cell(s.trim(), name),
+                None => text_cell("(not found)"),
+            };
+            table.write_data_row(&[text_cell(name), val], None)?;
+        }
+        Ok(Some(table))
+    }
+
+    fn report_top_probes_table<'a>(&self, html: &'a HtmlAllocator) -> Result<HtmlTable<'a>> {
+        let title = format!("Top {TOP_PROBES_N} probes by sum ({TOP_PROBES_FIELD})");
+        let column_titles = ["probe", "n", "sum", "average", "median", "sd", "unit"];
+        let mut table = titled_table(html, &title, column_titles.len(), Some(&column_titles))?;
+
+        let output_base_dir = self.parent().parent().base_path().clone_arc();
+        let probe_stats = ResultsIndex::open_read_only(output_base_dir)
+            .and_then(|index| index.run_probe_stats(self, TOP_PROBES_FIELD, TOP_PROBES_N));
+        match probe_stats {
+            Ok(probe_stats) => {
+                let opt = |v: Option<i64>| text_cell(v.map(|v| v.to_string()).unwrap_or_default());
+                for RunProbeStats {
+                    probe,
+                    unit,
+                    n,
+                    sum,
+                    average,
+                    median,
+                    sd,
+                } in probe_stats
+                {
+                    table.write_data_row(
+                        &[
+                            text_cell(probe),
+                            opt(n),
+                            opt(sum),
+                            opt(average),
+                            opt(median),
+                            opt(sd),
+                            text_cell(unit),
+                        ],
+                        None,
+                    )?;
+                }
+            }
+            Err(e) => {
+                warn!(
+                    "no probe stats for the report in {:?}: {e:#}",
+                    self.to_path()
+                );
+                table.print(text_cell(format!("(not available: {e:#})")))?;
+            }
+        }
+        Ok(table)
+    }
+
+    fn report_files_table<'a>(&self, html: &'a HtmlAllocator) -> Result<HtmlTable<'a>> {
+        let mut table = titled_table(html, "Files", 1, None)?;
+        for name in file_names(self.to_path(), |name| name != RUN_REPORT_FILE_NAME)? {
+            table.print(link_cell(name.as_str(), name.as_str()))?;
+        }
+        Ok(table)
+    }
+
+    fn report_navigation_table<'a>(&self, html: &'a HtmlAllocator) -> Result<HtmlTable<'a>> {
+        let (previous, next) = self.neighbour_runs()?;
+        let mut table = titled_table(html, "Runs for the same commit and parameters", 1, None)?;
+        let mut run_link = |label: &str, run_dir: Option<RunDir>| -> Result<()> {
+            let val = match run_dir {
+                Some(run_dir) => {
+                    let timestamp = run_dir.timestamp().as_str();
+                    link_cell(
+                        format!("{label}: {timestamp}"),
+                        format!("../{timestamp}/{RUN_REPORT_FILE_NAME}"),
+                    )
+                }
+                None => text_cell(format!("{label}: (none)")),
+            };
+            table.print(val)
+        };
+        run_link("previous run", previous)?;
+        run_link("next run", next)?;
+
+        table.print(link_cell("all runs and summaries", "../"))?;
+        let key_dir_path = self.parent().to_path();
+        for name in file_names(key_dir_path, |name| name.contains("-summary"))? {
+            table.print(link_cell(name.as_str(), format!("../{name}")))?;
+        }
+        Ok(table)
+    }
+
+    /// Write the `index.html` file in this run dir. Reads the top
+    /// probes from the results index, thus should be called after
+    /// `update_results_index`.
+    pub fn write_report_html(&self, run_config: &RunConfig) -> Result<()> {
+        let html = HtmlAllocator::new(1000000, Arc::new("write_report_html"));
+
+        let mut sections = html.new_vec();
+        sections.push(table_section(&html, self.report_navigation_table(&html)?)?)?;
+        sections.push(table_section(&html, self.report_parameters_table(&html)?)?)?;
+        if let Some(table) = self.report_log_extracts_table(&html, run_config)? {
+            sections.push(table_section(&html, table)?)?;
+        }
+        sections.push(table_section(&html, self.report_top_probes_table(&html)?)?)?;
+
+        // The flame graphs (clicking opens the interactive version)
+        for name in file_names(self.to_path(), |name| {
+            name.starts_with("single-") && name.ends_with(".svg")
+        })? {
+            sections.push(html.div(
+                [],
+                [
+                    html.p([], [html.text(name.as_str())?])?,
+                    html.a(
+                        [att("href", name.as_str())],
+                        [html.img(
+                            [
+                                att("src", name.as_str()),
+                                att("alt", name.as_str()),
+                                att("style", "max-width: 100%;"),
+                            ],
+                            [],
+                        )?],
+                    )?,
+                ],
+            )?)?;
+        }
+
+        sections.push(table_section(&html, self.report_files_table(&html)?)?)?;
+
+        let doc = html.html(
+            [],
+            [
+                html.head([], [html.meta([att("charset", "utf-8")], [])?])?,
+                html.body([], sections)?,
+            ],
+        )?;
+
+        let path = self.append_str(RUN_REPORT_FILE_NAME)?;
+        let (tmp_file, mut out) = tempfile(path.clone(), false)?;
+        out.write_all(html.to_html_string(doc, true).as_bytes())
+            .map_err(ctx!("writing to {path:?}"))?;
+        out.flush().map_err(ctx!("writing to {path:?}"))?;
+        drop(out);
+        tmp_file.finish()?;
+        Ok(())
+    }
+
+    /// Write the `index.html` file for this run, and re-write the one
+    /// of the previous run for the same key so that it links to this
+    /// one. Errors are only reported as warnings, the pages are not
+    /// essential.
+    pub fn update_report_html(&self, run_config: &RunConfig) {
+        let previous = self.neighbour_runs().map(|(previous, _next)| previous);
+        let result = self.write_report_html(run_config).and_then(|()| {
+            if let Some(previous) = previous? {
+                previous.write_report_html(run_config)?;
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            warn!(
+                "ignoring error writing the report page for run dir {:?}: {e:#}",
+                self.to_path()
+            );
+        }
+    }
+}
@@ -16,6 +16,7 @@ use kstring::KString;
 
 use crate::{
     clone, ctx,
+    evaluator::stats_cache::stats_cache_path,
     git::GitHash,
     info,
     run::{
@@ -25,6 +26,10 @@ use crate::{
             BenchmarkingJobParameters, CustomParameters, ExtendPath, RunParameters,
             UncheckedCustomParameters,
         },
+        output_directory::{
+            commit_overview::COMMITS_DIR_NAME, comparisons::COMPARISONS_DIR_NAME,
+            sweeps::SWEEPS_DIR_NAME,
+        },
     },
     serde_types::{
         allowed_env_var::AllowedEnvVar, date_and_time::DateTimeWithOffset,
@@ -36,6 +41,15 @@ use crate::{
     },
 };
 
+/// The directory at the top of the output directory holding symlinks
+/// to the `KeyDir`s of the latest runs, named by run timestamp
+pub const LATEST_DIR_NAME: &str = "latest";
+
+/// The directory at the top of the output directory holding
+/// directories with redirects to the `RunDir`s of the latest runs,
+/// named by run timestamp
+pub const LATEST_REDIR_DIR_NAME: &str = "latest-redir";
+
 // --- The types ----------------------------------------------------------------
 
 /// The dir representing all of a key except for the commit id
@@ -207,6 +221,16 @@ impl CheckedOrUncheckedCustomParameters {
             }
         }
     }
+
+    /// The parameters as (name, value) pairs, sorted by name
+    pub fn key_val_strs(&self) -> Vec<(&str, &str)> {
+        match self {
+            CheckedOrUncheckedCustomParameters::UncheckedCustomParameters(v) => {
+                v.key_val_strs().collect()
+            }
+            CheckedOrUncheckedCustomParameters::CustomParameters(v) => v.key_val_strs().collect(),
+        }
+    }
 }
 
 impl Display for CheckedOrUncheckedCustomParameters {
@@ -378,6 +402,7 @@ impl ParametersDir {
             initial_boost: _,
             command,
             custom_parameters,
+            sweep: _,
         } = job_template;
         let target_name = command.target_name.clone();
         let custom_parameters = custom_parameters.clone_arc().into();
@@ -556,6 +581,12 @@ impl RunDir {
             .expect("evobench_log_path has filename")
     }
 
+    /// The cache of the statistics calculated from the evobench.log
+    /// file (see `evaluator/stats_cache.rs`)
+    pub fn evobench_stats_cache_path(&self) -> PathBuf {
+        stats_cache_path(&self.evobench_log_path()).expect("evobench_log_path has filename")
+    }
+
     /// The optional output location that target projects can use,
     /// passed to it via the `BENCH_OUTPUT_LOG` env variable then
     /// compressed/moved to this location.
@@ -676,6 +707,53 @@ impl SubDirs for OutputSubdir {
     }
 }
 
+/// All `KeyDir`s in the output directory at `output_base_dir` (not
+/// those below the `latest` and other special directories, or below
+/// top-level directories starting with a dot, which are used for
+/// staging)
+pub fn find_key_dirs(output_base_dir: &Path) -> Result<Vec<Arc<KeyDir>>> {
+    fn find(dir: &Path, is_top: bool, out: &mut Vec<Arc<KeyDir>>) -> Result<()> {
+        for entry in std::fs::read_dir(dir).map_err(ctx!("opening dir {dir:?}"))? {
+            let entry = entry.map_err(ctx!("reading dir {dir:?}"))?;
+            // Does not follow symlinks, thus skips the entries in
+            // `latest/`
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let file_name = entry.file_name();
+            let Some(file_name) = file_name.to_str() else {
+                continue;
+            };
+            if is_top
+                && (file_name.starts_with('.')
+                    || [
+                        LATEST_DIR_NAME,
+                        LATEST_REDIR_DIR_NAME,
+                        COMPARISONS_DIR_NAME,
+                        SWEEPS_DIR_NAME,
+                        COMMITS_DIR_NAME,
+                    ]
+                    .contains(&file_name))
+            {
+                continue;
+            }
+            let path = entry.path();
+            if GitHash::from_str(file_name).is_ok() {
+                match KeyDir::try_from(path.into_arc_path()) {
+                    Ok(key_dir) => out.push(key_dir.into()),
+                    Err(e) => info!("ignoring dir {:?}: {e:#}", entry.path()),
+                }
+            } else {
+                find(&path, false, out)?;
+            }
+        }
+        Ok(())
+    }
+    let mut key_dirs = Vec::new();
+    find(output_base_dir, true, &mut key_dirs)?;
+    Ok(key_dirs)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
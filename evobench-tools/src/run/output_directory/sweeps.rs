@@ -0,0 +1,156 @@
+//! Tables showing the results for the values of a swept custom
+//! parameter (see `sweep` in job templates) side by side.
+//!
+//! For each commit, swept parameter, and combination of values of the
+//! other parameters, there is a table at
+//! `sweeps/$target_name/$other_custom_parameters/$swept_parameter/$commit_id/avg-sweep.xlsx`
+//! (with `-host-$host_class` before the extension for results from
+//! workers, as for the summaries), with one column per value of the
+//! swept parameter for which there are results. The table is only
+//! generated once there are results for at least two values.
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Result;
+use cj_path_util::path_util::AppendToPath;
+use kstring::KString;
+
+use crate::{
+    ctx, info,
+    run::{
+        config::RunConfig,
+        env_vars::AllowableCustomEnvVar,
+        key::{ExtendPath, UncheckedCustomParameters},
+        output_directory::{
+            comparisons::evobench_log_paths, post_process::evobench_eval, structure::KeyDir,
+        },
+    },
+    serde_types::{allowed_env_var::AllowedEnvVar, proper_filename::ProperFilename},
+    utillib::arc::CloneArc,
+};
+
+pub const SWEEPS_DIR_NAME: &str = "sweeps";
+
+fn sweep_table_file_name(host_class: Option<&ProperFilename>) -> String {
+    let mut file_name = String::from("avg-sweep");
+    if let Some(host_class) = host_class {
+        file_name = format!("{file_name}-host-{}", host_class.as_str());
+    }
+    file_name.push_str(".xlsx");
+    file_name
+}
+
+impl KeyDir {
+    /// (Re-)generate the sweep tables that the results in this key
+    /// dir are part of, i.e. for the job templates in `run_config`
+    /// that were expanded from a `sweep` and have the parameters of
+    /// this key dir.
+    pub fn update_sweeps_for_key_dir(self: &Arc<Self>, run_config: &RunConfig) -> Result<()> {
+        let parameters_dir = self.parent();
+        let output_base_dir = parameters_dir.base_path();
+        let target_name = parameters_dir.target_name();
+        let commit_id = self.commit_id().to_string();
+
+        // (swept parameter, other parameters) -> values of the swept
+        // parameter; the same sweep may be configured in multiple
+        // places
+        let mut sweeps: BTreeMap<
+            (
+                AllowedEnvVar<AllowableCustomEnvVar>,
+                BTreeMap<AllowedEnvVar<AllowableCustomEnvVar>, KString>,
+            ),
+            &[KString],
+        > = BTreeMap::new();
+        for job_template in run_config.all_job_templates() {
+            let Some(sweep) = &job_template.sweep else {
+                continue;
+            };
+            if job_template.to_parameters_dir(output_base_dir.clone_arc()) != **parameters_dir {
+                continue;
+            }
+            let custom_parameters: BTreeMap<_, KString> = job_template
+                .custom_parameters
+                .btree_map()
+                .iter()
+                .map(|(key, val)| (key.clone(), KString::from_ref(val.as_str())))
+                .collect();
+            for (swept_key, values) in sweep.iter() {
+                let mut others = custom_parameters.clone();
+                others.remove(swept_key);
+                sweeps.insert((swept_key.clone(), others), values);
+            }
+        }
+
+        for ((swept_key, others), values) in sweeps {
+            // host class -> (label, paths) per value with results
+            let mut columns_by_host_class: BTreeMap<
+                Option<ProperFilename>,
+                Vec<(String, Vec<PathBuf>)>,
+            > = BTreeMap::new();
+            for value in values {
+                let mut custom_parameters = others.clone();
+                custom_parameters.insert(swept_key.clone(), value.clone());
+                let key_dir_path = UncheckedCustomParameters::from(custom_parameters)
+                    .extend_path(output_base_dir.append(target_name.as_str()))
+                    .append(&commit_id);
+                if !std::fs::exists(&key_dir_path)
+                    .map_err(ctx!("checking path {key_dir_path:?}"))?
+                {
+                    continue;
+                }
+                let key_dir: Arc<KeyDir> =
+                    KeyDir::try_from(Arc::<Path>::from(key_dir_path))?.into();
+                for (host_class, run_dirs) in key_dir.run_dirs_by_host_class()? {
+                    let paths = evobench_log_paths(&run_dirs)?;
+                    if paths.is_empty() {
+                        continue;
+                    }
+                    columns_by_host_class
+                        .entry(host_class)
+                        .or_default()
+                        .push((format!("{}={value}", swept_key.as_str()), paths));
+                }
+            }
+
+            for (host_class, columns) in columns_by_host_class {
+                if columns.len() < 2 {
+                    continue;
+                }
+                let table_dir = UncheckedCustomParameters::from(others.clone())
+                    .extend_path(
+                        output_base_dir
+                            .append(SWEEPS_DIR_NAME)
+                            .append(target_name.as_str()),
+                    )
+                    .append(swept_key.as_str())
+                    .append(&commit_id);
+                std::fs::create_dir_all(&table_dir).map_err(ctx!("creating dir {table_dir:?}"))?;
+                let table_path = table_dir.append(sweep_table_file_name(host_class.as_ref()));
+                info!("generating sweep table {table_path:?}");
+
+                let mut args: Vec<OsString> = vec![
+                    "sweep".into(),
+                    "--stats-cache".into(),
+                    "--summary-field".into(),
+                    "avg".into(),
+                    "--excel".into(),
+                    table_path.into(),
+                    "--".into(),
+                ];
+                for (label, paths) in columns {
+                    args.push(label.into());
+                    args.push("[".into());
+                    args.extend(paths.into_iter().map(OsString::from));
+                    args.push("]".into());
+                }
+                evobench_eval(&args)?;
+            }
+        }
+        Ok(())
+    }
+}
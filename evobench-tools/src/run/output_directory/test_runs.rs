@@ -0,0 +1,69 @@
+//! Key dirs and run dirs for tests
+
+use std::{path::Path, sync::Arc};
+
+use crate::{
+    evaluator::stats_cache::{StatsCacheEntry, StatsCacheFile},
+    run::{
+        benchmarking_job::BenchmarkingJob,
+        output_directory::{
+            post_process::summary_key_details,
+            structure::{KeyDir, RunDir, ToPath},
+        },
+    },
+    serde_types::date_and_time::DateTimeWithOffset,
+    utillib::arc::CloneArc,
+};
+
+/// The key dir in `output_base_dir` holding the runs of `job`
+pub fn job_key_dir(output_base_dir: &Path, job: &BenchmarkingJob) -> Arc<KeyDir> {
+    KeyDir::from_base_target_params(
+        output_base_dir.into(),
+        job.public.command.target_name.clone(),
+        &job.public.run_parameters,
+    )
+}
+
+/// The key dir for `BenchmarkingJob::for_tests(target_name,
+/// commit_id, None)`
+pub fn test_key_dir(output_base_dir: &Path, target_name: &str, commit_id: &str) -> Arc<KeyDir> {
+    job_key_dir(
+        output_base_dir,
+        &BenchmarkingJob::for_tests(target_name, commit_id, None),
+    )
+}
+
+/// Create the run dir for `timestamp` in `key_dir`, with an
+/// evobench.log with the contents `log` if given (not a valid log
+/// file, tests needing one have to write it themselves)
+pub fn add_test_run(
+    key_dir: &Arc<KeyDir>,
+    timestamp: DateTimeWithOffset,
+    log: Option<&str>,
+) -> RunDir {
+    let run_dir = key_dir.clone_arc().append_subdir(timestamp);
+    std::fs::create_dir_all(run_dir.to_path()).expect("creating run dir");
+    if let Some(log) = log {
+        std::fs::write(run_dir.evobench_log_path(), log).expect("writing evobench.log");
+    }
+    run_dir
+}
+
+/// Write a stats cache file for `run_dir` with (empty) entries for
+/// all of `summary_key_details`, i.e. one that can stand in for the
+/// evobench.log file
+pub fn write_test_stats_cache(run_dir: &RunDir) {
+    let mut cache = StatsCacheFile::empty();
+    for key_details in &summary_key_details() {
+        cache.insert(StatsCacheEntry {
+            key_details: key_details.into(),
+            real_time: Vec::new(),
+            cpu_time: Vec::new(),
+            sys_time: Vec::new(),
+            ctx_switches: Vec::new(),
+        });
+    }
+    cache
+        .save(&run_dir.evobench_stats_cache_path())
+        .expect("writing stats cache");
+}
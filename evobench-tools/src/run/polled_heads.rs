@@ -0,0 +1,455 @@
+//! Remember the last commit seen on each polled branch, so that
+//! `evobench poll` can insert jobs for all commits pushed since then,
+//! not just for the new branch head.
+//!
+//! The heads are kept as a `KeyVal` database (one entry per branch)
+//! in the global app state dir. Tags matched by `ref_patterns_for_poll`
+//! are recorded there, too, so that jobs are only inserted for tags
+//! that are new (or moved). The tags existing when a tag pattern is
+//! polled for the first time are taken as already seen (otherwise
+//! adding a pattern would insert jobs for the whole history of
+//! releases), which is tracked in a second database.
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    num::NonZeroUsize,
+    time::SystemTime,
+};
+
+use anyhow::Result;
+
+use crate::{
+    git::{GitCommit, GitHash, git_log_commits},
+    info,
+    key_val_fs::{
+        as_key::AsKey,
+        key_val::{KeyVal, KeyValConfig, KeyValSync},
+    },
+    serde_types::date_and_time::DateTimeWithOffset,
+    utillib::crypto_hash::crypto_hash,
+    warn,
+};
+
+use super::{
+    global_app_state_dir::GlobalAppStateDir,
+    polling_pool::{PolledName, PollingPool},
+    ref_pattern::RefPattern,
+    working_directory_pool::WorkingDirectoryId,
+};
+
+/// Default for `PollCommitRangeOpts.max_commits`
+const DEFAULT_MAX_COMMITS: usize = 100;
+
+/// Which commits `evobench poll` inserts jobs for when a branch has
+/// moved since the last poll (see `RemoteRepository.commit_range`)
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(rename = "PollCommitRange")]
+pub struct PollCommitRangeOpts {
+    /// Whether to also include the commits of branches merged into
+    /// the polled branch. Default: false, i.e. only the commits on
+    /// the first-parent chain are benchmarked.
+    pub all_parents: Option<bool>,
+
+    /// The maximum number of commits per branch and poll to insert
+    /// jobs for; if more commits were pushed, only the newest ones
+    /// are taken. Default: 100. Set to 1 to only benchmark the
+    /// branch head.
+    pub max_commits: Option<NonZeroUsize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PollCommitRange {
+    pub all_parents: bool,
+    pub max_commits: NonZeroUsize,
+}
+
+impl Default for PollCommitRange {
+    fn default() -> Self {
+        Self {
+            all_parents: false,
+            max_commits: DEFAULT_MAX_COMMITS.try_into().expect("non-zero"),
+        }
+    }
+}
+
+impl PollCommitRangeOpts {
+    pub fn check(&self) -> PollCommitRange {
+        let Self {
+            all_parents,
+            max_commits,
+        } = self;
+        let default = PollCommitRange::default();
+        PollCommitRange {
+            all_parents: all_parents.unwrap_or(default.all_parents),
+            max_commits: max_commits.unwrap_or(default.max_commits),
+        }
+    }
+}
+
+/// Given the output of `git log $previous..$head`, return the new
+/// commits, oldest first, so that parents always come before their
+/// children (and inserting jobs in this order keeps the commit order
+/// in the queues). Always contains `head` as the last element, even
+/// if it is not in `commits` (e.g. when the branch was reset to an
+/// older commit).
+pub fn commits_in_range(
+    commits: &[GitCommit<GitHash>],
+    head: &GitHash,
+    range: &PollCommitRange,
+) -> Vec<GitHash> {
+    let PollCommitRange {
+        all_parents,
+        max_commits,
+    } = range;
+    let parents_by_commit: BTreeMap<&GitHash, &[GitHash]> = commits
+        .iter()
+        .map(|commit| (&commit.commit_hash, commit.parents.as_slice()))
+        .collect();
+
+    let mut ordered: Vec<GitHash> = Vec::new();
+    if *all_parents {
+        // Depth-first post-order walk, first parents first
+        let mut seen = BTreeSet::new();
+        let mut stack = vec![(head, false)];
+        while let Some((commit, expanded)) = stack.pop() {
+            if expanded {
+                ordered.push(commit.clone());
+                continue;
+            }
+            let Some(parents) = parents_by_commit.get(commit) else {
+                continue;
+            };
+            if !seen.insert(commit) {
+                continue;
+            }
+            stack.push((commit, true));
+            for parent in parents.iter().rev() {
+                if !seen.contains(parent) {
+                    stack.push((parent, false));
+                }
+            }
+        }
+    } else {
+        let mut commit = head;
+        while let Some(parents) = parents_by_commit.get(commit) {
+            ordered.push(commit.clone());
+            let Some(first_parent) = parents.first() else {
+                break;
+            };
+            commit = first_parent;
+        }
+        ordered.reverse();
+    }
+
+    if ordered.is_empty() {
+        ordered.push(head.clone());
+    }
+    let num_skip = ordered.len().saturating_sub(max_commits.get());
+    ordered.split_off(num_skip)
+}
+
+/// Key for the heads database: branch names contain '/', thus hashed.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PolledNameHash(String);
+
+impl PolledNameHash {
+    fn from_name(name: &PolledName) -> Self {
+        Self(crypto_hash(&name.to_string()))
+    }
+}
+
+impl AsKey for PolledNameHash {
+    fn as_filename_str(&self) -> Cow<'_, str> {
+        (&self.0).into()
+    }
+
+    fn try_from_filename_str(file_name: &str) -> Option<Self> {
+        Some(Self(file_name.into()))
+    }
+}
+
+/// The last seen head of one polled branch or tag
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolledHead {
+    /// The unhashed name, for inspection
+    pub name: String,
+    pub commit_id: GitHash,
+    pub last_update: SystemTime,
+}
+
+/// The record of a tag pattern having been polled
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolledTagPattern {
+    /// The pattern, for inspection
+    pub pattern: String,
+    pub first_poll: SystemTime,
+}
+
+/// Access to the persistent record of the last seen branch heads and
+/// tags
+#[derive(Debug)]
+pub struct PolledHeads {
+    key_val: KeyVal<PolledNameHash, PolledHead>,
+    tag_patterns: KeyVal<PolledNameHash, PolledTagPattern>,
+}
+
+impl PolledHeads {
+    pub fn open(global_app_state_dir: &GlobalAppStateDir) -> Result<Self> {
+        let config = KeyValConfig {
+            sync: KeyValSync::All,
+            // already created anyway
+            create_dir_if_not_exists: false,
+        };
+        let key_val = KeyVal::open(
+            global_app_state_dir.polled_heads_base()?,
+            config.clone(),
+            None,
+        )?;
+        let tag_patterns = KeyVal::open(
+            global_app_state_dir.polled_tag_patterns_base()?,
+            config,
+            None,
+        )?;
+        Ok(Self {
+            key_val,
+            tag_patterns,
+        })
+    }
+
+    pub fn get(&self, name: &PolledName) -> Result<Option<PolledHead>> {
+        Ok(self.key_val.get(&PolledNameHash::from_name(name))?)
+    }
+
+    pub fn set(&self, name: &PolledName, commit_id: &GitHash) -> Result<()> {
+        self.key_val.insert(
+            &PolledNameHash::from_name(name),
+            &PolledHead {
+                name: name.to_string(),
+                commit_id: commit_id.clone(),
+                last_update: SystemTime::now(),
+            },
+            false,
+        )?;
+        Ok(())
+    }
+
+    fn tag_pattern_key(pattern: &RefPattern) -> PolledNameHash {
+        PolledNameHash(crypto_hash(&pattern.to_string()))
+    }
+
+    /// Whether `pattern` has been polled before (see
+    /// `set_tag_pattern_polled`)
+    pub fn tag_pattern_polled(&self, pattern: &RefPattern) -> Result<bool> {
+        Ok(self
+            .tag_patterns
+            .get(&Self::tag_pattern_key(pattern))?
+            .is_some())
+    }
+
+    /// Record that the tags matched by `pattern` have been recorded
+    /// via `set`, if not already done
+    pub fn set_tag_pattern_polled(&self, pattern: &RefPattern) -> Result<()> {
+        if !self.tag_pattern_polled(pattern)? {
+            self.tag_patterns.insert(
+                &Self::tag_pattern_key(pattern),
+                &PolledTagPattern {
+                    pattern: pattern.to_string(),
+                    first_poll: SystemTime::now(),
+                },
+                false,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// For a tag `name` (matched by `pattern`) pointing to `head`:
+    /// `head` if the tag is new or has moved since the last poll,
+    /// nothing if it was seen before or `pattern` is polled for the
+    /// first time.
+    pub fn new_tag_commits(
+        &self,
+        name: &PolledName,
+        head: &GitHash,
+        pattern: &RefPattern,
+    ) -> Result<Vec<GitHash>> {
+        if let Some(previous) = self.get(name)? {
+            if previous.commit_id == *head {
+                return Ok(vec![]);
+            }
+            info!("{name} has moved from {} to {head}", previous.commit_id);
+            return Ok(vec![head.clone()]);
+        }
+        if self.tag_pattern_polled(pattern)? {
+            Ok(vec![head.clone()])
+        } else {
+            info!("{name}: first poll of pattern {pattern}, taking the tag as already seen");
+            Ok(vec![])
+        }
+    }
+
+    /// The commits to insert jobs for, given the current `head` of
+    /// the ref `name` (matched by `pattern`, if any): for tags, see
+    /// `new_tag_commits`; for branches with a recorded previous head,
+    /// all new commits since then as determined by `range`; otherwise
+    /// (first poll of a branch, previous head unknown to Git) just
+    /// `head`. Oldest first.
+    pub fn new_commits(
+        &self,
+        polling_pool: &mut PollingPool,
+        working_directory_id: WorkingDirectoryId,
+        name: &PolledName,
+        pattern: Option<&RefPattern>,
+        head: &GitHash,
+        range: &PollCommitRange,
+    ) -> Result<Vec<GitHash>> {
+        if let PolledName::Tag(_) = name {
+            let Some(pattern) = pattern else {
+                // (Tags are only polled via patterns currently)
+                return Ok(vec![head.clone()]);
+            };
+            return self.new_tag_commits(name, head, pattern);
+        }
+        let Some(previous) = self.get(name)? else {
+            return Ok(vec![head.clone()]);
+        };
+        if previous.commit_id == *head {
+            return Ok(vec![head.clone()]);
+        }
+        polling_pool.process_in_working_directory(
+            working_directory_id,
+            &DateTimeWithOffset::now(None),
+            |mut working_directory| {
+                let working_directory = working_directory.get().expect("still there");
+                let git_working_dir = &working_directory.git_working_dir;
+                let previous_str = previous.commit_id.to_string();
+                if !git_working_dir.contains_reference(&previous_str)? {
+                    warn!(
+                        "previous head {previous_str} of {name} is not in the repository \
+                         anymore, only taking the current head"
+                    );
+                    return Ok(vec![head.clone()]);
+                }
+                let commits = git_log_commits(
+                    git_working_dir.working_dir_path_ref(),
+                    &format!("{previous_str}..{head}"),
+                )?;
+                let new_commits = commits_in_range(&commits, head, range);
+                info!(
+                    "{name}: {} new commit(s) since {previous_str}, taking {}",
+                    commits.len(),
+                    new_commits.len()
+                );
+                Ok(new_commits)
+            },
+            &format!("getting the new commits on {name}"),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{date_and_time::unixtime::Unixtime, utillib::test_dir::TestDir};
+
+    use super::*;
+
+    fn hash(i: u8) -> GitHash {
+        GitHash::from([i; 20])
+    }
+
+    fn commit(i: u8, parents: &[u8]) -> GitCommit<GitHash> {
+        GitCommit {
+            commit_hash: hash(i),
+            author_time: Unixtime(0),
+            committer_time: Unixtime(0),
+            parents: parents.iter().map(|p| hash(*p)).collect(),
+        }
+    }
+
+    #[test]
+    fn t_commits_in_range() {
+        // 1 is the previous head; 5 merges 4 (branched off at 1, with
+        // 3 as its parent) into 2; git log order, newest first
+        let commits = [
+            commit(6, &[5]),
+            commit(5, &[2, 4]),
+            commit(4, &[3]),
+            commit(3, &[1]),
+            commit(2, &[1]),
+        ];
+        let first_parent = PollCommitRange::default();
+        let all_parents = PollCommitRange {
+            all_parents: true,
+            ..PollCommitRange::default()
+        };
+        assert_eq!(
+            commits_in_range(&commits, &hash(6), &first_parent),
+            [hash(2), hash(5), hash(6)]
+        );
+        assert_eq!(
+            commits_in_range(&commits, &hash(6), &all_parents),
+            [hash(2), hash(3), hash(4), hash(5), hash(6)]
+        );
+        let max_2 = PollCommitRange {
+            max_commits: 2.try_into().unwrap(),
+            ..all_parents
+        };
+        assert_eq!(
+            commits_in_range(&commits, &hash(6), &max_2),
+            [hash(5), hash(6)]
+        );
+        // Head not in range (e.g. reset)
+        assert_eq!(commits_in_range(&[], &hash(1), &first_parent), [hash(1)]);
+    }
+
+    #[test]
+    fn t_new_tag_commits() -> Result<()> {
+        let test_dir = TestDir::new("polled-tags");
+        let global_app_state_dir = GlobalAppStateDir::with_root_dir(test_dir.path().into())?;
+        let polled_heads = PolledHeads::open(&global_app_state_dir)?;
+        let pattern = RefPattern::TagGlob("v*".into());
+        let tag = |name: &str| PolledName::Tag(name.into());
+        let none: [GitHash; 0] = [];
+
+        // First poll: the existing tags are taken as seen
+        assert!(!polled_heads.tag_pattern_polled(&pattern)?);
+        assert_eq!(
+            polled_heads.new_tag_commits(&tag("v1"), &hash(1), &pattern)?,
+            none
+        );
+        assert_eq!(
+            polled_heads.new_tag_commits(&tag("v2"), &hash(2), &pattern)?,
+            none
+        );
+        // (What `evobench poll` records afterwards)
+        polled_heads.set(&tag("v1"), &hash(1))?;
+        polled_heads.set(&tag("v2"), &hash(2))?;
+        polled_heads.set_tag_pattern_polled(&pattern)?;
+        assert!(polled_heads.tag_pattern_polled(&pattern)?);
+
+        // Later polls: only new or moved tags
+        assert_eq!(
+            polled_heads.new_tag_commits(&tag("v1"), &hash(1), &pattern)?,
+            none
+        );
+        assert_eq!(
+            polled_heads.new_tag_commits(&tag("v2"), &hash(4), &pattern)?,
+            [hash(4)]
+        );
+        assert_eq!(
+            polled_heads.new_tag_commits(&tag("v3"), &hash(3), &pattern)?,
+            [hash(3)]
+        );
+
+        // Another pattern is polled for the first time
+        let other_pattern = RefPattern::TagGlob("release-*".into());
+        assert_eq!(
+            polled_heads.new_tag_commits(&tag("release-1"), &hash(5), &other_pattern)?,
+            none
+        );
+        Ok(())
+    }
+}
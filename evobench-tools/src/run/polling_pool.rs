@@ -1,14 +1,16 @@
 //! Handle polling the upstream project repository for changes, and
 //! also check commit ids for insertions for validity
 
-use std::{collections::BTreeMap, num::NonZero, path::PathBuf, str::FromStr, sync::Arc};
+use std::{fmt::Display, num::NonZero, path::PathBuf, str::FromStr, sync::Arc};
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 use itertools::Itertools;
+use kstring::KString;
 use run_git::git::GitWorkingDir;
 
 use crate::{
-    git::GitHash,
+    ctx,
+    git::{GitGraph, GitHash},
     run::{
         working_directory::{
             REMOTE_NAME, WorkingDirectoryAutoCleanOpts, WorkingDirectoryWithPoolMut,
@@ -20,15 +22,84 @@ use crate::{
         git_reference::GitReference, git_url::GitUrl,
     },
     utillib::arc::CloneArc,
+    warn,
 };
 
 use super::{
-    config::JobTemplate,
+    config::{JobTemplate, RemoteRepository},
+    ref_pattern::{RefKind, RefPattern},
     working_directory_pool::{
         WorkingDirectoryId, WorkingDirectoryPool, WorkingDirectoryPoolBaseDir,
     },
 };
 
+/// A branch or tag name found by `PollingPool::resolve_branch_names`
+#[derive(Debug, Clone)]
+pub enum PolledName {
+    Branch(GitBranchName),
+    Tag(KString),
+}
+
+impl Display for PolledName {
+    /// Shows branches as just their name, tags as `tag $name`; this
+    /// is used as the `reason` for the inserted jobs.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolledName::Branch(branch_name) => f.write_str(branch_name.as_str()),
+            PolledName::Tag(tag_name) => write!(f, "tag {tag_name}"),
+        }
+    }
+}
+
+pub struct PolledRef<'b> {
+    pub name: PolledName,
+    /// The pattern from `ref_patterns_for_poll` that matched the
+    /// name, `None` if listed in `remote_branch_names_for_poll`
+    pub pattern: Option<&'b RefPattern>,
+    pub commit_id: GitHash,
+    pub job_templates: Arc<[JobTemplate]>,
+}
+
+/// All branches of the remote and all tags, with the commit ids they
+/// point to (annotated tags are peeled).
+fn list_remote_refs(git_working_dir: &GitWorkingDir) -> Result<Vec<(RefKind, String, GitHash)>> {
+    let branches_prefix = format!("refs/remotes/{REMOTE_NAME}/");
+    let tags_prefix = "refs/tags/";
+    let s = git_working_dir.git_stdout_string_trimmed(&[
+        "for-each-ref",
+        "--format=%(refname)%09%(objectname)%09%(*objectname)",
+        branches_prefix.as_str(),
+        tags_prefix,
+    ])?;
+    let mut refs = Vec::new();
+    for line in s.lines() {
+        // (The last line may have lost its trailing tab through the
+        // trimming, hence peeled is optional.)
+        let mut parts = line.split('\t');
+        let (Some(refname), Some(objectname)) = (parts.next(), parts.next()) else {
+            bail!("invalid line from git for-each-ref: {line:?}")
+        };
+        let peeled = parts.next().unwrap_or("");
+        let (kind, name) = if let Some(name) = refname.strip_prefix(&branches_prefix) {
+            if name == "HEAD" {
+                continue;
+            }
+            (RefKind::Branch, name)
+        } else if let Some(name) = refname.strip_prefix(tags_prefix) {
+            (RefKind::Tag, name)
+        } else {
+            bail!("unexpected ref name from git for-each-ref: {refname:?}")
+        };
+        let commit_id = if peeled.is_empty() {
+            objectname
+        } else {
+            peeled
+        };
+        refs.push((kind, name.to_owned(), GitHash::from_str(commit_id)?));
+    }
+    Ok(refs)
+}
+
 fn check_exists(git_working_dir: &GitWorkingDir, commit: &GitHash) -> Result<bool> {
     let commit_str = commit.to_string();
     git_working_dir.contains_reference(&commit_str)
@@ -62,6 +133,8 @@ impl PollingPool {
                 },
                 remote_repository_url,
                 base_dir,
+                // Only 1 working directory, nothing to share
+                shared_objects: false,
                 signal_change: None,
             },
             true,
@@ -125,6 +198,32 @@ impl PollingPool {
         Ok(res)
     }
 
+    /// Add the histories of `commits` to `git_graph` and save it
+    /// (only the commits not in the graph yet are retrieved). To keep
+    /// the graph up to date after fetching (it is otherwise only
+    /// updated when looking up versioned datasets).
+    pub fn update_git_graph<'c>(
+        &mut self,
+        working_directory_id: WorkingDirectoryId,
+        git_graph: &GitGraph,
+        commits: impl IntoIterator<Item = &'c GitHash>,
+    ) -> Result<()> {
+        self.process_in_working_directory(
+            working_directory_id,
+            &DateTimeWithOffset::now(None),
+            |mut working_directory| {
+                let working_directory = working_directory.get().expect("still there");
+                let path = working_directory.git_working_dir.working_dir_path_ref();
+                let mut git_graph_data = git_graph.lock();
+                for commit in commits {
+                    git_graph_data.add_history_from_dir_ref(path, &commit.to_string())?;
+                }
+                git_graph_data.save()
+            },
+            "updating the git graph",
+        )
+    }
+
     /// Currently `working_directory_id` (get it from
     /// `updated_working_dir`) always represents the same single
     /// working directory, but maybe that will change?
@@ -156,16 +255,54 @@ impl PollingPool {
         Ok(r)
     }
 
-    /// Returns the resolved commit ids for the requested names, and
-    /// any names that failed to resolve.
+    /// Fetch the head of pull request `number` to
+    /// `refs/evobench/pull/$number`, and check that `head` is
+    /// present afterwards. GitHub and Gitea keep the head of a pull
+    /// request as `refs/pull/$number/head` in the base repository,
+    /// also for pull requests from forks, whose commits are otherwise
+    /// not reachable from any branch.
+    pub fn fetch_pull_request(
+        &mut self,
+        working_directory_id: WorkingDirectoryId,
+        number: u64,
+        head: &GitHash,
+    ) -> Result<()> {
+        self.process_in_working_directory(
+            working_directory_id,
+            &DateTimeWithOffset::now(None),
+            |mut working_directory| {
+                let working_directory = working_directory.get().expect("still there");
+                let git_working_dir = &working_directory.git_working_dir;
+                let refspec = format!("+refs/pull/{number}/head:refs/evobench/pull/{number}");
+                if !git_working_dir.git(&["fetch", REMOTE_NAME, refspec.as_str()], true)? {
+                    bail!("git fetch {REMOTE_NAME} {refspec} failed")
+                }
+                if !check_exists(git_working_dir, head)? {
+                    bail!(
+                        "commit {head} is not in refs/pull/{number}/head \
+                         (has the pull request been updated again?)"
+                    )
+                }
+                Ok(())
+            },
+            &format!("fetching pull request #{number}"),
+        )
+    }
+
+    /// Returns the resolved commit ids for the branch names listed
+    /// in `remote_branch_names_for_poll` and for the remote branches
+    /// and tags matched by `ref_patterns_for_poll`, and any listed
+    /// names that failed to resolve.
     pub fn resolve_branch_names<'b>(
         &mut self,
         working_directory_id: WorkingDirectoryId,
-        branch_names: &'b BTreeMap<GitBranchName, Arc<[JobTemplate]>>,
-    ) -> Result<(
-        Vec<(&'b GitBranchName, GitHash, Arc<[JobTemplate]>)>,
-        Vec<String>,
-    )> {
+        remote_repository: &'b RemoteRepository,
+    ) -> Result<(Vec<PolledRef<'b>>, Vec<String>)> {
+        let RemoteRepository {
+            remote_branch_names_for_poll,
+            ref_patterns_for_poll,
+            ..
+        } = remote_repository;
         self.process_in_working_directory(
             working_directory_id,
             &DateTimeWithOffset::now(None),
@@ -174,17 +311,67 @@ impl PollingPool {
                 let git_working_dir = &working_directory.git_working_dir;
                 let mut non_resolving = Vec::new();
                 let mut ids = Vec::new();
-                for (name, job_templates) in branch_names {
+                for (name, job_templates) in remote_branch_names_for_poll {
                     let ref_string = name.to_ref_string_in_remote(REMOTE_NAME);
                     if let Some(id) = git_working_dir.git_rev_parse(&ref_string, true)? {
-                        ids.push((name, GitHash::from_str(&id)?, job_templates.clone_arc()))
+                        ids.push(PolledRef {
+                            name: PolledName::Branch(name.clone()),
+                            pattern: None,
+                            commit_id: GitHash::from_str(&id)?,
+                            job_templates: job_templates.clone_arc(),
+                        })
                     } else {
                         non_resolving.push(ref_string);
                     }
                 }
+                if !ref_patterns_for_poll.is_empty() {
+                    for (kind, name, commit_id) in list_remote_refs(git_working_dir)? {
+                        if kind == RefKind::Branch {
+                            if let Ok(branch_name) = GitBranchName::from_str(&name) {
+                                if remote_branch_names_for_poll.contains_key(&branch_name) {
+                                    continue;
+                                }
+                            }
+                        }
+                        let Some((matcher, job_templates)) = ref_patterns_for_poll
+                            .iter()
+                            .find(|(matcher, _)| matcher.matches(kind, &name))
+                        else {
+                            continue;
+                        };
+                        let name = match kind {
+                            RefKind::Branch => match GitBranchName::from_str(&name) {
+                                Ok(branch_name) => PolledName::Branch(branch_name),
+                                Err(e) => {
+                                    warn!(
+                                        "ignoring remote branch {name:?} matched by pattern \
+                                         {}: {e:#}",
+                                        matcher.pattern
+                                    );
+                                    continue;
+                                }
+                            },
+                            RefKind::Tag => PolledName::Tag(name.into()),
+                        };
+                        ids.push(PolledRef {
+                            name,
+                            pattern: Some(&matcher.pattern),
+                            commit_id,
+                            job_templates: job_templates.clone_arc(),
+                        })
+                    }
+                }
                 Ok((ids, non_resolving))
             },
-            &format!("resolving branch names {}", branch_names.keys().join(", ")),
+            &format!(
+                "resolving branch names {}{}",
+                remote_branch_names_for_poll.keys().join(", "),
+                if ref_patterns_for_poll.is_empty() {
+                    ""
+                } else {
+                    " and ref patterns"
+                }
+            ),
         )
     }
 
@@ -225,4 +412,43 @@ impl PollingPool {
             "resolving references",
         )
     }
+
+    /// Resolve the remote branch `branch_name` and return its head
+    /// together with its merge-base with the remote branch
+    /// `base_branch_name` (in that order).
+    pub fn merge_base(
+        &mut self,
+        working_directory_id: WorkingDirectoryId,
+        branch_name: &GitBranchName,
+        base_branch_name: &GitBranchName,
+    ) -> Result<(GitHash, GitHash)> {
+        self.process_in_working_directory(
+            working_directory_id,
+            &DateTimeWithOffset::now(None),
+            |mut working_directory| {
+                let working_directory = working_directory.get().expect("still there");
+                let git_working_dir = &working_directory.git_working_dir;
+
+                let branch_ref = branch_name.to_ref_string_in_remote(REMOTE_NAME);
+                let base_ref = base_branch_name.to_ref_string_in_remote(REMOTE_NAME);
+                let Some(head) = git_working_dir.git_rev_parse(&branch_ref, true)? else {
+                    bail!("branch {branch_name} does not exist in the remote repository")
+                };
+                if git_working_dir.git_rev_parse(&base_ref, true)?.is_none() {
+                    bail!("branch {base_branch_name} does not exist in the remote repository")
+                }
+                let merge_base = git_working_dir.git_stdout_string_trimmed(&[
+                    "merge-base",
+                    branch_ref.as_str(),
+                    base_ref.as_str(),
+                ])?;
+                Ok((
+                    GitHash::from_str(&head).expect("git always returns git hashes"),
+                    GitHash::from_str(&merge_base)
+                        .map_err(ctx!("parsing output of git merge-base"))?,
+                ))
+            },
+            "getting merge-base",
+        )
+    }
 }
@@ -0,0 +1,127 @@
+//! Patterns over remote branch and tag names, used by `evobench poll`
+//! (see `RemoteRepository.ref_patterns_for_poll`).
+
+use std::fmt::Display;
+
+use anyhow::{Result, anyhow};
+use kstring::KString;
+use regex::Regex;
+
+use crate::serde_types::regex::SerializableRegex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RefKind {
+    Branch,
+    Tag,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum RefPattern {
+    /// A glob over the remote branch names, where `*` matches any
+    /// sequence of characters (including `/`) and `?` any single
+    /// character, e.g. `"bench/*"`.
+    BranchGlob(KString),
+    /// A regular expression over the remote branch names; it must
+    /// match the whole name, e.g. `"release-.*"`.
+    BranchRegex(SerializableRegex),
+    /// Like `BranchGlob`, but over tag names, e.g. `"v*"`.
+    TagGlob(KString),
+    /// Like `BranchRegex`, but over tag names.
+    TagRegex(SerializableRegex),
+}
+
+impl Display for RefPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RefPattern::BranchGlob(s) => write!(f, "BranchGlob({:?})", s.as_str()),
+            RefPattern::BranchRegex(r) => write!(f, "BranchRegex({r})"),
+            RefPattern::TagGlob(s) => write!(f, "TagGlob({:?})", s.as_str()),
+            RefPattern::TagRegex(r) => write!(f, "TagRegex({r})"),
+        }
+    }
+}
+
+/// Translate a glob as described for `RefPattern::BranchGlob` to a
+/// regular expression matching the whole string.
+fn glob_to_regex_string(glob: &str) -> String {
+    let mut s = String::from("^(?:");
+    for c in glob.chars() {
+        match c {
+            '*' => s.push_str(".*"),
+            '?' => s.push('.'),
+            _ => s.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    s.push_str(")$");
+    s
+}
+
+impl RefPattern {
+    pub fn kind(&self) -> RefKind {
+        match self {
+            RefPattern::BranchGlob(_) | RefPattern::BranchRegex(_) => RefKind::Branch,
+            RefPattern::TagGlob(_) | RefPattern::TagRegex(_) => RefKind::Tag,
+        }
+    }
+
+    pub fn to_matcher(&self) -> Result<RefMatcher> {
+        let regex_string = match self {
+            RefPattern::BranchGlob(glob) | RefPattern::TagGlob(glob) => glob_to_regex_string(glob),
+            RefPattern::BranchRegex(r) | RefPattern::TagRegex(r) => {
+                let r: &str = r.as_ref();
+                format!("^(?:{r})$")
+            }
+        };
+        let regex = Regex::new(&regex_string)
+            .map_err(|e| anyhow!("converting pattern {self} to regex: {e:#}"))?;
+        Ok(RefMatcher {
+            pattern: self.clone(),
+            regex,
+        })
+    }
+}
+
+/// A checked `RefPattern`
+#[derive(Debug, Clone)]
+pub struct RefMatcher {
+    pub pattern: RefPattern,
+    regex: Regex,
+}
+
+impl RefMatcher {
+    /// Whether the name of a ref of kind `kind` is matched
+    pub fn matches(&self, kind: RefKind, name: &str) -> bool {
+        self.pattern.kind() == kind && self.regex.is_match(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn t_matches() -> Result<()> {
+        let glob = RefPattern::BranchGlob("bench/*".into()).to_matcher()?;
+        assert!(glob.matches(RefKind::Branch, "bench/foo"));
+        assert!(glob.matches(RefKind::Branch, "bench/foo/bar"));
+        assert!(!glob.matches(RefKind::Branch, "xbench/foo"));
+        assert!(!glob.matches(RefKind::Tag, "bench/foo"));
+
+        let glob = RefPattern::TagGlob("v1.?".into()).to_matcher()?;
+        assert!(glob.matches(RefKind::Tag, "v1.2"));
+        // The dot is literal
+        assert!(!glob.matches(RefKind::Tag, "v1x2"));
+        assert!(!glob.matches(RefKind::Tag, "v1.23"));
+
+        let regex = RefPattern::BranchRegex(SerializableRegex::from_str("release-.*|main")?)
+            .to_matcher()?;
+        assert!(regex.matches(RefKind::Branch, "release-1.0"));
+        assert!(regex.matches(RefKind::Branch, "main"));
+        // Must match the whole name
+        assert!(!regex.matches(RefKind::Branch, "old-release-1.0"));
+        assert!(!regex.matches(RefKind::Branch, "mainly"));
+        Ok(())
+    }
+}
@@ -19,7 +19,9 @@ use regex::Regex;
 use crate::{
     config_file::ron_to_string_pretty,
     ctx,
+    date_and_time::unixtime::Unixtime,
     git::GitHash,
+    git_ext::MoreGitWorkingDir,
     git_tags::GitTags,
     html_files::write_redirect_html_file,
     info,
@@ -33,7 +35,9 @@ use crate::{
         key::{BenchmarkingJobParameters, RunParameters},
         output_directory::{
             post_process::compress_file_as,
-            structure::{KeyDir, ReplaceBasePath, RunDir, ToPath},
+            structure::{
+                KeyDir, LATEST_DIR_NAME, LATEST_REDIR_DIR_NAME, ReplaceBasePath, RunDir, ToPath,
+            },
         },
         run_queues::RunQueuesData,
         versioned_dataset_dir::VersionedDatasetDir,
@@ -47,6 +51,7 @@ use crate::{
         into_arc_path::IntoArcPath,
         logging::{LogLevel, log_level},
     },
+    warn,
 };
 
 use super::{
@@ -198,7 +203,7 @@ impl<'pool, 'run_queues, 'j, 's> JobRunnerWithJob<'pool, 'run_queues, 'j, 's> {
             .process_in_working_directory(
                 working_directory_id,
                 &self.job_runner.timestamp,
-                |mut working_directory| -> Result<(&ProperDirname, PathBuf)> {
+                |mut working_directory| -> Result<(&ProperDirname, PathBuf, Option<Unixtime>)> {
                     // Have `checkout` always run git fetch to update
                     // the remote tags, to get them even if there have
                     // been past runs where they were not present yet;
@@ -211,14 +216,39 @@ impl<'pool, 'run_queues, 'j, 's> JobRunnerWithJob<'pool, 'run_queues, 'j, 's> {
                     // leading to the working directory ending in
                     // error state and on repetition the job being
                     // aborted).
-                    let fetched_tags = working_directory
-                        .get()
-                        .expect("not removed")
-                        .checkout(commit_id.clone(), FetchTags::Always)?;
+                    let fetched_tags = working_directory.get().expect("not removed").checkout(
+                        commit_id.clone(),
+                        FetchTags::Always,
+                        &conf.remote_repository.checkout,
+                    )?;
 
                     // Drop the lock on the pool
                     let working_directory = working_directory.into_inner().expect("not removed");
 
+                    // For the results index
+                    let commit_time = match working_directory
+                        .git_working_dir
+                        .commit_times(std::slice::from_ref(commit_id))
+                    {
+                        Ok(times) => times.get(commit_id).copied(),
+                        Err(e) => {
+                            warn!("could not get the commit time of {commit_id}: {e:#}");
+                            None
+                        }
+                    };
+                    // For the commit overview; `None` if not known, `Some(None)`
+                    // for root commits
+                    let first_parent = match working_directory
+                        .git_working_dir
+                        .first_parents(std::slice::from_ref(commit_id))
+                    {
+                        Ok(mut parents) => parents.remove(commit_id),
+                        Err(e) => {
+                            warn!("could not get the first parent of {commit_id}: {e:#}");
+                            None
+                        }
+                    };
+
                     let dataset_dir = dataset_dir_for(
                         conf.versioned_datasets_base_dir.as_deref(),
                         &custom_parameters,
@@ -324,7 +354,12 @@ impl<'pool, 'run_queues, 'j, 's> JobRunnerWithJob<'pool, 'run_queues, 'j, 's> {
                     if status.success() {
                         info!("running {cmd_in_dir} succeeded");
 
-                        Ok((target_name, command_output_file.into_path()))
+                        Ok((
+                            target_name,
+                            command_output_file.into_path(),
+                            commit_time,
+                            first_parent,
+                        ))
                     } else {
                         info!("running {cmd_in_dir} failed.");
 
@@ -379,7 +414,7 @@ impl<'pool, 'run_queues, 'j, 's> JobRunnerWithJob<'pool, 'run_queues, 'j, 's> {
             // in case part of the actions creating the run output
             // directory fails. -- Also see "latest-redir" below.
             {
-                let latest_dir = self.job_runner.output_base_dir.join("latest");
+                let latest_dir = self.job_runner.output_base_dir.join(LATEST_DIR_NAME);
                 create_dir_all(&latest_dir).map_err(ctx!("create_dir_all {latest_dir:?}"))?;
 
                 // To get a relative path, simply use ".." as the base
@@ -412,7 +447,7 @@ impl<'pool, 'run_queues, 'j, 's> JobRunnerWithJob<'pool, 'run_queues, 'j, 's> {
             // parent directory--hence redirect into the run dir, not
             // key dir.  -- Also see "latest" above.
             if let Some(output_dir_url) = &self.job_runner.run_config().output_dir.url {
-                let latest_dir = self.job_runner.output_base_dir.join("latest-redir");
+                let latest_dir = self.job_runner.output_base_dir.join(LATEST_REDIR_DIR_NAME);
                 let subdir: PathBuf = latest_dir.join(self.job_runner.timestamp.as_str());
                 {
                     let path = &subdir;
@@ -442,7 +477,8 @@ impl<'pool, 'run_queues, 'j, 's> JobRunnerWithJob<'pool, 'run_queues, 'j, 's> {
                 let evobench_log_tmp =
                     compress_file_as(&evobench_log, run_dir.evobench_log_path(), true)?;
 
-                let (target_name, standard_log_tempfile) = log_extraction;
+                let (target_name, standard_log_tempfile, commit_time, first_parent) =
+                    log_extraction;
                 compress_file_as(&standard_log_tempfile, run_dir.standard_log_path(), false)?;
                 // It's OK to delete the original now, but we'll make use of
                 // it for reading back.
@@ -485,7 +521,12 @@ impl<'pool, 'run_queues, 'j, 's> JobRunnerWithJob<'pool, 'run_queues, 'j, 's> {
                     &self.job_runner.run_config(),
                     // Do not omit generation of evobench.log stats
                     false,
+                    commit_time,
                 )?;
+
+                if let Some(first_parent) = first_parent {
+                    key_dir.update_first_parent_in_results_index(first_parent);
+                }
             }
 
             // Now that the results for the run are in the right place, we
@@ -493,6 +534,7 @@ impl<'pool, 'run_queues, 'j, 's> JobRunnerWithJob<'pool, 'run_queues, 'j, 's> {
             key_dir.generate_summaries_for_key_dir(
                 // Do not omit generation of evobench.log stats
                 false,
+                self.job_runner.run_config(),
             )?;
         }
 
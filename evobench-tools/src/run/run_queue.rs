@@ -7,15 +7,19 @@ use crate::{
         key_val::{KeyVal, KeyValError},
         queue::{Queue, QueueGetItemOptions, QueueItem, QueueIterationOptions, TimeKey},
     },
-    run::{benchmarking_job::BenchmarkingJobState, run_job::JobRunnerWithJob},
+    run::{
+        benchmarking_job::BenchmarkingJobState, job_counters::JobCounters,
+        run_job::JobRunnerWithJob,
+    },
     serde_types::{priority::Priority, proper_filename::ProperFilename},
     utillib::logging::{LogLevel, log_level},
+    warn,
 };
 
 use super::{
     benchmarking_job::{BenchmarkingJob, BenchmarkingJobPublic},
     config::ScheduleCondition,
-    working_directory_pool::WorkingDirectoryId,
+    working_directory_pool::{ProcessingPhase, WorkingDirectoryId},
 };
 
 #[derive(Debug, PartialEq)]
@@ -125,7 +129,7 @@ pub struct RunQueueDataWithNext<'conf, 'run_queue, 'r> {
 /// generally?) (This is related to, but currently independent of, the
 /// `run::working_directory::Status` type which is currently used to
 /// determine "R" status column in `evobench list`.)
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy)]
 #[must_use]
 pub enum JobStatus {
     /// It will still be run
@@ -162,34 +166,70 @@ impl JobStatus {
 impl<'conf, 'r> RunQueueWithNext<'conf, 'r> {
     /// Run the given job, which must be from this queue. `item`
     /// represents the queue entry of this job, and is used for
-    /// locking and deletion--it must not already be locked!
+    /// locking and deletion--it is locked here unless it was
+    /// retrieved with a lock (`no_lock: false`).
     ///
-    /// Returns the status of the job after running it.
+    /// Returns the status of the job after running it. The run is
+    /// counted in `job_counters`, if given.
     pub fn run_job(
         &self,
         item: &QueueItem<BenchmarkingJob>,
         job_runner_with_job: &mut JobRunnerWithJob,
         erroneous_jobs_queue: Option<&RunQueue>,
         done_jobs_queue: Option<&RunQueue>,
+        job_counters: Option<&JobCounters>,
         working_directory_id: WorkingDirectoryId,
     ) -> Result<JobStatus> {
-        let _lock = item.lock_exclusive()?;
+        let job = job_runner_with_job.job_data.job;
+        self.run_job_with(
+            item,
+            job,
+            erroneous_jobs_queue,
+            done_jobs_queue,
+            job_counters,
+            Some(working_directory_id),
+            |reason, schedule_condition| {
+                job_runner_with_job.run_job(working_directory_id, reason, schedule_condition)
+            },
+        )
+    }
+
+    /// Like `run_job`, but the run is carried out by `run` (given the
+    /// job's reason and the schedule condition of the queue), which
+    /// allows the run to happen elsewhere (e.g. on a worker
+    /// machine). `working_directory_id` is stored in the job state as
+    /// the last working directory, if given, otherwise the previous
+    /// value is kept.
+    pub fn run_job_with(
+        &self,
+        item: &QueueItem<BenchmarkingJob>,
+        job: &BenchmarkingJob,
+        erroneous_jobs_queue: Option<&RunQueue>,
+        done_jobs_queue: Option<&RunQueue>,
+        job_counters: Option<&JobCounters>,
+        working_directory_id: Option<WorkingDirectoryId>,
+        run: impl FnOnce(&Option<String>, &ScheduleCondition) -> Result<()>,
+    ) -> Result<JobStatus> {
+        // Items retrieved with a lock are already locked
+        let _lock = if item.is_locked() {
+            None
+        } else {
+            Some(item.lock_exclusive()?)
+        };
 
         let BenchmarkingJobState {
             remaining_count,
             mut remaining_error_budget,
-            last_working_directory: _,
-        } = job_runner_with_job.job_data.job.state.clone();
+            last_working_directory,
+        } = job.state.clone();
+        let last_working_directory = working_directory_id.or(last_working_directory);
 
         let finish_completed_job = |remaining_count| -> Result<JobStatus> {
-            let job = job_runner_with_job
-                .job_data
-                .job
-                .clone_for_queue_reinsertion(BenchmarkingJobState {
-                    remaining_count,
-                    remaining_error_budget,
-                    last_working_directory: Some(working_directory_id),
-                });
+            let job = job.clone_for_queue_reinsertion(BenchmarkingJobState {
+                remaining_count,
+                remaining_error_budget,
+                last_working_directory,
+            });
             info!(
                 "job completed: {}",
                 ron_to_string_pretty(&job).expect("no err")
@@ -204,14 +244,11 @@ impl<'conf, 'r> RunQueueWithNext<'conf, 'r> {
         };
 
         let handle_out_of_error_budget = || -> Result<JobStatus> {
-            let job = job_runner_with_job
-                .job_data
-                .job
-                .clone_for_queue_reinsertion(BenchmarkingJobState {
-                    remaining_count,
-                    remaining_error_budget: 0,
-                    last_working_directory: Some(working_directory_id),
-                });
+            let job = job.clone_for_queue_reinsertion(BenchmarkingJobState {
+                remaining_count,
+                remaining_error_budget: 0,
+                last_working_directory,
+            });
 
             let retained = if let Some(queue) = &erroneous_jobs_queue {
                 queue.push_front(&job)?;
@@ -232,38 +269,39 @@ impl<'conf, 'r> RunQueueWithNext<'conf, 'r> {
             // Getting these via job.benchmarking_job_parameters() instead
             run_parameters: _,
             command: _,
-        } = job_runner_with_job.job_data.job.public.clone();
+        } = job.public.clone();
 
         let job_status;
 
         if remaining_error_budget > 0 {
             if remaining_count > 0 {
-                if let Err(error) = job_runner_with_job.run_job(
-                    working_directory_id,
-                    &reason,
-                    &self.current.schedule_condition,
-                ) {
+                let result = run(&reason, &self.current.schedule_condition);
+                if let Some(job_counters) = job_counters {
+                    // Counting is not worth failing the job over
+                    if let Err(e) = job_counters.record_run(result.is_ok()) {
+                        warn!("ignoring error counting the job run: {e:#}");
+                    }
+                }
+                if let Err(error) = result {
                     remaining_error_budget = remaining_error_budget - 1;
 
                     // XX this should use more important error
                     // logging than info!; (XX also, repetitive
                     // BenchmarkingJob recreation and cloning.)
                     info!(
-                        "job gave error: {}: {error:#?}",
+                        "job gave error in {:?} phase: {}: {error:#?}",
+                        ProcessingPhase::of_error(&error),
                         // XX: give job_runner_ext as the context? And
                         // anyway, todo layered error zones.
-                        ron_to_string_pretty(&job_runner_with_job.job_data.job).expect("no err")
+                        ron_to_string_pretty(job).expect("no err")
                     );
                     if remaining_error_budget > 0 {
                         // Re-schedule
-                        let job = job_runner_with_job
-                            .job_data
-                            .job
-                            .clone_for_queue_reinsertion(BenchmarkingJobState {
-                                remaining_count,
-                                remaining_error_budget,
-                                last_working_directory: Some(working_directory_id),
-                            });
+                        let job = job.clone_for_queue_reinsertion(BenchmarkingJobState {
+                            remaining_count,
+                            remaining_error_budget,
+                            last_working_directory,
+                        });
                         self.current.push_front(&job)?;
                         job_status = JobStatus::Active;
                     } else {
@@ -306,14 +344,11 @@ impl<'conf, 'r> RunQueueWithNext<'conf, 'r> {
                             }
                         }
 
-                        let job = job_runner_with_job
-                            .job_data
-                            .job
-                            .clone_for_queue_reinsertion(BenchmarkingJobState {
-                                remaining_count,
-                                remaining_error_budget,
-                                last_working_directory: Some(working_directory_id),
-                            });
+                        let job = job.clone_for_queue_reinsertion(BenchmarkingJobState {
+                            remaining_count,
+                            remaining_error_budget,
+                            last_working_directory,
+                        });
                         if let Some(queue) = maybe_queue {
                             queue.push_front(&job)?;
                             job_status = JobStatus::Active;
@@ -18,7 +18,7 @@ use crate::{
     git::GitHash,
     info,
     key_val_fs::{
-        key_val::{KeyValConfig, KeyValSync},
+        key_val::{KeyValConfig, KeyValError, KeyValSync},
         queue::{Queue, QueueGetItemOptions, QueueItem, TimeKey},
     },
     run::{
@@ -32,7 +32,10 @@ use crate::{
 use super::{
     benchmarking_job::BenchmarkingJob,
     config::{QueuesConfig, ScheduleCondition},
+    distributed::Leases,
+    fair_share::{FairShareSnapshot, FairShareUsage},
     global_app_state_dir::GlobalAppStateDir,
+    job_counters::JobCounters,
     run_context::RunContext,
     run_job::JobRunner,
     run_queue::{RunQueue, RunQueueData, RunQueueDataWithNext, RunQueueWithNext},
@@ -59,6 +62,21 @@ pub struct RunQueues {
     #[borrows(config)]
     #[covariant]
     done_jobs_queue: Option<RunQueue<'this>>,
+
+    #[borrows(config)]
+    #[covariant]
+    superseded_jobs_queue: Option<RunQueue<'this>>,
+
+    /// Only opened if `config.fair_share` is given (and, when not
+    /// creating dirs, if usage has been recorded before)
+    fair_share_usage: Option<FairShareUsage>,
+
+    /// Only opened if `config.coordinator` is given
+    leases: Option<Leases>,
+
+    /// `None` when not creating dirs if no run has been recorded
+    /// before
+    job_counters: Option<JobCounters>,
 }
 
 /// A loaded copy of the on-disk data, for on-the-fly
@@ -68,6 +86,13 @@ pub struct RunQueuesData<'run_queues> {
     pipeline_data: Vec<RunQueueData<'run_queues, 'run_queues>>,
     /// Value is (index in pipeline_data, index within its queue_data)
     jobs_by_commit_id: BTreeMap<GitHash, Vec<(usize, usize)>>,
+    /// The fair-share usage at the time of loading, if
+    /// `config.fair_share` is given
+    fair_share_snapshot: Option<FairShareSnapshot>,
+    /// The keys of the entries leased to workers at the time of
+    /// loading, by queue name (empty unless `config.coordinator` is
+    /// given)
+    leased_entries: BTreeMap<ProperFilename, BTreeSet<TimeKey>>,
 }
 
 impl RunQueues {
@@ -83,6 +108,9 @@ impl RunQueues {
             if let Some(queue) = self.borrow_done_jobs_queue().as_ref() {
                 co.yield_(queue).await;
             }
+            if let Some(queue) = self.borrow_superseded_jobs_queue().as_ref() {
+                co.yield_(queue).await;
+            }
         })
         .into_iter()
     }
@@ -99,10 +127,26 @@ impl RunQueues {
         self.borrow_done_jobs_queue().as_ref()
     }
 
+    pub fn superseded_jobs_queue(&self) -> Option<&RunQueue<'_>> {
+        self.borrow_superseded_jobs_queue().as_ref()
+    }
+
     pub fn first(&self) -> &RunQueue<'_> {
         &self.pipeline()[0]
     }
 
+    pub fn fair_share_usage(&self) -> Option<&FairShareUsage> {
+        self.borrow_fair_share_usage().as_ref()
+    }
+
+    pub fn leases(&self) -> Option<&Leases> {
+        self.borrow_leases().as_ref()
+    }
+
+    pub fn job_counters(&self) -> Option<&JobCounters> {
+        self.borrow_job_counters().as_ref()
+    }
+
     pub fn data<'run_queues>(&'run_queues self) -> Result<RunQueuesData<'run_queues>> {
         let pipeline_data: Vec<RunQueueData> = self
             .pipeline()
@@ -123,10 +167,26 @@ impl RunQueues {
                 }
             }
         }
+        let fair_share_snapshot = if let (Some(opts), Some(usage)) =
+            (&self.borrow_config().fair_share, self.fair_share_usage())
+        {
+            Some(usage.snapshot(opts, SystemTime::now())?)
+        } else {
+            None
+        };
+        let leased_entries = if let (Some(opts), Some(leases)) =
+            (&self.borrow_config().coordinator, self.leases())
+        {
+            leases.leased_entries(opts, SystemTime::now())?
+        } else {
+            BTreeMap::new()
+        };
         Ok(RunQueuesData {
             run_queues: self,
             pipeline_data,
             jobs_by_commit_id,
+            fair_share_snapshot,
+            leased_entries,
         })
     }
 
@@ -150,6 +210,7 @@ impl RunQueues {
         let pipeline = self.pipeline();
         let erroneous_jobs_queue = self.erroneous_jobs_queue();
         let done_jobs_queue = self.done_jobs_queue();
+        let superseded_jobs_queue = self.superseded_jobs_queue();
         if pipeline.is_empty() {
             bail!(
                 "no queues defined -- need at least one, also \
@@ -219,6 +280,15 @@ impl RunQueues {
         };
         check_extra_queue("erroneous_jobs_queue", erroneous_jobs_queue)?;
         check_extra_queue("done_jobs_queue", done_jobs_queue)?;
+        check_extra_queue("superseded_jobs_queue", superseded_jobs_queue)?;
+
+        if let Some(fair_share) = &self.borrow_config().fair_share {
+            fair_share.check()?;
+        }
+
+        if let Some(coordinator) = &self.borrow_config().coordinator {
+            coordinator.check()?;
+        }
 
         Ok(())
     }
@@ -232,6 +302,20 @@ impl RunQueues {
         let run_queues_basedir =
             config.run_queues_basedir(create_dirs_if_not_exist, global_app_state_dir)?;
 
+        let fair_share_usage = if config.fair_share.is_some() {
+            FairShareUsage::open(&run_queues_basedir, create_dirs_if_not_exist)?
+        } else {
+            None
+        };
+
+        let leases = if config.coordinator.is_some() {
+            Some(Leases::open(&run_queues_basedir, true)?)
+        } else {
+            None
+        };
+
+        let job_counters = JobCounters::open(&run_queues_basedir, create_dirs_if_not_exist)?;
+
         fn make_run_queue<'this>(
             (filename, schedule_condition): &'this (ProperFilename, ScheduleCondition),
             run_queues_basedir: &PathBuf,
@@ -297,6 +381,22 @@ impl RunQueues {
                     Ok(None)
                 }
             },
+            // superseded_jobs_queue:
+            |config| {
+                if let Some(cfg) = config.superseded_jobs_queue.as_ref() {
+                    Ok(Some(make_run_queue(
+                        cfg,
+                        &run_queues_basedir,
+                        create_dirs_if_not_exist,
+                        signal_change.clone(),
+                    )?))
+                } else {
+                    Ok(None)
+                }
+            },
+            fair_share_usage,
+            leases,
+            job_counters,
         )?;
 
         slf.check_run_queues()?;
@@ -324,6 +424,80 @@ impl<'run_queues> RunQueuesData<'run_queues> {
         !self.jobs_by_commit_id(commit_id).is_empty()
     }
 
+    /// The priority used for scheduling: `priority` (the total
+    /// priority from the queue entry) as is, or adjusted via the
+    /// fair-share policy if configured.
+    pub fn scheduling_priority(&self, job: &BenchmarkingJob, priority: Priority) -> Priority {
+        if let (Some(opts), Some(snapshot)) = (
+            &self.run_queues.borrow_config().fair_share,
+            &self.fair_share_snapshot,
+        ) {
+            snapshot.adjusted_priority(opts, job, priority)
+        } else {
+            priority
+        }
+    }
+
+    /// Whether the entry with `key` in the queue named `queue_name`
+    /// is leased to a worker
+    pub fn is_leased(&self, queue_name: &ProperFilename, key: &TimeKey) -> bool {
+        self.leased_entries
+            .get(queue_name)
+            .map(|keys| keys.contains(key))
+            .unwrap_or(false)
+    }
+
+    /// Take the lock on `item` (retrieved without lock from
+    /// `run_queue`) for running it locally. If this installation is a
+    /// coordinator, the leases in `self` may predate leases granted
+    /// since, thus re-check them while holding the leases lock;
+    /// `lease_job` does not lease entries that are locked. Returns
+    /// `None` if the entry has been leased or has disappeared in the
+    /// mean time.
+    fn lock_unless_leased(
+        &self,
+        run_queue: &'run_queues RunQueue<'run_queues>,
+        item: QueueItem<'run_queues, BenchmarkingJob>,
+    ) -> Result<Option<QueueItem<'run_queues, BenchmarkingJob>>> {
+        let (Some(opts), Some(leases)) = (
+            &self.run_queues.borrow_config().coordinator,
+            self.run_queues.leases(),
+        ) else {
+            return Ok(Some(item));
+        };
+        let key = item.key()?;
+        let _leases_lock = leases.lock_exclusive()?;
+        let leased = leases
+            .leased_entries(opts, SystemTime::now())?
+            .get(&run_queue.file_name)
+            .map(|keys| keys.contains(&key))
+            .unwrap_or(false);
+        if leased {
+            info!("entry {key} has been leased in the mean time, skipping it");
+            return Ok(None);
+        }
+        match run_queue.queue.get_item(
+            &key,
+            QueueGetItemOptions {
+                verbose: log_level() >= LogLevel::Info,
+                no_lock: false,
+                error_when_locked: true,
+                delete_first: false,
+            },
+        ) {
+            Ok(Some(item)) if item.is_locked() => Ok(Some(item)),
+            Ok(_) => {
+                info!("entry {key} has disappeared in the mean time, skipping it");
+                Ok(None)
+            }
+            Err(KeyValError::LockTaken { .. }) => {
+                info!("entry {key} is locked by another process, skipping it");
+                Ok(None)
+            }
+            Err(e) => Err(e)?,
+        }
+    }
+
     /// Iterator over all entries for that commit id. Still efficient,
     /// since it just returns references to existing tuples.--Not
     /// actually used, might it be useful in the future?
@@ -401,18 +575,26 @@ impl<'run_queues> RunQueuesData<'run_queues> {
         // Get the single most prioritized job from each queue (if
         // any), then of those the most prioritized one. Using
         // min_by_key since this takes the first of the equal jobs,
-        // unlike max_by_key.
+        // unlike max_by_key. The priorities compared are those after
+        // the fair-share adjustment, if configured. Entries leased to
+        // workers are skipped.
         if let Some(((key, job, prio), rq, dtr)) = self
             .active_queues(now)
             .filter_map(|(rq, dtr)| -> Option<_> {
+                let queue_name = &rq.current.run_queue().file_name;
                 let entry = rq
                     .current
                     .entries()
-                    .min_by_key(|(_, _, job_priority)| job_priority.neg())?;
+                    .filter(|(key, _, _)| !self.is_leased(queue_name, key))
+                    .min_by_key(|(_, job, job_priority)| {
+                        self.scheduling_priority(job, *job_priority).neg()
+                    })?;
 
                 Some((entry, rq, dtr))
             })
-            .min_by_key(|((_, _, job_priority), _, _)| job_priority.neg())
+            .min_by_key(|((_, job, job_priority), _, _)| {
+                self.scheduling_priority(job, *job_priority).neg()
+            })
         {
             if let Some(item) = rq.current.run_queue().queue.get_item(
                 key,
@@ -433,9 +615,51 @@ impl<'run_queues> RunQueuesData<'run_queues> {
         }
     }
 
+    /// The job to lease to a worker next: the most prioritized one
+    /// across all runnable queues, as (queue, key, job), unless it is
+    /// being run locally right now. The caller must hold the lock on
+    /// the leases, and have loaded `self` after taking it.
+    pub fn next_job_for_lease<'s>(
+        &'s self,
+        now: DateTime<Local>,
+    ) -> Result<
+        Option<(
+            &'run_queues RunQueue<'run_queues>,
+            TimeKey,
+            &'s BenchmarkingJob,
+        )>,
+    > {
+        let Some((rqdwn, _dtr, item, job, _)) = self.most_prioritized_job(now)? else {
+            return Ok(None);
+        };
+        let run_queue = rqdwn.current.run_queue();
+        let key = item.key()?;
+        match run_queue.queue.get_item(
+            &key,
+            QueueGetItemOptions {
+                verbose: log_level() >= LogLevel::Info,
+                no_lock: false,
+                error_when_locked: true,
+                delete_first: false,
+            },
+        ) {
+            Ok(Some(_item)) => Ok(Some((run_queue, key, job))),
+            Ok(None) => {
+                info!("entry {key} has disappeared in the mean time, skipping it");
+                Ok(None)
+            }
+            Err(KeyValError::LockTaken { .. }) => {
+                info!("entry {key} is being run locally, not leasing it");
+                Ok(None)
+            }
+            Err(e) => Err(e)?,
+        }
+    }
+
     /// Run the first or most prioritized job in the queues. Returns
     /// the job and its status after running it if one was found,
-    /// None if all runnable queues are empty.
+    /// None if all runnable queues are empty (or the entry found was
+    /// leased to a worker in the mean time).
     ///
     /// This method needs to be run in a loop forever for daemon style
     /// processing. The reason this doesn't do the looping inside is
@@ -461,6 +685,10 @@ impl<'run_queues> RunQueuesData<'run_queues> {
 
         if let Some((rqdwn, dtr, item, job, _)) = job {
             let rq = rqdwn.current.run_queue();
+            let Some(item) = self.lock_unless_leased(rq, item)? else {
+                // Let the caller reload the queues
+                return Ok(None);
+            };
             run_context.stop_start_be(rq.schedule_condition.stop_start())?;
             if let Some(dtr) = dtr {
                 run_context.running_job_in_windowed_queue(rq, dtr);
@@ -477,6 +705,7 @@ impl<'run_queues> RunQueuesData<'run_queues> {
                 lock.clear_current_working_directory()?;
             }
 
+            let start_time = SystemTime::now();
             let job_status = rqdwn.run_queue_with_next().run_job(
                 &item,
                 &mut JobRunnerWithJob {
@@ -488,9 +717,20 @@ impl<'run_queues> RunQueuesData<'run_queues> {
                 },
                 self.run_queues.erroneous_jobs_queue(),
                 self.run_queues.done_jobs_queue(),
+                self.run_queues.job_counters(),
                 working_directory_id,
             )?;
 
+            if let (Some(opts), Some(usage)) = (
+                &self.run_queues.borrow_config().fair_share,
+                self.run_queues.fair_share_usage(),
+            ) {
+                // Failed runs consume machine time, too, thus record
+                // regardless of the outcome.
+                let run_time = start_time.elapsed().unwrap_or_default();
+                usage.record(opts, job, run_time)?;
+            }
+
             Ok(Some((job, job_status)))
         } else {
             run_context.stop_start_be(None)?;
@@ -499,3 +739,125 @@ impl<'run_queues> RunQueuesData<'run_queues> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        run::distributed::{CoordinatorOpts, lease_job},
+        utillib::test_dir::TestDir,
+    };
+
+    use super::*;
+
+    fn name(s: &str) -> ProperFilename {
+        s.parse().expect("valid name")
+    }
+
+    /// A coordinator with one `Immediately` queue and the workers
+    /// `w1` and `w2`
+    fn coordinator_queues(test_dir: &TestDir) -> Result<RunQueues> {
+        let config = QueuesConfig {
+            run_queues_basedir: Some(
+                test_dir
+                    .path()
+                    .join("queues")
+                    .to_str()
+                    .expect("UTF-8 path")
+                    .parse()?,
+            ),
+            pipeline: vec![(
+                name("immediate"),
+                ScheduleCondition::Immediately {
+                    situation: name("immediate"),
+                },
+            )],
+            erroneous_jobs_queue: None,
+            done_jobs_queue: None,
+            superseded_jobs_queue: None,
+            view_jobs_max_len: 10,
+            fair_share: None,
+            coordinator: Some(CoordinatorOpts {
+                workers: [(name("w1"), name("c")), (name("w2"), name("c"))].into(),
+                lease_timeout_hours: 1.,
+            }),
+        };
+        RunQueues::open(
+            Arc::new(config),
+            true,
+            &GlobalAppStateDir::with_root_dir(test_dir.path().join("state"))?,
+            None,
+        )
+    }
+
+    #[test]
+    fn t_two_workers_and_local_run() -> Result<()> {
+        let test_dir = TestDir::new("two-workers");
+        let queues = coordinator_queues(&test_dir)?;
+        let commit1 = "1111111111111111111111111111111111111111";
+        let commit2 = "2222222222222222222222222222222222222222";
+        queues
+            .first()
+            .push_front(&BenchmarkingJob::for_tests("bench", commit1, None))?;
+        queues
+            .first()
+            .push_front(&BenchmarkingJob::for_tests("bench", commit2, None))?;
+
+        // Loaded by the local runner before the leases are granted
+        let stale_data = queues.data()?;
+
+        let grant1 = lease_job(&queues, &name("w1"))?.expect("a job to lease");
+        let grant2 = lease_job(&queues, &name("w2"))?.expect("a job to lease");
+        let leased_commits: BTreeSet<String> = [&grant1, &grant2]
+            .iter()
+            .map(|grant| grant.job.public.run_parameters.commit_id.to_string())
+            .collect();
+        assert_eq!(
+            leased_commits,
+            [commit1.to_string(), commit2.to_string()].into()
+        );
+        assert!(lease_job(&queues, &name("w1"))?.is_none());
+        assert!(lease_job(&queues, &name("w3")).is_err());
+
+        // The stale data still offers a job, but it can't be claimed
+        let (rqdwn, _dtr, item, _job, _prio) = stale_data
+            .most_prioritized_job(get_now_chrono())?
+            .expect("job in stale data");
+        assert!(
+            stale_data
+                .lock_unless_leased(rqdwn.current.run_queue(), item)?
+                .is_none()
+        );
+
+        // Fresh data skips the leased entries
+        assert!(
+            queues
+                .data()?
+                .most_prioritized_job(get_now_chrono())?
+                .is_none()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn t_no_lease_while_run_locally() -> Result<()> {
+        let test_dir = TestDir::new("lease-while-local");
+        let queues = coordinator_queues(&test_dir)?;
+        queues.first().push_front(&BenchmarkingJob::for_tests(
+            "bench",
+            "1111111111111111111111111111111111111111",
+            None,
+        ))?;
+
+        let data = queues.data()?;
+        let (rqdwn, _dtr, item, _job, _prio) =
+            data.most_prioritized_job(get_now_chrono())?.expect("a job");
+        let item = data
+            .lock_unless_leased(rqdwn.current.run_queue(), item)?
+            .expect("not leased");
+        assert!(item.is_locked());
+        assert!(lease_job(&queues, &name("w1"))?.is_none());
+        drop(item);
+        assert!(lease_job(&queues, &name("w1"))?.is_some());
+        Ok(())
+    }
+}
@@ -0,0 +1,579 @@
+//! `evobench serve`: a small HTTP server for local use, serving the
+//! output directory (so that no external web server and no
+//! `output_dir.url` setting are needed), and rendering the `evobench
+//! list`, `wd list` and `status` views on demand via the HTML backend
+//! of `OutputTable`. `.zstd` files are decompressed for viewing
+//! (append `?raw` to the URL to get the compressed file). The pages
+//! reload themselves when the queues or working directories change,
+//! as signalled via `PollingSignals`.
+//!
+//! Like the webhook server, this handles one request per
+//! connection. Requests are read and files served by a small pool of
+//! threads; the views are generated one at a time by the thread
+//! calling `OutputServer::serve`, which owns the queues and the
+//! change signals.
+
+use std::{
+    borrow::Cow,
+    fmt::Write as _,
+    io::Read,
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::{Component, Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+        mpsc::{Sender, channel},
+    },
+    time::Duration,
+};
+
+use ahtml::{HtmlAllocator, Node, SerHtmlFrag, att};
+use anyhow::{Result, anyhow, bail};
+use auri::url_encoding::url_decode;
+use chj_unix_util::polling_signals::PollingSignals;
+
+use crate::{
+    ctx, info,
+    io_utils::zstd_file::decompressed_file,
+    output_table::{OutputTable, html::HtmlTable},
+    run::{
+        config::RunConfig,
+        eta::RunDurationEstimator,
+        output_directory::html_files::LinkCellValue,
+        run_queues::RunQueues,
+        sub_command::{
+            list::{OutputTableOpts, ParameterView},
+            open_working_directory_pool,
+            wd::WdListOpts,
+        },
+        webhook::{Request, read_request, write_response_with_content_type},
+        working_directory_pool::WorkingDirectoryPoolBaseDir,
+    },
+    utillib::arc::CloneArc,
+    warn,
+};
+
+/// The default address for `evobench serve`
+pub const DEFAULT_SERVE_LISTEN: &str = "127.0.0.1:8096";
+
+/// The path prefix for the generated views; the output directory is
+/// served at all other paths
+const VIEWS_PREFIX: &str = "/_evobench/";
+
+/// How often the pages check for changes, in milliseconds
+const RELOAD_CHECK_INTERVAL_MS: u64 = 2000;
+
+/// Timeout for reading a request or writing the response
+const IO_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// The number of threads reading requests and serving files
+const NUM_THREADS: usize = 4;
+
+const TEXT_PLAIN: &str = "text/plain; charset=utf-8";
+const TEXT_HTML: &str = "text/html; charset=utf-8";
+
+/// What the views are generated from
+pub struct ServeContext<'t> {
+    pub conf: &'t RunConfig,
+    pub working_directory_base_dir: &'t Arc<WorkingDirectoryPoolBaseDir>,
+    pub queues: &'t RunQueues,
+    /// Generates the text shown by `evobench status`
+    pub status: &'t dyn Fn() -> Result<String>,
+}
+
+struct Response {
+    status: u16,
+    content_type: Cow<'static, str>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn text(status: u16, body: impl Into<String>) -> Self {
+        let mut body: String = body.into();
+        body.push('\n');
+        Self {
+            status,
+            content_type: TEXT_PLAIN.into(),
+            body: body.into_bytes(),
+        }
+    }
+
+    fn html(body: String) -> Self {
+        Self {
+            status: 200,
+            content_type: TEXT_HTML.into(),
+            body: body.into_bytes(),
+        }
+    }
+}
+
+fn content_type_for(path: &Path) -> &'static str {
+    match path.extension().and_then(|s| s.to_str()) {
+        Some("html") => TEXT_HTML,
+        Some("svg") => "image/svg+xml",
+        Some("xlsx") => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        Some("json") => "application/json",
+        Some("sqlite") => "application/vnd.sqlite3",
+        Some("ron" | "txt" | "log" | "csv" | "prom") => TEXT_PLAIN,
+        _ => "application/octet-stream",
+    }
+}
+
+/// Map the (decoded) URL path to a path in `base_dir`, refusing
+/// anything that could lead outside of it
+fn local_path(base_dir: &Path, url_path: &str) -> Option<PathBuf> {
+    let relative = Path::new(url_path.trim_start_matches('/'));
+    if relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        Some(base_dir.join(relative))
+    } else {
+        None
+    }
+}
+
+/// Percent-encode a (decoded) URL path, keeping the `/` separators
+fn url_encode_path(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for b in path.bytes() {
+        match b {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'.'
+            | b'_'
+            | b'~'
+            | b'/'
+            | b'='
+            | b':'
+            | b','
+            | b'@' => out.push(b as char),
+            _ => write!(out, "%{b:02X}").expect("no error writing to String"),
+        }
+    }
+    out
+}
+
+/// Split a request path into the decoded path and the query
+fn split_request_path(request_path: &str) -> Result<(String, Option<&str>)> {
+    let (path, query) = match request_path.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (request_path, None),
+    };
+    Ok((url_decode(path)?, query))
+}
+
+fn write_response(stream: &mut TcpStream, response: Response) -> Result<()> {
+    let Response {
+        status,
+        content_type,
+        body,
+    } = response;
+    write_response_with_content_type(stream, status, &content_type, &body)
+}
+
+pub struct OutputServer {
+    listener: TcpListener,
+    /// For the queues and working directories; only used by the
+    /// thread generating the views
+    change_signals: Mutex<Vec<PollingSignals>>,
+    /// For the ETAs in the list view, kept so that the durations of
+    /// previous runs are only read once; created on first use
+    estimator: Mutex<Option<RunDurationEstimator>>,
+    /// Increased whenever one of `change_signals` got a signal; the
+    /// pages reload when it changed since they were generated. Only
+    /// checked when generating views (including the `generation`
+    /// view polled by the pages), thus pages served by the other
+    /// threads may show an older value, which only leads to one more
+    /// reload.
+    generation: AtomicU64,
+}
+
+impl OutputServer {
+    /// Only loopback addresses are accepted for `listen`, since the
+    /// server has no access control
+    pub fn bind(listen: SocketAddr, change_signals: Vec<PollingSignals>) -> Result<Self> {
+        if !listen.ip().is_loopback() {
+            bail!(
+                "refusing to listen on {listen}: `evobench serve` only listens \
+                 on loopback addresses"
+            )
+        }
+        let listener = TcpListener::bind(listen).map_err(ctx!("listening on {listen}"))?;
+        info!("serving on http://{listen}/");
+        Ok(Self {
+            listener,
+            change_signals: Mutex::new(change_signals),
+            estimator: Mutex::new(None),
+            generation: AtomicU64::new(0),
+        })
+    }
+
+    /// Handle requests forever. Errors while handling a request are
+    /// reported to the client (and logged), not returned.
+    pub fn serve(&self, context: &ServeContext) -> Result<()> {
+        let output_base_dir: &Path = &context.conf.output_dir.path;
+        let (view_requests, view_requests_receiver) = channel();
+        std::thread::scope(|scope| {
+            for _ in 0..NUM_THREADS {
+                let view_requests = view_requests.clone();
+                scope.spawn(move || self.serve_files(output_base_dir, view_requests));
+            }
+            drop(view_requests);
+            for (mut stream, request) in view_requests_receiver {
+                let response = self.view_response(&request, context);
+                if let Err(e) = write_response(&mut stream, response) {
+                    warn!("handling request: {e:#}");
+                }
+            }
+        });
+        bail!("all threads serving requests have stopped")
+    }
+
+    /// Accept connections forever, answer requests for files, and
+    /// pass requests for views on to `view_requests`
+    fn serve_files(&self, output_base_dir: &Path, view_requests: Sender<(TcpStream, Request)>) {
+        loop {
+            let mut stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    warn!("accepting connection: {e}");
+                    continue;
+                }
+            };
+            let response = (|| -> Result<Option<Response>> {
+                stream.set_read_timeout(Some(IO_TIMEOUT))?;
+                stream.set_write_timeout(Some(IO_TIMEOUT))?;
+                let request = match read_request(&mut stream) {
+                    Ok(request) => request,
+                    Err(e) => return Ok(Some(Response::text(400, format!("{e:#}")))),
+                };
+                if request.method != "GET" {
+                    return Ok(Some(Response::text(
+                        405,
+                        format!("method {} not allowed", request.method),
+                    )));
+                }
+                let (path, query) = split_request_path(&request.path)?;
+                if path.starts_with(VIEWS_PREFIX) {
+                    let stream = stream.try_clone()?;
+                    view_requests
+                        .send((stream, request))
+                        .map_err(|_| anyhow!("the view handler has stopped"))?;
+                    return Ok(None);
+                }
+                Ok(Some(
+                    self.file_response(output_base_dir, &path, query)
+                        .unwrap_or_else(|e| {
+                            warn!("{} {}: {e:#}", request.method, request.path);
+                            Response::text(500, format!("{e:#}"))
+                        }),
+                ))
+            })();
+            let result = match response {
+                Ok(Some(response)) => write_response(&mut stream, response),
+                Ok(None) => Ok(()),
+                Err(e) => write_response(&mut stream, Response::text(400, format!("{e:#}"))),
+            };
+            if let Err(e) = result {
+                warn!("handling request: {e:#}");
+            }
+        }
+    }
+
+    fn update_generation(&self) {
+        let mut change_signals = self
+            .change_signals
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut changed = false;
+        for signals in change_signals.iter_mut() {
+            // Check all of them, to reset their state
+            if signals.got_signals() {
+                changed = true;
+            }
+        }
+        if changed {
+            self.generation.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// For requests below `VIEWS_PREFIX`
+    fn view_response(&self, request: &Request, context: &ServeContext) -> Response {
+        let result = (|| -> Result<Response> {
+            let (path, query) = split_request_path(&request.path)?;
+            let view = path.strip_prefix(VIEWS_PREFIX).unwrap_or(&path);
+
+            self.update_generation();
+
+            match view {
+                "generation" => Ok(Response::text(
+                    200,
+                    self.generation.load(Ordering::SeqCst).to_string(),
+                )),
+                "list" => self.list_page(context, query == Some("all")),
+                "wd" => self.wd_page(context),
+                "status" => self.status_page(context),
+                _ => Ok(Response::text(404, format!("unknown view {view:?}"))),
+            }
+        })();
+        result.unwrap_or_else(|e| {
+            warn!("{} {}: {e:#}", request.method, request.path);
+            Response::text(500, format!("{e:#}"))
+        })
+    }
+
+    /// For all other requests: the file or directory listing at
+    /// `path` in `output_base_dir`
+    fn file_response(
+        &self,
+        output_base_dir: &Path,
+        path: &str,
+        query: Option<&str>,
+    ) -> Result<Response> {
+        let Some(local_path) = local_path(output_base_dir, path) else {
+            return Ok(Response::text(404, format!("invalid path {path:?}")));
+        };
+        let metadata = match std::fs::metadata(&local_path) {
+            Ok(m) => m,
+            Err(e) => match e.kind() {
+                std::io::ErrorKind::NotFound => {
+                    return Ok(Response::text(404, format!("not found: {path:?}")));
+                }
+                _ => Err(e).map_err(ctx!("getting metadata for {local_path:?}"))?,
+            },
+        };
+        if metadata.is_dir() {
+            self.directory_page(path, &local_path)
+        } else {
+            let is_zstd = local_path.extension().and_then(|s| s.to_str()) == Some("zstd");
+            if is_zstd && query != Some("raw") {
+                let mut body = Vec::new();
+                decompressed_file(&local_path, None)?
+                    .read_to_end(&mut body)
+                    .map_err(ctx!("decompressing {local_path:?}"))?;
+                Ok(Response {
+                    status: 200,
+                    content_type: TEXT_PLAIN.into(),
+                    body,
+                })
+            } else {
+                let body =
+                    std::fs::read(&local_path).map_err(ctx!("reading file {local_path:?}"))?;
+                Ok(Response {
+                    status: 200,
+                    content_type: content_type_for(&local_path).into(),
+                    body,
+                })
+            }
+        }
+    }
+
+    /// Wrap `content` into an HTML document with navigation links and
+    /// the script that reloads the page on changes
+    fn page(&self, html: &HtmlAllocator, content: Node) -> Result<Response> {
+        let script: Arc<str> = format!(
+            r##"
+<script>
+  const generation = "{}";
+  setInterval(async () => {{
+    try {{
+      const response = await fetch("{VIEWS_PREFIX}generation");
+      if ((await response.text()).trim() != generation) {{
+        location.reload();
+      }}
+    }} catch (e) {{}}
+  }}, {RELOAD_CHECK_INTERVAL_MS});
+</script>
+"##,
+            self.generation.load(Ordering::SeqCst)
+        )
+        .into();
+
+        let mut nav = html.new_vec();
+        for (i, (label, url)) in [
+            ("Jobs", format!("{VIEWS_PREFIX}list")),
+            ("All jobs", format!("{VIEWS_PREFIX}list?all")),
+            ("Working directories", format!("{VIEWS_PREFIX}wd")),
+            ("Status", format!("{VIEWS_PREFIX}status")),
+            ("Output directory", "/".into()),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            if i > 0 {
+                nav.push(html.text(" | ")?)?;
+            }
+            nav.push(html.a([att("href", url)], [html.text(label)?])?)?;
+        }
+
+        let doc = html.html(
+            [],
+            [
+                html.head(
+                    [],
+                    html.preserialized(SerHtmlFrag {
+                        meta: &ahtml::SCRIPT_META,
+                        string: script,
+                    })?,
+                )?,
+                html.body([], [html.p([], nav)?, content])?,
+            ],
+        )?;
+        Ok(Response::html(html.to_html_string(doc, true)))
+    }
+
+    fn list_page(&self, context: &ServeContext, all: bool) -> Result<Response> {
+        let ServeContext {
+            conf,
+            working_directory_base_dir,
+            queues,
+            status: _,
+        } = context;
+        let html = HtmlAllocator::new(1000000, Arc::new("serve list"));
+        let output_table_opts = OutputTableOpts {
+            verbose: false,
+            all,
+            n: None,
+            no_eta: false,
+            output_url: Some("/".into()),
+            parameter_view: Some(ParameterView::Separated),
+        };
+        let mut estimator = self
+            .estimator
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let estimator = estimator
+            .get_or_insert_with(|| RunDurationEstimator::new(conf.output_dir.path.clone_arc()));
+        let link_skipped = format!("{VIEWS_PREFIX}list?all");
+        let body = output_table_opts.output_to_table(
+            HtmlTable::new(output_table_opts.num_columns(), &html),
+            conf,
+            Some(&link_skipped),
+            working_directory_base_dir,
+            queues,
+            Some(estimator),
+        )?;
+        let content = html.table([], body)?;
+        self.page(&html, content)
+    }
+
+    fn wd_page(&self, context: &ServeContext) -> Result<Response> {
+        let working_directory_pool = open_working_directory_pool(
+            context.conf,
+            context.working_directory_base_dir.clone(),
+            true,
+            None,
+        )?
+        .into_inner();
+        let wd_list_opts = WdListOpts {
+            active: false,
+            error: false,
+            numeric_sort: false,
+            du_sort: false,
+            show_commit: true,
+            show_du: false,
+        };
+        let html = HtmlAllocator::new(1000000, Arc::new("serve wd"));
+        let table = HtmlTable::new(wd_list_opts.titles().len(), &html);
+        let body = wd_list_opts.output_to_table(table, &working_directory_pool)?;
+        let content = html.table([], body)?;
+        self.page(&html, content)
+    }
+
+    fn status_page(&self, context: &ServeContext) -> Result<Response> {
+        let status = (context.status)()?;
+        let html = HtmlAllocator::new(1000000, Arc::new("serve status"));
+        let mut table = HtmlTable::new(1, &html);
+        table.print(status.as_str())?;
+        let content = html.table([], table.finish()?)?;
+        self.page(&html, content)
+    }
+
+    fn directory_page(&self, url_path: &str, local_path: &Path) -> Result<Response> {
+        let url_dir = if url_path.ends_with('/') {
+            url_path.to_owned()
+        } else {
+            format!("{url_path}/")
+        };
+        let mut entries: Vec<(String, bool)> = std::fs::read_dir(local_path)
+            .map_err(ctx!("opening dir {local_path:?}"))?
+            .map(|entry| -> Result<_> {
+                let entry = entry.map_err(ctx!("reading dir {local_path:?}"))?;
+                let name = entry
+                    .file_name()
+                    .into_string()
+                    .map_err(|name| anyhow!("file name is not valid UTF-8: {name:?}"))?;
+                // Follows symlinks (`latest/`)
+                let is_dir = entry.path().is_dir();
+                Ok((name, is_dir))
+            })
+            .collect::<Result<_>>()?;
+        entries.sort();
+
+        let html = HtmlAllocator::new(1000000, Arc::new("serve directory"));
+        let mut table = HtmlTable::new(1, &html);
+        if url_dir != "/" {
+            table.write_data_row(
+                &[LinkCellValue {
+                    s: "..".into(),
+                    url: Some(format!("{}..", url_encode_path(&url_dir))),
+                }],
+                None,
+            )?;
+        }
+        for (name, is_dir) in entries {
+            let s = if is_dir { format!("{name}/") } else { name };
+            table.write_data_row(
+                &[LinkCellValue {
+                    url: Some(url_encode_path(&format!("{url_dir}{s}"))),
+                    s,
+                }],
+                None,
+            )?;
+        }
+        let content = html.div(
+            [],
+            [
+                html.p([], [html.text(url_dir.as_str())?])?,
+                html.table([], table.finish()?)?,
+            ],
+        )?;
+        self.page(&html, content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_local_path() {
+        let base = Path::new("/out");
+        assert_eq!(
+            local_path(base, "/api/A=1/"),
+            Some(PathBuf::from("/out/api/A=1"))
+        );
+        assert_eq!(local_path(base, "/"), Some(PathBuf::from("/out/")));
+        assert_eq!(local_path(base, "/api/../../etc/passwd"), None);
+        assert_eq!(
+            local_path(base, "//etc/passwd"),
+            Some(PathBuf::from("/out/etc/passwd"))
+        );
+    }
+
+    #[test]
+    fn t_url_encode_path() -> Result<()> {
+        let path = "/api/A=1/c/2026-01-01T00:00:00+00:00/a #1?%.txt";
+        let encoded = url_encode_path(path);
+        assert_eq!(
+            encoded,
+            "/api/A=1/c/2026-01-01T00:00:00%2B00:00/a%20%231%3F%25.txt"
+        );
+        assert_eq!(split_request_path(&encoded)?, (path.to_owned(), None));
+        assert_eq!(url_encode_path("/ä/"), "/%C3%A4/");
+        Ok(())
+    }
+}
@@ -0,0 +1,46 @@
+use std::io::{Write, stdout};
+
+use anyhow::Result;
+
+use crate::run::config::RunConfig;
+
+/// Information about the configuration
+#[derive(Debug, clap::Subcommand)]
+pub enum ConfigSubCommand {
+    /// Show the targets with their custom parameters: whether they
+    /// are required, their default value if any, and the allowed
+    /// values (as a type, list, range or regex)
+    ShowTargets,
+}
+
+impl ConfigSubCommand {
+    pub fn run(self, conf: &RunConfig) -> Result<()> {
+        match self {
+            ConfigSubCommand::ShowTargets => {
+                let mut out = stdout().lock();
+                for (target_name, target) in &conf.targets {
+                    writeln!(&mut out, "{}", target_name.as_str())?;
+                    for (key, allowed_custom_parameter) in &target.allowed_custom_parameters {
+                        let mut flags = vec![if allowed_custom_parameter.required {
+                            "required".to_string()
+                        } else {
+                            "optional".to_string()
+                        }];
+                        if let Some(default) = &allowed_custom_parameter.default {
+                            flags.push(format!("default: {:?}", default.as_str()));
+                        }
+                        writeln!(
+                            &mut out,
+                            "  {}\t{}\t({})",
+                            key.as_str(),
+                            allowed_custom_parameter.r#type,
+                            flags.join(", ")
+                        )?;
+                    }
+                }
+                out.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
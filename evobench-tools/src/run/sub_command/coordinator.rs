@@ -0,0 +1,113 @@
+use std::{
+    io::{Write, stdout},
+    time::SystemTime,
+};
+
+use anyhow::{Result, bail};
+
+use crate::{
+    config_file::ron_to_string_pretty,
+    info,
+    run::{
+        config::RunConfig,
+        distributed::{LeaseId, complete_lease, lease_job},
+        run_queues::RunQueues,
+    },
+    serde_types::{date_and_time::system_time_to_rfc3339, proper_filename::ProperFilename},
+};
+
+/// The coordinator side of running jobs on several machines; these
+/// commands are invoked by workers (`evobench run --worker`) via
+/// their configured `coordinator_command`, except for `leases`.
+#[derive(Debug, clap::Subcommand)]
+pub enum Coordinator {
+    /// Lease the most prioritized runnable job to a worker, printing
+    /// the lease (or `None` if there is no runnable job) as RON to
+    /// stdout
+    Lease {
+        /// The name of the worker, as registered in the `workers`
+        /// field of the `coordinator` config
+        #[clap(long)]
+        worker: ProperFilename,
+    },
+
+    /// Finish a leased job: store the run directory (read as a tar
+    /// stream from stdin) in the output directory and update the
+    /// summaries, then update the job in its queue like `evobench
+    /// run` does
+    Complete {
+        /// The name of the worker completing the lease; must be the
+        /// one the job was leased to
+        #[clap(long)]
+        worker: ProperFilename,
+
+        /// Report the run as failed instead (nothing is read from
+        /// stdin); this uses up one unit of the job's error budget
+        #[clap(long)]
+        failed: bool,
+
+        /// The lease id as given by `lease`
+        lease_id: LeaseId,
+    },
+
+    /// Show the current leases
+    Leases,
+}
+
+impl Coordinator {
+    pub fn run(self, conf: &RunConfig, queues: &RunQueues) -> Result<()> {
+        match self {
+            Coordinator::Lease { worker } => {
+                let grant = lease_job(queues, &worker)?;
+                let mut out = stdout().lock();
+                writeln!(&mut out, "{}", ron_to_string_pretty(&grant)?)?;
+                out.flush()?;
+            }
+            Coordinator::Complete {
+                worker,
+                failed,
+                lease_id,
+            } => {
+                let job_status = complete_lease(queues, conf, &worker, &lease_id, failed)?;
+                info!(
+                    "completed lease {} (failed: {failed}), job status now: {job_status:?}",
+                    lease_id.as_str()
+                );
+            }
+            Coordinator::Leases => {
+                let Some(leases) = queues.leases() else {
+                    bail!(
+                        "this installation is not configured as a coordinator \
+                         (`queues.coordinator`)"
+                    )
+                };
+                let opts = queues
+                    .borrow_config()
+                    .coordinator
+                    .as_ref()
+                    .expect("leases are only opened if configured");
+                let now = SystemTime::now();
+                let mut out = stdout().lock();
+                for (id, lease) in leases.all()? {
+                    writeln!(
+                        &mut out,
+                        "{}\t{}\t{}\t{}\t{}\t{}{}",
+                        id.as_str(),
+                        system_time_to_rfc3339(lease.leased_at, None),
+                        lease.worker.as_str(),
+                        lease.host_class.as_str(),
+                        lease.queue_name.as_str(),
+                        lease.job.public.run_parameters.commit_id,
+                        if lease.is_expired(opts, now) {
+                            "\t(expired)"
+                        } else {
+                            ""
+                        }
+                    )?;
+                }
+                out.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,10 +1,17 @@
 //! -- see insert.md -- todo: copy here automatically via script?
 
-use std::{collections::BTreeSet, fmt::Display, path::PathBuf, str::FromStr};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    path::PathBuf,
+    str::FromStr,
+    sync::Arc,
+};
 
 use anyhow::{Result, anyhow, bail};
 use cj_path_util::unix::fixup_path::CURRENT_DIRECTORY;
 use itertools::Itertools;
+use kstring::KString;
 use run_git::git::GitWorkingDir;
 
 use crate::{
@@ -17,19 +24,25 @@ use crate::{
             BenchmarkingJob, BenchmarkingJobOpts, BenchmarkingJobReasonOpt,
             BenchmarkingJobSettingsOpts,
         },
-        config::{JobTemplate, RunConfigBundle, ShareableConfig},
+        config::{JobTemplate, JobTemplateOpts, RunConfigBundle, ShareableConfig},
+        env_vars::AllowableCustomEnvVar,
         insert_jobs::{DryRunOpt, ForceOpt, QuietOpt, insert_jobs},
+        output_directory::{
+            comparisons::Comparison,
+            structure::{ParametersDir, SubDirs},
+        },
         polling_pool::PollingPool,
         run_queues::RunQueues,
         sub_command::open_polling_pool,
         working_directory::REMOTE_NAME,
     },
     serde_types::{
-        date_and_time::DateTimeWithOffset, git_branch_name::GitBranchName,
-        git_reference::GitReference, priority::Priority,
+        allowed_env_var::AllowedEnvVar, date_and_time::DateTimeWithOffset,
+        git_branch_name::GitBranchName, git_reference::GitReference, priority::Priority,
+        proper_dirname::ProperDirname,
     },
     serde_util::serde_read_json,
-    utillib::fallback::FallingBackTo,
+    utillib::{arc::CloneArc, fallback::FallingBackTo},
 };
 
 #[derive(Debug, Clone, clap::Args)]
@@ -159,6 +172,26 @@ impl FromStr for LocalOrRemote {
     }
 }
 
+/// A `KEY=VALUE[,VALUE...]` argument to `insert adhoc`
+#[derive(Debug, Clone)]
+pub struct CustomParameterArg {
+    key: AllowedEnvVar<AllowableCustomEnvVar>,
+    values: Vec<KString>,
+}
+
+impl FromStr for CustomParameterArg {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (key, values) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expecting KEY=VALUE, got {s:?}"))?;
+        let key = AllowedEnvVar::from_str(key)?;
+        let values = values.split(',').map(KString::from_ref).collect();
+        Ok(Self { key, values })
+    }
+}
+
 /// Options to change insertion behaviour
 #[derive(clap::Args, Debug)]
 pub struct InsertBehaviourOpts {
@@ -313,6 +346,64 @@ pub enum Insert {
         more_reference_names: Vec<GitReference>,
     },
 
+    /// Take a job template given on the command line instead of
+    /// from the configuration file, and commits from explicitly
+    /// specified references. Multiple values for a parameter insert
+    /// jobs for each of them, and for each combination with the
+    /// values of the other parameters (like `sweep` in job templates
+    /// in the configuration file).
+    #[command(after_help = "  Note: more job‑setting options are available in the parent command!")]
+    Adhoc {
+        #[clap(flatten)]
+        opts: InsertOpts,
+
+        /// The name of the target (one of the `target_name` values in
+        /// the `targets` field of the configuration file)
+        #[clap(long)]
+        target: ProperDirname,
+
+        /// A custom parameter for the target, as `KEY=VALUE`. Several
+        /// values can be given separated by commas
+        /// (e.g. `CONCURRENCY=1,8,64`). Can be given multiple times,
+        /// for different keys.
+        #[clap(long = "param")]
+        params: Vec<CustomParameterArg>,
+
+        /// Whether to look up Git references in the remote repository
+        /// or in a local clone (in which case the current working dir
+        /// must be inside it)
+        #[clap(value_enum)]
+        local_or_remote: LocalOrRemote,
+
+        /// Git references to the commits that should be benchmarked
+        /// (commit ids, branch oder tag names, and other syntax like
+        /// `HEAD^`).
+        reference_names: Vec<GitReference>,
+    },
+
+    /// Benchmark a pull request: take the head of the remote branch
+    /// `branch_name` and its merge-base with the `--against` branch,
+    /// and insert jobs for both (merge-base first) with a shared
+    /// reason. The pair is recorded in the output directory, and a
+    /// change report is generated once both commits have results,
+    /// under `comparisons/$merge_base..$head/` there (linked from
+    /// `comparisons.html`). Takes the template definitions of
+    /// `branch_name` from the configuration file, or if there are
+    /// none, those of the `--against` branch.
+    #[command(after_help = "  Note: more job‑setting options are available in the parent command!")]
+    Pr {
+        #[clap(flatten)]
+        opts: InsertOpts,
+
+        /// The branch that the pull request is to be merged into,
+        /// e.g. `main`
+        #[clap(long)]
+        against: GitBranchName,
+
+        /// The branch of the pull request
+        branch_name: GitBranchName,
+    },
+
     /// Take template definitions and commit from job specification
     /// files (e.g. to re-use files of failed jobs from queues, or
     /// edit manually)
@@ -364,6 +455,31 @@ fn insert_templates_with_references(
     let commits: BTreeSet<GitHash> = commits.into_iter().filter_map(|v| v).collect();
     info!("reference_names {reference_names:?} resolve to commits {commits:?}");
 
+    insert_templates_for_commits(
+        shareable_config,
+        force_opt,
+        quiet_opt,
+        dry_run_opt,
+        insert_benchmarking_job_opts,
+        queues,
+        job_templates,
+        commits,
+    )
+}
+
+/// Insert jobs for all `job_templates` for each of `commits`, in
+/// that order; `insert_benchmarking_job_opts` must already be
+/// completed with the config entries.
+fn insert_templates_for_commits(
+    shareable_config: &ShareableConfig,
+    force_opt: ForceOpt,
+    quiet_opt: QuietOpt,
+    dry_run_opt: DryRunOpt,
+    insert_benchmarking_job_opts: InsertBenchmarkingJobOpts,
+    queues: &RunQueues,
+    job_templates: &[JobTemplate],
+    commits: impl IntoIterator<Item = GitHash>,
+) -> Result<usize> {
     let benchmarking_jobs: Vec<BenchmarkingJob> = commits
         .into_iter()
         .map(|commit_id| {
@@ -386,6 +502,29 @@ fn insert_templates_with_references(
     )
 }
 
+/// The comparison for a pull request on `branch_name`, at `head`,
+/// against the branch `against`, with which it has the merge-base
+/// `merge_base`. `reason` defaults to naming both branches.
+fn pull_request_comparison(
+    branch_name: &GitBranchName,
+    against: &GitBranchName,
+    head: GitHash,
+    merge_base: GitHash,
+    reason: Option<String>,
+) -> Result<Comparison> {
+    if head == merge_base {
+        bail!(
+            "branch {branch_name} has no commits that are not in {against}, \
+             nothing to compare"
+        )
+    }
+    Ok(Comparison {
+        reason: reason.unwrap_or_else(|| format!("PR {branch_name} vs {against}")),
+        from_commit: merge_base,
+        to_commit: head,
+    })
+}
+
 impl Insert {
     pub fn run(self, run_config_bundle: &RunConfigBundle, queues: &RunQueues) -> Result<usize> {
         let conf = &run_config_bundle.shareable.run_config;
@@ -434,12 +573,13 @@ impl Insert {
             } => {
                 let job_templates = conf
                     .remote_repository
-                    .remote_branch_names_for_poll
-                    .get(&branch_name)
+                    .job_templates_for_branch(&branch_name)
                     .ok_or_else(|| {
                         anyhow!(
                             "there is no entry under \
                              `remote_repository.remote_branch_names_for_poll` \
+                             and no matching branch pattern in \
+                             `remote_repository.ref_patterns_for_poll` \
                              for branch name {branch_name}"
                         )
                     })?;
@@ -481,12 +621,13 @@ impl Insert {
 
                 let job_templates = conf
                     .remote_repository
-                    .remote_branch_names_for_poll
-                    .get(&branch_name)
+                    .job_templates_for_branch(&branch_name)
                     .ok_or_else(|| {
                         anyhow!(
                             "there is no entry under \
                              `remote_repository.remote_branch_names_for_poll` \
+                             and no matching branch pattern in \
+                             `remote_repository.ref_patterns_for_poll` \
                              for branch name {branch_name}"
                         )
                     })?;
@@ -511,6 +652,137 @@ impl Insert {
                 )
             }
 
+            Insert::Adhoc {
+                mut opts,
+                target,
+                params,
+                local_or_remote,
+                reference_names,
+            } => {
+                // Parameters with a single value are fixed, the others
+                // are swept over
+                let mut custom_parameters = BTreeMap::new();
+                let mut sweep = BTreeMap::new();
+                for CustomParameterArg { key, mut values } in params {
+                    if custom_parameters.contains_key(&key) || sweep.contains_key(&key) {
+                        bail!("parameter {:?} was given more than once", key.as_str())
+                    }
+                    if values.len() == 1 {
+                        custom_parameters.insert(key, values.pop().expect("checked len"));
+                    } else {
+                        sweep.insert(key, values);
+                    }
+                }
+                let job_templates: Vec<JobTemplate> =
+                    JobTemplateOpts::adhoc(target.clone(), custom_parameters, sweep)
+                        .check(&conf.targets)?;
+                info!(
+                    "inserting jobs for {} parameter set(s)",
+                    job_templates.len()
+                );
+
+                let reference_names: BTreeSet<GitReference> = reference_names.into_iter().collect();
+
+                opts.insert_benchmarking_job_opts
+                    .reason
+                    .reason
+                    .get_or_insert(format!("A {}", target.as_str()));
+
+                let gwd = local_or_remote.load(&run_config_bundle.shareable)?;
+                insert_templates_with_references(
+                    &run_config_bundle.shareable,
+                    opts,
+                    queues,
+                    gwd,
+                    &job_templates,
+                    reference_names,
+                )
+            }
+
+            Insert::Pr {
+                opts,
+                against,
+                branch_name,
+            } => {
+                let job_templates = conf
+                    .remote_repository
+                    .job_templates_for_branch(&branch_name)
+                    .or_else(|| conf.remote_repository.job_templates_for_branch(&against))
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "there is no entry under \
+                             `remote_repository.remote_branch_names_for_poll` \
+                             and no matching branch pattern in \
+                             `remote_repository.ref_patterns_for_poll` \
+                             for branch name {branch_name} nor {against}"
+                        )
+                    })?;
+
+                let mut polling_pool = open_polling_pool(&run_config_bundle.shareable)?;
+                let working_dir_id = polling_pool.updated_working_dir()?;
+                let (head, merge_base) =
+                    polling_pool.merge_base(working_dir_id, &branch_name, &against)?;
+                info!("{branch_name} is at {head}, merge-base with {against} is {merge_base}");
+
+                let InsertOpts {
+                    insert_behaviour_opts:
+                        InsertBehaviourOpts {
+                            force_opt,
+                            quiet_opt,
+                            dry_run_opt,
+                        },
+                    mut insert_benchmarking_job_opts,
+                } = opts;
+                let comparison = pull_request_comparison(
+                    &branch_name,
+                    &against,
+                    head,
+                    merge_base,
+                    insert_benchmarking_job_opts.reason.reason.take(),
+                )?;
+                insert_benchmarking_job_opts.reason.reason = Some(comparison.reason.clone());
+                let insert_benchmarking_job_opts =
+                    insert_benchmarking_job_opts.complete_with(&conf.benchmarking_job_settings);
+
+                let dry_run = dry_run_opt.dry_run;
+                if !dry_run {
+                    comparison.save(&conf.output_dir.path)?;
+                }
+
+                let n = insert_templates_for_commits(
+                    &run_config_bundle.shareable,
+                    force_opt,
+                    quiet_opt,
+                    dry_run_opt,
+                    insert_benchmarking_job_opts,
+                    queues,
+                    &job_templates,
+                    [comparison.from_commit.clone(), comparison.to_commit.clone()],
+                )?;
+
+                if !dry_run {
+                    // Both commits may have results already (in which
+                    // case no jobs were inserted, unless forced)
+                    let parameters_dirs: BTreeSet<ParametersDir> = job_templates
+                        .iter()
+                        .map(|job_template| {
+                            job_template.to_parameters_dir(conf.output_dir.path.clone_arc())
+                        })
+                        .collect();
+                    for parameters_dir in parameters_dirs {
+                        let key_dir = Arc::new(parameters_dir)
+                            .append_subdir_str(&comparison.to_commit.to_string())?;
+                        Arc::new(key_dir).update_comparisons_for_key_dir()?;
+                    }
+                    info!(
+                        "the change report will be in {:?}",
+                        comparison.dir_path(&conf.output_dir.path)
+                    );
+                }
+
+                Ok(n)
+            }
+
             Insert::JobFiles {
                 opts,
                 force_invalid_opt,
@@ -557,3 +829,52 @@ impl Insert {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_pull_request_comparison() {
+        let branch_name: GitBranchName = "feature".parse().unwrap();
+        let against: GitBranchName = "main".parse().unwrap();
+        let head: GitHash = "2222222222222222222222222222222222222222".parse().unwrap();
+        let merge_base: GitHash = "1111111111111111111111111111111111111111".parse().unwrap();
+
+        // The merge-base is the baseline, the head is compared to it
+        let comparison = pull_request_comparison(
+            &branch_name,
+            &against,
+            head.clone(),
+            merge_base.clone(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            comparison,
+            Comparison {
+                reason: "PR feature vs main".into(),
+                from_commit: merge_base.clone(),
+                to_commit: head.clone(),
+            }
+        );
+        assert_eq!(
+            comparison.dir_name(),
+            "1111111111111111111111111111111111111111..\
+             2222222222222222222222222222222222222222"
+        );
+
+        let comparison = pull_request_comparison(
+            &branch_name,
+            &against,
+            head.clone(),
+            merge_base,
+            Some("my reason".into()),
+        )
+        .unwrap();
+        assert_eq!(comparison.reason, "my reason");
+
+        // A branch without own commits
+        assert!(pull_request_comparison(&branch_name, &against, head.clone(), head, None).is_err());
+    }
+}
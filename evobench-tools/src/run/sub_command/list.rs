@@ -18,6 +18,7 @@ use crate::{
     output_table::{BarKind, FontSize, WithUrlOnDemand},
     run::{
         config::RunConfig,
+        eta::{RunDurationEstimator, simulate},
         output_directory::structure::{KeyDir, ToPath},
         run_queue::RunQueue,
         run_queues::RunQueues,
@@ -68,14 +69,17 @@ pub enum ParameterView {
 }
 
 impl ParameterView {
-    pub fn titles(self) -> Vec<&'static str> {
+    /// The column titles; the "ETA" column is only shown `with_eta`
+    pub fn titles(self, with_eta: bool) -> Vec<&'static str> {
         let mut titles = vec![
             "Insertion_time",
             "S", // Status
             "Prio",
-            "WD",
-            "Reason",
         ];
+        if with_eta {
+            titles.push("ETA");
+        }
+        titles.extend_from_slice(&["WD", "Reason"]);
         match self {
             ParameterView::Separated => {
                 titles.extend_from_slice(&["Commit_id", "Target_name", "Custom_parameters"]);
@@ -98,7 +102,8 @@ pub struct OutputTableOpts {
     #[clap(short, long)]
     pub verbose: bool,
 
-    /// Show all jobs in the extra queues (done and failures); by
+    /// Show all jobs in the extra queues (done, failures and
+    /// superseded); by
     /// default, only the last `view_jobs_max_len` jobs are shown
     /// as stated in the QueuesConfig.
     #[clap(short, long)]
@@ -110,12 +115,34 @@ pub struct OutputTableOpts {
     #[clap(short, long)]
     pub n: Option<usize>,
 
+    /// Do not estimate when the jobs will be run (the estimates are
+    /// based on the durations of previous runs, which requires
+    /// reading their log files), and leave out the ETA column
+    #[clap(long)]
+    pub no_eta: bool,
+
+    /// The URL where the output directory is served, used for the
+    /// links (default: `output_dir.url` from the configuration)
+    #[clap(long)]
+    pub output_url: Option<String>,
+
     /// How to show the job parameters
     #[clap(subcommand)]
     pub parameter_view: Option<ParameterView>,
 }
 
 impl OutputTableOpts {
+    /// The number of columns of the table
+    pub fn num_columns(&self) -> usize {
+        self.parameter_view
+            .unwrap_or_default()
+            .titles(!self.no_eta)
+            .len()
+    }
+
+    /// `estimator` is used for the ETAs if given (keep one around to
+    /// avoid re-reading the logs of previous runs), otherwise a new
+    /// one is created; unused if `no_eta` is set.
     pub fn output_to_table<'link_skipped, Table: OutputTable>(
         &self,
         mut table: Table,
@@ -123,15 +150,19 @@ impl OutputTableOpts {
         link_skipped: Option<&'link_skipped str>,
         working_directory_base_dir: &Arc<WorkingDirectoryPoolBaseDir>,
         queues: &RunQueues,
+        estimator: Option<&mut RunDurationEstimator>,
     ) -> Result<Table::Output> {
         let Self {
             verbose,
             all,
             n,
+            no_eta,
+            output_url,
             parameter_view,
         } = self;
 
         let parameter_view = parameter_view.unwrap_or_default();
+        let output_url: Option<&str> = output_url.as_deref().or(conf.output_dir.url.as_deref());
 
         // The base of the path that's used for the `path` view
         let path_base: Option<Arc<Path>> = {
@@ -141,13 +172,13 @@ impl OutputTableOpts {
                     ParameterPathKind::Relative => PathBuf::from("").into(),
                     ParameterPathKind::Full => conf.output_dir.path.clone_arc(),
                     ParameterPathKind::Url => {
-                        let url = conf.output_dir.url.as_ref().ok_or_else(|| {
+                        let url = output_url.ok_or_else(|| {
                             anyhow!(
                                 "the URL viewing feature requires the `output_dir.url` \
-                                 field in the configuration to be set"
+                                 field in the configuration to be set, or `--output-url`"
                             )
                         })?;
-                        PathBuf::from(&**url).into()
+                        PathBuf::from(url).into()
                     }
                 }),
             }
@@ -166,7 +197,7 @@ impl OutputTableOpts {
 
         {
             let titles: Vec<_> = parameter_view
-                .titles()
+                .titles(!*no_eta)
                 .into_iter()
                 .map(|s| OutputTableTitle {
                     text: Cow::Borrowed(s),
@@ -190,6 +221,24 @@ impl OutputTableOpts {
 
         let now = SystemTime::now();
 
+        let queues_data = queues.data()?;
+
+        let etas = if *no_eta {
+            None
+        } else {
+            let mut tmp;
+            let estimator = if let Some(estimator) = estimator {
+                estimator
+            } else {
+                tmp = RunDurationEstimator::new(conf.output_dir.path.clone_arc());
+                &mut tmp
+            };
+            Some(simulate(&queues_data, estimator, now)?)
+        };
+        if let Some(etas) = &etas {
+            table.print(format!("{}\n", etas.summary()))?;
+        }
+
         // Not kept in sync with what happens during for loop; but
         // then it is really about the status stored inside
         // `pool`, thus that doesn't even matter!
@@ -316,11 +365,19 @@ impl OutputTableOpts {
                             "R"
                         };
                         (s, true)
+                    } else if queues_data.is_leased(&run_queue.file_name, &key) {
+                        ("L", false) // leased to a worker
                     } else {
                         ("", false)
                     }
                 };
                 let priority = &*job.priority()?.to_string();
+                let eta = etas.as_ref().map(|etas| {
+                    etas.eta
+                        .get(&(run_queue.file_name.clone(), key.clone()))
+                        .map(|eta| eta.format("%m-%d %H:%M").to_string())
+                        .unwrap_or_default()
+                });
                 let wd = if is_locked {
                     opt_current_working_directory
                         .map(|v| v.to_string())
@@ -343,13 +400,11 @@ impl OutputTableOpts {
                     let datetime: DateTime<Local> = system_time.into();
                     datetime.to_rfc3339()
                 };
-                row.extend_from_slice(&[
-                    (&*time).into(),
-                    locking.into(),
-                    priority.into(),
-                    (&*wd).into(),
-                    reason.into(),
-                ]);
+                row.extend_from_slice(&[(&*time).into(), locking.into(), priority.into()]);
+                if let Some(eta) = &eta {
+                    row.push((&**eta).into());
+                }
+                row.extend_from_slice(&[(&*wd).into(), reason.into()]);
 
                 let commit_id;
                 let custom_parameters;
@@ -357,7 +412,7 @@ impl OutputTableOpts {
                 let path;
                 let gen_url_cache: OnceCell<Arc<Path>> = OnceCell::new();
                 let gen_url = || -> Option<Cow<'_, str>> {
-                    if let Some(url) = &conf.output_dir.url {
+                    if let Some(url) = output_url {
                         Some(
                             gen_url_cache
                                 .get_or_init(|| {
@@ -482,6 +537,10 @@ impl OutputTableOpts {
             queues.erroneous_jobs_queue(),
             &mut table,
         )?;
+        // Optional feature, only show if configured
+        if let Some(run_queue) = queues.superseded_jobs_queue() {
+            show_queue("superseded", run_queue, true, &mut table, BarKind::Thin)?;
+        }
 
         table.finish()
     }
@@ -505,16 +564,20 @@ fn make_terminal_table<O: io::Write + IsTerminal>(
     out: O,
     verbose: bool,
     view: ParameterView,
+    with_eta: bool,
 ) -> TerminalTable<O> {
     let insertion_time_width = if verbose { 82 } else { 37 };
-    let widths =
-    //     t                    R pr WD reason commit target
-        &[insertion_time_width, 3, 6, 5, 25, 42, TARGET_NAME_WIDTH];
-    let widths = match view {
-        ParameterView::Separated => widths,
-        ParameterView::Path { kind: _ } => &widths[0..5],
-    };
-    TerminalTable::new(widths, terminal_table_opts.clone(), out)
+    let mut widths =
+    //       t                    R pr ETA WD reason commit target
+        vec![insertion_time_width, 3, 6, 12, 5, 25, 42, TARGET_NAME_WIDTH];
+    match view {
+        ParameterView::Separated => (),
+        ParameterView::Path { kind: _ } => widths.truncate(6),
+    }
+    if !with_eta {
+        widths.remove(3);
+    }
+    TerminalTable::new(&widths, terminal_table_opts.clone(), out)
 }
 
 impl ListOpts {
@@ -539,6 +602,7 @@ impl ListOpts {
                 &output_table_opts,
                 None,
                 None,
+                None,
                 stdout().lock(),
             )?;
         } else {
@@ -548,6 +612,7 @@ impl ListOpts {
                 out,
                 output_table_opts.verbose,
                 output_table_opts.parameter_view.unwrap_or_default(),
+                !output_table_opts.no_eta,
             );
 
             let mut out = output_table_opts.output_to_table(
@@ -556,6 +621,7 @@ impl ListOpts {
                 None,
                 working_directory_base_dir,
                 queues,
+                None,
             )?;
 
             out.flush()?;
@@ -12,9 +12,12 @@ use crate::run::{
     },
 };
 
+pub mod config;
+pub mod coordinator;
 pub mod insert;
 pub mod list;
 pub mod list_all;
+pub mod query;
 pub mod wd;
 pub mod wd_log;
 
@@ -41,6 +44,7 @@ pub fn open_working_directory_pool(
         base_dir: _,
         capacity,
         auto_clean,
+        shared_objects,
     } = &*conf.working_directory_pool;
 
     WorkingDirectoryPool::open(
@@ -49,6 +53,7 @@ pub fn open_working_directory_pool(
             auto_clean: auto_clean.clone(),
             remote_repository_url: conf.remote_repository.url.clone(),
             base_dir,
+            shared_objects: shared_objects.unwrap_or(false),
             signal_change,
         },
         create_dir_if_not_exists,
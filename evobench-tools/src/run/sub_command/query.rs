@@ -0,0 +1,198 @@
+use std::{
+    borrow::Cow,
+    io::{Write, stdout},
+};
+
+use anyhow::Result;
+use rusqlite::types::Value;
+use strum_macros::EnumString;
+
+use crate::{
+    output_table::{
+        OutputTable, OutputTableTitle,
+        terminal::{TerminalTable, TerminalTableOpts},
+    },
+    run::{config::RunConfig, output_directory::results_index::ResultsIndex},
+    utillib::{arc::CloneArc, csv::csv_line},
+};
+
+const DEFAULT_COLUMNS: &str = "commit_date, commit_id, target, custom_parameters, \
+                               timestamp, field, probe, n, average, median, unit";
+
+#[derive(Debug, EnumString, PartialEq, Clone, Copy)]
+#[strum(serialize_all = "kebab_case")]
+pub enum QueryFormat {
+    Table,
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct QueryOpts {
+    /// Condition (an SQL expression) on the columns of the `results`
+    /// view, e.g. `"target = 'api' AND probe LIKE '%parse%'"`. Can
+    /// be given multiple times, the conditions are combined with
+    /// AND.
+    #[clap(long = "where", short)]
+    where_: Vec<String>,
+
+    /// The columns (or SQL expressions) to show, comma-separated
+    /// (default: commit_date, commit_id, target, custom_parameters,
+    /// timestamp, field, probe, n, average, median, unit). All
+    /// columns: target, commit_id, commit_time, commit_date,
+    /// custom_parameters, timestamp, run_time, situation,
+    /// host_class, field, probe, unit, n, sum, average, median, sd,
+    /// run_dir.
+    #[clap(long, short)]
+    columns: Option<String>,
+
+    /// SQL ordering, e.g. `"commit_time DESC, probe"` (default:
+    /// by commit time, run timestamp, field and probe)
+    #[clap(long)]
+    order_by: Option<String>,
+
+    /// Show at most this many rows
+    #[clap(long, short)]
+    limit: Option<u64>,
+
+    /// Run this complete SQL query instead of building one from
+    /// the options above (the database is opened read-only). The
+    /// tables are `runs`, `run_parameters`, `probe_stats` and
+    /// `commits`.
+    #[clap(long, conflicts_with_all = ["where_", "columns", "order_by", "limit"])]
+    sql: Option<String>,
+
+    /// The output format (table, csv, json)
+    #[clap(long, default_value = "table")]
+    format: QueryFormat,
+
+    #[clap(flatten)]
+    terminal_table_opts: TerminalTableOpts,
+}
+
+impl QueryOpts {
+    fn sql(&self) -> String {
+        let Self {
+            where_,
+            columns,
+            order_by,
+            limit,
+            sql,
+            format: _,
+            terminal_table_opts: _,
+        } = self;
+        if let Some(sql) = sql {
+            return sql.clone();
+        }
+        let mut sql = format!(
+            "SELECT {} FROM results",
+            columns.as_deref().unwrap_or(DEFAULT_COLUMNS)
+        );
+        if !where_.is_empty() {
+            let conditions: Vec<String> = where_.iter().map(|w| format!("({w})")).collect();
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        sql.push_str(" ORDER BY ");
+        sql.push_str(
+            order_by
+                .as_deref()
+                .unwrap_or("commit_time, timestamp, field, probe"),
+        );
+        if let Some(limit) = limit {
+            sql.push_str(&format!(" LIMIT {limit}"));
+        }
+        sql
+    }
+
+    pub fn run(self, conf: &RunConfig) -> Result<()> {
+        let index = ResultsIndex::open_read_only(conf.output_dir.path.clone_arc())?;
+        let result = index.query(&self.sql())?;
+
+        let Self {
+            format,
+            terminal_table_opts,
+            ..
+        } = self;
+        let mut out = stdout().lock();
+        match format {
+            QueryFormat::Table => {
+                let rows: Vec<Vec<String>> = result
+                    .rows
+                    .iter()
+                    .map(|row| row.iter().map(value_to_string).collect())
+                    .collect();
+                let num_columns = result.columns.len();
+                let widths: Vec<usize> = (0..num_columns.saturating_sub(1))
+                    .map(|i| {
+                        rows.iter()
+                            .map(|row| row[i].len())
+                            .chain([result.columns[i].len()])
+                            .max()
+                            .unwrap_or(0)
+                            + 2
+                    })
+                    .collect();
+                let mut table = TerminalTable::new(&widths, terminal_table_opts, out);
+                let titles: Vec<OutputTableTitle> = result
+                    .columns
+                    .iter()
+                    .map(|column| OutputTableTitle {
+                        text: Cow::Borrowed(column.as_str()),
+                        span: 1,
+                        anchor_name: None,
+                    })
+                    .collect();
+                table.write_title_row(&titles, None)?;
+                for row in &rows {
+                    table.write_data_row(row, None)?;
+                }
+                let _ = table.finish()?;
+            }
+            QueryFormat::Csv => {
+                writeln!(out, "{}", csv_line(&result.columns))?;
+                for row in &result.rows {
+                    writeln!(out, "{}", csv_line(row.iter().map(value_to_string)))?;
+                }
+            }
+            QueryFormat::Json => {
+                let rows: Vec<serde_json::Map<String, serde_json::Value>> = result
+                    .rows
+                    .iter()
+                    .map(|row| {
+                        result
+                            .columns
+                            .iter()
+                            .cloned()
+                            .zip(row.iter().map(value_to_json))
+                            .collect()
+                    })
+                    .collect();
+                serde_json::to_writer_pretty(&mut out, &rows)?;
+                writeln!(out)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Integer(v) => v.to_string(),
+        Value::Real(v) => v.to_string(),
+        Value::Text(v) => v.clone(),
+        Value::Blob(v) => v.iter().map(|b| format!("{b:02x}")).collect(),
+    }
+}
+
+fn value_to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Integer(v) => (*v).into(),
+        Value::Real(v) => serde_json::Number::from_f64(*v)
+            .map(serde_json::Value::Number)
+            .unwrap_or(serde_json::Value::Null),
+        Value::Text(_) | Value::Blob(_) => value_to_string(value).into(),
+    }
+}
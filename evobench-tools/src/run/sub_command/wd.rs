@@ -31,8 +31,8 @@ use crate::{
         versioned_dataset_dir::VersionedDatasetDir,
         working_directory::{FetchedTags, Status, WorkingDirectory, WorkingDirectoryStatus},
         working_directory_pool::{
-            WdAllowBareOpt, WorkingDirectoryId, WorkingDirectoryIdOpt, WorkingDirectoryPoolBaseDir,
-            finish_parsing_working_directory_ids,
+            WdAllowBareOpt, WorkingDirectoryId, WorkingDirectoryIdOpt, WorkingDirectoryPool,
+            WorkingDirectoryPoolBaseDir, finish_parsing_working_directory_ids,
         },
     },
     serde_types::date_and_time::system_time_to_rfc3339,
@@ -61,6 +61,188 @@ pub fn open_queue_change_signals(
         .map_err(ctx!("open_queue_change_signals"))
 }
 
+/// Which working directories `wd list` shows, and how
+#[derive(Debug, Clone, Copy)]
+pub struct WdListOpts {
+    /// Show the active working directories (if both `active` and
+    /// `error` are false, all are shown)
+    pub active: bool,
+    /// Show the working directories that have been set aside
+    pub error: bool,
+    /// Sort by id instead of the `last_use` timestamp
+    pub numeric_sort: bool,
+    /// Sort by disk usage (requires `show_du`)
+    pub du_sort: bool,
+    /// Show the checked-out commit (slow)
+    pub show_commit: bool,
+    /// Show the disk usage (slow)
+    pub show_du: bool,
+}
+
+impl WdListOpts {
+    /// The column widths for the terminal table
+    pub fn widths(&self) -> Vec<usize> {
+        let mut widths = vec![5 + 2, Status::MAX_STR_LEN + 2, 8 + 2, 35 + 2, 35 + 2];
+        if self.show_commit {
+            widths.push(40 + 2);
+        }
+        if self.show_du {
+            widths.push(7 + 2);
+        }
+        widths.pop();
+        widths
+    }
+
+    pub fn titles(&self) -> Vec<OutputTableTitle<'static>> {
+        let mut titles = vec!["id", "status", "num_runs", "creation_timestamp", "last_use"];
+        if self.show_commit {
+            titles.push("commit_id");
+        }
+        if self.show_du {
+            titles.push("du_GiB");
+        }
+        titles
+            .into_iter()
+            .map(|s| OutputTableTitle {
+                text: Cow::Borrowed(s),
+                span: 1,
+                anchor_name: None,
+            })
+            .collect()
+    }
+
+    /// The ids of the working directories to show, in the order to
+    /// show them (except for `du_sort`, which is only applied in
+    /// `output_to_table`)
+    pub fn ids(&self, working_directory_pool: &WorkingDirectoryPool) -> Vec<WorkingDirectoryId> {
+        let Self {
+            active,
+            error,
+            numeric_sort,
+            du_sort: _,
+            show_commit: _,
+            show_du: _,
+        } = *self;
+        let mut all_entries: Vec<_> = working_directory_pool.all_entries().collect();
+        if numeric_sort {
+            // Leave as is, it's already sorted
+        } else {
+            all_entries.sort_by(|a, b| a.1.last_use.cmp(&b.1.last_use))
+        }
+        all_entries
+            .into_iter()
+            .filter(|(_, wd)| {
+                let status = wd.working_directory_status.status;
+                match (active, error) {
+                    (true, true) | (false, false) => true,
+                    (true, false) => status.can_be_used_for_jobs(),
+                    (false, true) => !status.can_be_used_for_jobs(),
+                }
+            })
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    /// Write the title row and a row for every working directory to
+    /// `table`
+    pub fn output_to_table<Table: OutputTable>(
+        &self,
+        mut table: Table,
+        working_directory_pool: &WorkingDirectoryPool,
+    ) -> Result<Table::Output> {
+        let Self {
+            active: _,
+            error: _,
+            numeric_sort: _,
+            du_sort,
+            show_commit,
+            show_du,
+        } = *self;
+
+        table.write_title_row(&self.titles(), None)?;
+
+        let show_as_table: Vec<_> = self
+            .ids(working_directory_pool)
+            .into_iter()
+            .map(|id| {
+                let wd = working_directory_pool
+                    .get_working_directory(id)
+                    .expect("got it from all_entries");
+                let WorkingDirectoryStatus {
+                    creation_timestamp,
+                    num_runs,
+                    status,
+                } = &wd.working_directory_status;
+                (
+                    vec![
+                        id.to_string(),
+                        status.to_string(),
+                        num_runs.to_string(),
+                        creation_timestamp.to_string(),
+                        system_time_to_rfc3339(wd.last_use, None),
+                    ],
+                    wd.working_directory_path(),
+                    wd.commit.clone(),
+                )
+            })
+            .collect();
+
+        let mut rows = show_as_table
+            .into_par_iter()
+            .map(|(mut row, wdp, opt_commit)| -> Result<_> {
+                let get_commit = || -> Result<String> {
+                    let commit = if let Some(commit) = opt_commit {
+                        info!("already had a commit! how comes?");
+                        commit
+                    } else {
+                        wdp.noncached_commit()?
+                    };
+                    Ok(commit.to_string())
+                };
+                let get_du = || -> Result<String> {
+                    let gdu = GetDirDiskUsage {
+                        one_file_system: false,
+                        share_globally: false,
+                        shared_inodes: Default::default(),
+                    };
+                    let du = gdu.dir_disk_usage(wdp.clone().into(), 0)?;
+                    let shared_inodes = gdu.shared_inodes.lock().expect("no crash");
+                    let bytes = du.total(&shared_inodes);
+                    Ok(bytes_to_gib_string(bytes))
+                };
+                if show_commit && show_du {
+                    let (commit, du) = rayon::join(get_commit, get_du);
+                    row.push(commit?);
+                    row.push(du?);
+                } else {
+                    if show_commit {
+                        row.push(get_commit()?);
+                    }
+                    if show_du {
+                        row.push(get_du()?);
+                    }
+                }
+                Ok(row)
+            })
+            .collect::<Result<Vec<Vec<String>>>>()?;
+
+        if du_sort {
+            // Sort the strings, this works thanks to the
+            // adjusted formatting
+            let mut col = 5;
+            if show_commit {
+                col += 1;
+            }
+            rows.sort_by(|a, b| a[col].cmp(&b[col]));
+        }
+
+        for row in rows {
+            table.write_data_row(&row, None)?;
+        }
+        table.finish()
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum GetRunLockError {
     #[error("{0}")]
@@ -371,150 +553,26 @@ impl Wd {
                 // Note: numeric_sort && du_sort is actually fine,
                 // first sort numerically, then by size.
 
-                let show_commit = !no_commit;
-                let show_du = !no_du;
-
-                let widths = {
-                    let mut widths = vec![5 + 2, Status::MAX_STR_LEN + 2, 8 + 2, 35 + 2, 35 + 2];
-                    if show_commit {
-                        widths.push(40 + 2);
-                    }
-                    if show_du {
-                        widths.push(7 + 2);
-                    }
-                    widths.pop();
-                    widths
-                };
-                let titles: Vec<OutputTableTitle> = {
-                    let mut titles =
-                        vec!["id", "status", "num_runs", "creation_timestamp", "last_use"];
-                    if show_commit {
-                        titles.push("commit_id");
-                    }
-                    if show_du {
-                        titles.push("du_GiB");
-                    }
-                    titles
-                        .into_iter()
-                        .map(|s| OutputTableTitle {
-                            text: Cow::Borrowed(s),
-                            span: 1,
-                            anchor_name: None,
-                        })
-                        .collect()
-                };
-
-                let table = if id_only {
-                    None
-                } else {
-                    let mut table =
-                        TerminalTable::new(&widths, terminal_table_opts, stdout().lock());
-                    table.write_title_row(&titles, None)?;
-                    Some(table)
+                let wd_list_opts = WdListOpts {
+                    active,
+                    error,
+                    numeric_sort,
+                    du_sort,
+                    show_commit: !no_commit,
+                    show_du: !no_du,
                 };
 
-                let all_ids: Vec<_> = {
-                    let mut all_entries: Vec<_> = working_directory_pool.all_entries().collect();
-                    if numeric_sort {
-                        // Leave as is, it's already sorted
-                    } else {
-                        all_entries.sort_by(|a, b| a.1.last_use.cmp(&b.1.last_use))
-                    }
-                    // *Must* let go of the &WorkingDirectory values
-                    // or we can't borrow the pool mutably below. Bummer.
-                    all_entries.into_iter().map(|(id, _)| id).collect()
-                };
-
-                let mut show_as_table = Vec::new();
-
-                for id in all_ids {
-                    let wd = working_directory_pool
-                        .get_working_directory(id)
-                        .expect("got it from all_entries");
-                    let WorkingDirectoryStatus {
-                        creation_timestamp,
-                        num_runs,
-                        status,
-                    } = &wd.working_directory_status;
-
-                    let show = match (active, error) {
-                        (true, true) | (false, false) => true,
-                        (true, false) => status.can_be_used_for_jobs(),
-                        (false, true) => !status.can_be_used_for_jobs(),
-                    };
-                    if show {
-                        if table.is_some() {
-                            show_as_table.push((
-                                vec![
-                                    id.to_string(),
-                                    status.to_string(),
-                                    num_runs.to_string(),
-                                    creation_timestamp.to_string(),
-                                    system_time_to_rfc3339(wd.last_use, None),
-                                ],
-                                wd.working_directory_path(),
-                                wd.commit.clone(),
-                            ));
-                        } else {
-                            println!("{id}");
-                        }
-                    }
-                }
-
-                if let Some(mut table) = table {
-                    let mut rows = show_as_table
-                        .into_par_iter()
-                        .map(|(mut row, wdp, opt_commit)| -> Result<_> {
-                            let get_commit = || -> Result<String> {
-                                let commit = if let Some(commit) = opt_commit {
-                                    info!("already had a commit! how comes?");
-                                    commit
-                                } else {
-                                    wdp.noncached_commit()?
-                                };
-                                Ok(commit.to_string())
-                            };
-                            let get_du = || -> Result<String> {
-                                let gdu = GetDirDiskUsage {
-                                    one_file_system: false,
-                                    share_globally: false,
-                                    shared_inodes: Default::default(),
-                                };
-                                let du = gdu.dir_disk_usage(wdp.clone().into(), 0)?;
-                                let shared_inodes = gdu.shared_inodes.lock().expect("no crash");
-                                let bytes = du.total(&shared_inodes);
-                                Ok(bytes_to_gib_string(bytes))
-                            };
-                            if show_commit && show_du {
-                                let (commit, du) = rayon::join(get_commit, get_du);
-                                row.push(commit?);
-                                row.push(du?);
-                            } else {
-                                if show_commit {
-                                    row.push(get_commit()?);
-                                }
-                                if show_du {
-                                    row.push(get_du()?);
-                                }
-                            }
-                            Ok(row)
-                        })
-                        .collect::<Result<Vec<Vec<String>>>>()?;
-
-                    if du_sort {
-                        // Sort the strings, this works thanks to the
-                        // adjusted formatting
-                        let mut col = 5;
-                        if show_commit {
-                            col += 1;
-                        }
-                        rows.sort_by(|a, b| a[col].cmp(&b[col]));
-                    }
-
-                    for row in rows {
-                        table.write_data_row(&row, None)?;
+                if id_only {
+                    for id in wd_list_opts.ids(&working_directory_pool) {
+                        println!("{id}");
                     }
-                    let _ = table.finish()?;
+                } else {
+                    let table = TerminalTable::new(
+                        &wd_list_opts.widths(),
+                        terminal_table_opts,
+                        stdout().lock(),
+                    );
+                    let _ = wd_list_opts.output_to_table(table, &working_directory_pool)?;
                 }
                 Ok(())
             }
@@ -786,7 +844,8 @@ impl Wd {
                 vars.push((check("COMMIT_ID"), commit_id_str.as_ref()));
                 vars.push((check("COMMIT_TAGS"), commit_tags.as_ref()));
 
-                let versioned_dataset_dir = VersionedDatasetDir::new();
+                let versioned_dataset_dir =
+                    VersionedDatasetDir::open(&shareable_config.global_app_state_dir)?;
                 let dataset_dir_;
                 if let Some(dataset_dir) = dataset_dir_for(
                     conf.versioned_datasets_base_dir.as_deref(),
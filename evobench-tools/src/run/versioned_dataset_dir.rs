@@ -13,6 +13,7 @@ use run_git::git::GitWorkingDir;
 use crate::{
     ctx, debug,
     git::{GitGraph, GitGraphData, GitHash},
+    run::global_app_state_dir::GlobalAppStateDir,
     serde_types::proper_filename::ProperFilename,
     warn,
 };
@@ -89,6 +90,14 @@ impl VersionedDatasetDir {
         }
     }
 
+    /// Using the commit graph cache file in `global_app_state_dir`,
+    /// which is updated with the new commits after each lookup.
+    pub fn open(global_app_state_dir: &GlobalAppStateDir) -> Result<Self> {
+        Ok(Self {
+            git_graph: GitGraph::open(global_app_state_dir.git_graph_cache_path())?,
+        })
+    }
+
     pub fn updated_git_graph<'s>(
         &'s self,
         git_working_dir: &'s GitWorkingDir,
@@ -104,6 +113,9 @@ impl VersionedDatasetDir {
             // XX should pass &GitHash instead
             &commit_id.to_string(),
         )?;
+        if let Err(e) = git_graph_data.save() {
+            warn!("could not save the git graph: {e:#}");
+        }
         Ok(VersionedDatasetDirLock {
             git_working_dir,
             commit_id,
@@ -0,0 +1,723 @@
+//! Receiver for push and pull request webhooks from GitHub or Gitea
+//! (`evobench webhook serve`), as a lower latency alternative to
+//! `evobench poll`.
+//!
+//! This is a minimal HTTP/1.1 server handling one request per
+//! connection, meant to run behind a reverse proxy (which does TLS).
+//! Requests are only acted upon if their HMAC-SHA256 signature
+//! (`X-Hub-Signature-256` or `X-Gitea-Signature` header) matches the
+//! configured secret. Pull requests from forks are only acted upon
+//! if enabled via `allow_fork_pull_requests`, as their code is run on
+//! the benchmarking host.
+
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, anyhow, bail};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    ctx,
+    git::GitHash,
+    info,
+    run::{
+        benchmarking_job::{BenchmarkingJobOpts, BenchmarkingJobReasonOpt},
+        config::ShareableConfig,
+        insert_jobs::{DryRunOpt, ForceOpt, QuietOpt, insert_jobs},
+        polled_heads::PolledHeads,
+        polling_pool::PolledName,
+        run_queues::RunQueues,
+        sub_command::{insert::InsertBenchmarkingJobOpts, open_polling_pool},
+    },
+    serde_types::{git_branch_name::GitBranchName, tilde_path::TildePath},
+    warn,
+};
+
+/// Requests with larger bodies are refused
+const MAX_BODY_SIZE: usize = 20_000_000;
+
+/// Requests with longer request or header lines are refused
+const MAX_LINE_LEN: usize = 8192;
+
+/// Requests with more header lines are refused
+const MAX_HEADERS: usize = 100;
+
+/// Timeout for reading a request or writing the response
+const IO_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// How often to check for new connections (and whether to exit)
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(rename = "Webhook")]
+pub struct WebhookOpts {
+    /// The address to listen on, e.g. `"127.0.0.1:8095"`
+    pub listen: SocketAddr,
+
+    /// Path to a file holding the secret configured for the webhook
+    /// on the forge (trailing whitespace is ignored). Supports `~/`.
+    pub secret_file: TildePath<PathBuf>,
+
+    /// Whether to insert jobs for pull requests from forks (default:
+    /// false). Anyone who can open a pull request can then have their
+    /// code run on the benchmarking host.
+    pub allow_fork_pull_requests: Option<bool>,
+}
+
+/// HMAC-SHA256 as per RFC 2104
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    const BLOCK_SIZE: usize = 64;
+    let mut key_block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        key_block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        key_block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(key_block.map(|b| b ^ 0x36));
+    inner.update(message);
+    let inner_hash = inner.finalize();
+    let mut outer = Sha256::new();
+    outer.update(key_block.map(|b| b ^ 0x5c));
+    outer.update(inner_hash);
+    outer.finalize().into()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Comparison that takes the same time wherever the first difference is
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// An HTTP request, with lower-cased header names
+#[derive(Debug)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: BTreeMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(|s| s.as_str())
+    }
+
+    /// Check the signature given by GitHub (`X-Hub-Signature-256:
+    /// sha256=$hex`) or Gitea (`X-Gitea-Signature: $hex`)
+    pub fn verify_signature(&self, secret: &[u8]) -> Result<()> {
+        let given = if let Some(s) = self.header("x-hub-signature-256") {
+            s.strip_prefix("sha256=")
+                .ok_or_else(|| anyhow!("X-Hub-Signature-256 header does not start with sha256="))?
+        } else if let Some(s) = self.header("x-gitea-signature") {
+            s
+        } else {
+            bail!("request is not signed")
+        };
+        let expected = to_hex(&hmac_sha256(secret, &self.body));
+        if constant_time_eq(
+            given.trim().to_ascii_lowercase().as_bytes(),
+            expected.as_bytes(),
+        ) {
+            Ok(())
+        } else {
+            bail!("invalid signature")
+        }
+    }
+}
+
+/// Read a line (including the line ending) into `line`, refusing
+/// lines longer than `MAX_LINE_LEN`. Returns 0 at the end of the
+/// input.
+fn read_line_limited(reader: &mut impl BufRead, line: &mut String) -> Result<usize> {
+    line.clear();
+    let n = reader.by_ref().take(MAX_LINE_LEN as u64).read_line(line)?;
+    if n == MAX_LINE_LEN && !line.ends_with('\n') {
+        bail!("request or header line is longer than {MAX_LINE_LEN} bytes")
+    }
+    Ok(n)
+}
+
+pub(crate) fn read_request(stream: &mut impl Read) -> Result<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    read_line_limited(&mut reader, &mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        bail!("invalid request line {line:?}")
+    };
+    let (method, path) = (method.to_owned(), path.to_owned());
+
+    let mut headers = BTreeMap::new();
+    let mut num_headers = 0;
+    loop {
+        if read_line_limited(&mut reader, &mut line)? == 0 {
+            bail!("connection closed while reading headers")
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        num_headers += 1;
+        if num_headers > MAX_HEADERS {
+            bail!("request has more than {MAX_HEADERS} headers")
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| anyhow!("invalid header line {line:?}"))?;
+        headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_owned());
+    }
+
+    let content_length: usize = if let Some(s) = headers.get("content-length") {
+        s.parse()
+            .map_err(|e| anyhow!("invalid Content-Length {s:?}: {e}"))?
+    } else {
+        0
+    };
+    if content_length > MAX_BODY_SIZE {
+        bail!("request body of {content_length} bytes is too large")
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    Ok(Request {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
+
+fn write_response(stream: &mut TcpStream, status: u16, message: &str) -> Result<()> {
+    write_response_with_content_type(
+        stream,
+        status,
+        "text/plain; charset=utf-8",
+        format!("{message}\n").as_bytes(),
+    )
+}
+
+pub(crate) fn write_response_with_content_type(
+    stream: &mut TcpStream,
+    status: u16,
+    content_type: &str,
+    body: &[u8],
+) -> Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status} {reason}\r\n\
+         Content-Type: {content_type}\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()?;
+    Ok(())
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct PushPayload {
+    #[serde(rename = "ref")]
+    git_ref: String,
+    after: String,
+    #[serde(default)]
+    deleted: bool,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct PullRequestPayload {
+    action: String,
+    number: u64,
+    pull_request: PullRequestPayloadPr,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct PullRequestPayloadPr {
+    head: PullRequestPayloadHead,
+    base: PullRequestPayloadBase,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct PullRequestPayloadHead {
+    #[serde(rename = "ref")]
+    branch: String,
+    sha: String,
+    /// `owner:branch`
+    label: String,
+    /// Null on GitHub if the fork has been deleted
+    #[serde(default)]
+    repo: Option<PayloadRepo>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct PullRequestPayloadBase {
+    #[serde(rename = "ref")]
+    branch: String,
+    repo: PayloadRepo,
+}
+
+#[derive(Debug, PartialEq, serde::Deserialize)]
+struct PayloadRepo {
+    full_name: String,
+}
+
+/// The parts of a webhook request that are of interest
+#[derive(Debug, PartialEq)]
+pub enum WebhookEvent {
+    /// A push to a branch
+    Push {
+        branch: GitBranchName,
+        head: GitHash,
+    },
+    /// A pull request that was opened or updated
+    PullRequest {
+        number: u64,
+        /// The branch whose job templates are used: the head branch
+        /// for pull requests within the repository, the base branch
+        /// for pull requests from forks (the names of their branches
+        /// mean nothing in the repository)
+        branch: GitBranchName,
+        /// The `owner:branch` label of the head, for pull requests
+        /// from forks
+        fork_label: Option<String>,
+        head: GitHash,
+    },
+    /// Something we don't act on, with the reason
+    Ignored(String),
+}
+
+impl WebhookEvent {
+    /// Parse a request with the `X-GitHub-Event` or `X-Gitea-Event`
+    /// header and a JSON body.
+    pub fn from_request(request: &Request) -> Result<Self> {
+        let event = request
+            .header("x-github-event")
+            .or_else(|| request.header("x-gitea-event"))
+            .ok_or_else(|| anyhow!("missing X-GitHub-Event or X-Gitea-Event header"))?;
+        match event {
+            "push" => {
+                let PushPayload {
+                    git_ref,
+                    after,
+                    deleted,
+                } = serde_json::from_slice(&request.body).map_err(ctx!("parsing push payload"))?;
+                let Some(branch) = git_ref.strip_prefix("refs/heads/") else {
+                    return Ok(Self::Ignored(format!("push to non-branch {git_ref:?}")));
+                };
+                let head = GitHash::from_str(&after)?;
+                if deleted || head.to_string().bytes().all(|b| b == b'0') {
+                    return Ok(Self::Ignored(format!("deletion of branch {branch:?}")));
+                }
+                Ok(Self::Push {
+                    branch: branch.parse()?,
+                    head,
+                })
+            }
+            "pull_request" => {
+                let PullRequestPayload {
+                    action,
+                    number,
+                    pull_request,
+                } = serde_json::from_slice(&request.body)
+                    .map_err(ctx!("parsing pull_request payload"))?;
+                // "synchronized" is Gitea's spelling
+                match action.as_str() {
+                    "opened" | "reopened" | "synchronize" | "synchronized" => (),
+                    _ => return Ok(Self::Ignored(format!("pull request action {action:?}"))),
+                }
+                let PullRequestPayloadPr { head, base } = pull_request;
+                let PullRequestPayloadHead {
+                    branch,
+                    sha,
+                    label,
+                    repo,
+                } = head;
+                let (branch, fork_label) = if repo.as_ref() == Some(&base.repo) {
+                    (branch, None)
+                } else {
+                    (base.branch, Some(label))
+                };
+                Ok(Self::PullRequest {
+                    number,
+                    branch: branch.parse()?,
+                    fork_label,
+                    head: GitHash::from_str(&sha)?,
+                })
+            }
+            _ => Ok(Self::Ignored(format!("event {event:?}"))),
+        }
+    }
+
+    /// Turn pull requests from forks into `Ignored` unless
+    /// `allow_fork_pull_requests` is true
+    pub fn check_fork_allowed(self, allow_fork_pull_requests: bool) -> Self {
+        match self {
+            Self::PullRequest {
+                number,
+                fork_label: Some(fork_label),
+                ..
+            } if !allow_fork_pull_requests => Self::Ignored(format!(
+                "pull request #{number} from fork {fork_label:?} \
+                 (`allow_fork_pull_requests` is not enabled)"
+            )),
+            event => event,
+        }
+    }
+}
+
+pub struct WebhookServer {
+    listener: TcpListener,
+    secret: Vec<u8>,
+    allow_fork_pull_requests: bool,
+}
+
+impl WebhookServer {
+    pub fn bind(opts: &WebhookOpts) -> Result<Self> {
+        let WebhookOpts {
+            listen,
+            secret_file,
+            allow_fork_pull_requests,
+        } = opts;
+        let secret_path = secret_file.resolve()?;
+        let secret = std::fs::read_to_string(&secret_path)
+            .map_err(ctx!("reading webhook secret file {secret_path:?}"))?;
+        let secret = secret.trim_end();
+        if secret.is_empty() {
+            bail!("webhook secret file {secret_path:?} is empty")
+        }
+        let listener = TcpListener::bind(listen).map_err(ctx!("listening on {listen}"))?;
+        listener.set_nonblocking(true)?;
+        info!("webhook server listening on {listen}");
+        Ok(Self {
+            listener,
+            secret: secret.as_bytes().to_owned(),
+            allow_fork_pull_requests: allow_fork_pull_requests.unwrap_or(false),
+        })
+    }
+
+    /// Wait up to `timeout` for a request and handle it. Returns
+    /// `None` if no request came in, otherwise the number of jobs
+    /// inserted. Errors while handling a request are reported to the
+    /// client (and logged), not returned.
+    pub fn handle_next_request(
+        &self,
+        config: &ShareableConfig,
+        queues: &RunQueues,
+        dry_run_opt: &DryRunOpt,
+        timeout: Duration,
+    ) -> Result<Option<usize>> {
+        let start = Instant::now();
+        let (mut stream, peer) = loop {
+            match self.listener.accept() {
+                Ok(v) => break v,
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    if start.elapsed() >= timeout {
+                        return Ok(None);
+                    }
+                    std::thread::sleep(ACCEPT_POLL_INTERVAL);
+                }
+                Err(e) => bail!("accepting webhook connection: {e}"),
+            }
+        };
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+        let (status, message, num_inserted) =
+            match self.handle_request(&mut stream, config, queues, dry_run_opt) {
+                Ok(v) => v,
+                Err(e) => (500, format!("{e:#}"), 0),
+            };
+        if status == 200 {
+            info!("webhook request from {peer}: {message}");
+        } else {
+            warn!("webhook request from {peer}: {status} {message}");
+        }
+        if let Err(e) = write_response(&mut stream, status, &message) {
+            warn!("writing response to {peer}: {e:#}");
+        }
+        Ok(Some(num_inserted))
+    }
+
+    /// Returns the HTTP status, message and number of jobs inserted
+    fn handle_request(
+        &self,
+        stream: &mut TcpStream,
+        config: &ShareableConfig,
+        queues: &RunQueues,
+        dry_run_opt: &DryRunOpt,
+    ) -> Result<(u16, String, usize)> {
+        let request = match read_request(stream) {
+            Ok(request) => request,
+            Err(e) => return Ok((400, format!("{e:#}"), 0)),
+        };
+        if request.method != "POST" {
+            return Ok((405, format!("method {} not allowed", request.method), 0));
+        }
+        if let Err(e) = request.verify_signature(&self.secret) {
+            return Ok((401, format!("{e:#}"), 0));
+        }
+        let event = match WebhookEvent::from_request(&request) {
+            Ok(event) => event.check_fork_allowed(self.allow_fork_pull_requests),
+            Err(e) => return Ok((400, format!("{e:#}"), 0)),
+        };
+        let n = insert_jobs_for_event(&event, config, queues, dry_run_opt)?;
+        Ok((200, format!("{event:?}: inserted {n} jobs"), n))
+    }
+}
+
+/// Insert the jobs for `event`, using the job templates of the
+/// branch (as for `evobench poll`). For pushes, all new commits since
+/// the last seen head of the branch are taken (and the head updated),
+/// as for `evobench poll`. For pull requests, the head commit is
+/// fetched via the pull request's ref (see
+/// `PollingPool::fetch_pull_request`). Returns the number of jobs
+/// inserted.
+pub fn insert_jobs_for_event(
+    event: &WebhookEvent,
+    config: &ShareableConfig,
+    queues: &RunQueues,
+    dry_run_opt: &DryRunOpt,
+) -> Result<usize> {
+    let (branch, head, reason) = match event {
+        WebhookEvent::Push { branch, head } => (branch, head, branch.to_string()),
+        WebhookEvent::PullRequest {
+            number,
+            branch,
+            fork_label,
+            head,
+        } => {
+            let reason = if let Some(fork_label) = fork_label {
+                format!("PR #{number} ({fork_label})")
+            } else {
+                format!("PR #{number} ({branch})")
+            };
+            (branch, head, reason)
+        }
+        WebhookEvent::Ignored(_) => return Ok(0),
+    };
+    let conf = &config.run_config;
+    let Some(job_templates) = conf.remote_repository.job_templates_for_branch(branch) else {
+        info!("branch {branch} is not configured for polling, ignoring");
+        return Ok(0);
+    };
+
+    let polled_heads = PolledHeads::open(&config.global_app_state_dir)?;
+    let polled_name = PolledName::Branch(branch.clone());
+    let commits = {
+        let mut polling_pool = open_polling_pool(config)?;
+        let working_directory_id = polling_pool.updated_working_dir()?;
+        match event {
+            WebhookEvent::Push { .. } => polled_heads.new_commits(
+                &mut polling_pool,
+                working_directory_id,
+                &polled_name,
+                None,
+                head,
+                &conf.remote_repository.commit_range,
+            )?,
+            WebhookEvent::PullRequest { number, .. } => {
+                polling_pool.fetch_pull_request(working_directory_id, *number, head)?;
+                vec![head.clone()]
+            }
+            WebhookEvent::Ignored(_) => unreachable!("returned above"),
+        }
+    };
+
+    let mut benchmarking_jobs = Vec::new();
+    for commit_id in commits {
+        let opts = BenchmarkingJobOpts {
+            insert_benchmarking_job_opts: InsertBenchmarkingJobOpts {
+                reason: BenchmarkingJobReasonOpt {
+                    reason: Some(reason.clone()),
+                },
+                benchmarking_job_settings: (*conf.benchmarking_job_settings).clone(),
+                priority: None,
+                initial_boost: None,
+            },
+            commit_id,
+        };
+        benchmarking_jobs.append(&mut opts.complete_jobs(job_templates));
+    }
+
+    let n = insert_jobs(
+        benchmarking_jobs,
+        config,
+        dry_run_opt.clone(),
+        ForceOpt { force: false },
+        QuietOpt { quiet: true },
+        queues,
+    )?;
+
+    if let WebhookEvent::Push { .. } = event {
+        if !dry_run_opt.dry_run {
+            polled_heads.set(&polled_name, head)?;
+        }
+    }
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"test-secret";
+
+    fn request(event: &str, body: &str) -> Request {
+        let mut headers = BTreeMap::new();
+        headers.insert("x-github-event".into(), event.into());
+        headers.insert(
+            "x-hub-signature-256".into(),
+            format!("sha256={}", to_hex(&hmac_sha256(SECRET, body.as_bytes()))),
+        );
+        Request {
+            method: "POST".into(),
+            path: "/".into(),
+            headers,
+            body: body.as_bytes().to_owned(),
+        }
+    }
+
+    #[test]
+    fn t_hmac_sha256() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            to_hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        // RFC 4231, test case 6 (key longer than the block size)
+        assert_eq!(
+            to_hex(&hmac_sha256(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            )),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[test]
+    fn t_fixtures() -> Result<()> {
+        let push = request(
+            "push",
+            include_str!("../../test-data/webhook/github-push.json"),
+        );
+        push.verify_signature(SECRET)?;
+        assert!(push.verify_signature(b"wrong").is_err());
+        assert_eq!(
+            WebhookEvent::from_request(&push)?,
+            WebhookEvent::Push {
+                branch: "bench/faster-parser".parse()?,
+                head: "5e9c0c5a0f0e1f3fbd1c2a1e8d9c0b7a6f5e4d3c".parse()?,
+            }
+        );
+
+        let pr = request(
+            "pull_request",
+            include_str!("../../test-data/webhook/github-pull_request.json"),
+        );
+        assert_eq!(
+            WebhookEvent::from_request(&pr)?,
+            WebhookEvent::PullRequest {
+                number: 42,
+                branch: "bench/faster-parser".parse()?,
+                fork_label: None,
+                head: "5e9c0c5a0f0e1f3fbd1c2a1e8d9c0b7a6f5e4d3c".parse()?,
+            }
+        );
+
+        // From a fork: the base branch is used, not the fork's
+        // branch name
+        let fork_pr = request(
+            "pull_request",
+            include_str!("../../test-data/webhook/github-pull_request-fork.json"),
+        );
+        assert_eq!(
+            WebhookEvent::from_request(&fork_pr)?,
+            WebhookEvent::PullRequest {
+                number: 43,
+                branch: "main".parse()?,
+                fork_label: Some("contributor:patch-1".into()),
+                head: "7f6e5d4c3b2a19080706050403020100fedcba98".parse()?,
+            }
+        );
+
+        let mut gitea = request(
+            "push",
+            include_str!("../../test-data/webhook/gitea-push.json"),
+        );
+        let signature = gitea.headers.remove("x-hub-signature-256").unwrap();
+        gitea.headers.insert(
+            "x-gitea-signature".into(),
+            signature.strip_prefix("sha256=").unwrap().into(),
+        );
+        gitea.verify_signature(SECRET)?;
+        assert_eq!(
+            WebhookEvent::from_request(&gitea)?,
+            WebhookEvent::Push {
+                branch: "main".parse()?,
+                head: "0b1c2d3e4f5a6b7c8d9e0f1a2b3c4d5e6f7a8b9c".parse()?,
+            }
+        );
+
+        assert!(matches!(
+            WebhookEvent::from_request(&fork_pr)?.check_fork_allowed(false),
+            WebhookEvent::Ignored(_)
+        ));
+        assert_eq!(
+            WebhookEvent::from_request(&fork_pr)?.check_fork_allowed(true),
+            WebhookEvent::from_request(&fork_pr)?
+        );
+        assert_eq!(
+            WebhookEvent::from_request(&pr)?.check_fork_allowed(false),
+            WebhookEvent::from_request(&pr)?
+        );
+
+        let ping = request("ping", "{}");
+        assert!(matches!(
+            WebhookEvent::from_request(&ping)?,
+            WebhookEvent::Ignored(_)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn t_read_request() -> Result<()> {
+        let request = read_request(
+            &mut &b"POST /hook HTTP/1.1\r\nContent-Length: 2\r\nX-GitHub-Event: push\r\n\r\n{}"[..],
+        )?;
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/hook");
+        assert_eq!(request.header("x-github-event"), Some("push"));
+        assert_eq!(request.body, b"{}");
+
+        let long_line = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_LINE_LEN));
+        let e = read_request(&mut long_line.as_bytes()).unwrap_err();
+        assert!(format!("{e:#}").contains("longer than"), "{e:#}");
+
+        let many_headers = format!(
+            "GET / HTTP/1.1\r\n{}\r\n",
+            "X-A: b\r\n".repeat(MAX_HEADERS + 1)
+        );
+        assert!(read_request(&mut many_headers.as_bytes()).is_err());
+        Ok(())
+    }
+}
@@ -13,6 +13,7 @@ use std::{
 
 use anyhow::{Result, anyhow, bail};
 use chj_unix_util::polling_signals::PollingSignalsSender;
+use cj_path_util::path_util::AppendToPath;
 use derive_more::From;
 use run_git::{
     git::{GitResetMode, GitWorkingDir, git_clone},
@@ -26,8 +27,11 @@ use crate::{
     git::GitHash,
     git_ext::MoreGitWorkingDir,
     info,
-    run::working_directory_pool::{
-        WorkingDirectoryId, WorkingDirectoryPoolGuard, WorkingDirectoryPoolGuardMut,
+    run::{
+        git_mirror::GitMirror,
+        working_directory_pool::{
+            WorkingDirectoryId, WorkingDirectoryPoolGuard, WorkingDirectoryPoolGuardMut,
+        },
     },
     serde_types::{date_and_time::DateTimeWithOffset, git_url::GitUrl},
     utillib::arc::CloneArc,
@@ -59,6 +63,36 @@ pub struct WorkingDirectoryAutoCleanOpts {
     pub wait_until_commit_done: bool,
 }
 
+/// Per-repository options for preparing the working tree after
+/// checking out a commit
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+#[serde(rename = "Checkout")]
+pub struct CheckoutOpts {
+    /// If true, recursively initialize and update the Git submodules
+    /// after each checkout. When the working directory pool has
+    /// `shared_objects` enabled, the submodules are mirrored in the
+    /// pool's mirror, too, and the working directories use those
+    /// mirrors' objects. Default: false.
+    pub submodules: Option<bool>,
+
+    /// If true, run `git lfs fetch` and `git lfs checkout` after each
+    /// checkout (and submodule update), to replace the Git LFS
+    /// pointer files with their contents. Requires `git-lfs` to be
+    /// installed. Default: false.
+    pub lfs: Option<bool>,
+}
+
+/// An error while checking out a commit (including fetching, and
+/// updating submodules and LFS files), as opposed to an error while
+/// running the benchmark.
+#[derive(Debug, thiserror::Error)]
+#[error("checkout of commit {commit} failed: {error:#}")]
+pub struct CheckoutError {
+    pub commit: GitHash,
+    pub error: anyhow::Error,
+}
+
 const NO_OPTIONS: &[&str] = &[];
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
@@ -98,6 +132,14 @@ impl Status {
 
     pub const MAX_STR_LEN: usize = 11;
 
+    pub const ALL: [Status; 5] = [
+        Status::CheckedOut,
+        Status::Processing,
+        Status::Error,
+        Status::Finished,
+        Status::Examination,
+    ];
+
     pub fn as_str(self) -> &'static str {
         match self {
             Status::CheckedOut => "checked-out",
@@ -250,6 +292,8 @@ pub struct WorkingDirectory {
     // To signal changes in working directory status files. Note the
     // warning in WorkingDirectoryPool!
     signal_change: Option<PollingSignalsSender>,
+    /// If the pool has a mirror, fetching is done via the mirror
+    mirror: Option<Arc<GitMirror>>,
 }
 
 pub struct WorkingDirectoryWithPoolLock<'guard> {
@@ -353,6 +397,7 @@ impl WorkingDirectory {
     pub fn open<'pool>(
         path: PathBuf,
         url: &GitUrl,
+        mirror: Option<Arc<GitMirror>>,
         guard: &WorkingDirectoryPoolGuard<'pool>,
         omit_check: bool,
         signal_change: Option<PollingSignalsSender>,
@@ -413,6 +458,7 @@ impl WorkingDirectory {
             working_directory_status_needs_saving,
             last_use: mtime,
             signal_change,
+            mirror,
         };
         let mut slf_lck = guard.locked_working_directory_mut(&mut slf);
         // XX chaos: Do not change the status if it already
@@ -421,15 +467,22 @@ impl WorkingDirectory {
         Ok(slf)
     }
 
+    /// Clone `url`, or if `mirror` is given, the mirror (see
+    /// `GitMirror::clone_with_reference`).
     pub fn clone_repo<'pool>(
         base_dir: &Path,
         dir_file_name: &str,
         url: &GitUrl,
+        mirror: Option<Arc<GitMirror>>,
         guard: &WorkingDirectoryPoolGuard<'pool>,
         signal_change: Option<PollingSignalsSender>,
     ) -> Result<Self> {
         let quiet = false;
-        let git_working_dir = git_clone(&base_dir, [], url.as_str(), dir_file_name, quiet)?;
+        let git_working_dir = if let Some(mirror) = &mirror {
+            mirror.clone_with_reference(base_dir, dir_file_name, quiet)?
+        } else {
+            git_clone(&base_dir, [], url.as_str(), dir_file_name, quiet)?
+        };
         let commit: GitHash = git_working_dir.get_head_commit_id()?.parse()?;
         let status = WorkingDirectoryStatus::new();
         let mtime = status.creation_timestamp.to_systemtime();
@@ -441,6 +494,7 @@ impl WorkingDirectory {
             working_directory_status_needs_saving: true,
             last_use: mtime,
             signal_change,
+            mirror,
         };
         let mut slf_lck = guard.locked_working_directory_mut(&mut slf);
         slf_lck.set_and_save_status(Status::CheckedOut)?;
@@ -496,10 +550,20 @@ impl WorkingDirectory {
 
     /// Unconditionally run `git fetch --tags` in the working dir. Is
     /// called by `checkout` as needed. If `commit_id` is given, it is
-    /// fetched explicitly
+    /// fetched explicitly. If the pool has a mirror, the mirror is
+    /// updated and fetched from instead.
     pub fn fetch(&self, commit_id: Option<&GitHash>) -> Result<FetchedTags> {
         let git_working_dir = &self.git_working_dir;
 
+        if let Some(mirror) = &self.mirror {
+            mirror.fetch_into(git_working_dir, commit_id)?;
+            info!(
+                "checkout({:?}, {commit_id:?}): fetched via mirror",
+                git_working_dir.working_dir_path_ref()
+            );
+            return Ok(FetchedTags::Yes);
+        }
+
         // Fetching tags in case `dataset_dir_for_commit` is
         // used.
         let fetch_all_tags = true;
@@ -554,8 +618,25 @@ impl<'guard> WorkingDirectoryWithPoolLockMut<'guard> {
         }
     }
 
-    /// Checks and is a no-op if already on the commit.
-    pub fn checkout(&mut self, commit: GitHash, fetch_tags: FetchTags) -> Result<FetchedTags> {
+    /// Checks and is a no-op if already on the commit, except for
+    /// updating submodules and LFS files if enabled in
+    /// `checkout_opts`. Errors are wrapped as `CheckoutError`.
+    pub fn checkout(
+        &mut self,
+        commit: GitHash,
+        fetch_tags: FetchTags,
+        checkout_opts: &CheckoutOpts,
+    ) -> Result<FetchedTags> {
+        self.try_checkout(commit.clone(), fetch_tags, checkout_opts)
+            .map_err(|error| CheckoutError { commit, error }.into())
+    }
+
+    fn try_checkout(
+        &mut self,
+        commit: GitHash,
+        fetch_tags: FetchTags,
+        checkout_opts: &CheckoutOpts,
+    ) -> Result<FetchedTags> {
         let commit_str = commit.to_string();
         let quiet = false;
         let current_commit = self.wd.git_working_dir.get_head_commit_id()?;
@@ -595,9 +676,69 @@ impl<'guard> WorkingDirectoryWithPoolLockMut<'guard> {
             self.wd.commit = Some(commit);
             self.set_and_save_status(Status::CheckedOut)?;
         }
+
+        let CheckoutOpts { submodules, lfs } = checkout_opts;
+        if submodules.unwrap_or(false) {
+            self.update_submodules()?;
+        }
+        if lfs.unwrap_or(false) {
+            self.update_lfs_files()?;
+        }
         Ok(ran_fetch)
     }
 
+    /// Run `git $args` in the working directory, failing on non-zero
+    /// exit status
+    fn run_git(&self, args: &[&str]) -> Result<()> {
+        let git_working_dir = &self.wd.git_working_dir;
+        if !git_working_dir.git(args, false)? {
+            bail!(
+                "git {args:?} in {:?} failed",
+                git_working_dir.working_dir_path_ref()
+            )
+        }
+        Ok(())
+    }
+
+    /// Recursively initialize and update the submodules to the
+    /// commits recorded in the checked-out commit, discarding local
+    /// changes in them. Via the mirror if present.
+    fn update_submodules(&self) -> Result<()> {
+        let git_working_dir = &self.wd.git_working_dir;
+        let gitmodules_path = git_working_dir.working_dir_path_ref().append(".gitmodules");
+        if !std::fs::exists(&gitmodules_path).map_err(ctx!("checking path {gitmodules_path:?}"))? {
+            return Ok(());
+        }
+
+        // Pick up url changes in `.gitmodules`, and register new
+        // submodules (resolving relative urls)
+        self.run_git(&["submodule", "sync", "--recursive"])?;
+        self.run_git(&["submodule", "init"])?;
+
+        if let Some(mirror) = &self.wd.mirror {
+            mirror.update_submodules_of(git_working_dir)?;
+        } else {
+            self.run_git(&["submodule", "update", "--init", "--recursive", "--force"])?;
+        }
+        info!(
+            "checkout({:?}): updated submodules",
+            git_working_dir.working_dir_path_ref()
+        );
+        Ok(())
+    }
+
+    /// Fetch the Git LFS files for the checked-out commit and replace
+    /// the pointer files with them
+    fn update_lfs_files(&self) -> Result<()> {
+        self.run_git(&["lfs", "fetch", REMOTE_NAME, "HEAD"])?;
+        self.run_git(&["lfs", "checkout"])?;
+        info!(
+            "checkout({:?}): fetched and checked out LFS files",
+            self.wd.git_working_dir.working_dir_path_ref()
+        );
+        Ok(())
+    }
+
     /// Set status to `status`. Also increments the run count if the
     /// status changed to Status::Processing, and (re-)saves
     /// `$n.status` file if needed.
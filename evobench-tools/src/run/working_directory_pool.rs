@@ -31,6 +31,7 @@ use crate::{
     info, io_utils,
     io_utils::owning_lockable_file::{OwningExclusiveFileLock, OwningLockableFile},
     run::{
+        git_mirror::GitMirror,
         key::{BenchmarkingJobParameters, RunParameters},
         working_directory::{
             WorkingDirectoryAutoCleanOpts, WorkingDirectoryPath, WorkingDirectoryWithPoolLock,
@@ -43,7 +44,7 @@ use crate::{
 
 use super::{
     run_queues::RunQueuesData,
-    working_directory::{Status, WorkingDirectory, WorkingDirectoryStatus},
+    working_directory::{CheckoutError, Status, WorkingDirectory, WorkingDirectoryStatus},
 };
 
 /// For `RunConfigOpts` configuration file.  `remote_repository_url`
@@ -69,6 +70,17 @@ pub struct WorkingDirectoryPoolOpts {
     /// deletion by the runner with no involvement of the target
     /// project.
     pub auto_clean: Option<WorkingDirectoryAutoCleanOpts>,
+
+    /// If true, keep a bare mirror of the remote repository in the
+    /// base dir (`mirror.git`), holding the Git objects shared by
+    /// the working directories: those are created as clones with
+    /// `--reference` to the mirror, and fetching is done on the
+    /// mirror once, then from the mirror into the working
+    /// directories. Existing working directories continue to work
+    /// (but only new ones share the objects). Do not delete the
+    /// mirror while there are working directories created with it,
+    /// even after disabling this option. Default: false.
+    pub shared_objects: Option<bool>,
 }
 
 /// Completed options, and WorkingDirectoryPoolBaseDir even has an
@@ -81,6 +93,9 @@ pub struct WorkingDirectoryPoolContext {
     pub auto_clean: Option<WorkingDirectoryAutoCleanOpts>,
     pub remote_repository_url: GitUrl,
     pub base_dir: Arc<WorkingDirectoryPoolBaseDir>,
+    /// Whether to use a mirror, see
+    /// `WorkingDirectoryPoolOpts.shared_objects`
+    pub shared_objects: bool,
     /// Where to signal changes with the "current" link or working
     /// directory status files. (Do *not* pass the
     /// `working_directory_change_signals` here, those are for
@@ -319,6 +334,8 @@ struct WorkingDirectoryPoolState {
 pub struct WorkingDirectoryPool {
     /// Immutable, but contains an open lockable file handle
     context: WorkingDirectoryPoolContext,
+    /// Present if `context.shared_objects` is true
+    mirror: Option<Arc<GitMirror>>,
     /// The mutable state
     state: WorkingDirectoryPoolState,
 }
@@ -403,9 +420,30 @@ pub struct ProcessingError {
     /// things that are not benchmark runs
     benchmarking_job_parameters: Option<BenchmarkingJobParameters>,
     context: String,
+    phase: ProcessingPhase,
     error: String,
 }
 
+/// In which phase of the processing an error happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ProcessingPhase {
+    /// Checking out the commit, including fetching, and updating
+    /// submodules and LFS files (see `CheckoutError`)
+    Checkout,
+    /// Running the action, e.g. the benchmark
+    Action,
+}
+
+impl ProcessingPhase {
+    pub fn of_error(error: &anyhow::Error) -> Self {
+        if error.downcast_ref::<CheckoutError>().is_some() {
+            ProcessingPhase::Checkout
+        } else {
+            ProcessingPhase::Action
+        }
+    }
+}
+
 impl WorkingDirectoryPool {
     /// Get exclusive lock, but sharing self
     pub fn lock<'t>(&'t self, locker: &str) -> Result<WorkingDirectoryPoolGuard<'t>> {
@@ -437,6 +475,15 @@ impl WorkingDirectoryPool {
         // files
         let lock = context.base_dir.get_lock("WorkingDirectoryPool::open")?;
 
+        let mirror = if context.shared_objects {
+            Some(Arc::new(GitMirror::new(
+                context.base_dir.path().into(),
+                context.remote_repository_url.clone(),
+            )))
+        } else {
+            None
+        };
+
         let mut next_id: u64 = 0;
 
         // To tell WorkingDirectory::open that we do have the lock we
@@ -500,6 +547,7 @@ impl WorkingDirectoryPool {
                     let wd = WorkingDirectory::open(
                         path,
                         &context.remote_repository_url,
+                        mirror.clone(),
                         &guard,
                         omit_check,
                         context.signal_change.clone(),
@@ -519,6 +567,7 @@ impl WorkingDirectoryPool {
 
         let slf = WorkingDirectoryPool {
             context,
+            mirror,
             state: WorkingDirectoryPoolState {
                 next_id,
                 all_entries,
@@ -708,6 +757,7 @@ impl WorkingDirectoryPool {
                     ProcessingError {
                         benchmarking_job_parameters: benchmarking_job_parameters.cloned(),
                         context: context.to_string(),
+                        phase: ProcessingPhase::of_error(&error),
                         error: err.clone(),
                     },
                     timestamp,
@@ -718,8 +768,9 @@ impl WorkingDirectoryPool {
                     // Do not show error as it might be large; XX
                     // which is a mis-feature!
                     "process_working_directory {working_directory_id} \
-                     ({:?} for {context} at_{timestamp}) failed.",
-                    benchmarking_job_parameters.map(BenchmarkingJobParameters::slow_hash)
+                     ({:?} for {context} at_{timestamp}) failed in {:?} phase.",
+                    benchmarking_job_parameters.map(BenchmarkingJobParameters::slow_hash),
+                    ProcessingPhase::of_error(&error)
                 );
 
                 Err(error)
@@ -801,6 +852,7 @@ impl<'pool> WorkingDirectoryPoolGuardMut<'pool> {
             self.pool.base_dir().path(),
             &id.to_directory_file_name(),
             self.pool.git_url(),
+            self.pool.mirror.clone(),
             &self.shared(),
             self.pool.context.signal_change.clone(),
         )?;
@@ -39,6 +39,10 @@ fn decode_hex<const N: usize>(input: &[u8], output: &mut [u8; N]) -> Result<()>
 pub struct GitHash([u8; 20]);
 
 impl GitHash {
+    pub fn as_bytes(&self) -> &[u8; 20] {
+        &self.0
+    }
+
     pub fn to_reference(&self) -> GitReference {
         self.to_string()
             .parse()
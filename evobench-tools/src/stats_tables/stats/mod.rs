@@ -15,6 +15,7 @@ use fixed::FixedU128;
 use fixed::traits::LossyFrom;
 use fixed::types::extra::U32;
 use num_traits::Zero;
+use serde::{Deserialize, Serialize};
 
 use super::{
     stats::{
@@ -143,6 +144,21 @@ pub struct Stats<ViewType, const TILE_COUNT: usize> {
     pub tiles: Vec<u64>,
 }
 
+/// The contents of a `Stats` in serializable form (without the
+/// `ViewType`, which the user has to know), for caching.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StatsData {
+    pub num_values: usize,
+    pub sum: u128,
+    /// The bits of the `FixedU128<U32>`
+    pub average: u128,
+    pub median: u64,
+    /// The bits of the `FixedU128<U32>`
+    pub variance: u128,
+    pub tiles: Vec<u64>,
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum StatsError {
     #[error("no inputs given")]
@@ -220,6 +236,51 @@ impl<ViewType, const TILE_COUNT: usize> Stats<ViewType, TILE_COUNT> {
         }
     }
 
+    pub fn to_data(&self) -> StatsData {
+        let Self {
+            view_type: _,
+            num_values,
+            sum,
+            average,
+            median,
+            variance,
+            tiles,
+        } = self;
+        StatsData {
+            num_values: *num_values,
+            sum: *sum,
+            average: average.to_bits(),
+            median: *median,
+            variance: variance.to_bits(),
+            tiles: tiles.clone(),
+        }
+    }
+
+    /// Gives an error if the number of tiles does not match
+    /// `TILE_COUNT`.
+    pub fn from_data(data: StatsData) -> anyhow::Result<Self> {
+        let StatsData {
+            num_values,
+            sum,
+            average,
+            median,
+            variance,
+            tiles,
+        } = data;
+        if tiles.len() != TILE_COUNT {
+            bail!("expecting {TILE_COUNT} tiles, got {}", tiles.len())
+        }
+        Ok(Stats {
+            view_type: PhantomData::default(),
+            num_values,
+            sum,
+            average: FixedU128::from_bits(average),
+            median,
+            variance: FixedU128::from_bits(variance),
+            tiles,
+        })
+    }
+
     /// Make stats from values from field `field`: this determines the
     /// ViewType of the resulting `Stats` struct: count or ViewType.
     pub fn from_values_from_field(
@@ -559,6 +620,21 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn t_data_roundtrip() -> Result<()> {
+        let stats = Stats::<u64, 4>::from_values(weighted(&[23, 4, 8, 30, 7]))?;
+        let data = stats.to_data();
+        let json = serde_json::to_string(&data)?;
+        let data2: StatsData = serde_json::from_str(&json)?;
+        assert_eq!(data2, data);
+        let stats2 = Stats::<u64, 4>::from_data(data2)?;
+        assert_eq!(stats2.average, stats.average);
+        assert_eq!(stats2.variance, stats.variance);
+        assert_eq!(stats2.tiles, stats.tiles);
+        assert!(Stats::<u64, 5>::from_data(data).is_err());
+        Ok(())
+    }
+
     #[test]
     fn t_weights() -> Result<()> {
         let data = weighted(&[23, 4, 9, 4, 4, 7]); // 4 4 4 7 9 23
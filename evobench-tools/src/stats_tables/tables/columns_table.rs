@@ -0,0 +1,101 @@
+//! A table with one value column per table it was built from
+//!
+//! Used to show how a statistic changes along some dimension, e.g.
+//! across the values of a swept custom parameter, by combining the
+//! same field from several tables with the same kind of rows.
+
+use std::{borrow::Cow, collections::BTreeMap};
+
+use genawaiter::rc::Gen;
+
+use crate::{join::KeyVal, stats_tables::stats::StatsField};
+
+use super::{
+    table_field_view::TableFieldView,
+    table_view::{ColumnFormatting, Highlight, TableView, Unit},
+};
+
+pub struct ColumnsTable {
+    name: String,
+    key_column: (Cow<'static, str>, Unit, ColumnFormatting),
+    column_labels: Vec<String>,
+    /// Sorted by key; the values are in the order of `column_labels`,
+    /// `None` where the table for the column did not have the row
+    rows: Vec<KeyVal<String, Vec<Option<u64>>>>,
+}
+
+impl ColumnsTable {
+    /// Combine the values for `stats_field` from `columns`, each
+    /// table giving one column labelled with the given string. The
+    /// rows are the union of the rows of all tables. The tables must
+    /// be of the same kind (the name, key column and unit are taken
+    /// from the first). Returns `None` if `columns` is empty.
+    pub fn from_table_field_views<const TILE_COUNT: usize>(
+        columns: &[(&str, &dyn TableFieldView<TILE_COUNT>)],
+        stats_field: StatsField<TILE_COUNT>,
+    ) -> Option<Self> {
+        let (_, first) = columns.first()?;
+        let name = first.table_name().into_owned();
+        let key_column = (*first.table_view_header()).as_ref()[0].clone();
+        let unit = first.resolution_unit();
+
+        let mut rows: BTreeMap<String, Vec<Option<u64>>> = BTreeMap::new();
+        for (i, (_, table)) in columns.iter().enumerate() {
+            for KeyVal { key, val } in table.table_key_vals(stats_field) {
+                let vals = rows
+                    .entry(key.to_string())
+                    .or_insert_with(|| vec![None; columns.len()]);
+                vals[i] = Some(val);
+            }
+        }
+
+        Some(Self {
+            name,
+            key_column,
+            column_labels: columns
+                .iter()
+                .map(|(label, _)| format!("{label}\n({unit})"))
+                .collect(),
+            rows: rows
+                .into_iter()
+                .map(|(key, val)| KeyVal { key, val })
+                .collect(),
+        })
+    }
+}
+
+impl TableView for ColumnsTable {
+    fn table_name(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.name)
+    }
+
+    fn table_view_header(&self) -> Box<dyn AsRef<[(Cow<'static, str>, Unit, ColumnFormatting)]>> {
+        let mut header = vec![self.key_column.clone()];
+        for label in &self.column_labels {
+            header.push((label.clone().into(), Unit::None, ColumnFormatting::Number));
+        }
+        Box::new(header)
+    }
+
+    fn table_view_body<'s>(
+        &'s self,
+    ) -> Box<dyn Iterator<Item = Cow<'s, [(Cow<'s, str>, Highlight)]>> + 's> {
+        Box::new(
+            Gen::new(|co| async move {
+                for KeyVal { key, val } in &self.rows {
+                    let mut vals: Vec<(Cow<str>, Highlight)> = Vec::new();
+                    vals.push((key.as_str().into(), Highlight::Neutral));
+                    for val in val {
+                        let val: Cow<str> = match val {
+                            Some(val) => val.to_string().into(),
+                            None => "".into(),
+                        };
+                        vals.push((val, Highlight::Neutral));
+                    }
+                    co.yield_(vals.into()).await;
+                }
+            })
+            .into_iter(),
+        )
+    }
+}
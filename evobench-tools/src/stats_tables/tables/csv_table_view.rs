@@ -0,0 +1,57 @@
+//! Convert `TableView`s to CSV files, one per table
+
+use std::{fs::File, io::BufWriter, io::Write, path::Path};
+
+use anyhow::{Context, Result, anyhow};
+use cj_path_util::unix::polyfill::add_extension;
+
+use super::table_view::{ColumnFormatting, TableView};
+use crate::{io_utils::div::xrename, utillib::csv::csv_line};
+
+/// Write `table` to `file`. The header row has the column names with
+/// the unit in parentheses, e.g. "avg (ms)"; spacer columns are left
+/// out.
+pub fn csv_file_write(table: &dyn TableView, file: &Path) -> Result<()> {
+    let _titles = table.table_view_header();
+    let titles = (*_titles).as_ref();
+
+    let column_indices: Vec<usize> = titles
+        .iter()
+        .enumerate()
+        .filter_map(|(i, (_, _, column_formatting))| match column_formatting {
+            ColumnFormatting::Spacer => None,
+            ColumnFormatting::Number | ColumnFormatting::String { width_chars: _ } => Some(i),
+        })
+        .collect();
+
+    let file_tmp =
+        add_extension(file, "tmp").ok_or_else(|| anyhow!("path misses a filename: {file:?}"))?;
+    {
+        let mut out = BufWriter::new(
+            File::create(&file_tmp).with_context(|| anyhow!("creating file {file_tmp:?}"))?,
+        );
+        let header = column_indices.iter().map(|i| {
+            let (label, unit, _) = &titles[*i];
+            // Labels may contain line breaks for Excel
+            let label = label.replace('\n', " ");
+            match unit.name() {
+                Some(unit) => format!("{label} ({unit})"),
+                None => label,
+            }
+        });
+        writeln!(out, "{}", csv_line(header))?;
+        for row in table.table_view_body() {
+            let vals = column_indices.iter().map(|i| {
+                row.get(*i)
+                    .map(|(val, _highlight)| val.as_ref())
+                    .unwrap_or("")
+            });
+            writeln!(out, "{}", csv_line(vals))?;
+        }
+        out.flush()
+            .with_context(|| anyhow!("writing to file {file_tmp:?}"))?;
+    }
+    xrename(&file_tmp, &file)?;
+
+    Ok(())
+}
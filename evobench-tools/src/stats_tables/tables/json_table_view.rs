@@ -0,0 +1,236 @@
+//! Convert a sequence of `TableView`s to a JSON file
+//!
+//! The types in this module define the format; their field names are
+//! stable (new fields may be added, existing ones are not renamed or
+//! removed without increasing `JSON_TABLES_FORMAT_VERSION`). The JSON
+//! schema derived from them is printed by `evobench-eval json-schema`.
+
+use std::{fs::File, io::BufWriter, io::Write, path::Path};
+
+use anyhow::{Context, Result, anyhow};
+use cj_path_util::unix::polyfill::add_extension;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::table_view::{ColumnFormatting, TableView};
+use crate::io_utils::div::xrename;
+
+pub const JSON_TABLES_FORMAT_VERSION: u32 = 1;
+
+/// The top level of the JSON file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct JsonTables {
+    /// `JSON_TABLES_FORMAT_VERSION`
+    pub format_version: u32,
+    pub tables: Vec<JsonTable>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct JsonTable {
+    /// E.g. "real", "cpu", "sys", "ctx-switches"
+    pub name: String,
+    /// Spacer columns are left out
+    pub columns: Vec<JsonColumn>,
+    /// One value per entry in `columns`
+    pub rows: Vec<Vec<JsonValue>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct JsonColumn {
+    /// The column title, e.g. "n", "avg", "0.25" (percentiles), or
+    /// the label of the key column (the probe names or call paths)
+    pub name: String,
+    /// The unit of the values, e.g. "ms" or "count"; `null` for
+    /// dimensionless values and strings
+    pub unit: Option<String>,
+    pub kind: JsonColumnKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JsonColumnKind {
+    /// The values are numbers (or `null`)
+    Number,
+    /// The values are strings
+    String,
+}
+
+/// A cell value: a number, a string, or `null` for empty cells
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum JsonValue {
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Null,
+}
+
+impl JsonValue {
+    fn from_cell(val: &str, kind: JsonColumnKind) -> Self {
+        if val.is_empty() {
+            return JsonValue::Null;
+        }
+        match kind {
+            JsonColumnKind::Number => {
+                if let Ok(v) = val.parse() {
+                    JsonValue::Integer(v)
+                } else if let Ok(v) = val.parse::<f64>() {
+                    if v.is_finite() {
+                        JsonValue::Float(v)
+                    } else {
+                        JsonValue::Null
+                    }
+                } else {
+                    JsonValue::String(val.into())
+                }
+            }
+            JsonColumnKind::String => JsonValue::String(val.into()),
+        }
+    }
+}
+
+impl JsonTable {
+    pub fn from_table_view(table: &dyn TableView) -> Self {
+        let _titles = table.table_view_header();
+        let titles = (*_titles).as_ref();
+
+        // The indices of the non-spacer columns, with their kind
+        let column_kinds: Vec<(usize, JsonColumnKind)> = titles
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (_, _, column_formatting))| match column_formatting {
+                ColumnFormatting::Spacer => None,
+                ColumnFormatting::Number => Some((i, JsonColumnKind::Number)),
+                ColumnFormatting::String { width_chars: _ } => Some((i, JsonColumnKind::String)),
+            })
+            .collect();
+
+        let columns = column_kinds
+            .iter()
+            .map(|(i, kind)| {
+                let (label, unit, _) = &titles[*i];
+                JsonColumn {
+                    // Labels may contain line breaks for Excel
+                    name: label.replace('\n', " "),
+                    unit: unit.name().map(String::from),
+                    kind: *kind,
+                }
+            })
+            .collect();
+
+        let rows = table
+            .table_view_body()
+            .map(|row| {
+                column_kinds
+                    .iter()
+                    .map(|(i, kind)| match row.get(*i) {
+                        Some((val, _highlight)) => JsonValue::from_cell(val, *kind),
+                        None => JsonValue::Null,
+                    })
+                    .collect()
+            })
+            .collect();
+
+        JsonTable {
+            name: table.table_name().into_owned(),
+            columns,
+            rows,
+        }
+    }
+}
+
+/// The JSON schema of the files written by `json_file_write`
+pub fn json_tables_schema() -> String {
+    let schema = schemars::schema_for!(JsonTables);
+    serde_json::to_string_pretty(&schema).expect("schema serializes")
+}
+
+pub fn json_file_write<'t>(
+    tables: impl IntoIterator<Item = &'t (dyn TableView + 't)>,
+    file: &Path,
+) -> Result<()> {
+    let json_tables = JsonTables {
+        format_version: JSON_TABLES_FORMAT_VERSION,
+        tables: tables
+            .into_iter()
+            .map(|table| JsonTable::from_table_view(table))
+            .collect(),
+    };
+
+    let file_tmp =
+        add_extension(file, "tmp").ok_or_else(|| anyhow!("path misses a filename: {file:?}"))?;
+    {
+        let mut out = BufWriter::new(
+            File::create(&file_tmp).with_context(|| anyhow!("creating file {file_tmp:?}"))?,
+        );
+        serde_json::to_writer_pretty(&mut out, &json_tables)
+            .with_context(|| anyhow!("writing to file {file_tmp:?}"))?;
+        writeln!(out)?;
+        out.flush()
+            .with_context(|| anyhow!("writing to file {file_tmp:?}"))?;
+    }
+    xrename(&file_tmp, &file)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_json_value_from_cell() {
+        assert_eq!(
+            JsonValue::from_cell("12", JsonColumnKind::Number),
+            JsonValue::Integer(12)
+        );
+        assert_eq!(
+            JsonValue::from_cell("0.125", JsonColumnKind::Number),
+            JsonValue::Float(0.125)
+        );
+        assert_eq!(
+            JsonValue::from_cell("12", JsonColumnKind::String),
+            JsonValue::String("12".into())
+        );
+        assert_eq!(
+            JsonValue::from_cell("", JsonColumnKind::Number),
+            JsonValue::Null
+        );
+        assert_eq!(
+            serde_json::to_string(&vec![
+                JsonValue::Integer(1),
+                JsonValue::String("a".into()),
+                JsonValue::Null
+            ])
+            .unwrap(),
+            r#"[1,"a",null]"#
+        );
+    }
+
+    #[test]
+    fn t_json_tables_schema() {
+        let schema: serde_json::Value = serde_json::from_str(&json_tables_schema()).unwrap();
+        let property_names = |schema: &serde_json::Value| -> Vec<String> {
+            let mut names: Vec<String> = schema["properties"]
+                .as_object()
+                .expect("has properties")
+                .keys()
+                .cloned()
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(property_names(&schema), ["format_version", "tables"]);
+        assert_eq!(
+            property_names(&schema["$defs"]["JsonTable"]),
+            ["columns", "name", "rows"]
+        );
+        assert_eq!(
+            property_names(&schema["$defs"]["JsonColumn"]),
+            ["kind", "name", "unit"]
+        );
+        let kind = schema["$defs"]["JsonColumnKind"].to_string();
+        assert!(kind.contains(r#""number""#), "{kind}");
+        assert!(kind.contains(r#""string""#), "{kind}");
+    }
+}
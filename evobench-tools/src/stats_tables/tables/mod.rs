@@ -1,7 +1,10 @@
 //! A table based representation for descriptive statistics
 
 pub mod change;
+pub mod columns_table;
+pub mod csv_table_view;
 pub mod excel_table_view;
+pub mod json_table_view;
 pub mod table;
 pub mod table_field_view;
 pub mod table_view;
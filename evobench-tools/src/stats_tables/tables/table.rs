@@ -126,6 +126,61 @@ impl<'key, K: TableKind, ViewType: Debug + ToStatsString + From<u64> + Resolutio
     }
 }
 
+/// Pair up the rows with the same key in `from_rows` and `to_rows`
+/// (both sorted by key), for those that `as_stats_or_count` gives a
+/// value for.
+fn change_rows<
+    'key,
+    K: TableKind,
+    T,
+    ViewType: Debug,
+    const TILE_COUNT: usize,
+    Better: IsBetter,
+>(
+    kind: &K,
+    from_rows: &[KeyVal<Cow<'key, str>, T>],
+    to_rows: &[KeyVal<Cow<'key, str>, T>],
+    as_stats_or_count: impl Fn(&T) -> Option<&StatsOrCount<ViewType, TILE_COUNT>>,
+    extract: fn(&Stats<ViewType, TILE_COUNT>) -> u64,
+) -> Table<'key, K, Change<Better>> {
+    let mut rows: Vec<KeyVal<_, _>> = Vec::new();
+    for either_or_both in from_rows
+        .iter()
+        .merge_join_by(to_rows, |a, b| a.key.cmp(&b.key))
+    {
+        if let EitherOrBoth::Both(from, to) = either_or_both {
+            let (Some(from_val), Some(to_val)) =
+                (as_stats_or_count(&from.val), as_stats_or_count(&to.val))
+            else {
+                continue;
+            };
+            match (from_val, to_val) {
+                (StatsOrCount::Stats(from_stats), StatsOrCount::Stats(to_stats)) => {
+                    rows.push(KeyVal {
+                        key: from.key.clone(), // OK, usually with a ref anyway?
+                        val: Change::new(extract(from_stats), extract(to_stats)),
+                    });
+                    // XX but also pass data for significance!
+                }
+                (StatsOrCount::Count(_from), StatsOrCount::Count(_to)) => {
+                    // Ignore bare counts for comparisons. -- XX
+                    // or should it output the relation? Usually
+                    // 1, but? But only when counts were asked! ->
+                    // take this boolean info about the extract
+                    // function as argument?
+                }
+                _ => panic!("not in sync, {from_val:?} vs. {to_val:?}"),
+            }
+        }
+        // Silently ignore rows with keys that only appear on one
+        // side.
+    }
+    Table {
+        kind: kind.clone(), // XX?
+        rows,
+    }
+}
+
 impl<'key, K: TableKind, ViewType: Debug, const TILE_COUNT: usize>
     Table<'key, K, StatsOrCount<ViewType, TILE_COUNT>>
 {
@@ -136,37 +191,147 @@ impl<'key, K: TableKind, ViewType: Debug, const TILE_COUNT: usize>
         to: &Self,
         extract: fn(&Stats<ViewType, TILE_COUNT>) -> u64,
     ) -> Table<'key, K, Change<Better>> {
-        let mut rows: Vec<KeyVal<_, _>> = Vec::new();
-        for either_or_both in self
-            .rows
-            .iter()
-            .merge_join_by(&to.rows, |a, b| a.key.cmp(&b.key))
-        {
-            if let EitherOrBoth::Both(from, to) = either_or_both {
-                match (&from.val, &to.val) {
-                    (StatsOrCount::Stats(from_stats), StatsOrCount::Stats(to_stats)) => {
-                        rows.push(KeyVal {
-                            key: from.key.clone(), // OK, usually with a ref anyway?
-                            val: Change::new(extract(from_stats), extract(to_stats)),
-                        });
-                        // XX but also pass data for significance!
-                    }
-                    (StatsOrCount::Count(_from), StatsOrCount::Count(_to)) => {
-                        // Ignore bare counts for comparisons. -- XX
-                        // or should it output the relation? Usually
-                        // 1, but? But only when counts were asked! ->
-                        // take this boolean info about the extract
-                        // function as argument?
-                    }
-                    _ => panic!("not in sync, {from:?} vs. {to:?}"),
-                }
-            }
-            // Silently ignore rows with keys that only appear on one
-            // side.
+        change_rows(&self.kind, &self.rows, &to.rows, |val| Some(val), extract)
+    }
+}
+
+impl<'key, K: TableKind, ViewType: Debug, const TILE_COUNT: usize>
+    Table<'key, K, StatsOrCountOrSubStats<ViewType, TILE_COUNT>>
+{
+    /// Same as `change` on tables of `StatsOrCount` (which is what
+    /// the rows of summary tables hold); rows with `SubStats` are
+    /// ignored.
+    pub fn change<Better: IsBetter>(
+        &self,
+        to: &Self,
+        extract: fn(&Stats<ViewType, TILE_COUNT>) -> u64,
+    ) -> Table<'key, K, Change<Better>> {
+        change_rows(
+            &self.kind,
+            &self.rows,
+            &to.rows,
+            |val| match val {
+                StatsOrCountOrSubStats::StatsOrCount(stats_or_count) => Some(stats_or_count),
+                StatsOrCountOrSubStats::SubStats(_) => None,
+            },
+            extract,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stats_tables::{
+        stats::weighted::{WEIGHT_ONE, WeightedValue},
+        tables::change::SmallerIsBetter,
+    };
+
+    use super::*;
+
+    #[derive(Clone)]
+    struct TestKind;
+
+    impl TableKind for TestKind {
+        fn table_name(&self) -> Cow<'_, str> {
+            "test".into()
+        }
+        fn table_key_label(&self) -> Cow<'_, str> {
+            "key".into()
+        }
+        fn table_key_column_width(&self) -> Option<f64> {
+            None
         }
+    }
+
+    fn stats(vals: &[u64]) -> Stats<u64, 4> {
+        Stats::from_values(
+            vals.iter()
+                .map(|&value| WeightedValue {
+                    value,
+                    weight: WEIGHT_ONE,
+                })
+                .collect(),
+        )
+        .unwrap()
+    }
+
+    fn table(
+        rows: Vec<(&'static str, StatsOrCount<u64, 4>)>,
+    ) -> Table<'static, TestKind, StatsOrCount<u64, 4>> {
         Table {
-            kind: self.kind.clone(), // XX?
-            rows,
+            kind: TestKind,
+            rows: rows
+                .into_iter()
+                .map(|(key, val)| KeyVal {
+                    key: key.into(),
+                    val,
+                })
+                .collect(),
         }
     }
+
+    fn changes<Better: IsBetter>(
+        table: &Table<'static, TestKind, Change<Better>>,
+    ) -> Vec<(&str, u64, u64)> {
+        table
+            .rows
+            .iter()
+            .map(|KeyVal { key, val }| (key.as_ref(), val.from, val.to))
+            .collect()
+    }
+
+    #[test]
+    fn t_change() {
+        let from = table(vec![
+            ("a", StatsOrCount::Stats(stats(&[10, 30]))),
+            ("b", StatsOrCount::Stats(stats(&[40]))),
+            ("c", StatsOrCount::Stats(stats(&[5]))),
+            ("e", StatsOrCount::Count(3)),
+        ]);
+        let to = table(vec![
+            ("a", StatsOrCount::Stats(stats(&[30]))),
+            ("b", StatsOrCount::Stats(stats(&[10, 20]))),
+            ("d", StatsOrCount::Stats(stats(&[7]))),
+            ("e", StatsOrCount::Count(4)),
+        ]);
+        // Rows only on one side, and counts, are dropped
+        let change = from.change::<SmallerIsBetter>(&to, Stats::average_u64);
+        assert_eq!(changes(&change), [("a", 20, 30), ("b", 40, 15)]);
+    }
+
+    #[test]
+    fn t_change_with_sub_stats() {
+        let table = |rows: Vec<(&'static str, StatsOrCountOrSubStats<u64, 4>)>| Table {
+            kind: TestKind,
+            rows: rows
+                .into_iter()
+                .map(|(key, val)| KeyVal {
+                    key: Cow::from(key),
+                    val,
+                })
+                .collect(),
+        };
+        let from = table(vec![
+            (
+                "a",
+                StatsOrCountOrSubStats::StatsOrCount(StatsOrCount::Stats(stats(&[10]))),
+            ),
+            (
+                "b",
+                StatsOrCountOrSubStats::SubStats(SubStats::Count(stats(&[1]))),
+            ),
+        ]);
+        let to = table(vec![
+            (
+                "a",
+                StatsOrCountOrSubStats::StatsOrCount(StatsOrCount::Stats(stats(&[20]))),
+            ),
+            (
+                "b",
+                StatsOrCountOrSubStats::SubStats(SubStats::Count(stats(&[2]))),
+            ),
+        ]);
+        let change = from.change::<SmallerIsBetter>(&to, Stats::average_u64);
+        assert_eq!(changes(&change), [("a", 10, 20)]);
+    }
 }
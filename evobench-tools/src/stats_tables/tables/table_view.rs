@@ -17,6 +17,18 @@ pub enum Unit {
     ViewType(&'static str),
 }
 
+impl Unit {
+    /// The unit name to show, if any
+    pub fn name(self) -> Option<&'static str> {
+        match self {
+            Unit::None => None,
+            Unit::DimensionLess => None,
+            Unit::Count => Some("count"),
+            Unit::ViewType(unit) => Some(unit),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum Highlight {
     /// Used for spacer columns, i.e. no value is there.
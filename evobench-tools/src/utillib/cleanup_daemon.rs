@@ -88,6 +88,13 @@ impl Deletion {
             Deletion::Dir(path) => path,
         }
     }
+
+    /// Delete the file or dir tree now, without going through a
+    /// `CleanupHandler` (see `CleanupHandler::delete_now`). Returns
+    /// None if the path did not exist.
+    pub fn delete_now(&self) -> Result<Option<String>> {
+        self.run_cleanup()
+    }
 }
 
 impl RunCleanup for Deletion {
@@ -294,6 +301,13 @@ impl CleanupHandler {
     pub fn register_temporary_command(&self, deletion: CleanupCommand) -> Result<TemporaryCommand> {
         Ok(TemporaryCommand(Some(self.register_cleanup(deletion)?)))
     }
+
+    /// Carry out `deletion` now; it is registered with the daemon
+    /// first, so that it is still completed if this process is
+    /// killed while deleting. Returns None if the path did not exist.
+    pub fn delete_now(&self, deletion: Deletion) -> Result<Option<String>> {
+        self.register_cleanup(deletion)?.cleanup_now()
+    }
 }
 
 struct ItemWithCleanup<Item: Into<CleanupItem>> {
@@ -0,0 +1,34 @@
+//! Writing CSV (as per RFC 4180)
+
+use std::borrow::Cow;
+
+/// Quote a CSV field as per RFC 4180, if necessary
+pub fn csv_quote(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\"")).into()
+    } else {
+        field.into()
+    }
+}
+
+/// The fields as a CSV line (without line terminator)
+pub fn csv_line<S: AsRef<str>>(fields: impl IntoIterator<Item = S>) -> String {
+    let fields: Vec<String> = fields
+        .into_iter()
+        .map(|field| csv_quote(field.as_ref()).into_owned())
+        .collect();
+    fields.join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_csv_quote() {
+        assert_eq!(csv_quote("foo"), "foo");
+        assert_eq!(csv_quote("a > b, c"), "\"a > b, c\"");
+        assert_eq!(csv_quote("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_line(["a", "b,c", ""]), "a,\"b,c\",");
+    }
+}
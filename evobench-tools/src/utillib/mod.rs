@@ -8,6 +8,7 @@ pub mod cleanup_daemon;
 pub mod clone;
 pub mod conslist;
 pub mod crypto_hash;
+pub mod csv;
 pub mod ctx;
 pub mod escaped_display;
 pub mod exit_status_ext;
@@ -32,6 +33,8 @@ pub mod recycle;
 pub mod ref_or_owned;
 pub mod safe_string;
 pub mod slice_or_box;
+#[cfg(test)]
+pub mod test_dir;
 pub mod tuple_transpose;
 pub mod type_name_short;
 pub mod unix;
@@ -0,0 +1,37 @@
+//! Temporary directories for tests
+
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// An empty directory below the system's temporary directory, deleted
+/// (recursively) when dropped
+pub struct TestDir(PathBuf);
+
+impl TestDir {
+    /// `name` is used as part of the directory name, together with
+    /// the process id and a counter to make it unique.
+    pub fn new(name: &str) -> Self {
+        let n = COUNTER.fetch_add(1, Ordering::SeqCst);
+        let path =
+            std::env::temp_dir().join(format!("evobench-test-{name}-{}-{n}", std::process::id()));
+        if path.exists() {
+            std::fs::remove_dir_all(&path).expect("removing stale test dir");
+        }
+        std::fs::create_dir_all(&path).expect("creating test dir");
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}